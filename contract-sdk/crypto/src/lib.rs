@@ -1,3 +1,4 @@
 //! A collection of types and cryptography helpers for the Oasis Contract SDK.
 
 pub mod ecdsa;
+pub mod signature;
@@ -0,0 +1,47 @@
+//! Signature verification helpers.
+use std::convert::TryFrom;
+
+use k256::ecdsa::{self, signature::DigestVerifier};
+use sha2::{Digest, Sha512Trunc256};
+
+/// Hash of the domain separation context and the message, which is what is actually signed.
+fn digest(context: &[u8], message: &[u8]) -> Sha512Trunc256 {
+    let mut digest = Sha512Trunc256::new();
+    digest.update(context);
+    digest.update(message);
+    digest
+}
+
+/// Verify an Ed25519 signature over the given message in the given domain separation context.
+pub fn verify_ed25519(key: &[u8], context: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    let key = match ed25519_dalek::PublicKey::from_bytes(key) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    let signature = match ed25519_dalek::Signature::try_from(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    key.verify_strict(digest(context, message).finalize().as_slice(), &signature)
+        .is_ok()
+}
+
+/// Verify a DER-encoded ECDSA/secp256k1 signature over the given message in the given domain
+/// separation context. The public key must be in compressed form.
+pub fn verify_secp256k1(key: &[u8], context: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    if key.len() != 33 {
+        return false;
+    }
+    let key = match ecdsa::VerifyingKey::from_sec1_bytes(key) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    let signature = match ecdsa::Signature::from_der(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    key.verify_digest(digest(context, message), &signature)
+        .is_ok()
+}
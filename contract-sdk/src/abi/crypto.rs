@@ -9,4 +9,18 @@ extern "C" {
         output_ptr: u32,
         output_len: u32,
     );
+
+    /// Returns zero in case the signature is valid.
+    #[link_name = "signature_verify"]
+    pub(crate) fn crypto_signature_verify(
+        kind: u32,
+        key_ptr: u32,
+        key_len: u32,
+        context_ptr: u32,
+        context_len: u32,
+        message_ptr: u32,
+        message_len: u32,
+        signature_ptr: u32,
+        signature_len: u32,
+    ) -> u32;
 }
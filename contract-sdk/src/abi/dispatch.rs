@@ -1,13 +1,13 @@
 //! Request dispatch ABI.
 use crate::{
     context,
-    contract::Contract,
+    contract::{self, Contract},
     error,
     event::Event,
     memory::HostRegion,
     types::{
-        address::Address, event::Event as RawEvent, message::Message, storage::StoreKind, token,
-        ExecutionContext, ExecutionOk, ExecutionResult, InstanceId,
+        address::Address, event::Event as RawEvent, hash::Hash, message::Message,
+        storage::StoreKind, token, ExecutionContext, ExecutionOk, ExecutionResult, InstanceId,
     },
 };
 
@@ -22,6 +22,9 @@ struct Context {
 
     messages: Vec<Message>,
     events: Vec<RawEvent>,
+
+    /// Whether the context is used for a read-only query.
+    read_only: bool,
 }
 
 impl From<ExecutionContext> for Context {
@@ -35,6 +38,8 @@ impl From<ExecutionContext> for Context {
 
             messages: vec![],
             events: vec![],
+
+            read_only: false,
         }
     }
 }
@@ -56,14 +61,34 @@ impl context::Context for Context {
         &self.ec.caller_address
     }
 
+    fn tx_signer(&self) -> &Address {
+        &self.ec.tx_signer
+    }
+
+    fn tx_hash(&self) -> Hash {
+        self.ec.tx_hash
+    }
+
     fn deposited_tokens(&self) -> &[token::BaseUnits] {
         &self.ec.deposited_tokens
     }
 
+    fn take_deposited_tokens(&mut self) -> Vec<token::BaseUnits> {
+        std::mem::take(&mut self.ec.deposited_tokens)
+    }
+
     fn emit_message(&mut self, msg: Message) {
+        assert!(
+            !self.read_only,
+            "messages cannot be emitted in query context"
+        );
         self.messages.push(msg);
     }
 
+    fn emitted_messages(&self) -> &[Message] {
+        &self.messages
+    }
+
     fn emit_event<E: Event>(&mut self, event: E) {
         self.events.push(event.into_raw());
     }
@@ -106,10 +131,17 @@ fn handle_result<R, E>(ctx: Context, result: Result<Option<R>, E>) -> *const Hos
 where
     R: cbor::Encode,
     E: error::Error,
+{
+    handle_encoded_result(ctx, result.map(|data| data.map(cbor::to_vec)))
+}
+
+fn handle_encoded_result<E>(ctx: Context, result: Result<Option<Vec<u8>>, E>) -> *const HostRegion
+where
+    E: error::Error,
 {
     let result = match result {
         Ok(data) => ExecutionResult::Ok(ExecutionOk {
-            data: data.map(cbor::to_vec).unwrap_or_default(),
+            data: data.unwrap_or_default(),
             messages: ctx.messages,
             events: ctx.events,
         }),
@@ -154,6 +186,7 @@ pub fn handle_reply<C: Contract>(
     request_len: u32,
 ) -> *const HostRegion {
     let (mut ctx, reply) = load_request_context(ctx_ptr, ctx_len, request_ptr, request_len);
+    context::release_reentrancy_guards(&mut ctx, &reply);
     let result = C::handle_reply(&mut ctx, reply);
     handle_result(ctx, result)
 }
@@ -193,8 +226,11 @@ pub fn query<C: Contract>(
     request_len: u32,
 ) -> *const HostRegion {
     let (mut ctx, request) = load_request_context(ctx_ptr, ctx_len, request_ptr, request_len);
-    let result = C::query(&mut ctx, request).map(Option::Some);
-    handle_result(ctx, result)
+    ctx.read_only = true;
+    let result = C::query(&mut ctx, request)
+        .map(contract::encode_query_response)
+        .map(Option::Some);
+    handle_encoded_result(ctx, result)
 }
 
 /// A macro that creates WASM entry points.
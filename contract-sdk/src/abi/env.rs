@@ -8,6 +8,7 @@ use crate::{
     env::{Crypto, Env},
     memory::{HostRegion, HostRegionRef},
     types::{
+        crypto::SignatureKind,
         env::{QueryRequest, QueryResponse},
         InstanceId,
     },
@@ -73,4 +74,33 @@ impl Crypto for HostEnv {
 
         dst
     }
+
+    fn verify_signature(
+        &self,
+        scheme: SignatureKind,
+        public_key: &[u8],
+        context: &[u8],
+        message: &[u8],
+        signature: &[u8],
+    ) -> bool {
+        let key_region = HostRegionRef::from_slice(public_key);
+        let context_region = HostRegionRef::from_slice(context);
+        let message_region = HostRegionRef::from_slice(message);
+        let signature_region = HostRegionRef::from_slice(signature);
+
+        let result = unsafe {
+            crypto::crypto_signature_verify(
+                scheme as u32,
+                key_region.offset,
+                key_region.length,
+                context_region.offset,
+                context_region.length,
+                message_region.offset,
+                message_region.length,
+                signature_region.offset,
+                signature_region.length,
+            )
+        };
+        result == 0
+    }
 }
@@ -0,0 +1,382 @@
+//! Storage-backed collections.
+//!
+//! Collections keep their entries in contract storage instead of loading them into memory, so
+//! each operation only touches the entries it needs. Iteration is deterministic and follows
+//! insertion order: new keys are appended at the end, while overwriting an existing key keeps
+//! its position.
+use std::marker::PhantomData;
+
+use crate::storage::Store;
+
+/// Key under which the number of entries is stored.
+const KEY_LEN: &[u8] = &[0x00];
+/// Key under which the (encoded) first key is stored.
+const KEY_HEAD: &[u8] = &[0x01];
+/// Key under which the (encoded) last key is stored.
+const KEY_TAIL: &[u8] = &[0x02];
+/// Prefix of keys under which values are stored.
+const PREFIX_VALUES: u8 = 0x03;
+/// Prefix of keys under which links between entries are stored.
+const PREFIX_LINKS: u8 = 0x04;
+
+/// Links to the neighbouring entries in insertion order.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+struct Links {
+    #[cbor(optional)]
+    prev: Option<Vec<u8>>,
+    #[cbor(optional)]
+    next: Option<Vec<u8>>,
+}
+
+/// An insertion-ordered map of encoded keys to raw values.
+struct RawMap<S: Store> {
+    store: S,
+}
+
+impl<S: Store> RawMap<S> {
+    fn value_key(key: &[u8]) -> Vec<u8> {
+        [&[PREFIX_VALUES], key].concat()
+    }
+
+    fn links_key(key: &[u8]) -> Vec<u8> {
+        [&[PREFIX_LINKS], key].concat()
+    }
+
+    fn decode<T: cbor::Decode>(data: &[u8]) -> T {
+        cbor::from_slice(data).expect("collection storage should be well-formed")
+    }
+
+    fn len(&self) -> u64 {
+        self.store
+            .get(KEY_LEN)
+            .map(|data| Self::decode(&data))
+            .unwrap_or_default()
+    }
+
+    fn set_len(&mut self, len: u64) {
+        self.store.insert(KEY_LEN, &cbor::to_vec(len));
+    }
+
+    fn head(&self) -> Option<Vec<u8>> {
+        self.store.get(KEY_HEAD)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.store.get(&Self::value_key(key))
+    }
+
+    fn links(&self, key: &[u8]) -> Links {
+        self.store
+            .get(&Self::links_key(key))
+            .map(|data| Self::decode(&data))
+            .unwrap_or_default()
+    }
+
+    fn set_links(&mut self, key: &[u8], links: &Links) {
+        self.store
+            .insert(&Self::links_key(key), &cbor::to_vec(links.clone()));
+    }
+
+    /// Update the given neighbour, or the head/tail pointer in case there is none.
+    fn relink(&mut self, neighbour: Option<&[u8]>, end: &[u8], update: impl FnOnce(&mut Links)) {
+        match neighbour {
+            Some(neighbour) => {
+                let mut links = self.links(neighbour);
+                update(&mut links);
+                self.set_links(neighbour, &links);
+            }
+            None => {
+                let mut links = Links::default();
+                update(&mut links);
+                match links.prev.or(links.next) {
+                    Some(key) => self.store.insert(end, &key),
+                    None => self.store.remove(end),
+                }
+            }
+        }
+    }
+
+    /// Insert a value, returning the previous one if any.
+    fn insert(&mut self, key: &[u8], value: &[u8]) -> Option<Vec<u8>> {
+        let previous = self.get(key);
+        self.store.insert(&Self::value_key(key), value);
+        if previous.is_some() {
+            return previous;
+        }
+
+        // Append the new key at the end.
+        let tail = self.store.get(KEY_TAIL);
+        self.relink(tail.as_deref(), KEY_HEAD, |links| {
+            links.next = Some(key.to_vec())
+        });
+        self.store.insert(KEY_TAIL, key);
+        self.set_links(
+            key,
+            &Links {
+                prev: tail,
+                next: None,
+            },
+        );
+        self.set_len(self.len() + 1);
+
+        None
+    }
+
+    /// Remove a value, returning it if it existed.
+    fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let previous = self.get(key)?;
+        self.store.remove(&Self::value_key(key));
+
+        // Unlink the key from its neighbours.
+        let Links { prev, next } = self.links(key);
+        self.store.remove(&Self::links_key(key));
+        let next_ref = next.clone();
+        self.relink(prev.as_deref(), KEY_HEAD, |links| links.next = next_ref);
+        self.relink(next.as_deref(), KEY_TAIL, |links| links.prev = prev);
+        self.set_len(self.len() - 1);
+
+        Some(previous)
+    }
+}
+
+/// Iterator over encoded keys and raw values of a [`RawMap`] in insertion order.
+struct RawIter<'a, S: Store> {
+    map: &'a RawMap<S>,
+    next: Option<Vec<u8>>,
+}
+
+impl<'a, S: Store> Iterator for RawIter<'a, S> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.next.take()?;
+        let value = self
+            .map
+            .get(&key)
+            .expect("linked collection entries should exist");
+        self.next = self.map.links(&key).next;
+        Some((key, value))
+    }
+}
+
+/// A map backed by contract storage.
+///
+/// The map should be given its own key space, e.g. by using [`Store::scoped`].
+pub struct StorageMap<S: Store, K, V> {
+    raw: RawMap<S>,
+    _types: PhantomData<(K, V)>,
+}
+
+impl<S, K, V> StorageMap<S, K, V>
+where
+    S: Store,
+    K: cbor::Encode + cbor::Decode + Clone,
+    V: cbor::Encode + cbor::Decode,
+{
+    /// Create a new map over the given store.
+    pub fn new(store: S) -> Self {
+        Self {
+            raw: RawMap { store },
+            _types: PhantomData,
+        }
+    }
+
+    fn encode_key(key: &K) -> Vec<u8> {
+        cbor::to_vec(key.clone())
+    }
+
+    /// Fetch the value of the given key.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.raw
+            .get(&Self::encode_key(key))
+            .map(|data| RawMap::<S>::decode(&data))
+    }
+
+    /// Whether the map contains the given key.
+    pub fn contains(&self, key: &K) -> bool {
+        self.raw.get(&Self::encode_key(key)).is_some()
+    }
+
+    /// Insert the given key/value pair, returning the previous value if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.raw
+            .insert(&Self::encode_key(&key), &cbor::to_vec(value))
+            .map(|data| RawMap::<S>::decode(&data))
+    }
+
+    /// Remove the given key, returning its value if it existed.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.raw
+            .remove(&Self::encode_key(key))
+            .map(|data| RawMap::<S>::decode(&data))
+    }
+
+    /// Number of entries in the map.
+    pub fn len(&self) -> u64 {
+        self.raw.len()
+    }
+
+    /// Whether the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the entries of the map in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> + '_ {
+        RawIter {
+            map: &self.raw,
+            next: self.raw.head(),
+        }
+        .map(|(key, value)| (RawMap::<S>::decode(&key), RawMap::<S>::decode(&value)))
+    }
+}
+
+/// A set backed by contract storage.
+///
+/// The set should be given its own key space, e.g. by using [`Store::scoped`].
+pub struct StorageSet<S: Store, K> {
+    raw: RawMap<S>,
+    _types: PhantomData<K>,
+}
+
+impl<S, K> StorageSet<S, K>
+where
+    S: Store,
+    K: cbor::Encode + cbor::Decode + Clone,
+{
+    /// Create a new set over the given store.
+    pub fn new(store: S) -> Self {
+        Self {
+            raw: RawMap { store },
+            _types: PhantomData,
+        }
+    }
+
+    fn encode_key(key: &K) -> Vec<u8> {
+        cbor::to_vec(key.clone())
+    }
+
+    /// Whether the set contains the given key.
+    pub fn contains(&self, key: &K) -> bool {
+        self.raw.get(&Self::encode_key(key)).is_some()
+    }
+
+    /// Insert the given key, returning whether it was newly inserted.
+    pub fn insert(&mut self, key: K) -> bool {
+        self.raw.insert(&Self::encode_key(&key), &[]).is_none()
+    }
+
+    /// Remove the given key, returning whether it was present.
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.raw.remove(&Self::encode_key(key)).is_some()
+    }
+
+    /// Number of keys in the set.
+    pub fn len(&self) -> u64 {
+        self.raw.len()
+    }
+
+    /// Whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the keys of the set in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = K> + '_ {
+        RawIter {
+            map: &self.raw,
+            next: self.raw.head(),
+        }
+        .map(|(key, _)| RawMap::<S>::decode(&key))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::MockStore;
+
+    #[test]
+    fn test_storage_map() {
+        let mut store = MockStore::new();
+        let mut map: StorageMap<_, String, u64> = StorageMap::new(store.scoped(b"map"));
+        assert!(map.is_empty());
+        assert_eq!(map.get(&"a".to_string()), None);
+
+        // Insertion.
+        assert_eq!(map.insert("c".to_string(), 3), None);
+        assert_eq!(map.insert("a".to_string(), 1), None);
+        assert_eq!(map.insert("b".to_string(), 2), None);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(&"a".to_string()), Some(1));
+        assert!(map.contains(&"b".to_string()));
+        assert!(!map.contains(&"d".to_string()));
+
+        // Overwriting should keep the length and the position.
+        assert_eq!(map.insert("a".to_string(), 10), Some(1));
+        assert_eq!(map.len(), 3);
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            vec![
+                ("c".to_string(), 3),
+                ("a".to_string(), 10),
+                ("b".to_string(), 2),
+            ]
+        );
+
+        // Removal.
+        assert_eq!(map.remove(&"a".to_string()), Some(10));
+        assert_eq!(map.remove(&"a".to_string()), None);
+        assert_eq!(map.len(), 2);
+        assert!(!map.contains(&"a".to_string()));
+
+        // Re-inserted keys are appended at the end.
+        map.insert("a".to_string(), 1);
+        assert_eq!(
+            map.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            vec!["c".to_string(), "b".to_string(), "a".to_string()]
+        );
+
+        for key in ["a", "b", "c"] {
+            map.remove(&key.to_string());
+        }
+        assert!(map.is_empty());
+        assert_eq!(map.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_storage_set() {
+        let mut store = MockStore::new();
+        let mut set: StorageSet<_, u64> = StorageSet::new(store.scoped(b"set"));
+
+        assert!(set.insert(3));
+        assert!(set.insert(1));
+        assert!(!set.insert(3), "duplicate insert should be reported");
+        assert!(set.insert(2));
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&1));
+
+        // Removal from the head, middle and tail.
+        for (removed, expected) in [(3, vec![1, 2]), (1, vec![2]), (2, vec![])] {
+            assert!(set.remove(&removed));
+            assert!(!set.remove(&removed), "repeated remove should be reported");
+            assert_eq!(set.iter().collect::<Vec<_>>(), expected);
+            assert_eq!(set.len(), expected.len() as u64);
+        }
+
+        // Sets should be usable after being emptied.
+        assert!(set.insert(4));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![4]);
+    }
+
+    #[test]
+    fn test_isolation() {
+        let mut store = MockStore::new();
+        StorageSet::new(store.scoped(b"a")).insert(1u64);
+        StorageSet::new(store.scoped(b"b")).insert(2u64);
+
+        let a: StorageSet<_, u64> = StorageSet::new(store.scoped(b"a"));
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(a.len(), 1);
+    }
+}
@@ -1,11 +1,125 @@
 //! Contract execution context.
+use std::collections::BTreeMap;
+
 use crate::{
     env::{Crypto, Env},
     event::Event,
     storage::Store,
-    types::{address::Address, message::Message, token, InstanceId},
+    types::{
+        self,
+        address::Address,
+        env::{ContractsQuery, ContractsResponse, QueryResponse},
+        hash::Hash,
+        message::{CallResult, Message, NotifyReply, Reply},
+        modules::{
+            accounts,
+            contracts::{Call, InstanceMetadata, Instantiate, InstantiateResult, Policy},
+        },
+        token, CodeId, InstanceId,
+    },
 };
 
+/// Method used for instantiating contracts.
+const METHOD_INSTANTIATE: &str = "contracts.Instantiate";
+/// Method used for calling contracts.
+const METHOD_CALL: &str = "contracts.Call";
+/// Method used for transferring tokens.
+const METHOD_TRANSFER: &str = "accounts.Transfer";
+
+/// Error returned when processing the reply to an instantiate message.
+#[derive(Debug, thiserror::Error)]
+pub enum InstantiateError {
+    #[error("instantiation failed (module: {module} code: {code})")]
+    Failed { module: String, code: u32 },
+
+    #[error("malformed instantiate result")]
+    MalformedResult,
+
+    #[error("unexpected reply")]
+    UnexpectedReply,
+}
+
+/// Error returned when processing the reply to a module call message.
+#[derive(Debug, thiserror::Error)]
+pub enum CallModuleError {
+    #[error("module call failed (module: {module} code: {code})")]
+    Failed { module: String, code: u32 },
+
+    #[error("malformed module call result")]
+    MalformedResult,
+
+    #[error("unexpected reply")]
+    UnexpectedReply,
+}
+
+/// Confidential store key under which the guards held by [`Context::non_reentrant`] are recorded.
+pub const REENTRANCY_GUARDS_KEY: &[u8] = b"__reentrancy_guards";
+
+/// Error returned when a non-reentrant entrypoint is re-entered within the same call chain.
+#[derive(Debug, thiserror::Error)]
+#[error("reentrant call to non-reentrant entrypoint {entrypoint}")]
+pub struct ReentrancyError {
+    /// Name of the entrypoint that was re-entered.
+    pub entrypoint: String,
+}
+
+/// Reentrancy guards held by an instance within a transaction.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+struct ReentrancyGuards {
+    /// Hash of the transaction in which the guards are held.
+    tx_hash: Hash,
+    /// Held entrypoints together with the number of pending replies for each message identifier.
+    held: BTreeMap<String, BTreeMap<u64, u32>>,
+}
+
+impl ReentrancyGuards {
+    fn load<C: Context>(ctx: &mut C) -> Self {
+        let tx_hash = ctx.tx_hash();
+        ctx.confidential_store()
+            .get(REENTRANCY_GUARDS_KEY)
+            .and_then(|raw| cbor::from_slice::<Self>(&raw).ok())
+            // Guards held by earlier transactions have been released when they completed.
+            .filter(|guards| guards.tx_hash == tx_hash)
+            .unwrap_or(Self {
+                tx_hash,
+                held: BTreeMap::new(),
+            })
+    }
+
+    fn store<C: Context>(self, ctx: &mut C) {
+        if self.held.is_empty() {
+            ctx.confidential_store().remove(REENTRANCY_GUARDS_KEY);
+        } else {
+            ctx.confidential_store()
+                .insert(REENTRANCY_GUARDS_KEY, &cbor::to_vec(self));
+        }
+    }
+}
+
+/// Releases the reentrancy guards that were waiting for the given reply.
+///
+/// Each delivered reply accounts for a single pending reply with its identifier, regardless of
+/// whether the call succeeded or failed. This is called by the contract dispatch glue before the
+/// reply is passed to `Contract::handle_reply`, so contracts do not need to call it themselves.
+pub fn release_reentrancy_guards<C: Context>(ctx: &mut C, reply: &Reply) {
+    let id = match reply {
+        Reply::Call { id, .. } => *id,
+        _ => return,
+    };
+
+    let mut guards = ReentrancyGuards::load(ctx);
+    for pending in guards.held.values_mut() {
+        if let Some(count) = pending.get_mut(&id) {
+            *count -= 1;
+            if *count == 0 {
+                pending.remove(&id);
+            }
+        }
+    }
+    guards.held.retain(|_, pending| !pending.is_empty());
+    guards.store(ctx);
+}
+
 /// Execution context.
 pub trait Context {
     /// The public store.
@@ -22,17 +136,120 @@ pub trait Context {
     fn instance_address(&self) -> &Address;
 
     /// Caller address.
+    ///
+    /// This is the immediate caller of the contract. In case the contract is being called by
+    /// another contract, this is the address of the calling contract instance.
     fn caller_address(&self) -> &Address;
 
+    /// Address of the signer of the enclosing transaction.
+    ///
+    /// Unlike [`caller_address`](Context::caller_address), this stays the same across the whole
+    /// call chain and always refers to the account that signed the transaction. In case the
+    /// transaction has multiple signers, this is the first signer. For queries it is the default
+    /// address.
+    fn tx_signer(&self) -> &Address;
+
+    /// Hash of the enclosing transaction.
+    ///
+    /// All contract invocations within the same call chain observe the same hash. For queries it
+    /// is the default hash.
+    fn tx_hash(&self) -> Hash;
+
+    /// Metadata of this contract instance, as currently recorded by the runtime.
+    ///
+    /// During an upgrade, the metadata reflects the code that is being upgraded from in
+    /// `pre_upgrade` and the new code in `post_upgrade`.
+    fn instance_metadata(&self) -> InstanceMetadata {
+        let id = self.instance_id();
+        match self.env().query(ContractsQuery::InstanceMetadata { id }) {
+            QueryResponse::Contracts(ContractsResponse::InstanceMetadata { metadata }) => metadata,
+            _ => panic!("instance metadata query failed"),
+        }
+    }
+
     /// Tokens deposited by the caller.
     fn deposited_tokens(&self) -> &[token::BaseUnits];
 
+    /// Takes the tokens deposited by the caller, leaving no deposit behind.
+    ///
+    /// This does not move any tokens, see [`refund_deposit`](Context::refund_deposit) for
+    /// returning them to the caller.
+    fn take_deposited_tokens(&mut self) -> Vec<token::BaseUnits>;
+
+    /// Returns all tokens deposited by the caller back to the caller.
+    ///
+    /// In case a call fails, all of its effects are reverted and the deposit is returned
+    /// automatically. This is meant for calls that reject an operation without failing, e.g.
+    /// when a sub-call made on behalf of the caller failed. The deposit is taken so that any
+    /// further refunds (and [`deposited_tokens`](Context::deposited_tokens)) observe no deposit.
+    fn refund_deposit(&mut self) {
+        let to = *self.caller_address();
+        for amount in self.take_deposited_tokens() {
+            self.emit_message(Message::Call {
+                id: 0,
+                reply: NotifyReply::Never,
+                method: METHOD_TRANSFER.to_string(),
+                body: cbor::to_value(accounts::Transfer { to, amount }),
+                max_gas: None,
+                data: None,
+            });
+        }
+    }
+
     /// Emits a message.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called while handling a query, as queries must not have side effects.
     fn emit_message(&mut self, msg: Message);
 
+    /// Messages emitted so far.
+    ///
+    /// The default implementation does not report any messages, in which case
+    /// [`non_reentrant`](Context::non_reentrant) guards are released as soon as the guarded
+    /// function returns.
+    fn emitted_messages(&self) -> &[Message] {
+        &[]
+    }
+
     /// Emits an event.
     fn emit_event<E: Event>(&mut self, event: E);
 
+    /// Emits multiple events, in order.
+    ///
+    /// Events are collected by the context and passed to the host together with the result of
+    /// the call, so this is equivalent to emitting each of the events separately.
+    fn emit_events<E: Event, I: IntoIterator<Item = E>>(&mut self, events: I) {
+        for event in events {
+            self.emit_event(event);
+        }
+    }
+
+    /// Emits a log with the given indexed topics and data.
+    ///
+    /// The log is emitted as an event of the [`LOG_MODULE_NAME`](types::event::LOG_MODULE_NAME)
+    /// module with the number of topics as its code, similar to EVM's `LOGn` instructions.
+    /// Subscribers can filter logs by the topic at any position.
+    ///
+    /// # Panics
+    ///
+    /// Panics in case more than [`MAX_TOPICS`](types::event::MAX_TOPICS) topics are given.
+    fn emit_log(&mut self, topics: &[&[u8]], data: &[u8]) {
+        assert!(
+            topics.len() <= types::event::MAX_TOPICS,
+            "too many log topics (count: {} max: {})",
+            topics.len(),
+            types::event::MAX_TOPICS,
+        );
+
+        self.emit_event(types::event::Event {
+            module: types::event::LOG_MODULE_NAME.to_string(),
+            code: topics.len() as u32,
+            data: data.to_vec(),
+            topics: topics.iter().map(|topic| topic.to_vec()).collect(),
+        });
+    }
+
     /// Public contract store.
     fn public_store(&mut self) -> &mut Self::PublicStore;
 
@@ -41,4 +258,862 @@ pub trait Context {
 
     /// Environment.
     fn env(&self) -> &Self::Env;
+
+    /// Runs the given function as the named non-reentrant entrypoint.
+    ///
+    /// Entering the same entrypoint again while it is held fails with a [`ReentrancyError`]
+    /// without running `f`. The guard is held while `f` runs and released once `f` returns.
+    /// Messages are only processed after the contract execution completes, so contracts called
+    /// by `f` may call back into this instance before its `handle_reply` is invoked. In case `f`
+    /// emits messages that always request a reply ([`NotifyReply::Always`]), the guard is
+    /// therefore only released once all of those replies have been delivered. Replies requested
+    /// only on error or only on success may never be delivered, so such messages do not extend
+    /// the guard.
+    ///
+    /// The held guards are recorded in the confidential store under [`REENTRANCY_GUARDS_KEY`]
+    /// together with the [`tx_hash`](Context::tx_hash) identifying the call chain, so guards that
+    /// are still held when the transaction completes do not affect later transactions. If `f`
+    /// fails, the guard is released before the error is returned. It should only be used in
+    /// calls, as queries cannot modify state, and requires the host to support the confidential
+    /// store.
+    fn non_reentrant<R, E, F>(&mut self, entrypoint: &str, f: F) -> Result<R, E>
+    where
+        Self: Sized,
+        F: FnOnce(&mut Self) -> Result<R, E>,
+        E: From<ReentrancyError>,
+    {
+        let mut guards = ReentrancyGuards::load(self);
+        if guards.held.contains_key(entrypoint) {
+            return Err(ReentrancyError {
+                entrypoint: entrypoint.to_string(),
+            }
+            .into());
+        }
+        guards.held.insert(entrypoint.to_string(), BTreeMap::new());
+        guards.store(self);
+
+        let emitted = self.emitted_messages().len();
+        let result = match f(self) {
+            Ok(result) => result,
+            Err(err) => {
+                let mut guards = ReentrancyGuards::load(self);
+                guards.held.remove(entrypoint);
+                guards.store(self);
+                return Err(err);
+            }
+        };
+
+        // Keep holding the guard until the replies to messages emitted by `f` are delivered. The
+        // same identifier may be used by multiple messages, each of which gets its own reply.
+        let mut pending: BTreeMap<u64, u32> = BTreeMap::new();
+        for msg in &self.emitted_messages()[emitted..] {
+            if let Message::Call {
+                id,
+                reply: NotifyReply::Always,
+                ..
+            } = msg
+            {
+                *pending.entry(*id).or_default() += 1;
+            }
+        }
+        let mut guards = ReentrancyGuards::load(self);
+        if pending.is_empty() {
+            guards.held.remove(entrypoint);
+        } else {
+            guards.held.insert(entrypoint.to_string(), pending);
+        }
+        guards.store(self);
+
+        Ok(result)
+    }
+
+    /// Instantiates a new contract instance from the given code.
+    ///
+    /// Messages are only processed after the contract execution completes, so the identifier of
+    /// the new instance is delivered to `Contract::handle_reply` in a reply carrying the passed
+    /// `id`. Use [`instantiate_result`] to extract it from the reply. The new instance can only be
+    /// upgraded by the instantiating contract.
+    fn instantiate<Init: cbor::Encode>(
+        &mut self,
+        id: u64,
+        code_id: CodeId,
+        init: Init,
+        tokens: &[token::BaseUnits],
+    ) {
+        let body = Instantiate {
+            code_id,
+            upgrades_policy: Policy::Address(*self.instance_address()),
+            data: cbor::to_vec(init),
+            tokens: tokens.to_vec(),
+        };
+
+        self.emit_message(Message::Call {
+            id,
+            reply: NotifyReply::Always,
+            method: METHOD_INSTANTIATE.to_string(),
+            body: cbor::to_value(body),
+            max_gas: None,
+            data: None,
+        });
+    }
+
+    /// Calls another contract instance.
+    ///
+    /// In case `max_gas` is set, the callee can use at most the given amount of gas and running
+    /// out of it only fails the sub-call, not the calling contract. The result of the call is
+    /// delivered to `Contract::handle_reply` in a reply carrying the passed `id`.
+    fn call_contract<Req: cbor::Encode>(
+        &mut self,
+        id: u64,
+        instance_id: InstanceId,
+        request: Req,
+        tokens: &[token::BaseUnits],
+        max_gas: Option<u64>,
+    ) {
+        let body = Call {
+            id: instance_id,
+            data: cbor::to_vec(request),
+            tokens: tokens.to_vec(),
+        };
+
+        self.emit_message(Message::Call {
+            id,
+            reply: NotifyReply::Always,
+            method: METHOD_CALL.to_string(),
+            body: cbor::to_value(body),
+            max_gas,
+            data: None,
+        });
+    }
+
+    /// Calls a runtime module method (e.g. `accounts.Transfer`) with the given arguments.
+    ///
+    /// The call is performed with the contract instance as the caller. Unlike contract calls,
+    /// module calls cannot carry a deposit, so any tokens must be specified as part of the method
+    /// arguments. The result of the call is delivered to `Contract::handle_reply` in a reply
+    /// carrying the passed `id`. Use [`call_module_result`] to decode it.
+    fn call_module<Args: cbor::Encode>(
+        &mut self,
+        id: u64,
+        method: &str,
+        args: Args,
+        max_gas: Option<u64>,
+    ) {
+        self.emit_message(Message::Call {
+            id,
+            reply: NotifyReply::Always,
+            method: method.to_string(),
+            body: cbor::to_value(args),
+            max_gas,
+            data: None,
+        });
+    }
+}
+
+/// Extracts the identifier of the newly created instance from the reply to a message emitted
+/// via [`Context::instantiate`].
+pub fn instantiate_result(reply: &Reply) -> Result<InstanceId, InstantiateError> {
+    match reply {
+        Reply::Call {
+            result: CallResult::Ok(value),
+            ..
+        } => {
+            let result: InstantiateResult =
+                cbor::from_value(value.clone()).map_err(|_| InstantiateError::MalformedResult)?;
+            Ok(result.id)
+        }
+        Reply::Call {
+            result: CallResult::Failed { module, code },
+            ..
+        } => Err(InstantiateError::Failed {
+            module: module.clone(),
+            code: *code,
+        }),
+        _ => Err(InstantiateError::UnexpectedReply),
+    }
+}
+
+/// Decodes the result from the reply to a message emitted via [`Context::call_module`].
+pub fn call_module_result<T: cbor::Decode>(reply: &Reply) -> Result<T, CallModuleError> {
+    match reply {
+        Reply::Call {
+            result: CallResult::Ok(value),
+            ..
+        } => cbor::from_value(value.clone()).map_err(|_| CallModuleError::MalformedResult),
+        Reply::Call {
+            result: CallResult::Failed { module, code },
+            ..
+        } => Err(CallModuleError::Failed {
+            module: module.clone(),
+            code: *code,
+        }),
+        _ => Err(CallModuleError::UnexpectedReply),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        contract::Contract,
+        storage::Store,
+        testing::MockContext,
+        types::{modules::accounts, testing::addresses, ExecutionContext},
+    };
+
+    const CHILD_INSTANTIATE_ID: u64 = 7;
+    const CHILD_KEY: &[u8] = b"child";
+
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    enum Request {
+        #[cbor(rename = "create")]
+        Create { code_id: CodeId, name: String },
+    }
+
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    enum Response {
+        #[cbor(rename = "created")]
+        Created { id: InstanceId },
+    }
+
+    /// A factory contract which instantiates child contracts.
+    struct Factory;
+
+    impl Contract for Factory {
+        type Request = Request;
+        type Response = Response;
+        type Error = std::convert::Infallible;
+
+        fn call<C: Context>(ctx: &mut C, request: Request) -> Result<Response, Self::Error> {
+            match request {
+                Request::Create { code_id, name } => {
+                    let tokens = ctx.deposited_tokens().to_vec();
+                    ctx.instantiate(CHILD_INSTANTIATE_ID, code_id, name, &tokens);
+                    Ok(Response::Created {
+                        id: Default::default(),
+                    })
+                }
+            }
+        }
+
+        fn query<C: Context>(_ctx: &mut C, _request: Request) -> Result<Response, Self::Error> {
+            unimplemented!()
+        }
+
+        fn handle_reply<C: Context>(
+            ctx: &mut C,
+            reply: Reply,
+        ) -> Result<Option<Response>, Self::Error> {
+            let id = instantiate_result(&reply).expect("instantiation should succeed");
+            ctx.public_store().insert(CHILD_KEY, &id.to_storage_key());
+
+            Ok(Some(Response::Created { id }))
+        }
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let mut ctx: MockContext = ExecutionContext {
+            instance_address: addresses::alice::address(),
+            deposited_tokens: vec![token::BaseUnits::new(100, token::Denomination::NATIVE)],
+            ..Default::default()
+        }
+        .into();
+
+        Factory::call(
+            &mut ctx,
+            Request::Create {
+                code_id: 3.into(),
+                name: "child".to_string(),
+            },
+        )
+        .expect("call should work");
+
+        assert_eq!(ctx.messages.len(), 1, "one message should be emitted");
+        let (id, body) = match ctx.messages.pop().unwrap() {
+            Message::Call {
+                id,
+                reply,
+                method,
+                body,
+                ..
+            } => {
+                assert_eq!(reply, NotifyReply::Always);
+                assert_eq!(method, "contracts.Instantiate");
+                (id, body)
+            }
+            _ => panic!("unexpected message"),
+        };
+        assert_eq!(id, CHILD_INSTANTIATE_ID);
+
+        let body: Instantiate = cbor::from_value(body).expect("body should be well-formed");
+        assert_eq!(body.code_id, 3.into());
+        assert_eq!(
+            body.upgrades_policy,
+            Policy::Address(addresses::alice::address())
+        );
+        assert_eq!(body.tokens, ctx.ec.deposited_tokens);
+        let init: String = cbor::from_slice(&body.data).expect("init should be well-formed");
+        assert_eq!(init, "child");
+
+        // Simulate the runtime replying with the new instance identifier.
+        let rsp = Factory::handle_reply(
+            &mut ctx,
+            Reply::Call {
+                id,
+                result: CallResult::Ok(cbor::to_value(InstantiateResult { id: 42.into() })),
+                data: None,
+            },
+        )
+        .expect("reply handling should work");
+        assert_eq!(rsp, Some(Response::Created { id: 42.into() }));
+        assert_eq!(
+            ctx.public_store.get(CHILD_KEY),
+            Some(42u64.to_be_bytes().to_vec())
+        );
+
+        // Failed instantiations should be reported.
+        let err = instantiate_result(&Reply::Call {
+            id,
+            result: CallResult::Failed {
+                module: "contracts".to_string(),
+                code: 12,
+            },
+            data: None,
+        })
+        .expect_err("failed instantiation should be reported");
+        assert!(matches!(err, InstantiateError::Failed { code: 12, .. }));
+    }
+
+    const FORWARD_ID: u64 = 9;
+    const SUBCALL_GAS: u64 = 10_000;
+
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    enum ProxyRequest {
+        #[cbor(rename = "forward")]
+        Forward { to: InstanceId },
+    }
+
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    enum ProxyResponse {
+        #[cbor(rename = "forwarded")]
+        Forwarded { success: bool },
+    }
+
+    /// A proxy contract which forwards calls to other contracts with a limited amount of gas.
+    struct Proxy;
+
+    impl Contract for Proxy {
+        type Request = ProxyRequest;
+        type Response = ProxyResponse;
+        type Error = std::convert::Infallible;
+
+        fn call<C: Context>(
+            ctx: &mut C,
+            request: ProxyRequest,
+        ) -> Result<ProxyResponse, Self::Error> {
+            match request {
+                ProxyRequest::Forward { to } => {
+                    ctx.call_contract(FORWARD_ID, to, "ping", &[], Some(SUBCALL_GAS));
+                    Ok(ProxyResponse::Forwarded { success: false })
+                }
+            }
+        }
+
+        fn query<C: Context>(
+            _ctx: &mut C,
+            _request: ProxyRequest,
+        ) -> Result<ProxyResponse, Self::Error> {
+            unimplemented!()
+        }
+
+        fn handle_reply<C: Context>(
+            _ctx: &mut C,
+            reply: Reply,
+        ) -> Result<Option<ProxyResponse>, Self::Error> {
+            match reply {
+                // Failed sub-calls are reported instead of failing the proxy.
+                Reply::Call { id, result, .. } if id == FORWARD_ID => {
+                    Ok(Some(ProxyResponse::Forwarded {
+                        success: result.is_success(),
+                    }))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+
+    #[test]
+    fn test_call_contract_gas_limit() {
+        let mut ctx: MockContext = ExecutionContext::default().into();
+
+        Proxy::call(&mut ctx, ProxyRequest::Forward { to: 5.into() }).expect("call should work");
+
+        assert_eq!(ctx.messages.len(), 1, "one message should be emitted");
+        let (id, body) = match ctx.messages.pop().unwrap() {
+            Message::Call {
+                id,
+                reply,
+                method,
+                body,
+                max_gas,
+                ..
+            } => {
+                assert_eq!(reply, NotifyReply::Always);
+                assert_eq!(method, "contracts.Call");
+                assert_eq!(max_gas, Some(SUBCALL_GAS));
+                (id, body)
+            }
+            _ => panic!("unexpected message"),
+        };
+        assert_eq!(id, FORWARD_ID);
+
+        let body: Call = cbor::from_value(body).expect("body should be well-formed");
+        assert_eq!(body.id, 5.into());
+        let request: String = cbor::from_slice(&body.data).expect("data should be well-formed");
+        assert_eq!(request, "ping");
+
+        // Simulate the callee running out of its gas allowance.
+        let rsp = Proxy::handle_reply(
+            &mut ctx,
+            Reply::Call {
+                id,
+                result: CallResult::Failed {
+                    module: "core".to_string(),
+                    code: 12,
+                },
+                data: None,
+            },
+        )
+        .expect("caller should survive the failed sub-call");
+        assert_eq!(rsp, Some(ProxyResponse::Forwarded { success: false }));
+    }
+
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    enum WhoamiRequest {
+        #[cbor(rename = "whoami")]
+        Whoami,
+    }
+
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    enum WhoamiResponse {
+        #[cbor(rename = "identity")]
+        Identity {
+            caller: Address,
+            signer: Address,
+            tx_hash: Hash,
+        },
+    }
+
+    /// A contract which reports who is calling it.
+    struct Whoami;
+
+    impl Contract for Whoami {
+        type Request = WhoamiRequest;
+        type Response = WhoamiResponse;
+        type Error = std::convert::Infallible;
+
+        fn call<C: Context>(
+            ctx: &mut C,
+            _request: WhoamiRequest,
+        ) -> Result<WhoamiResponse, Self::Error> {
+            Ok(WhoamiResponse::Identity {
+                caller: *ctx.caller_address(),
+                signer: *ctx.tx_signer(),
+                tx_hash: ctx.tx_hash(),
+            })
+        }
+
+        fn query<C: Context>(
+            _ctx: &mut C,
+            _request: WhoamiRequest,
+        ) -> Result<WhoamiResponse, Self::Error> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn test_tx_metadata() {
+        let tx_hash = Hash::from([42u8; Hash::SIZE]);
+
+        // Called directly by the transaction signer.
+        let mut ctx: MockContext = ExecutionContext {
+            caller_address: addresses::alice::address(),
+            tx_signer: addresses::alice::address(),
+            tx_hash,
+            ..Default::default()
+        }
+        .into();
+        let rsp = Whoami::call(&mut ctx, WhoamiRequest::Whoami).expect("call should work");
+        assert_eq!(
+            rsp,
+            WhoamiResponse::Identity {
+                caller: addresses::alice::address(),
+                signer: addresses::alice::address(),
+                tx_hash,
+            }
+        );
+
+        // Called by another contract as part of the same transaction.
+        let mut ctx: MockContext = ExecutionContext {
+            caller_address: addresses::bob::address(),
+            tx_signer: addresses::alice::address(),
+            tx_hash,
+            ..Default::default()
+        }
+        .into();
+        let rsp = Whoami::call(&mut ctx, WhoamiRequest::Whoami).expect("call should work");
+        assert_eq!(
+            rsp,
+            WhoamiResponse::Identity {
+                caller: addresses::bob::address(),
+                signer: addresses::alice::address(),
+                tx_hash,
+            },
+            "signer should differ from the immediate caller"
+        );
+    }
+
+    #[test]
+    fn test_instance_metadata() {
+        let metadata = InstanceMetadata {
+            code_id: 5.into(),
+            code_hash: Hash::from([7u8; Hash::SIZE]),
+            upgrades_policy: Policy::Address(addresses::alice::address()),
+        };
+
+        let mut ctx: MockContext = ExecutionContext {
+            instance_id: 3.into(),
+            ..Default::default()
+        }
+        .into();
+        ctx.env.instances.insert(3.into(), metadata.clone());
+        assert_eq!(ctx.instance_metadata(), metadata);
+
+        // Metadata of other instances is available through the environment.
+        let rsp = ctx
+            .env()
+            .query(ContractsQuery::InstanceMetadata { id: 4.into() });
+        assert!(matches!(rsp, QueryResponse::Error { code: 10, .. }));
+    }
+
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    enum VaultRequest {
+        #[cbor(rename = "withdraw")]
+        Withdraw { via: InstanceId },
+    }
+
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    enum VaultResponse {
+        #[cbor(rename = "withdrawn")]
+        Withdrawn,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    enum VaultError {
+        #[error("bad request")]
+        BadRequest,
+
+        #[error(transparent)]
+        Reentrancy(#[from] ReentrancyError),
+    }
+
+    impl crate::error::Error for VaultError {
+        fn module_name(&self) -> &str {
+            "vault"
+        }
+
+        fn code(&self) -> u32 {
+            match self {
+                Self::BadRequest => 1,
+                Self::Reentrancy(_) => 2,
+            }
+        }
+    }
+
+    /// A contract which hands out tokens through another contract that could call back into it.
+    struct Vault;
+
+    impl Contract for Vault {
+        type Request = VaultRequest;
+        type Response = VaultResponse;
+        type Error = VaultError;
+
+        fn call<C: Context>(
+            ctx: &mut C,
+            request: VaultRequest,
+        ) -> Result<VaultResponse, VaultError> {
+            match request {
+                VaultRequest::Withdraw { via } => ctx.non_reentrant("withdraw", |ctx| {
+                    ctx.call_contract(0, via, b"transfer".to_vec(), &[], None);
+                    Ok(VaultResponse::Withdrawn)
+                }),
+            }
+        }
+
+        fn query<C: Context>(
+            _ctx: &mut C,
+            _request: VaultRequest,
+        ) -> Result<VaultResponse, VaultError> {
+            Err(VaultError::BadRequest)
+        }
+    }
+
+    #[test]
+    fn test_non_reentrant() {
+        let mut ctx: MockContext = ExecutionContext {
+            caller_address: addresses::alice::address(),
+            tx_signer: addresses::alice::address(),
+            tx_hash: Hash::from([1u8; Hash::SIZE]),
+            ..Default::default()
+        }
+        .into();
+
+        let rsp = Vault::call(&mut ctx, VaultRequest::Withdraw { via: 7.into() })
+            .expect("first call should work");
+        assert_eq!(rsp, VaultResponse::Withdrawn);
+        assert_eq!(ctx.messages.len(), 1, "external call should be emitted");
+
+        // The called contract calls back into the vault before the reply is delivered.
+        ctx.ec.caller_address = addresses::bob::address();
+        let err = Vault::call(&mut ctx, VaultRequest::Withdraw { via: 7.into() })
+            .expect_err("reentrant call should fail");
+        assert!(matches!(
+            err,
+            VaultError::Reentrancy(ReentrancyError { ref entrypoint }) if entrypoint == "withdraw"
+        ));
+        assert_eq!(ctx.messages.len(), 1, "reentrant call should not run");
+
+        // Once the reply is delivered, the guard should be released.
+        release_reentrancy_guards(
+            &mut ctx,
+            &Reply::Call {
+                id: 0,
+                result: CallResult::Ok(cbor::Value::Simple(cbor::SimpleValue::NullValue)),
+                data: None,
+            },
+        );
+        assert!(
+            ctx.confidential_store.get(REENTRANCY_GUARDS_KEY).is_none(),
+            "released guards should not be kept in storage"
+        );
+        ctx.ec.caller_address = addresses::alice::address();
+        Vault::call(&mut ctx, VaultRequest::Withdraw { via: 7.into() })
+            .expect("call after the reply should work");
+        assert_eq!(ctx.messages.len(), 2);
+
+        // Guards still held at the end of a transaction should not affect later transactions.
+        ctx.ec.tx_hash = Hash::from([2u8; Hash::SIZE]);
+        Vault::call(&mut ctx, VaultRequest::Withdraw { via: 7.into() })
+            .expect("call in a new transaction should work");
+        assert_eq!(ctx.messages.len(), 3);
+    }
+
+    #[test]
+    fn test_non_reentrant_pending_replies() {
+        let mut ctx: MockContext = ExecutionContext {
+            tx_hash: Hash::from([1u8; Hash::SIZE]),
+            ..Default::default()
+        }
+        .into();
+        let call = |id, reply| Message::Call {
+            id,
+            reply,
+            method: METHOD_CALL.to_string(),
+            body: cbor::Value::Simple(cbor::SimpleValue::NullValue),
+            max_gas: None,
+            data: None,
+        };
+        let reply = |id| Reply::Call {
+            id,
+            result: CallResult::Ok(cbor::Value::Simple(cbor::SimpleValue::NullValue)),
+            data: None,
+        };
+        let enter =
+            |ctx: &mut MockContext| ctx.non_reentrant("withdraw", |_| Ok::<_, VaultError>(()));
+
+        // Two messages share an identifier and a third one is only replied to on success.
+        ctx.non_reentrant("withdraw", |ctx| {
+            ctx.emit_message(call(1, NotifyReply::Always));
+            ctx.emit_message(call(1, NotifyReply::Always));
+            ctx.emit_message(call(2, NotifyReply::OnSuccess));
+            Ok::<_, VaultError>(())
+        })
+        .expect("first call should work");
+        assert!(
+            matches!(enter(&mut ctx), Err(VaultError::Reentrancy(_))),
+            "guard should be held while replies are pending"
+        );
+
+        // The guard should be held until both replies with the shared identifier are delivered.
+        release_reentrancy_guards(&mut ctx, &reply(1));
+        assert!(
+            matches!(enter(&mut ctx), Err(VaultError::Reentrancy(_))),
+            "guard should be held while a reply is pending"
+        );
+
+        // The reply to the message notifying only on success may never come.
+        release_reentrancy_guards(&mut ctx, &reply(1));
+        assert!(
+            ctx.confidential_store.get(REENTRANCY_GUARDS_KEY).is_none(),
+            "released guards should not be kept in storage"
+        );
+        enter(&mut ctx).expect("call after the replies should work");
+
+        // A failing call should release the guard in case the error is handled by the caller.
+        ctx.non_reentrant("withdraw", |_| Err::<(), _>(VaultError::BadRequest))
+            .expect_err("failing call should fail");
+        assert!(
+            ctx.confidential_store.get(REENTRANCY_GUARDS_KEY).is_none(),
+            "guards of failed calls should not be kept in storage"
+        );
+        enter(&mut ctx).expect("call after a failed call should work");
+    }
+
+    const PAY_ID: u64 = 11;
+    const PAID_KEY: &[u8] = b"paid";
+
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    enum PayerRequest {
+        #[cbor(rename = "pay")]
+        Pay { to: Address, amount: u128 },
+    }
+
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    enum PayerResponse {
+        #[cbor(rename = "paid")]
+        Paid { success: bool },
+    }
+
+    /// A contract which pays out native tokens from its own account.
+    struct Payer;
+
+    impl Contract for Payer {
+        type Request = PayerRequest;
+        type Response = PayerResponse;
+        type Error = std::convert::Infallible;
+
+        fn call<C: Context>(
+            ctx: &mut C,
+            request: PayerRequest,
+        ) -> Result<PayerResponse, Self::Error> {
+            match request {
+                PayerRequest::Pay { to, amount } => {
+                    ctx.call_module(
+                        PAY_ID,
+                        "accounts.Transfer",
+                        accounts::Transfer {
+                            to,
+                            amount: token::BaseUnits::new(amount, token::Denomination::NATIVE),
+                        },
+                        None,
+                    );
+                    Ok(PayerResponse::Paid { success: false })
+                }
+            }
+        }
+
+        fn query<C: Context>(
+            _ctx: &mut C,
+            _request: PayerRequest,
+        ) -> Result<PayerResponse, Self::Error> {
+            unimplemented!()
+        }
+
+        fn handle_reply<C: Context>(
+            ctx: &mut C,
+            reply: Reply,
+        ) -> Result<Option<PayerResponse>, Self::Error> {
+            match call_module_result::<()>(&reply) {
+                Ok(()) => {
+                    ctx.public_store().insert(PAID_KEY, b"yes");
+                    Ok(Some(PayerResponse::Paid { success: true }))
+                }
+                Err(CallModuleError::Failed { .. }) => {
+                    Ok(Some(PayerResponse::Paid { success: false }))
+                }
+                Err(err) => panic!("unexpected error: {}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn test_call_module() {
+        let mut ctx: MockContext = ExecutionContext {
+            instance_address: addresses::alice::address(),
+            ..Default::default()
+        }
+        .into();
+
+        Payer::call(
+            &mut ctx,
+            PayerRequest::Pay {
+                to: addresses::bob::address(),
+                amount: 1_000,
+            },
+        )
+        .expect("call should work");
+
+        assert_eq!(ctx.messages.len(), 1, "one message should be emitted");
+        let (id, body) = match ctx.messages.pop().unwrap() {
+            Message::Call {
+                id,
+                reply,
+                method,
+                body,
+                max_gas,
+                ..
+            } => {
+                assert_eq!(reply, NotifyReply::Always);
+                assert_eq!(method, "accounts.Transfer");
+                assert_eq!(max_gas, None);
+                (id, body)
+            }
+            _ => panic!("unexpected message"),
+        };
+        assert_eq!(id, PAY_ID);
+
+        let body: accounts::Transfer = cbor::from_value(body).expect("body should be well-formed");
+        assert_eq!(body.to, addresses::bob::address());
+        assert_eq!(body.amount.amount(), 1_000);
+        assert_eq!(body.amount.denomination(), &token::Denomination::NATIVE);
+
+        // Simulate the runtime reporting insufficient balance.
+        let rsp = Payer::handle_reply(
+            &mut ctx,
+            Reply::Call {
+                id,
+                result: CallResult::Failed {
+                    module: "accounts".to_string(),
+                    code: 5,
+                },
+                data: None,
+            },
+        )
+        .expect("failed transfer should be handled");
+        assert_eq!(rsp, Some(PayerResponse::Paid { success: false }));
+        assert_eq!(ctx.public_store.get(PAID_KEY), None);
+
+        // Simulate a successful transfer.
+        let rsp = Payer::handle_reply(
+            &mut ctx,
+            Reply::Call {
+                id,
+                result: CallResult::Ok(cbor::Value::Simple(cbor::SimpleValue::NullValue)),
+                data: None,
+            },
+        )
+        .expect("successful transfer should be handled");
+        assert_eq!(rsp, Some(PayerResponse::Paid { success: true }));
+        assert_eq!(ctx.public_store.get(PAID_KEY), Some(b"yes".to_vec()));
+
+        // Malformed results should be reported.
+        let err = call_module_result::<u64>(&Reply::Call {
+            id,
+            result: CallResult::Ok(cbor::to_value("not a number")),
+            data: None,
+        })
+        .expect_err("malformed result should be reported");
+        assert!(matches!(err, CallModuleError::MalformedResult));
+    }
 }
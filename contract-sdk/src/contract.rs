@@ -1,5 +1,5 @@
 //! The contract trait.
-use crate::{context::Context, error, types};
+use crate::{context::Context, error, storage, types};
 
 /// Trait that needs to be implemented by contract implementations.
 pub trait Contract {
@@ -51,3 +51,45 @@ pub trait Contract {
         Ok(())
     }
 }
+
+/// Encode a query response using canonical CBOR.
+///
+/// Query responses are returned to clients as-is, so they are always canonically encoded to give
+/// them a stable wire format. In debug builds the response is additionally checked to round-trip
+/// (see [`storage::assert_canonical`]).
+pub fn encode_query_response<R: cbor::Encode>(response: R) -> Vec<u8> {
+    let data = cbor::to_vec(response);
+    storage::assert_canonical(&data);
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[derive(cbor::Encode)]
+    struct Response {
+        balances: BTreeMap<String, u64>,
+    }
+
+    #[test]
+    fn test_encode_query_response() {
+        let mut balances = BTreeMap::new();
+        balances.insert("aa".to_string(), 1);
+        balances.insert("b".to_string(), 2);
+
+        // Map keys should be ordered by length first, unlike the natural ordering.
+        assert_eq!(
+            encode_query_response(Response { balances }),
+            vec![
+                0xa1, // map(1)
+                0x68, b'b', b'a', b'l', b'a', b'n', b'c', b'e', b's', // "balances"
+                0xa2, // map(2)
+                0x61, b'b', 0x02, // "b": 2
+                0x62, b'a', b'a', 0x01, // "aa": 1
+            ]
+        );
+    }
+}
@@ -2,6 +2,7 @@
 use oasis_contract_sdk_types::address::Address;
 
 use crate::types::{
+    crypto::SignatureKind,
     env::{QueryRequest, QueryResponse},
     InstanceId,
 };
@@ -19,4 +20,21 @@ pub trait Env {
 pub trait Crypto {
     /// ECDSA public key recovery function.
     fn ecdsa_recover(&self, input: &[u8]) -> [u8; 65];
+
+    /// Verify a signature over the given message made by the given public key, returning whether
+    /// the signature is valid. Ed25519 keys are given in their 32-byte form, Secp256k1 keys in
+    /// compressed form with DER-encoded signatures.
+    ///
+    /// The signature must have been made in the given domain separation context, which should
+    /// be unique to the contract and the purpose of the signature (e.g. by including the
+    /// instance address) so that signatures made for other purposes, such as for transactions
+    /// or for other contracts, cannot be replayed.
+    fn verify_signature(
+        &self,
+        scheme: SignatureKind,
+        public_key: &[u8],
+        context: &[u8],
+        message: &[u8],
+        signature: &[u8],
+    ) -> bool;
 }
@@ -34,6 +34,7 @@ pub trait Event: Sized + cbor::Encode {
             module: self.module_name().to_string(),
             code: self.code(),
             data: cbor::to_vec(self),
+            topics: Vec::new(),
         }
     }
 }
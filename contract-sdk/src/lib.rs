@@ -3,11 +3,13 @@
 
 #[cfg(target_arch = "wasm32")]
 pub mod abi;
+pub mod collections;
 pub mod context;
 pub mod contract;
 pub mod env;
 pub mod error;
 pub mod event;
+pub mod math;
 pub mod memory;
 pub mod storage;
 #[cfg(not(target_arch = "wasm32"))]
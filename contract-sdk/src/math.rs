@@ -0,0 +1,232 @@
+//! Safe arithmetic for token amounts.
+//!
+//! All helpers operate on `u128` amounts (matching [`BaseUnits`](crate::types::token::BaseUnits))
+//! and report overflows instead of wrapping around.
+
+/// Error returned by arithmetic operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum MathError {
+    #[error("arithmetic overflow")]
+    Overflow,
+
+    #[error("arithmetic underflow")]
+    Underflow,
+
+    #[error("division by zero")]
+    DivisionByZero,
+}
+
+/// Adds two amounts.
+pub fn checked_add(a: u128, b: u128) -> Result<u128, MathError> {
+    a.checked_add(b).ok_or(MathError::Overflow)
+}
+
+/// Subtracts `b` from `a`.
+pub fn checked_sub(a: u128, b: u128) -> Result<u128, MathError> {
+    a.checked_sub(b).ok_or(MathError::Underflow)
+}
+
+/// Multiplies two amounts.
+pub fn checked_mul(a: u128, b: u128) -> Result<u128, MathError> {
+    a.checked_mul(b).ok_or(MathError::Overflow)
+}
+
+/// Divides `a` by `b`, rounding down.
+pub fn checked_div(a: u128, b: u128) -> Result<u128, MathError> {
+    a.checked_div(b).ok_or(MathError::DivisionByZero)
+}
+
+/// Adds two amounts, saturating at `u128::MAX`.
+pub fn saturating_add(a: u128, b: u128) -> u128 {
+    a.saturating_add(b)
+}
+
+/// Subtracts `b` from `a`, saturating at zero.
+pub fn saturating_sub(a: u128, b: u128) -> u128 {
+    a.saturating_sub(b)
+}
+
+/// Computes `a * b / c` rounding down.
+///
+/// The intermediate product is computed with full 256-bit precision, so this only fails in case
+/// the final result does not fit into an `u128`.
+pub fn checked_mul_div(a: u128, b: u128, c: u128) -> Result<u128, MathError> {
+    mul_div(a, b, c).map(|(quotient, _)| quotient)
+}
+
+/// Computes `a * b / c` rounding up.
+pub fn checked_mul_div_ceil(a: u128, b: u128, c: u128) -> Result<u128, MathError> {
+    let (quotient, remainder) = mul_div(a, b, c)?;
+    if remainder == 0 {
+        return Ok(quotient);
+    }
+    checked_add(quotient, 1)
+}
+
+fn mul_div(a: u128, b: u128, c: u128) -> Result<(u128, u128), MathError> {
+    if c == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+    let (hi, lo) = mul_wide(a, b);
+    div_wide(hi, lo, c).ok_or(MathError::Overflow)
+}
+
+/// Multiplies two values, returning the high and low halves of the 256-bit product.
+fn mul_wide(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+
+    let (a1, a0) = (a >> 64, a & MASK);
+    let (b1, b0) = (b >> 64, b & MASK);
+    let p00 = a0 * b0;
+    let p01 = a0 * b1;
+    let p10 = a1 * b0;
+    let p11 = a1 * b1;
+
+    let mid = (p00 >> 64) + (p01 & MASK) + (p10 & MASK);
+    let lo = (p00 & MASK) | ((mid & MASK) << 64);
+    let hi = p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64);
+    (hi, lo)
+}
+
+/// Divides a 256-bit value by `d`, returning the quotient and remainder or `None` in case the
+/// quotient does not fit into an `u128`.
+fn div_wide(hi: u128, lo: u128, d: u128) -> Option<(u128, u128)> {
+    if hi >= d {
+        return None;
+    }
+
+    // Binary long division, the remainder is always kept below the divisor.
+    let mut remainder = hi;
+    let mut quotient = 0u128;
+    for i in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((lo >> i) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= d {
+            remainder = remainder.wrapping_sub(d);
+            quotient |= 1;
+        }
+    }
+    Some((quotient, remainder))
+}
+
+/// A fixed-point ratio (e.g. a fee or an interest rate) that can be applied to amounts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ratio {
+    numerator: u128,
+    denominator: u128,
+}
+
+impl Ratio {
+    /// Number of basis points in a whole.
+    pub const BASIS_POINTS: u128 = 10_000;
+
+    /// Creates a new ratio of `numerator / denominator`.
+    pub fn new(numerator: u128, denominator: u128) -> Result<Self, MathError> {
+        if denominator == 0 {
+            return Err(MathError::DivisionByZero);
+        }
+        Ok(Self {
+            numerator,
+            denominator,
+        })
+    }
+
+    /// Creates a new ratio from the given number of basis points (1/100th of a percent).
+    pub fn from_basis_points(basis_points: u128) -> Self {
+        Self {
+            numerator: basis_points,
+            denominator: Self::BASIS_POINTS,
+        }
+    }
+
+    /// Applies the ratio to the given amount, rounding down.
+    pub fn apply(&self, amount: u128) -> Result<u128, MathError> {
+        checked_mul_div(amount, self.numerator, self.denominator)
+    }
+
+    /// Applies the ratio to the given amount, rounding up.
+    ///
+    /// This is useful for amounts owed to the contract (e.g. fees) which should never be rounded
+    /// in favor of the caller.
+    pub fn apply_ceil(&self, amount: u128) -> Result<u128, MathError> {
+        checked_mul_div_ceil(amount, self.numerator, self.denominator)
+    }
+
+    /// Applies the ratio to the given amount, rounding down and saturating at `u128::MAX`.
+    pub fn saturating_apply(&self, amount: u128) -> u128 {
+        self.apply(amount).unwrap_or(u128::MAX)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checked_ops() {
+        assert_eq!(checked_add(u128::MAX - 1, 1), Ok(u128::MAX));
+        assert_eq!(checked_add(u128::MAX, 1), Err(MathError::Overflow));
+        assert_eq!(checked_sub(1, 1), Ok(0));
+        assert_eq!(checked_sub(0, 1), Err(MathError::Underflow));
+        assert_eq!(checked_mul(u128::MAX, 1), Ok(u128::MAX));
+        assert_eq!(checked_mul(u128::MAX, 2), Err(MathError::Overflow));
+        assert_eq!(checked_div(7, 2), Ok(3));
+        assert_eq!(checked_div(7, 0), Err(MathError::DivisionByZero));
+
+        assert_eq!(saturating_add(u128::MAX, 1), u128::MAX);
+        assert_eq!(saturating_sub(0, 1), 0);
+    }
+
+    #[test]
+    fn test_mul_div() {
+        // Intermediate products exceeding u128 should not overflow.
+        assert_eq!(
+            checked_mul_div(u128::MAX, u128::MAX, u128::MAX),
+            Ok(u128::MAX)
+        );
+        assert_eq!(checked_mul_div(u128::MAX, 3, 4), Ok(u128::MAX / 4 * 3 + 2));
+        let big = (1u128 << 64) + 5;
+        let other = (1u128 << 64) + 7;
+        assert_eq!(checked_mul_div(big, other, other), Ok(big));
+
+        // Results exceeding u128 should fail.
+        assert_eq!(checked_mul_div(u128::MAX, 2, 1), Err(MathError::Overflow));
+        assert_eq!(checked_mul_div(1 << 127, 4, 2), Err(MathError::Overflow));
+        assert_eq!(checked_mul_div(1, 1, 0), Err(MathError::DivisionByZero));
+
+        // Rounding.
+        assert_eq!(checked_mul_div(10, 1, 3), Ok(3));
+        assert_eq!(checked_mul_div_ceil(10, 1, 3), Ok(4));
+        assert_eq!(checked_mul_div_ceil(9, 1, 3), Ok(3));
+        assert_eq!(
+            checked_mul_div_ceil(u128::MAX, u128::MAX - 1, u128::MAX - 1),
+            Ok(u128::MAX)
+        );
+        assert_eq!(
+            checked_mul_div_ceil(u128::MAX, 3, 2),
+            Err(MathError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_ratio() {
+        assert_eq!(Ratio::new(1, 0), Err(MathError::DivisionByZero));
+
+        // A 2.5% fee.
+        let fee = Ratio::from_basis_points(250);
+        assert_eq!(fee.apply(1_000_000), Ok(25_000));
+        assert_eq!(fee.apply(39), Ok(0));
+        assert_eq!(fee.apply_ceil(39), Ok(1));
+        assert_eq!(fee.apply(u128::MAX), Ok(u128::MAX / 40));
+
+        // Ratios above one can overflow.
+        let double = Ratio::new(2, 1).unwrap();
+        assert_eq!(double.apply(u128::MAX / 2), Ok(u128::MAX - 1));
+        assert_eq!(double.apply(u128::MAX / 2 + 1), Err(MathError::Overflow));
+        assert_eq!(double.saturating_apply(u128::MAX), u128::MAX);
+
+        let zero = Ratio::new(0, 1).unwrap();
+        assert_eq!(zero.apply(u128::MAX), Ok(0));
+    }
+}
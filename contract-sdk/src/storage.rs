@@ -10,4 +10,219 @@ pub trait Store {
 
     /// Remove a given key from contract storage.
     fn remove(&mut self, key: &[u8]);
+
+    /// Returns a view of the store where all keys are prefixed by the given prefix.
+    fn scoped<P: AsRef<[u8]>>(&mut self, prefix: P) -> PrefixStore<&mut Self, P>
+    where
+        Self: Sized,
+    {
+        PrefixStore::new(self, prefix)
+    }
+}
+
+impl<S: Store + ?Sized> Store for &mut S {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        (**self).get(key)
+    }
+
+    fn insert(&mut self, key: &[u8], value: &[u8]) {
+        (**self).insert(key, value)
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        (**self).remove(key)
+    }
+}
+
+/// A key/value store that prefixes all keys with the given length-prefixed prefix.
+///
+/// This can be used to give contract sub-components their own isolated key space. As the prefix
+/// is length-prefixed, scopes never collide even when one prefix is a prefix of another.
+pub struct PrefixStore<S: Store, P: AsRef<[u8]>> {
+    inner: S,
+    prefix: P,
+}
+
+impl<S: Store, P: AsRef<[u8]>> PrefixStore<S, P> {
+    /// Create a new prefix store with the given prefix.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the prefix length is greater than 255 bytes.
+    pub fn new(inner: S, prefix: P) -> Self {
+        assert!(
+            prefix.as_ref().len() <= u8::MAX as usize,
+            "prefix length greater than 255"
+        );
+        Self { inner, prefix }
+    }
+
+    fn key(&self, key: &[u8]) -> Vec<u8> {
+        // The prefix length has been checked on construction.
+        let prefix = self.prefix.as_ref();
+        [&[prefix.len() as u8], prefix, key].concat()
+    }
+}
+
+impl<S: Store, P: AsRef<[u8]>> Store for PrefixStore<S, P> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.inner.get(&self.key(key))
+    }
+
+    fn insert(&mut self, key: &[u8], value: &[u8]) {
+        let key = self.key(key);
+        self.inner.insert(&key, value)
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        let key = self.key(key);
+        self.inner.remove(&key)
+    }
+}
+
+/// Encode the given value using canonical CBOR.
+///
+/// Equal values always produce identical bytes, so the output can safely be hashed or used in
+/// commitments. In debug builds the output is additionally checked with [`assert_canonical`].
+pub fn canonical_encode<T: cbor::Encode + Clone>(value: &T) -> Vec<u8> {
+    let data = cbor::to_vec(value.clone());
+    assert_canonical(&data);
+    data
+}
+
+/// Assert that the given data is canonically encoded CBOR by decoding and re-encoding it.
+///
+/// This is only checked in debug builds and is a no-op otherwise.
+///
+/// # Panics
+///
+/// Panics if the data cannot be decoded or if re-encoding it does not produce identical bytes.
+pub fn assert_canonical(data: &[u8]) {
+    #[cfg(debug_assertions)]
+    {
+        let value: cbor::Value = cbor::from_slice(data).expect("data should be well-formed CBOR");
+        assert_eq!(
+            cbor::to_vec(value),
+            data,
+            "data should be canonically encoded"
+        );
+    }
+    #[cfg(not(debug_assertions))]
+    let _ = data;
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::testing::MockStore;
+
+    #[derive(Clone, Default, cbor::Encode, cbor::Decode)]
+    struct Entry {
+        names: BTreeMap<String, u64>,
+        owners: BTreeMap<Vec<u8>, String>,
+        a: u64,
+    }
+
+    #[test]
+    fn test_prefix_store() {
+        let mut store = MockStore::new();
+
+        store.scoped(b"a").insert(b"key", b"value a");
+        store.scoped(b"b").insert(b"key", b"value b");
+
+        // Scopes should not collide on the same logical key.
+        assert_eq!(store.scoped(b"a").get(b"key"), Some(b"value a".to_vec()));
+        assert_eq!(store.scoped(b"b").get(b"key"), Some(b"value b".to_vec()));
+        assert_eq!(store.get(b"\x01akey"), Some(b"value a".to_vec()));
+        assert_eq!(store.get(b"key"), None);
+
+        // Scopes should not collide when one prefix is a prefix of another.
+        store.scoped(b"ab").insert(b"c", b"value ab");
+        store.scoped(b"a").insert(b"bc", b"value a");
+        assert_eq!(store.scoped(b"ab").get(b"c"), Some(b"value ab".to_vec()));
+        assert_eq!(store.scoped(b"a").get(b"bc"), Some(b"value a".to_vec()));
+
+        // Scopes should nest.
+        let mut outer = store.scoped(b"outer");
+        outer.scoped(b"inner").insert(b"key", b"nested");
+        assert_eq!(
+            store.get(b"\x05outer\x05innerkey"),
+            Some(b"nested".to_vec())
+        );
+
+        store.scoped(b"a").remove(b"key");
+        assert_eq!(store.scoped(b"a").get(b"key"), None);
+        assert_eq!(store.scoped(b"b").get(b"key"), Some(b"value b".to_vec()));
+    }
+
+    /// Return the encoded keys of the given CBOR map.
+    fn map_keys(value: &cbor::Value) -> Vec<Vec<u8>> {
+        match value {
+            cbor::Value::Map(items) => items.iter().map(|(k, _)| cbor::to_vec(k.clone())).collect(),
+            _ => panic!("value should be a map"),
+        }
+    }
+
+    /// Whether the given encoded keys are in canonical order (shorter keys first, then
+    /// lexicographic).
+    fn is_canonical_order(keys: &[Vec<u8>]) -> bool {
+        keys.windows(2)
+            .all(|w| (w[0].len(), &w[0]) < (w[1].len(), &w[1]))
+    }
+
+    #[test]
+    fn test_canonical_encode() {
+        let mut entry = Entry {
+            a: 1,
+            ..Default::default()
+        };
+        // Insertion and natural ordering differ from the canonical ordering.
+        for name in ["zzz", "b", "aa", "c"] {
+            entry.names.insert(name.to_string(), 1);
+        }
+        for owner in [b"alice".to_vec(), b"bob".to_vec(), b"al".to_vec()] {
+            entry.owners.insert(owner, "owner".to_string());
+        }
+
+        let data = canonical_encode(&entry);
+        assert_eq!(
+            data,
+            canonical_encode(&entry.clone()),
+            "encoding should be stable"
+        );
+
+        let value: cbor::Value = cbor::from_slice(&data).unwrap();
+        assert!(
+            is_canonical_order(&map_keys(&value)),
+            "struct fields should be in canonical order"
+        );
+        let fields = match value {
+            cbor::Value::Map(items) => items,
+            _ => unreachable!(),
+        };
+        for (_, field) in fields
+            .iter()
+            .filter(|(_, v)| matches!(v, cbor::Value::Map(_)))
+        {
+            assert!(
+                is_canonical_order(&map_keys(field)),
+                "map keys should be in canonical order"
+            );
+        }
+
+        let decoded: Entry = cbor::from_slice(&data).unwrap();
+        assert_eq!(decoded.names, entry.names);
+        assert_eq!(decoded.owners, entry.owners);
+        assert_eq!(decoded.a, entry.a);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn test_assert_canonical_non_canonical() {
+        // {"aa": 1, "b": 2} where the longer key is incorrectly ordered first.
+        assert_canonical(&[0xa2, 0x62, 0x61, 0x61, 0x01, 0x61, 0x62, 0x02]);
+    }
 }
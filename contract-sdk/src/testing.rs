@@ -1,5 +1,5 @@
 //! Utilities for testing smart contracts.
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, fmt::Debug};
 
 use oasis_contract_sdk_crypto as crypto;
 
@@ -10,9 +10,12 @@ use crate::{
     storage::Store,
     types::{
         address::Address,
-        env::{QueryRequest, QueryResponse},
+        crypto::SignatureKind,
+        env::{ContractsQuery, ContractsResponse, QueryRequest, QueryResponse},
         event::Event as RawEvent,
-        message::Message,
+        hash::Hash,
+        message::{Message, NotifyReply},
+        modules::contracts::InstanceMetadata,
         token, ExecutionContext, InstanceId,
     },
 };
@@ -48,12 +51,17 @@ impl Store for MockStore {
 
 /// Mock environment.
 #[derive(Clone, Default)]
-pub struct MockEnv {}
+pub struct MockEnv {
+    /// Metadata of known contract instances.
+    pub instances: BTreeMap<InstanceId, InstanceMetadata>,
+}
 
 impl MockEnv {
     /// Create a new mock environment.
     pub fn new() -> Self {
-        Self {}
+        Self {
+            instances: BTreeMap::new(),
+        }
     }
 }
 
@@ -65,6 +73,19 @@ impl Env for MockEnv {
                 epoch: 2,
                 timestamp: 100_000,
             },
+            QueryRequest::Contracts(ContractsQuery::InstanceMetadata { id }) => {
+                match self.instances.get(&id) {
+                    Some(metadata) => ContractsResponse::InstanceMetadata {
+                        metadata: metadata.clone(),
+                    }
+                    .into(),
+                    None => QueryResponse::Error {
+                        module: "contracts".to_string(),
+                        code: 10,
+                        message: format!("instance {} not found", id.as_u64()),
+                    },
+                }
+            }
             _ => unimplemented!(),
         }
     }
@@ -83,6 +104,24 @@ impl Crypto for MockEnv {
     fn ecdsa_recover(&self, input: &[u8]) -> [u8; 65] {
         crypto::ecdsa::recover(input).unwrap()
     }
+
+    fn verify_signature(
+        &self,
+        scheme: SignatureKind,
+        public_key: &[u8],
+        context: &[u8],
+        message: &[u8],
+        signature: &[u8],
+    ) -> bool {
+        match scheme {
+            SignatureKind::Ed25519 => {
+                crypto::signature::verify_ed25519(public_key, context, message, signature)
+            }
+            SignatureKind::Secp256k1 => {
+                crypto::signature::verify_secp256k1(public_key, context, message, signature)
+            }
+        }
+    }
 }
 
 /// A mock contract context suitable for testing.
@@ -101,6 +140,12 @@ pub struct MockContext {
     pub messages: Vec<Message>,
     /// Emitted events.
     pub events: Vec<RawEvent>,
+    /// Identifiers of the instances that emitted the corresponding events in `events`.
+    pub event_instance_ids: Vec<InstanceId>,
+
+    /// Whether the context is used for a query, in which case emitting messages panics the same
+    /// way it does on the host.
+    pub read_only: bool,
 }
 
 impl From<ExecutionContext> for MockContext {
@@ -112,10 +157,106 @@ impl From<ExecutionContext> for MockContext {
             env: MockEnv::new(),
             messages: Vec::new(),
             events: Vec::new(),
+            event_instance_ids: Vec::new(),
+            read_only: false,
         }
     }
 }
 
+/// A call message emitted by a contract, with its body decoded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockCall<T> {
+    /// Identifier of the message.
+    pub id: u64,
+    /// When the contract wants to be notified of a reply.
+    pub reply: NotifyReply,
+    /// Decoded call body.
+    pub body: T,
+    /// Gas limit of the call.
+    pub max_gas: Option<u64>,
+}
+
+impl MockContext {
+    /// Return all emitted calls of the given method with their bodies decoded.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic in case the body of a matching call can not be decoded.
+    pub fn calls<T: cbor::Decode>(&self, method: &str) -> Vec<MockCall<T>> {
+        self.messages
+            .iter()
+            .filter_map(|msg| match msg {
+                Message::Call {
+                    id,
+                    reply,
+                    method: m,
+                    body,
+                    max_gas,
+                    ..
+                } if m == method => Some(MockCall {
+                    id: *id,
+                    reply: *reply,
+                    body: cbor::from_value(body.clone()).expect("malformed call body"),
+                    max_gas: *max_gas,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Return all emitted events together with the identifier of the instance that emitted them.
+    pub fn instance_events(&self) -> impl Iterator<Item = (InstanceId, &RawEvent)> {
+        self.event_instance_ids
+            .iter()
+            .copied()
+            .zip(self.events.iter())
+    }
+
+    /// Return all events with the given module and code emitted by the current instance, with
+    /// their data decoded.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic in case the data of a matching event can not be decoded.
+    pub fn events<T: cbor::Decode>(&self, module: &str, code: u32) -> Vec<T> {
+        self.instance_events()
+            .filter(|(instance_id, ev)| {
+                *instance_id == self.ec.instance_id && ev.module == module && ev.code == code
+            })
+            .map(|(_, ev)| cbor::from_slice(&ev.data).expect("malformed event data"))
+            .collect()
+    }
+
+    /// Assert that a call of the given method with the given body has been emitted.
+    pub fn assert_message<T: cbor::Decode + PartialEq + Debug>(&self, method: &str, body: &T) {
+        let calls = self.calls::<T>(method);
+        assert!(
+            calls.iter().any(|call| &call.body == body),
+            "expected call of {} with body {:?}, emitted calls: {:?}",
+            method,
+            body,
+            calls,
+        );
+    }
+
+    /// Assert that the given event has been emitted by the current instance.
+    pub fn assert_event<E: Event + Debug>(&self, event: E) {
+        let expected = format!("{:?}", event);
+        let raw = event.into_raw();
+        assert!(
+            self.instance_events().any(|(instance_id, ev)| {
+                instance_id == self.ec.instance_id
+                    && ev.module == raw.module
+                    && ev.code == raw.code
+                    && ev.data == raw.data
+            }),
+            "expected event {} to be emitted, emitted events: {:?}",
+            expected,
+            self.events,
+        );
+    }
+}
+
 impl Context for MockContext {
     type PublicStore = MockStore;
     type ConfidentialStore = MockStore;
@@ -133,16 +274,38 @@ impl Context for MockContext {
         &self.ec.caller_address
     }
 
+    fn tx_signer(&self) -> &Address {
+        &self.ec.tx_signer
+    }
+
+    fn tx_hash(&self) -> Hash {
+        self.ec.tx_hash
+    }
+
     fn deposited_tokens(&self) -> &[token::BaseUnits] {
         &self.ec.deposited_tokens
     }
 
+    fn take_deposited_tokens(&mut self) -> Vec<token::BaseUnits> {
+        std::mem::take(&mut self.ec.deposited_tokens)
+    }
+
     fn emit_message(&mut self, msg: Message) {
+        assert!(
+            !self.read_only,
+            "messages cannot be emitted in query context"
+        );
         self.messages.push(msg);
     }
 
+    fn emitted_messages(&self) -> &[Message] {
+        &self.messages
+    }
+
     fn emit_event<E: Event>(&mut self, event: E) {
+        // Record the emitting instance as the host does.
         self.events.push(event.into_raw());
+        self.event_instance_ids.push(self.ec.instance_id);
     }
 
     fn public_store(&mut self) -> &mut Self::PublicStore {
@@ -163,3 +326,316 @@ impl Context for MockContext {
 macro_rules! create_contract {
     ($name:ty) => {};
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        contract::Contract,
+        types::{modules::accounts, testing::addresses},
+    };
+
+    const PAY_ID: u64 = 1;
+
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    enum Request {
+        #[cbor(rename = "pay")]
+        Pay { to: Address },
+
+        #[cbor(rename = "pay_exact")]
+        PayExact { to: Address, amount: u128 },
+    }
+
+    /// Event emitted when a payment has been forwarded.
+    #[derive(Clone, Debug, PartialEq, cbor::Encode, cbor::Decode)]
+    struct Forwarded {
+        to: Address,
+        amount: u128,
+    }
+
+    impl Event for Forwarded {
+        fn module_name(&self) -> &str {
+            "forwarder"
+        }
+
+        fn code(&self) -> u32 {
+            1
+        }
+    }
+
+    /// A payable contract forwarding deposited tokens.
+    struct Forwarder;
+
+    impl Contract for Forwarder {
+        type Request = Request;
+        type Response = ();
+        type Error = std::convert::Infallible;
+
+        fn call<C: Context>(ctx: &mut C, request: Request) -> Result<(), Self::Error> {
+            match request {
+                Request::Pay { to } => {
+                    for amount in ctx.deposited_tokens().to_vec() {
+                        ctx.emit_event(Forwarded {
+                            to,
+                            amount: amount.amount(),
+                        });
+                        ctx.call_module(
+                            PAY_ID,
+                            "accounts.Transfer",
+                            accounts::Transfer { to, amount },
+                            None,
+                        );
+                    }
+                    Ok(())
+                }
+                Request::PayExact { to, amount } => {
+                    let expected = [token::BaseUnits::new(amount, token::Denomination::NATIVE)];
+                    if ctx.deposited_tokens() != expected {
+                        // Reject the payment without failing the call.
+                        ctx.refund_deposit();
+                        return Ok(());
+                    }
+                    Self::call(ctx, Request::Pay { to })
+                }
+            }
+        }
+
+        fn query<C: Context>(ctx: &mut C, request: Request) -> Result<(), Self::Error> {
+            // Attempt to forward the payment, which must not be possible from a query.
+            Self::call(ctx, request)
+        }
+    }
+
+    #[test]
+    fn test_assert_helpers() {
+        let mut ctx: MockContext = ExecutionContext {
+            instance_id: 3.into(),
+            deposited_tokens: vec![token::BaseUnits::new(100, token::Denomination::NATIVE)],
+            ..Default::default()
+        }
+        .into();
+
+        Forwarder::call(
+            &mut ctx,
+            Request::Pay {
+                to: addresses::bob::address(),
+            },
+        )
+        .expect("call should work");
+
+        let transfer = accounts::Transfer {
+            to: addresses::bob::address(),
+            amount: token::BaseUnits::new(100, token::Denomination::NATIVE),
+        };
+        ctx.assert_message("accounts.Transfer", &transfer);
+        assert_eq!(
+            ctx.calls::<accounts::Transfer>("accounts.Transfer"),
+            vec![MockCall {
+                id: PAY_ID,
+                reply: NotifyReply::Always,
+                body: transfer,
+                max_gas: None,
+            }]
+        );
+        assert!(ctx.calls::<accounts::Transfer>("accounts.Other").is_empty());
+
+        let forwarded = Forwarded {
+            to: addresses::bob::address(),
+            amount: 100,
+        };
+        ctx.assert_event(forwarded.clone());
+        assert_eq!(ctx.events::<Forwarded>("forwarder", 1), vec![forwarded]);
+        assert!(ctx.events::<Forwarded>("forwarder", 2).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "messages cannot be emitted in query context")]
+    fn test_query_emit_message() {
+        let mut ctx: MockContext = ExecutionContext {
+            instance_id: 3.into(),
+            deposited_tokens: vec![token::BaseUnits::new(100, token::Denomination::NATIVE)],
+            ..Default::default()
+        }
+        .into();
+        ctx.read_only = true;
+
+        let _ = Forwarder::query(
+            &mut ctx,
+            Request::Pay {
+                to: addresses::bob::address(),
+            },
+        );
+    }
+
+    #[test]
+    fn test_refund_deposit() {
+        let mut ctx: MockContext = ExecutionContext {
+            instance_id: 3.into(),
+            caller_address: addresses::alice::address(),
+            deposited_tokens: vec![token::BaseUnits::new(50, token::Denomination::NATIVE)],
+            ..Default::default()
+        }
+        .into();
+
+        // The payment should be rejected and the deposit refunded to the caller.
+        Forwarder::call(
+            &mut ctx,
+            Request::PayExact {
+                to: addresses::bob::address(),
+                amount: 100,
+            },
+        )
+        .expect("call should work");
+
+        let refund = accounts::Transfer {
+            to: addresses::alice::address(),
+            amount: token::BaseUnits::new(50, token::Denomination::NATIVE),
+        };
+        assert_eq!(
+            ctx.calls::<accounts::Transfer>("accounts.Transfer"),
+            vec![MockCall {
+                id: 0,
+                reply: NotifyReply::Never,
+                body: refund,
+                max_gas: None,
+            }]
+        );
+        assert!(ctx.events::<Forwarded>("forwarder", 1).is_empty());
+        assert!(ctx.deposited_tokens().is_empty());
+
+        // Refunding again should not emit any further transfers.
+        ctx.refund_deposit();
+        assert_eq!(
+            ctx.messages.len(),
+            1,
+            "deposit should only be refunded once"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "expected call of accounts.Transfer")]
+    fn test_assert_message_missing() {
+        let ctx: MockContext = ExecutionContext::default().into();
+        ctx.assert_message(
+            "accounts.Transfer",
+            &accounts::Transfer {
+                to: addresses::bob::address(),
+                amount: token::BaseUnits::new(100, token::Denomination::NATIVE),
+            },
+        );
+    }
+
+    #[test]
+    fn test_emit_event() {
+        let mut ctx: MockContext = ExecutionContext {
+            instance_id: 42.into(),
+            ..Default::default()
+        }
+        .into();
+
+        ctx.emit_event(RawEvent {
+            module: "test".to_string(),
+            code: 1,
+            data: b"hello".to_vec(),
+            topics: vec![],
+        });
+
+        assert_eq!(ctx.events.len(), 1, "event should be recorded");
+        assert_eq!(ctx.events[0].module, "test");
+        assert_eq!(ctx.events[0].code, 1);
+        assert_eq!(ctx.events[0].data, b"hello");
+
+        let (instance_id, _) = ctx
+            .instance_events()
+            .next()
+            .expect("event should be recorded");
+        assert_eq!(instance_id, 42.into(), "instance id should be recorded");
+    }
+
+    #[test]
+    fn test_emit_events() {
+        let mut ctx: MockContext = ExecutionContext::default().into();
+
+        ctx.emit_events((1..=3).map(|code| RawEvent {
+            module: "test".to_string(),
+            code,
+            data: vec![],
+            topics: vec![],
+        }));
+
+        let codes: Vec<_> = ctx.events.iter().map(|ev| ev.code).collect();
+        assert_eq!(
+            codes,
+            vec![1, 2, 3],
+            "all events should be recorded in order"
+        );
+    }
+
+    #[test]
+    fn test_emit_log() {
+        let mut ctx: MockContext = ExecutionContext::default().into();
+
+        ctx.emit_log(&[b"transfer", b"alice", b"bob"], b"100");
+        ctx.emit_log(&[b"transfer", b"bob", b"alice"], b"50");
+        ctx.emit_log(&[b"approve", b"alice"], b"10");
+
+        let event = &ctx.events[0];
+        assert_eq!(event.module, "log");
+        assert_eq!(event.code, 3, "code should be the number of topics");
+
+        // Filter by the second topic.
+        let data: Vec<_> = ctx
+            .events
+            .iter()
+            .filter(|ev| ev.topics.get(1).map(Vec::as_slice) == Some(&b"alice"[..]))
+            .map(|ev| ev.data.clone())
+            .collect();
+        assert_eq!(data, vec![b"100".to_vec(), b"10".to_vec()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "too many log topics")]
+    fn test_emit_log_too_many_topics() {
+        let mut ctx: MockContext = ExecutionContext::default().into();
+        ctx.emit_log(&[b"1", b"2", b"3", b"4", b"5"], b"");
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let env = MockEnv::new();
+        let context = b"test-contract: permit";
+        let message = b"hello world";
+
+        let cases = vec![
+            (
+                SignatureKind::Ed25519,
+                "79b5562e8fe654f94078b112e8a98ba7901f853ae695bed7e0e3910bad049664",
+                "3e31624e77a3908e034aa5dbfc8c85104699fad9c4c068a9e52916711feebb556679178efe982728d22aec0448f8fced5f31e4c66d314ec6a375becc49385f0e",
+            ),
+            (
+                SignatureKind::Secp256k1,
+                "03f973a0b87062c389d125d8199e803b832b6ac6bf7867a4f6cd87506060fc4c58",
+                "3045022100f74b4fdf44ae543f5def5d1b2052526f90680d52bdc687658e475bc7a9f0769602205ed44be6e3cc9211d5c4ab47e9dd8a2e4930a8a44e9a19ce16089a79774d8f44",
+            ),
+        ];
+        for (scheme, key, signature) in cases {
+            let key = hex::decode(key).unwrap();
+            let signature = hex::decode(signature).unwrap();
+
+            assert!(
+                env.verify_signature(scheme, &key, context, message, &signature),
+                "valid {:?} signature should verify",
+                scheme
+            );
+
+            // Signatures should be bound to the message and the context.
+            assert!(!env.verify_signature(scheme, &key, context, b"hello", &signature));
+            assert!(!env.verify_signature(scheme, &key, b"other", message, &signature));
+
+            // Malformed inputs should be rejected instead of panicking.
+            assert!(!env.verify_signature(scheme, &key, context, message, &signature[1..]));
+            assert!(!env.verify_signature(scheme, &key[1..], context, message, &signature));
+        }
+    }
+}
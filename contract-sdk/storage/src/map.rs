@@ -213,7 +213,7 @@ fn encode_length(key: &[u8]) -> [u8; 1] {
 
 #[cfg(test)]
 mod test {
-    use oasis_contract_sdk::testing::MockStore;
+    use oasis_contract_sdk::{storage::Store as _, testing::MockStore};
 
     use super::*;
 
@@ -251,6 +251,19 @@ mod test {
         assert_eq!(map.get(&store, ("foo", "bar")), None);
     }
 
+    #[test]
+    fn test_map_scoped() {
+        let mut store = MockStore::new();
+        let map: Map<&str, u64> = Map::new(b"test");
+
+        map.insert(&mut store.scoped(b"first"), "foo", 1);
+        map.insert(&mut store.scoped(b"second"), "foo", 2);
+
+        assert_eq!(map.get(&store.scoped(b"first"), "foo"), Some(1));
+        assert_eq!(map.get(&store.scoped(b"second"), "foo"), Some(2));
+        assert_eq!(map.get(&store, "foo"), None);
+    }
+
     #[test]
     fn test_encode_length() {
         assert_eq!(encode_length(b"foo"), [0x03]);
@@ -18,6 +18,10 @@ pub enum Error {
 }
 
 /// An account address.
+///
+/// Addresses are ordered lexicographically by their raw bytes (version followed by data), which
+/// is the same ordering as used by the runtime-side address type. This makes it safe to rely on
+/// the ordering of addresses (e.g. as `BTreeMap` keys) both on-chain and off-chain.
 #[derive(
     Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, cbor::Encode, cbor::Decode,
 )]
@@ -74,6 +78,11 @@ impl TryFrom<&[u8]> for Address {
     }
 }
 
+/// Sorts the given addresses in place using the canonical address ordering.
+pub fn sort_addresses(addresses: &mut [Address]) {
+    addresses.sort_unstable();
+}
+
 #[cfg(feature = "oasis-runtime-sdk")]
 impl From<oasis_runtime_sdk::types::address::Address> for Address {
     fn from(a: oasis_runtime_sdk::types::address::Address) -> Self {
@@ -130,4 +139,67 @@ mod test {
             Error::MalformedAddress,
         ));
     }
+
+    #[test]
+    fn test_sort_addresses() {
+        let addr = |version: u8, data: u8| {
+            let mut bytes = [data; ADDRESS_SIZE];
+            bytes[0] = version;
+            Address::from_bytes(&bytes).unwrap()
+        };
+        let mut addresses = vec![
+            addr(1, 0x00),
+            addr(0, 0xff),
+            addr(0, 0x01),
+            addr(1, 0x00),
+            addr(0, 0x10),
+        ];
+
+        sort_addresses(&mut addresses);
+        assert_eq!(
+            addresses,
+            vec![
+                addr(0, 0x01),
+                addr(0, 0x10),
+                addr(0, 0xff),
+                addr(1, 0x00),
+                addr(1, 0x00),
+            ],
+            "addresses should be ordered by version first and then by data"
+        );
+
+        // The ordering should match the ordering of the raw bytes.
+        let mut raw: Vec<Vec<u8>> = addresses.iter().map(|a| a.as_ref().to_vec()).collect();
+        raw.sort();
+        let by_bytes: Vec<Address> = raw
+            .iter()
+            .map(|b| Address::from_bytes(b).unwrap())
+            .collect();
+        assert_eq!(addresses, by_bytes);
+    }
+
+    #[cfg(feature = "oasis-runtime-sdk")]
+    #[test]
+    fn test_sort_addresses_matches_runtime() {
+        use oasis_runtime_sdk::testing::keys;
+
+        let runtime_addresses = vec![
+            keys::alice::address(),
+            keys::bob::address(),
+            keys::charlie::address(),
+            keys::dave::address(),
+        ];
+        let mut addresses: Vec<Address> =
+            runtime_addresses.iter().copied().map(Into::into).collect();
+        sort_addresses(&mut addresses);
+
+        let mut runtime_sorted = runtime_addresses;
+        runtime_sorted.sort();
+        let runtime_sorted: Vec<Address> = runtime_sorted.into_iter().map(Into::into).collect();
+
+        assert_eq!(
+            addresses, runtime_sorted,
+            "ordering should match the runtime-side ordering"
+        );
+    }
 }
@@ -0,0 +1,22 @@
+//! Cryptography-related types.
+use std::convert::TryFrom;
+
+/// Kind of the signature scheme.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SignatureKind {
+    Ed25519 = 0,
+    Secp256k1 = 1,
+}
+
+impl TryFrom<u32> for SignatureKind {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(SignatureKind::Ed25519),
+            1 => Ok(SignatureKind::Secp256k1),
+            _ => Err(value),
+        }
+    }
+}
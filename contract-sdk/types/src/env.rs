@@ -1,5 +1,7 @@
 //! Environment query-related types.
-use crate::{address::Address, token::Denomination};
+use crate::{
+    address::Address, modules::contracts::InstanceMetadata, token::Denomination, InstanceId,
+};
 
 /// A query request.
 #[non_exhaustive]
@@ -12,6 +14,10 @@ pub enum QueryRequest {
     /// Accounts queries.
     #[cbor(rename = "accounts")]
     Accounts(AccountsQuery),
+
+    /// Contracts queries.
+    #[cbor(rename = "contracts")]
+    Contracts(ContractsQuery),
 }
 
 /// A query response.
@@ -37,6 +43,10 @@ pub enum QueryResponse {
     /// Accounts queries.
     #[cbor(rename = "accounts")]
     Accounts(AccountsResponse),
+
+    /// Contracts queries.
+    #[cbor(rename = "contracts")]
+    Contracts(ContractsResponse),
 }
 
 /// Accounts API queries.
@@ -70,3 +80,32 @@ impl From<AccountsResponse> for QueryResponse {
         Self::Accounts(q)
     }
 }
+
+/// Contracts API queries.
+#[non_exhaustive]
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub enum ContractsQuery {
+    /// Query metadata of the given instance.
+    #[cbor(rename = "instance_metadata")]
+    InstanceMetadata { id: InstanceId },
+}
+
+impl From<ContractsQuery> for QueryRequest {
+    fn from(q: ContractsQuery) -> Self {
+        Self::Contracts(q)
+    }
+}
+
+/// Contracts API responses.
+#[non_exhaustive]
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub enum ContractsResponse {
+    /// Metadata of the given instance.
+    InstanceMetadata { metadata: InstanceMetadata },
+}
+
+impl From<ContractsResponse> for QueryResponse {
+    fn from(q: ContractsResponse) -> Self {
+        Self::Contracts(q)
+    }
+}
@@ -1,5 +1,12 @@
 //! Events.
 
+/// Maximum number of indexed topics of a single event.
+pub const MAX_TOPICS: usize = 4;
+
+/// Module name used for logs emitted with indexed topics. The event code of a log is the number
+/// of its topics.
+pub const LOG_MODULE_NAME: &str = "log";
+
 /// An event emitted from the contract.
 #[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
 pub struct Event {
@@ -13,4 +20,9 @@ pub struct Event {
     /// Arbitrary data associated with the event.
     #[cbor(optional, default, skip_serializing_if = "Vec::is_empty")]
     pub data: Vec<u8>,
+
+    /// Indexed topics that subscribers can filter the event by. At most [`MAX_TOPICS`] topics are
+    /// allowed.
+    #[cbor(optional, default, skip_serializing_if = "Vec::is_empty")]
+    pub topics: Vec<Vec<u8>>,
 }
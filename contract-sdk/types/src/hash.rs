@@ -0,0 +1,34 @@
+//! A minimal representation of an Oasis Runtime SDK hash.
+
+const HASH_SIZE: usize = 32;
+
+/// A SHA-512/256 hash.
+#[derive(
+    Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, cbor::Encode, cbor::Decode,
+)]
+#[cbor(transparent)]
+pub struct Hash([u8; HASH_SIZE]);
+
+impl Hash {
+    /// Size of a hash in bytes.
+    pub const SIZE: usize = HASH_SIZE;
+}
+
+impl AsRef<[u8]> for Hash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<[u8; HASH_SIZE]> for Hash {
+    fn from(h: [u8; HASH_SIZE]) -> Self {
+        Self(h)
+    }
+}
+
+#[cfg(feature = "oasis-runtime-sdk")]
+impl From<oasis_runtime_sdk::core::common::crypto::hash::Hash> for Hash {
+    fn from(h: oasis_runtime_sdk::core::common::crypto::hash::Hash) -> Self {
+        Self(h.0)
+    }
+}
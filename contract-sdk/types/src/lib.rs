@@ -1,8 +1,10 @@
 //! A collection of common types used by the Oasis Contract SDK.
 
 pub mod address;
+pub mod crypto;
 pub mod env;
 pub mod event;
+pub mod hash;
 pub mod message;
 pub mod modules;
 pub mod storage;
@@ -39,7 +41,9 @@ impl From<u64> for CodeId {
 }
 
 /// Unique deployed code instance identifier.
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, cbor::Decode, cbor::Encode)]
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, cbor::Decode, cbor::Encode,
+)]
 #[cbor(transparent)]
 pub struct InstanceId(u64);
 
@@ -81,6 +85,12 @@ pub struct ExecutionContext {
     /// Tokens deposited by the caller.
     #[cbor(optional, default, skip_serializing_if = "Vec::is_empty")]
     pub deposited_tokens: Vec<token::BaseUnits>,
+    /// Address of the signer of the enclosing transaction.
+    #[cbor(optional, default)]
+    pub tx_signer: address::Address,
+    /// Hash of the enclosing transaction.
+    #[cbor(optional, default)]
+    pub tx_hash: hash::Hash,
 }
 
 /// Contract execution result.
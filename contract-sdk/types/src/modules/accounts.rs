@@ -0,0 +1,11 @@
+use crate::{address::Address, token};
+
+/// Transfer call.
+#[derive(Clone, Debug, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct Transfer {
+    /// Address of the receiving account.
+    pub to: Address,
+
+    /// Amount to transfer.
+    pub amount: token::BaseUnits,
+}
@@ -1,4 +1,46 @@
-use crate::InstanceId;
+use crate::{address::Address, hash::Hash, token, CodeId, InstanceId};
+
+/// A generic policy that specifies who is allowed to perform an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub enum Policy {
+    #[cbor(rename = "nobody", as_struct)]
+    Nobody,
+
+    #[cbor(rename = "address")]
+    Address(Address),
+
+    #[cbor(rename = "everyone", as_struct)]
+    Everyone,
+}
+
+/// Instantiate call.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct Instantiate {
+    /// Identifier of code used by the instance.
+    pub code_id: CodeId,
+
+    /// Who is allowed to upgrade this instance.
+    pub upgrades_policy: Policy,
+
+    /// Arguments to contract's instantiation function.
+    pub data: Vec<u8>,
+
+    /// Tokens that should be sent to the contract as part of the instantiate call.
+    pub tokens: Vec<token::BaseUnits>,
+}
+
+/// Call call.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct Call {
+    /// Instance identifier.
+    pub id: InstanceId,
+
+    /// Call arguments.
+    pub data: Vec<u8>,
+
+    /// Tokens that should be sent to the contract as part of the call.
+    pub tokens: Vec<token::BaseUnits>,
+}
 
 /// Instantiate call result.
 #[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
@@ -6,3 +48,16 @@ pub struct InstantiateResult {
     /// Assigned instance identifier.
     pub id: InstanceId,
 }
+
+/// Instance metadata.
+#[derive(Clone, Debug, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct InstanceMetadata {
+    /// Identifier of code used by the instance. This changes whenever the instance is upgraded.
+    pub code_id: CodeId,
+
+    /// Hash of code used by the instance.
+    pub code_hash: Hash,
+
+    /// Who is allowed to upgrade this instance.
+    pub upgrades_policy: Policy,
+}
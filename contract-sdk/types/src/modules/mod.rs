@@ -1,3 +1,4 @@
 //!  A collection of types for easier calling into existing SDK modules.
 
+pub mod accounts;
 pub mod contracts;
@@ -73,7 +73,7 @@ pub fn derive_error(input: DeriveInput) -> TokenStream {
         .module_name
         .unwrap_or_else(|| syn::parse_quote!(MODULE_NAME));
 
-    let (module_name_body, code_body, abort_body) = convert_variants(
+    let (module_name_body, code_body, abort_body, known_codes_body) = convert_variants(
         &format_ident!("self"),
         module_name,
         &error.data.as_ref().take_enum().unwrap(),
@@ -98,6 +98,10 @@ pub fn derive_error(input: DeriveInput) -> TokenStream {
             fn into_abort(self) -> Result<__sdk::dispatcher::Error, Self> {
                 #abort_body
             }
+
+            fn known_codes() -> Vec<(u32, &'static str)> {
+                #known_codes_body
+            }
         }
 
         #[automatically_derived]
@@ -114,9 +118,14 @@ fn convert_variants(
     module_name: Path,
     variants: &[&ErrorVariant],
     autonumber: bool,
-) -> (TokenStream, TokenStream, TokenStream) {
+) -> (TokenStream, TokenStream, TokenStream, TokenStream) {
     if variants.is_empty() {
-        return (quote!(#module_name), quote!(0), quote!(Err(#enum_binding)));
+        return (
+            quote!(#module_name),
+            quote!(0),
+            quote!(Err(#enum_binding)),
+            quote!(Vec::new()),
+        );
     }
 
     let mut next_autonumber = 0u32;
@@ -148,10 +157,13 @@ fn convert_variants(
                 .unwrap()
                 .error("multiple abort variants specified")
                 .emit();
-            return (quote!(), quote!(), quote!());
+            return (quote!(), quote!(), quote!(), quote!());
         }
     };
 
+    // Codes of all non-transparent variants, for use by clients decoding errors.
+    let mut known_codes = Vec::new();
+
     let (module_name_matches, code_matches): (Vec<_>, Vec<_>) = variants
         .iter()
         .map(|variant| {
@@ -238,6 +250,9 @@ fn convert_variants(
                     }
                 };
 
+                let name = variant_ident.to_string();
+                known_codes.push(quote!((#code, #name)));
+
                 (
                     quote! {
                         Self::#variant_ident { .. } => #module_name,
@@ -262,6 +277,9 @@ fn convert_variants(
             }
         },
         abort_variant,
+        quote! {
+            vec![#(#known_codes),*]
+        },
     )
 }
 
@@ -298,6 +316,15 @@ mod tests {
                             _ => Err(self),
                         }
                     }
+                    fn known_codes() -> Vec<(u32, &'static str)> {
+                        vec![
+                            (0u32, "Error0"),
+                            (2u32, "Error2"),
+                            (1u32, "Error1"),
+                            (3u32, "Error3"),
+                            (4u32, "ErrorAbort"),
+                        ]
+                    }
                 }
                 #[automatically_derived]
                 impl From<Error> for __sdk::error::RuntimeError {
@@ -345,6 +372,9 @@ mod tests {
                     fn into_abort(self) -> Result<__sdk::dispatcher::Error, Self> {
                         Err(self)
                     }
+                    fn known_codes() -> Vec<(u32, &'static str)> {
+                        Vec::new()
+                    }
                 }
                 #[automatically_derived]
                 impl From<Error> for __sdk::error::RuntimeError {
@@ -386,6 +416,9 @@ mod tests {
                     fn into_abort(self) -> Result<__sdk::dispatcher::Error, Self> {
                         Err(self)
                     }
+                    fn known_codes() -> Vec<(u32, &'static str)> {
+                        vec![]
+                    }
                 }
                 #[automatically_derived]
                 impl From<Error> for __sdk::error::RuntimeError {
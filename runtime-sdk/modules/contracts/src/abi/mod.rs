@@ -2,6 +2,7 @@
 use oasis_contract_sdk_types::{message::Reply, ExecutionOk};
 use oasis_runtime_sdk::{
     context::Context,
+    core::common::crypto::hash::Hash,
     types::{address::Address, token},
 };
 
@@ -98,6 +99,10 @@ pub struct ExecutionContext<'ctx, C: Context> {
 
     /// Address of the caller.
     pub caller_address: Address,
+    /// Address of the signer of the enclosing transaction.
+    pub tx_signer: Address,
+    /// Hash of the enclosing transaction.
+    pub tx_hash: Hash,
 }
 
 /// Result of an execution that contains additional metadata like gas used.
@@ -1,18 +1,28 @@
 //! Environment query imports.
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 
 use oasis_contract_sdk_crypto as crypto;
 use oasis_contract_sdk_types::{
-    env::{AccountsQuery, AccountsResponse, QueryRequest, QueryResponse},
+    crypto::SignatureKind,
+    env::{
+        AccountsQuery, AccountsResponse, ContractsQuery, ContractsResponse, QueryRequest,
+        QueryResponse,
+    },
+    modules::contracts,
     InstanceId,
 };
-use oasis_runtime_sdk::{context::Context, modules::accounts::API as _};
+use oasis_runtime_sdk::{
+    context::Context,
+    crypto::signature::{ed25519, secp256k1, PublicKey, Signature},
+    error::Error as _,
+    modules::accounts::API as _,
+};
 
 use super::{memory::Region, OasisV1};
 use crate::{
     abi::{gas, ExecutionContext},
-    types::Instance,
-    Config, Error,
+    types::{Instance, Policy},
+    Config, Error, Module,
 };
 
 impl<Cfg: Config> OasisV1<Cfg> {
@@ -99,6 +109,9 @@ fn dispatch_query<Cfg: Config, C: Context>(ctx: &mut C, query: QueryRequest) ->
         // Accounts API queries.
         QueryRequest::Accounts(query) => dispatch_accounts_query::<Cfg, C>(ctx, query),
 
+        // Contracts API queries.
+        QueryRequest::Contracts(query) => dispatch_contracts_query::<Cfg, C>(ctx, query),
+
         _ => QueryResponse::Error {
             module: "".to_string(),
             code: 1,
@@ -135,6 +148,42 @@ fn dispatch_accounts_query<Cfg: Config, C: Context>(
     }
 }
 
+/// Perform contracts API query dispatch.
+fn dispatch_contracts_query<Cfg: Config, C: Context>(
+    ctx: &mut C,
+    query: ContractsQuery,
+) -> QueryResponse {
+    match query {
+        ContractsQuery::InstanceMetadata { id } => {
+            match Module::<Cfg>::load_instance_metadata(ctx, id) {
+                Ok(metadata) => ContractsResponse::InstanceMetadata {
+                    metadata: contracts::InstanceMetadata {
+                        code_id: metadata.code_id,
+                        code_hash: metadata.code_hash.into(),
+                        upgrades_policy: match metadata.upgrades_policy {
+                            Policy::Nobody => contracts::Policy::Nobody,
+                            Policy::Address(address) => contracts::Policy::Address(address.into()),
+                            Policy::Everyone => contracts::Policy::Everyone,
+                        },
+                    },
+                }
+                .into(),
+                Err(err) => QueryResponse::Error {
+                    module: err.module_name().to_string(),
+                    code: err.code(),
+                    message: err.to_string(),
+                },
+            }
+        }
+
+        _ => QueryResponse::Error {
+            module: "".to_string(),
+            code: 1,
+            message: "query not supported".to_string(),
+        },
+    }
+}
+
 impl<Cfg: Config> OasisV1<Cfg> {
     /// Link crypto helper functions.
     pub fn link_crypto<C: Context>(
@@ -173,6 +222,77 @@ impl<Cfg: Config> OasisV1<Cfg> {
             },
         );
 
+        // crypto.signature_verify(kind, key, context, message, signature) -> result
+        let _ = instance.link_function(
+            "crypto",
+            "signature_verify",
+            |ctx,
+             (kind, key, context, message, signature): (
+                u32,
+                (u32, u32),
+                (u32, u32),
+                (u32, u32),
+                (u32, u32),
+            )|
+             -> Result<u32, wasm3::Trap> {
+                // Make sure function was called in valid context.
+                let ec = ctx.context.ok_or(wasm3::Trap::Abort)?;
+
+                // Charge base gas amount plus size-dependent gas.
+                let total_gas = (|| {
+                    let base = ec.params.gas_costs.wasm_crypto_signature_verify_base;
+                    let size = u64::from(context.1).checked_add(message.1.into())?;
+                    let bytes = ec
+                        .params
+                        .gas_costs
+                        .wasm_crypto_signature_verify_byte
+                        .checked_mul(size)?;
+                    let total = base.checked_add(bytes)?;
+                    Some(total)
+                })()
+                .ok_or(wasm3::Trap::Abort)?;
+                gas::use_gas(ctx.instance, total_gas)?;
+
+                let kind = SignatureKind::try_from(kind).map_err(|_| wasm3::Trap::Abort)?;
+
+                ctx.instance
+                    .runtime()
+                    .try_with_memory(|memory| -> Result<_, wasm3::Trap> {
+                        let key = Region::from_arg(key)
+                            .as_slice(&memory)
+                            .map_err(|_| wasm3::Trap::Abort)?;
+                        let context = Region::from_arg(context)
+                            .as_slice(&memory)
+                            .map_err(|_| wasm3::Trap::Abort)?;
+                        let message = Region::from_arg(message)
+                            .as_slice(&memory)
+                            .map_err(|_| wasm3::Trap::Abort)?;
+                        let signature: Signature = Region::from_arg(signature)
+                            .as_slice(&memory)
+                            .map_err(|_| wasm3::Trap::Abort)?
+                            .to_vec()
+                            .into();
+
+                        let public_key = match kind {
+                            SignatureKind::Ed25519 => {
+                                ed25519::PublicKey::from_bytes(key).map(PublicKey::Ed25519)
+                            }
+                            SignatureKind::Secp256k1 => {
+                                secp256k1::PublicKey::from_bytes(key).map(PublicKey::Secp256k1)
+                            }
+                        };
+                        let valid = match public_key {
+                            Ok(public_key) => {
+                                public_key.verify(context, message, &signature).is_ok()
+                            }
+                            Err(_) => false,
+                        };
+
+                        Ok(if valid { 0 } else { 1 })
+                    })?
+            },
+        );
+
         Ok(())
     }
 }
@@ -65,6 +65,8 @@ impl<Cfg: Config> OasisV1<Cfg> {
                 instance_id: ctx.instance_info.id,
                 instance_address: ctx.instance_info.address().into(),
                 caller_address: ctx.caller_address.into(),
+                tx_signer: ctx.tx_signer.into(),
+                tx_hash: ctx.tx_hash.into(),
                 deposited_tokens: deposited_tokens.iter().map(|b| b.into()).collect(),
             },
         )
@@ -200,6 +200,8 @@ fn run_contract_with_defaults(
         };
         let mut exec_ctx = abi::ExecutionContext {
             caller_address: Default::default(),
+            tx_signer: Default::default(),
+            tx_hash: Default::default(),
             instance_info: &instance_info,
             tx_context: &mut ctx,
             params: &params,
@@ -115,6 +115,10 @@ pub enum Error {
     #[sdk_error(code = 19)]
     CodeAlreadyUpgraded(u64),
 
+    #[error("too many event topics (count: {0} max: {1})")]
+    #[sdk_error(code = 20)]
+    TooManyEventTopics(usize, usize),
+
     #[error("core: {0}")]
     #[sdk_error(transparent)]
     Core(#[from] modules::core::Error),
@@ -151,6 +155,10 @@ pub struct GasCosts {
 
     // Crypto operations.
     pub wasm_crypto_ecdsa_recover: u64,
+    #[cbor(optional, default)]
+    pub wasm_crypto_signature_verify_base: u64,
+    #[cbor(optional, default)]
+    pub wasm_crypto_signature_verify_byte: u64,
 }
 
 impl Default for GasCosts {
@@ -173,6 +181,8 @@ impl Default for GasCosts {
             wasm_env_query_base: 10,
 
             wasm_crypto_ecdsa_recover: 20,
+            wasm_crypto_signature_verify_base: 20,
+            wasm_crypto_signature_verify_byte: 1,
         }
     }
 }
@@ -310,6 +320,21 @@ impl<Cfg: Config> Module<Cfg> {
         Ok(instance_info)
     }
 
+    /// Load metadata of the specified instance.
+    fn load_instance_metadata<C: Context>(
+        ctx: &mut C,
+        instance_id: types::InstanceId,
+    ) -> Result<types::InstanceMetadata, Error> {
+        let instance_info = Self::load_instance_info(ctx, instance_id)?;
+        let code_info = Self::load_code_info(ctx, instance_info.code_id)?;
+
+        Ok(types::InstanceMetadata {
+            code_id: code_info.id,
+            code_hash: code_info.hash,
+            upgrades_policy: instance_info.upgrades_policy,
+        })
+    }
+
     /// Stores specified instance information.
     fn store_instance_info<C: Context>(
         ctx: &mut C,
@@ -469,6 +494,8 @@ impl<Cfg: Config> Module<Cfg> {
         };
         let mut exec_ctx = abi::ExecutionContext {
             caller_address: ctx.tx_caller_address(),
+            tx_signer: results::tx_signer(ctx),
+            tx_hash: Core::tx_hash(ctx),
             gas_limit: Core::remaining_tx_gas(ctx),
             instance_info: &instance_info,
             tx_context: ctx,
@@ -519,6 +546,8 @@ impl<Cfg: Config> Module<Cfg> {
         };
         let mut exec_ctx = abi::ExecutionContext {
             caller_address: ctx.tx_caller_address(),
+            tx_signer: results::tx_signer(ctx),
+            tx_hash: Core::tx_hash(ctx),
             gas_limit: Core::remaining_tx_gas(ctx),
             instance_info: &instance_info,
             tx_context: ctx,
@@ -574,6 +603,8 @@ impl<Cfg: Config> Module<Cfg> {
         };
         let mut exec_ctx = abi::ExecutionContext {
             caller_address: ctx.tx_caller_address(),
+            tx_signer: results::tx_signer(ctx),
+            tx_hash: Core::tx_hash(ctx),
             gas_limit: Core::remaining_tx_gas(ctx),
             instance_info: &instance_info,
             tx_context: ctx,
@@ -598,6 +629,8 @@ impl<Cfg: Config> Module<Cfg> {
             };
             let mut exec_ctx = abi::ExecutionContext {
                 caller_address: ctx.tx_caller_address(),
+                tx_signer: results::tx_signer(ctx),
+                tx_hash: Core::tx_hash(ctx),
                 gas_limit: Core::remaining_tx_gas(ctx),
                 instance_info: &instance_info,
                 tx_context: ctx,
@@ -629,6 +662,13 @@ impl<Cfg: Config> Module<Cfg> {
         Self::load_instance_info(ctx, args.id)
     }
 
+    fn query_instance_metadata<C: Context>(
+        ctx: &mut C,
+        args: types::InstanceQuery,
+    ) -> Result<types::InstanceMetadata, Error> {
+        Self::load_instance_metadata(ctx, args.id)
+    }
+
     fn query_instance_storage<C: Context>(
         ctx: &mut C,
         args: types::InstanceStorageQuery,
@@ -675,6 +715,8 @@ impl<Cfg: Config> Module<Cfg> {
         };
         let mut exec_ctx = abi::ExecutionContext {
             caller_address: Default::default(), // No caller for queries.
+            tx_signer: Default::default(),
+            tx_hash: Default::default(),
             gas_limit: cfg.query_custom_max_gas,
             instance_info: &instance_info,
             tx_context: ctx,
@@ -716,6 +758,9 @@ impl<Cfg: Config> module::MethodHandler for Module<Cfg> {
         match method {
             "contracts.Code" => module::dispatch_query(ctx, args, Self::query_code),
             "contracts.Instance" => module::dispatch_query(ctx, args, Self::query_instance),
+            "contracts.InstanceMetadata" => {
+                module::dispatch_query(ctx, args, Self::query_instance_metadata)
+            }
             "contracts.InstanceStorage" => {
                 module::dispatch_query(ctx, args, Self::query_instance_storage)
             }
@@ -724,6 +769,14 @@ impl<Cfg: Config> module::MethodHandler for Module<Cfg> {
             _ => module::DispatchResult::Unhandled(args),
         }
     }
+
+    fn register_error_codes(registry: &mut modules::core::types::ErrorRegistry) {
+        module::register_error_codes::<Self>(registry)
+    }
+
+    fn register_capabilities(capabilities: &mut modules::core::types::Capabilities) {
+        capabilities.insert("contracts".to_owned());
+    }
 }
 
 impl<Cfg: Config> Module<Cfg> {
@@ -763,4 +816,5 @@ impl<Cfg: Config> module::MigrationHandler for Module<Cfg> {
 
 impl<Cfg: Config> module::AuthHandler for Module<Cfg> {}
 impl<Cfg: Config> module::BlockHandler for Module<Cfg> {}
+impl<Cfg: Config> module::EventHandler for Module<Cfg> {}
 impl<Cfg: Config> module::InvariantHandler for Module<Cfg> {}
@@ -2,16 +2,15 @@
 use std::convert::TryInto;
 
 use oasis_contract_sdk_types::{
-    event::Event,
+    event::{Event, MAX_TOPICS},
     message::{Message, NotifyReply, Reply},
     ExecutionOk,
 };
 use oasis_runtime_sdk::{
     context::{BatchContext, Context, TxContext},
     dispatcher,
-    event::tag_for_event,
     modules::core::{self, API as _},
-    types::{token, transaction, transaction::CallerAddress},
+    types::{address::Address, token, transaction, transaction::CallerAddress},
 };
 
 use crate::{
@@ -23,6 +22,17 @@ use crate::{
 /// Context key used for tracking the execution call depth to make sure that the maximum depth is
 /// not exceeded as that could result in a stack overflow.
 const CONTEXT_KEY_DEPTH: &str = "contracts.CallDepth";
+/// Context key used for propagating the signer of the enclosing transaction to subcalls, as the
+/// caller of a subcall is the calling contract.
+const CONTEXT_KEY_TX_SIGNER: &str = "contracts.TxSigner";
+
+/// Address of the signer of the enclosing transaction.
+pub(crate) fn tx_signer<C: TxContext>(ctx: &mut C) -> Address {
+    if let Some(signer) = ctx.value::<Address>(CONTEXT_KEY_TX_SIGNER).get() {
+        return *signer;
+    }
+    ctx.tx_caller_address()
+}
 
 /// Process an execution result by performing gas accounting and returning the inner result.
 pub(crate) fn process_execution_result<C: TxContext>(
@@ -57,23 +67,28 @@ fn process_events<C: TxContext>(
 ) -> Result<(), Error> {
     // Transform contract events into tags using the SDK scheme.
     for event in events {
-        ctx.emit_tag(tag_for_event(
-            &if event.module.is_empty() {
-                format!("{}.{}", MODULE_NAME, contract.code_info.id.as_u64())
-            } else {
-                format!(
-                    "{}.{}.{}",
-                    MODULE_NAME,
-                    contract.code_info.id.as_u64(),
-                    event.module,
-                )
-            },
-            event.code,
-            cbor::to_vec(ContractEvent {
+        if event.topics.len() > MAX_TOPICS {
+            return Err(Error::TooManyEventTopics(event.topics.len(), MAX_TOPICS));
+        }
+
+        let module = if event.module.is_empty() {
+            format!("{}.{}", MODULE_NAME, contract.code_info.id.as_u64())
+        } else {
+            format!(
+                "{}.{}.{}",
+                MODULE_NAME,
+                contract.code_info.id.as_u64(),
+                event.module,
+            )
+        };
+        ctx.try_emit_tag(
+            ContractEvent {
                 id: contract.instance_info.id,
                 data: event.data,
-            }),
-        ));
+                topics: event.topics,
+            }
+            .into_tag(&module, event.code),
+        )?;
     }
 
     Ok(())
@@ -142,6 +157,9 @@ fn process_subcalls<Cfg: Config, C: TxContext>(
                 };
                 // Calculate how many consensus messages the child call can emit.
                 let remaining_messages = ctx.remaining_messages();
+                // The child call is part of the same enclosing transaction.
+                let tx_signer = tx_signer(ctx);
+                let tx_hash = core::Module::tx_hash(ctx);
 
                 // Execute a transaction in a child context.
                 let (result, gas, tags, messages) = ctx.with_child(ctx.mode(), |mut ctx| {
@@ -167,12 +185,16 @@ fn process_subcalls<Cfg: Config, C: TxContext>(
                                 gas: max_gas,
                                 consensus_messages: remaining_messages,
                             },
+                            features: 0,
                         },
                     };
 
                     let result = ctx.with_tx(0, tx, |mut ctx, call| {
                         // Propagate call depth.
                         ctx.value(CONTEXT_KEY_DEPTH).set(current_depth + 1);
+                        // Propagate enclosing transaction metadata.
+                        ctx.value(CONTEXT_KEY_TX_SIGNER).set(tx_signer);
+                        core::Module::set_tx_hash(&mut ctx, tx_hash);
 
                         // Dispatch the call.
                         let result =
@@ -204,7 +226,7 @@ fn process_subcalls<Cfg: Config, C: TxContext>(
 
                 // Forward any emitted tags.
                 for tag in tags {
-                    ctx.emit_tag(tag);
+                    ctx.try_emit_tag(tag)?;
                 }
 
                 // Forward any emitted runtime messages.
@@ -226,6 +248,8 @@ fn process_subcalls<Cfg: Config, C: TxContext>(
                         };
                         let mut exec_ctx = ExecutionContext {
                             caller_address: ctx.tx_caller_address(),
+                            tx_signer,
+                            tx_hash,
                             gas_limit: core::Module::remaining_tx_gas(ctx),
                             instance_info: contract.instance_info,
                             tx_context: ctx,
@@ -60,6 +60,7 @@ fn upload_hello_contract<C: BatchContext>(ctx: &mut C) -> types::CodeId {
                 gas: 1000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -108,6 +109,7 @@ fn deploy_hello_contract<C: BatchContext>(
                 gas: 1_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -227,6 +229,7 @@ fn test_hello_contract_call() {
                 gas: 1_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -281,7 +284,17 @@ fn test_hello_contract_call() {
         // Make sure a contract event was emitted and is properly formatted.
         assert_eq!(tags.len(), 2, "two events should have been emitted");
         assert_eq!(tags[0].key, b"accounts\x00\x00\x00\x01"); // accounts.Transfer (code = 1) event
-        assert_eq!(tags[1].key, b"contracts.0\x00\x00\x00\x01"); // contracts.1 (code = 1) event
+        assert_eq!(
+            tags[1].key,
+            [
+                &b"contracts.0"[..],
+                &instance_id.as_u64().to_be_bytes(),
+                b"\x00", // No topics.
+                b"\x00\x00\x00\x01",
+            ]
+            .concat(),
+            "contracts.0 (code = 1) event key should include the instance id"
+        );
 
         let event: types::ContractEvent =
             cbor::from_slice(&tags[1].value).expect("contract event should be wrapped");
@@ -319,6 +332,7 @@ fn test_hello_contract_call() {
                 gas: 1_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -382,6 +396,18 @@ fn test_hello_contract_call() {
         .expect("code query should succeed");
     assert_eq!(result.id, 0.into());
     assert_eq!(result.abi, types::ABI::OasisV1);
+    let code_hash = result.hash;
+
+    // Test instance metadata query.
+    let result =
+        Contracts::query_instance_metadata(&mut ctx, types::InstanceQuery { id: instance_id })
+            .expect("instance metadata query should succeed");
+    assert_eq!(result.code_id, 0.into());
+    assert_eq!(result.code_hash, code_hash);
+    assert!(matches!(
+        result.upgrades_policy,
+        types::Policy::Address(address) if address == keys::alice::address()
+    ));
 
     // Test storage query for the counter key.
     let result = Contracts::query_instance_storage(
@@ -424,6 +450,7 @@ fn test_hello_contract_call() {
                 gas: 1_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, invalid_tx.clone(), |mut tx_ctx, call| {
@@ -511,6 +538,7 @@ fn test_hello_contract_subcalls() {
                 gas: 2_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -523,6 +551,157 @@ fn test_hello_contract_subcalls() {
     });
 }
 
+#[test]
+fn test_hello_contract_subcall_gas_limit() {
+    const SUBCALL_MAX_GAS: u64 = 100_000;
+
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<ContractRuntime>(context::Mode::ExecuteTx);
+
+    ContractRuntime::migrate(&mut ctx);
+
+    let instance_id = deploy_hello_contract(&mut ctx, vec![]);
+
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "contracts.Call".to_owned(),
+            body: cbor::to_value(types::Call {
+                id: instance_id,
+                data: cbor::to_vec(cbor::cbor_map! {
+                    "call_self_limited" => cbor::cbor_map! {
+                        "max_gas" => cbor::cbor_int!(SUBCALL_MAX_GAS as i64),
+                    },
+                }),
+                tokens: vec![],
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 2_000_000,
+                consensus_messages: 0,
+            },
+            features: 0,
+        },
+    };
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        // The callee recurses until it runs out of its gas allowance, but the caller survives.
+        let result = Contracts::tx_call(&mut tx_ctx, cbor::from_value(call.body).unwrap())
+            .expect("call should succeed");
+        let result: cbor::Value =
+            cbor::from_slice(&result.0).expect("result should be correctly formatted");
+        assert_eq!(
+            result,
+            cbor::cbor_map! {
+                "hello" => cbor::cbor_map!{
+                    "greeting" => cbor::cbor_text!("subcall failed")
+                }
+            }
+        );
+
+        // The callee should not have drained the caller's gas.
+        assert!(
+            core::Module::remaining_tx_gas(&mut tx_ctx) > 0,
+            "caller should have gas left"
+        );
+    });
+}
+
+#[test]
+fn test_hello_contract_emit_logs() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<ContractRuntime>(context::Mode::ExecuteTx);
+
+    ContractRuntime::migrate(&mut ctx);
+
+    let instance_id = deploy_hello_contract(&mut ctx, vec![]);
+
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "contracts.Call".to_owned(),
+            body: cbor::to_value(types::Call {
+                id: instance_id,
+                data: cbor::to_vec(cbor::cbor_text!("emit_logs")), // Needs to conform to contract API.
+                tokens: vec![],
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1_000_000,
+                consensus_messages: 0,
+            },
+            features: 0,
+        },
+    };
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        Contracts::tx_call(&mut tx_ctx, cbor::from_value(call.body).unwrap())
+            .expect("call should succeed");
+
+        let (tags, _) = tx_ctx.commit();
+        assert_eq!(tags.len(), 3, "three events should have been emitted");
+
+        // Topic hashes should be part of the tag keys.
+        assert_eq!(
+            tags[0].key,
+            [
+                &b"contracts.0.log"[..],
+                &instance_id.as_u64().to_be_bytes(),
+                types::ContractEvent::topic_hash(b"transfer").as_ref(),
+                types::ContractEvent::topic_hash(b"alice").as_ref(),
+                types::ContractEvent::topic_hash(b"bob").as_ref(),
+                b"\x03",             // Number of topics.
+                b"\x00\x00\x00\x03", // Log events use the number of topics as the code.
+            ]
+            .concat(),
+        );
+        let filter = types::EventFilter::for_instance(instance_id)
+            .with_topic(0, b"transfer")
+            .with_topic(1, b"alice")
+            .with_topic(2, b"bob");
+        assert_eq!(filter.key("contracts.0.log", 3), Some(tags[0].key.clone()));
+
+        // Events should be selectable by any of their topics.
+        let data = |filter: types::EventFilter| -> Vec<Vec<u8>> {
+            tags.iter()
+                .filter_map(|tag| filter.apply(&tag.key, &tag.value))
+                .map(|event| event.data)
+                .collect()
+        };
+        assert_eq!(
+            data(types::EventFilter::for_instance(instance_id).with_topic(1, b"alice")),
+            vec![b"100".to_vec(), b"10".to_vec()],
+            "events should be filtered by the second topic"
+        );
+        assert_eq!(
+            data(types::EventFilter::for_instance(instance_id).with_topic(0, b"transfer")),
+            vec![b"100".to_vec(), b"50".to_vec()],
+            "events should be filtered by the first topic"
+        );
+        assert_eq!(
+            data(types::EventFilter::for_instance(instance_id).with_topic(2, b"alice")),
+            vec![b"50".to_vec()],
+            "events should be filtered by the third topic"
+        );
+        assert!(
+            data(types::EventFilter::for_instance(42.into()).with_topic(1, b"alice")).is_empty(),
+            "events of other instances should not match"
+        );
+    });
+}
+
 #[test]
 fn test_hello_contract_query() {
     let mut mock = mock::Mock::default();
@@ -560,6 +739,7 @@ fn test_hello_contract_query() {
                 gas: 1_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -600,6 +780,7 @@ fn test_hello_contract_query() {
                 gas: 1_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -640,6 +821,7 @@ fn test_hello_contract_query() {
                 gas: 1_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -692,6 +874,7 @@ fn test_hello_contract_upgrade() {
                 gas: 2_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -735,6 +918,7 @@ fn test_hello_contract_upgrade_fail_policy() {
                 gas: 2_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -780,6 +964,7 @@ fn test_hello_contract_upgrade_fail_pre() {
                 gas: 2_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -828,6 +1013,7 @@ fn test_hello_contract_upgrade_fail_post() {
                 gas: 2_000_000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
@@ -1,8 +1,9 @@
 //! Contracts module types.
+use oasis_contract_sdk_types::event::MAX_TOPICS;
 pub use oasis_contract_sdk_types::{CodeId, InstanceId};
 use oasis_runtime_sdk::{
     context::TxContext,
-    core::common::crypto::hash::Hash,
+    core::{common::crypto::hash::Hash, transaction::tags::Tag},
     types::{address::Address, token},
 };
 
@@ -80,6 +81,19 @@ pub struct Instance {
     pub upgrades_policy: Policy,
 }
 
+/// Deployed code instance metadata.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct InstanceMetadata {
+    /// Identifier of code used by the instance. This changes whenever the instance is upgraded.
+    pub code_id: CodeId,
+
+    /// Hash of code used by the instance.
+    pub code_hash: Hash,
+
+    /// Who is allowed to upgrade this instance.
+    pub upgrades_policy: Policy,
+}
+
 impl Instance {
     /// Address associated with a specific contract instance.
     pub fn address_for(id: InstanceId) -> Address {
@@ -252,6 +266,138 @@ pub struct ContractEvent {
     /// Raw event data emitted by the instance.
     #[cbor(optional, default, skip_serializing_if = "Vec::is_empty")]
     pub data: Vec<u8>,
+    /// Indexed topics of the event.
+    #[cbor(optional, default, skip_serializing_if = "Vec::is_empty")]
+    pub topics: Vec<Vec<u8>>,
+}
+
+/// Size of the topic hashes included in contract event tag keys.
+const TOPIC_HASH_SIZE: usize = 32;
+
+impl ContractEvent {
+    /// Hash of an event topic, as included in the tag key of the event.
+    pub fn topic_hash(topic: &[u8]) -> Hash {
+        Hash::digest_bytes(topic)
+    }
+
+    /// Converts an emitted contract event into a tag that can be emitted by the runtime.
+    ///
+    /// The identifier of the emitting instance and the hashes of the event topics are included in
+    /// the key so that subscribers can filter events by instance and by the topic at any position.
+    /// As the key still ends with the event code, it can be parsed like any other event key, with
+    /// the additional fields being part of the module name.
+    ///
+    /// The event must have at most [`MAX_TOPICS`] topics.
+    ///
+    /// # Key
+    ///
+    /// ```text
+    /// <module (variable size bytes)> <instance-id (big-endian u64)>
+    ///     <topic-hash (32 bytes)>... <topic-count (u8)> <code (big-endian u32)>
+    /// ```
+    ///
+    /// # Value
+    ///
+    /// CBOR-serialized contract event.
+    ///
+    pub fn into_tag(self, module: &str, code: u32) -> Tag {
+        assert!(self.topics.len() <= MAX_TOPICS, "too many event topics");
+
+        let mut key = [module.as_bytes(), &self.id.as_u64().to_be_bytes()].concat();
+        for topic in &self.topics {
+            key.extend_from_slice(Self::topic_hash(topic).as_ref());
+        }
+        key.push(self.topics.len() as u8);
+        key.extend_from_slice(&code.to_be_bytes());
+
+        Tag::new(key, cbor::to_vec(self))
+    }
+}
+
+/// Filter selecting the events emitted by a specific contract instance.
+///
+/// Contract events are emitted under the `contracts.<code-id>` module (optionally followed by the
+/// name of the contract's module) with the identifier of the emitting instance and the hashes of
+/// the event topics being part of the tag key (see [`ContractEvent::into_tag`]).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventFilter {
+    /// Identifier of the instance whose events are selected.
+    pub instance_id: InstanceId,
+    /// Topics that selected events must have at the corresponding positions. Positions without a
+    /// topic match any topic.
+    pub topics: Vec<Option<Vec<u8>>>,
+}
+
+impl EventFilter {
+    /// Create a filter selecting the events emitted by the given instance.
+    pub fn for_instance(instance_id: InstanceId) -> Self {
+        Self {
+            instance_id,
+            topics: Vec::new(),
+        }
+    }
+
+    /// Additionally require the topic at the given position to match.
+    pub fn with_topic(mut self, index: usize, topic: &[u8]) -> Self {
+        if self.topics.len() <= index {
+            self.topics.resize(index + 1, None);
+        }
+        self.topics[index] = Some(topic.to_vec());
+        self
+    }
+
+    /// Tag key of the events with the given module and code emitted by the filtered instance with
+    /// exactly the filtered topics, suitable for filtering events by key.
+    ///
+    /// Returns `None` in case the filter matches any topic at some position, as such events can
+    /// only be selected by comparing the topic hashes at the other positions of the key.
+    pub fn key(&self, module: &str, code: u32) -> Option<Vec<u8>> {
+        let topics = self.topics.iter().cloned().collect::<Option<Vec<_>>>()?;
+        let event = ContractEvent {
+            id: self.instance_id,
+            data: Vec::new(),
+            topics,
+        };
+        Some(event.into_tag(module, code).key)
+    }
+
+    /// Decode the event with the given tag key and value in case it has been emitted by the
+    /// filtered instance and its topics match.
+    pub fn apply(&self, key: &[u8], value: &[u8]) -> Option<ContractEvent> {
+        // Parse the key from the end as the module name is of variable size.
+        let (key, _) = key.split_at(key.len().checked_sub(4)?);
+        let (topic_count, key) = key.split_last()?;
+        let topic_count = usize::from(*topic_count);
+        if topic_count > MAX_TOPICS {
+            return None;
+        }
+        let (key, topic_hashes) =
+            key.split_at(key.len().checked_sub(topic_count * TOPIC_HASH_SIZE)?);
+        let module = key.strip_suffix(&self.instance_id.as_u64().to_be_bytes())?;
+        let module = std::str::from_utf8(module).ok()?;
+        if !module
+            .strip_prefix(MODULE_NAME)
+            .map_or(false, |rest| rest.starts_with('.'))
+        {
+            return None;
+        }
+
+        // Topics are matched by position against their hashes in the key.
+        for (index, topic) in self.topics.iter().enumerate() {
+            if let Some(topic) = topic {
+                let hash = topic_hashes.chunks(TOPIC_HASH_SIZE).nth(index)?;
+                if hash != ContractEvent::topic_hash(topic).as_ref() {
+                    return None;
+                }
+            }
+        }
+
+        let event: ContractEvent = cbor::from_slice(value).ok()?;
+        if event.id != self.instance_id {
+            return None;
+        }
+        Some(event)
+    }
 }
 
 #[cfg(test)]
@@ -288,4 +434,79 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_event_filter() {
+        use oasis_runtime_sdk::event::tag_for_event;
+
+        let contract_tag = |module: &str, id: u64| {
+            ContractEvent {
+                id: id.into(),
+                data: b"data".to_vec(),
+                topics: vec![b"transfer".to_vec(), b"alice".to_vec()],
+            }
+            .into_tag(module, 1)
+        };
+        let filter = EventFilter::for_instance(1.into());
+
+        // The instance identifier and the topic hashes should be part of the tag key.
+        let tag = contract_tag("contracts.0", 1);
+        assert_eq!(
+            tag.key,
+            [
+                &b"contracts.0\x00\x00\x00\x00\x00\x00\x00\x01"[..],
+                ContractEvent::topic_hash(b"transfer").as_ref(),
+                ContractEvent::topic_hash(b"alice").as_ref(),
+                b"\x02\x00\x00\x00\x01",
+            ]
+            .concat()
+        );
+        let full_filter = EventFilter::for_instance(1.into())
+            .with_topic(0, b"transfer")
+            .with_topic(1, b"alice");
+        assert_eq!(full_filter.key("contracts.0", 1), Some(tag.key.clone()));
+        assert_eq!(
+            filter.key("contracts.0", 1),
+            Some(b"contracts.0\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x01".to_vec()),
+            "key of events without topics should be returned"
+        );
+        assert_eq!(
+            EventFilter::for_instance(1.into())
+                .with_topic(1, b"alice")
+                .key("contracts.0", 1),
+            None,
+            "key should not be available when matching any topic"
+        );
+
+        // Events emitted by the filtered instance should match.
+        for module in ["contracts.0", "contracts.0.mymodule"].iter() {
+            let tag = contract_tag(module, 1);
+            let event = filter
+                .apply(&tag.key, &tag.value)
+                .expect("event emitted by instance should match");
+            assert_eq!(event.id, 1.into());
+            assert_eq!(event.data, b"data");
+        }
+
+        // Events emitted by other instances or modules should not match.
+        let tag = contract_tag("contracts.0", 2);
+        assert!(filter.apply(&tag.key, &tag.value).is_none());
+        // Keys not including the instance identifier should not match either.
+        let mut tag = contract_tag("contracts.0", 1);
+        tag.key = tag_for_event("contracts.0", 1, Vec::new()).key;
+        assert!(filter.apply(&tag.key, &tag.value).is_none());
+        let tag = tag_for_event("accounts", 1, b"data".to_vec());
+        assert!(filter.apply(&tag.key, &tag.value).is_none());
+        let tag = contract_tag("contractsfoo.0", 1);
+        assert!(filter.apply(&tag.key, &tag.value).is_none());
+
+        // Topics should be matched by position.
+        let tag = contract_tag("contracts.0", 1);
+        let filter = EventFilter::for_instance(1.into()).with_topic(1, b"alice");
+        assert!(filter.apply(&tag.key, &tag.value).is_some());
+        let filter = EventFilter::for_instance(1.into()).with_topic(1, b"bob");
+        assert!(filter.apply(&tag.key, &tag.value).is_none());
+        let filter = EventFilter::for_instance(1.into()).with_topic(2, b"alice");
+        assert!(filter.apply(&tag.key, &tag.value).is_none());
+    }
 }
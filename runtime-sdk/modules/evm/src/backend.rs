@@ -35,6 +35,11 @@ impl<'ctx, C: Context, Cfg: Config> Backend<'ctx, C, Cfg> {
             _cfg: PhantomData,
         }
     }
+
+    /// Number of events that can still be emitted by the current transaction.
+    pub fn remaining_events(&self) -> u32 {
+        self.ctx.borrow().remaining_events()
+    }
 }
 
 impl<'ctx, C: Context, Cfg: Config> EVMBackend for Backend<'ctx, C, Cfg> {
@@ -459,6 +459,7 @@ impl<Cfg: Config> API for Module<Cfg> {
                         gas: gas_limit,
                         consensus_messages: 0,
                     },
+                    features: 0,
                 },
             };
             sctx.with_tx(0, call_tx, |mut txctx, _call| {
@@ -557,6 +558,10 @@ impl<Cfg: Config> Module<Cfg> {
             .ok_or(Error::InsufficientBalance)?;
 
         let (vals, logs) = executor.into_state().deconstruct();
+        let logs: Vec<_> = logs.into_iter().collect();
+        if logs.len() > backend.remaining_events() as usize {
+            return Err(CoreError::TooManyEvents.into());
+        }
         backend.apply(vals, logs, true);
 
         core::Module::use_tx_gas(ctx, gas_used)?;
@@ -642,6 +647,14 @@ impl<Cfg: Config> module::MethodHandler for Module<Cfg> {
             _ => module::DispatchResult::Unhandled(args),
         }
     }
+
+    fn register_error_codes(registry: &mut core::types::ErrorRegistry) {
+        module::register_error_codes::<Self>(registry)
+    }
+
+    fn register_capabilities(capabilities: &mut core::types::Capabilities) {
+        capabilities.insert("evm".to_owned());
+    }
 }
 
 impl<Cfg: Config> Module<Cfg> {
@@ -712,4 +725,5 @@ impl<Cfg: Config> module::BlockHandler for Module<Cfg> {
     }
 }
 
+impl<Cfg: Config> module::EventHandler for Module<Cfg> {}
 impl<Cfg: Config> module::InvariantHandler for Module<Cfg> {}
@@ -179,6 +179,7 @@ fn decode_enveloped(
                 gas: gas_limit,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     })
 }
@@ -150,6 +150,7 @@ fn test_evm_calls() {
                 gas: 1000000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     // Run authentication handler to simulate nonce increments.
@@ -191,6 +192,7 @@ fn test_evm_calls() {
                 gas: 25000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     // Run authentication handler to simulate nonce increments.
@@ -282,6 +284,7 @@ fn test_evm_runtime() {
                 gas: 1000000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     // Run authentication handler to simulate nonce increments.
@@ -321,6 +324,7 @@ fn test_evm_runtime() {
                 gas: 10, // Not enough gas.
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     // Run authentication handler to simulate nonce increments.
@@ -368,6 +372,7 @@ fn test_evm_runtime() {
                 gas: 25000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     // Run authentication handler to simulate nonce increments.
@@ -435,6 +440,7 @@ fn test_evm_runtime() {
                 gas: 64000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     // Run authentication handler to simulate nonce increments.
@@ -477,6 +483,7 @@ fn test_evm_runtime() {
                 gas: 10, // Not enough gas.
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
     <EVMRuntime as Runtime>::Modules::authenticate_tx(&mut ctx, &out_of_gas_tx).unwrap();
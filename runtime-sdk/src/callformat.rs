@@ -62,9 +62,7 @@ pub fn decode_call<C: Context>(
                     .map_err(|_| Error::InvalidCallFormat(anyhow!("bad envelope")))?;
 
             // Make sure a key manager is available in this runtime.
-            let key_manager = ctx.key_manager().ok_or_else(|| {
-                Error::InvalidCallFormat(anyhow!("confidential transactions not available"))
-            })?;
+            let key_manager = ctx.key_manager().ok_or(Error::KeyManagerUnavailable)?;
 
             // If we are only doing checks, this is the most that we can do as in this case we may
             // be unable to access the key manager.
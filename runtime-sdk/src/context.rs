@@ -2,6 +2,7 @@
 use std::{
     any::Any,
     collections::btree_map::{BTreeMap, Entry},
+    convert::TryInto,
     fmt,
     marker::PhantomData,
     sync::Arc,
@@ -25,9 +26,10 @@ use oasis_core_runtime::{
 use crate::{
     event::Event,
     keymanager::KeyManagerClientWithContext,
-    modules::core::Error,
+    modules::{self, core::Error},
     runtime,
     storage::{self, NestedStore, Store},
+    trace,
     types::{address::Address, message::MessageEventHookInvocation, transaction},
 };
 
@@ -59,6 +61,30 @@ impl From<&Mode> for &'static str {
     }
 }
 
+/// How a transaction is being processed, from the point of view of a handler.
+///
+/// Handlers should branch on this instead of combining the individual mode predicates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// The transaction is only being checked for validity. Handlers should perform any checks
+    /// and charge gas, but should skip effects.
+    Check,
+    /// The transaction is being simulated. Effects are performed, but are discarded afterwards.
+    Simulate,
+    /// The transaction is being executed and its effects are persisted.
+    Execute,
+}
+
+impl From<Mode> for ExecutionMode {
+    fn from(m: Mode) -> Self {
+        match m {
+            Mode::CheckTx => ExecutionMode::Check,
+            Mode::SimulateTx => ExecutionMode::Simulate,
+            Mode::ExecuteTx => ExecutionMode::Execute,
+        }
+    }
+}
+
 /// Local configuration key the value of which determines whether expensive queries should be
 /// allowed or not.
 const LOCAL_CONFIG_ALLOW_EXPENSIVE_QUERIES: &str = "allow_expensive_queries";
@@ -76,14 +102,19 @@ pub trait Context {
     /// Context mode.
     fn mode(&self) -> Mode;
 
+    /// Execution mode that handlers should branch on.
+    fn execution_mode(&self) -> ExecutionMode {
+        self.mode().into()
+    }
+
     /// Whether the transaction is just being checked for validity.
     fn is_check_only(&self) -> bool {
-        self.mode() == Mode::CheckTx
+        self.execution_mode() == ExecutionMode::Check
     }
 
     /// Whether the transaction is just being simulated.
     fn is_simulation(&self) -> bool {
-        self.mode() == Mode::SimulateTx
+        self.execution_mode() == ExecutionMode::Simulate
     }
 
     /// Whether expensive queries are allowed based on local configuration.
@@ -143,12 +174,51 @@ pub trait Context {
     /// Current epoch.
     fn epoch(&self) -> consensus::beacon::EpochTime;
 
+    /// Deterministic pseudo-time computed as the median of recent block timestamps.
+    ///
+    /// Unlike the timestamp of a single block, a single proposer cannot skew it arbitrarily. In
+    /// case no timestamps have been recorded yet, the last runtime block timestamp is returned.
+    fn smoothed_time(&mut self) -> u64 {
+        modules::core::Module::smoothed_time(self.runtime_state())
+            .unwrap_or_else(|| self.runtime_header().timestamp)
+    }
+
     /// Emits an event by transforming it into a tag and emitting a tag.
-    fn emit_event<E: Event>(&mut self, event: E);
+    fn emit_event<E: Event>(&mut self, event: E) {
+        self.emit_tag(event.into_tag());
+    }
 
     /// Emits a tag.
+    ///
+    /// Tags emitted this way count towards the maximum number of events that can be emitted in
+    /// this context, but are emitted even in case the limit has been reached. Events emitted on
+    /// behalf of a transaction should use `try_emit_event` instead.
     fn emit_tag(&mut self, tag: Tag);
 
+    /// Emits an event by transforming it into a tag and emitting a tag, returning an error in case
+    /// the maximum number of events that can be emitted in this context has been reached.
+    fn try_emit_event<E: Event>(&mut self, event: E) -> Result<(), Error> {
+        self.try_emit_tag(event.into_tag())
+    }
+
+    /// Emits a tag, returning an error in case the maximum number of events that can be emitted in
+    /// this context has been reached.
+    fn try_emit_tag(&mut self, tag: Tag) -> Result<(), Error> {
+        if self.remaining_events() == 0 {
+            return Err(Error::TooManyEvents);
+        }
+        self.emit_tag(tag);
+        Ok(())
+    }
+
+    /// Number of events that can still be emitted.
+    fn remaining_events(&self) -> u32;
+
+    /// Set an upper limit on the number of events that can be emitted in this context. Note that
+    /// the limit can only be decreased and calling this function will return an error in case the
+    /// passed `max_events` is higher than the current limit.
+    fn limit_max_events(&mut self, max_events: u32) -> Result<(), Error>;
+
     /// Returns a child io_ctx.
     fn io_ctx(&self) -> IoContext;
 
@@ -162,6 +232,9 @@ pub trait Context {
     /// Number of consensus messages that can still be emitted.
     fn remaining_messages(&self) -> u32;
 
+    /// Tracer recording the operations performed in this context, if tracing is enabled.
+    fn tracer(&self) -> Option<&trace::Tracer>;
+
     /// Set an upper limit on the number of consensus messages that can be emitted in this context.
     /// Note that the limit can only be decreased and calling this function will return an error
     /// in case the passed `max_messages` is higher than the current limit.
@@ -188,6 +261,23 @@ pub trait Context {
     {
         self.with_child(Mode::SimulateTx, f)
     }
+
+    /// Executes a function in a simulation context with tracing enabled.
+    ///
+    /// State accesses, gas usage and emitted events and messages of any transactions executed in
+    /// the simulation context are recorded by the given tracer. Operations performed in nested
+    /// child contexts are only recorded once they are propagated to the traced transaction.
+    fn with_tracing<F, Rs>(&mut self, tracer: trace::Tracer, f: F) -> Rs
+    where
+        F: FnOnce(
+            RuntimeBatchContext<'_, Self::Runtime, storage::OverlayStore<&mut dyn Store>>,
+        ) -> Rs,
+    {
+        self.with_simulation(|mut ctx| {
+            ctx.tracer = Some(tracer);
+            f(ctx)
+        })
+    }
 }
 
 /// Runtime SDK batch-wide context.
@@ -215,6 +305,9 @@ pub trait TxContext: Context {
     /// Transaction authentication information.
     fn tx_auth_info(&self) -> &transaction::AuthInfo;
 
+    /// Format of the transaction's call as submitted, before the call has been decoded.
+    fn tx_call_format(&self) -> transaction::CallFormat;
+
     /// Authenticated address of the caller.
     ///
     /// In case there are multiple signers of a transaction, this will return the address
@@ -250,6 +343,8 @@ pub struct RuntimeBatchContext<'a, R: runtime::Runtime, S: NestedStore> {
     logger: slog::Logger,
 
     block_tags: Tags,
+    /// Maximum number of events that can be emitted.
+    max_events: u32,
 
     /// Maximum number of messages that can be emitted.
     max_messages: u32,
@@ -259,6 +354,9 @@ pub struct RuntimeBatchContext<'a, R: runtime::Runtime, S: NestedStore> {
     /// Per-context values.
     values: BTreeMap<&'static str, Box<dyn Any>>,
 
+    /// Execution tracer.
+    tracer: Option<trace::Tracer>,
+
     _runtime: PhantomData<R>,
 }
 
@@ -290,9 +388,11 @@ impl<'a, R: runtime::Runtime, S: NestedStore> RuntimeBatchContext<'a, R, S> {
             logger: get_logger("runtime-sdk")
                 .new(o!("ctx" => "dispatch", "mode" => Into::<&'static str>::into(&mode))),
             block_tags: Tags::new(),
+            max_events: u32::MAX,
             max_messages,
             messages: Vec::new(),
             values: BTreeMap::new(),
+            tracer: None,
             _runtime: PhantomData,
         }
     }
@@ -321,12 +421,75 @@ impl<'a, R: runtime::Runtime, S: NestedStore> RuntimeBatchContext<'a, R, S> {
             logger: get_logger("runtime-sdk")
                 .new(o!("ctx" => "dispatch", "mode" => Into::<&'static str>::into(&mode))),
             block_tags: Tags::new(),
+            max_events: u32::MAX,
             max_messages: ctx.max_messages,
             messages: Vec::new(),
             values: BTreeMap::new(),
+            tracer: None,
             _runtime: PhantomData,
         }
     }
+
+    /// Run the given function in a child context whose state changes are recorded instead of
+    /// being applied to this context.
+    ///
+    /// The child context starts without any per-context values. Its state changes, emitted
+    /// tags and messages and any values remaining in it are returned for later application via
+    /// `apply_speculation`.
+    pub(crate) fn with_speculation<F, Rs>(&mut self, f: F) -> (Rs, Speculation)
+    where
+        F: FnOnce(&mut RuntimeBatchContext<'_, R, storage::SpeculativeStore<&mut dyn Store>>) -> Rs,
+    {
+        let remaining_messages = self.remaining_messages();
+        let remaining_events = self.remaining_events();
+        let store = storage::SpeculativeStore::new((&mut self.runtime_storage) as &mut dyn Store);
+
+        let mut child_ctx = RuntimeBatchContext {
+            mode: self.mode,
+            host_info: self.host_info,
+            key_manager: self.key_manager.clone(),
+            runtime_header: self.runtime_header,
+            runtime_round_results: self.runtime_round_results,
+            runtime_storage: store,
+            consensus_state: self.consensus_state,
+            epoch: self.epoch,
+            io_ctx: self.io_ctx.clone(),
+            logger: self.logger.clone(),
+            block_tags: Tags::new(),
+            max_events: remaining_events,
+            max_messages: remaining_messages,
+            messages: Vec::new(),
+            values: BTreeMap::new(),
+            tracer: None,
+            _runtime: PhantomData,
+        };
+        let result = f(&mut child_ctx);
+
+        let speculation = Speculation {
+            changes: child_ctx.runtime_storage.changes(),
+            block_tags: child_ctx.block_tags,
+            messages: child_ctx.messages,
+            values: child_ctx.values,
+        };
+        (result, speculation)
+    }
+
+    /// Apply the outcome of a speculation to this context as if the speculated function has been
+    /// run in this context directly.
+    ///
+    /// This is only equivalent in case nothing that the speculated function depends on has
+    /// changed since the speculation, including the remaining event and message slots.
+    pub(crate) fn apply_speculation(&mut self, speculation: Speculation) {
+        speculation.changes.apply(&mut self.runtime_storage);
+        self.block_tags.extend(speculation.block_tags);
+        self.messages.extend(speculation.messages);
+        self.values.extend(speculation.values);
+    }
+
+    /// Whether any per-context values are set.
+    pub(crate) fn has_values(&self) -> bool {
+        !self.values.is_empty()
+    }
 }
 
 impl<'a, R: runtime::Runtime, S: NestedStore> Context for RuntimeBatchContext<'a, R, S> {
@@ -369,14 +532,31 @@ impl<'a, R: runtime::Runtime, S: NestedStore> Context for RuntimeBatchContext<'a
         self.epoch
     }
 
-    fn emit_event<E: Event>(&mut self, event: E) {
-        self.block_tags.push(event.into_tag());
-    }
-
     fn emit_tag(&mut self, tag: Tag) {
+        if let Some(tracer) = &self.tracer {
+            tracer.record(trace::Operation::EmitEvent {
+                key: tag.key.clone(),
+                value: tag.value.clone(),
+            });
+        }
+
         self.block_tags.push(tag);
     }
 
+    fn remaining_events(&self) -> u32 {
+        self.max_events
+            .saturating_sub(self.block_tags.len().try_into().unwrap_or(u32::MAX))
+    }
+
+    fn limit_max_events(&mut self, max_events: u32) -> Result<(), Error> {
+        if max_events > self.max_events {
+            return Err(Error::TooManyEvents);
+        }
+
+        self.max_events = max_events;
+        Ok(())
+    }
+
     fn io_ctx(&self) -> IoContext {
         IoContext::create_child(&self.io_ctx)
     }
@@ -394,6 +574,10 @@ impl<'a, R: runtime::Runtime, S: NestedStore> Context for RuntimeBatchContext<'a
         self.max_messages.saturating_sub(self.messages.len() as u32)
     }
 
+    fn tracer(&self) -> Option<&trace::Tracer> {
+        self.tracer.as_ref()
+    }
+
     fn limit_max_messages(&mut self, max_messages: u32) -> Result<(), Error> {
         if max_messages > self.max_messages {
             return Err(Error::OutOfMessageSlots);
@@ -410,6 +594,7 @@ impl<'a, R: runtime::Runtime, S: NestedStore> Context for RuntimeBatchContext<'a
         ) -> Rs,
     {
         let remaining_messages = self.remaining_messages();
+        let remaining_events = self.remaining_events();
         // Create a store wrapped by an overlay store so any state changes don't leak.
         let store = storage::OverlayStore::new((&mut self.runtime_storage) as &mut dyn Store);
 
@@ -427,12 +612,17 @@ impl<'a, R: runtime::Runtime, S: NestedStore> Context for RuntimeBatchContext<'a
                 .logger
                 .new(o!("ctx" => "dispatch", "mode" => Into::<&'static str>::into(&mode))),
             block_tags: Tags::new(),
+            max_events: match mode {
+                Mode::SimulateTx => self.max_events,
+                _ => remaining_events,
+            },
             max_messages: match mode {
                 Mode::SimulateTx => self.max_messages,
                 _ => remaining_messages,
             },
             messages: Vec::new(),
             values: BTreeMap::new(),
+            tracer: None,
             _runtime: PhantomData,
         };
         f(child_ctx)
@@ -449,7 +639,10 @@ impl<'a, R: runtime::Runtime, S: NestedStore> BatchContext for RuntimeBatchConte
     {
         let remaining_messages = self.remaining_messages();
         // Create a store wrapped by an overlay store so we can either rollback or commit.
-        let store = storage::OverlayStore::new(&mut self.runtime_storage);
+        let mut store = storage::OverlayStore::new(&mut self.runtime_storage);
+        if let Some(tracer) = &self.tracer {
+            store.set_tracer(tracer.clone());
+        }
 
         let tx_ctx = RuntimeTxContext {
             mode: self.mode,
@@ -466,11 +659,14 @@ impl<'a, R: runtime::Runtime, S: NestedStore> BatchContext for RuntimeBatchConte
                 .new(o!("ctx" => "transaction", "mode" => Into::<&'static str>::into(&self.mode))),
             tx_size,
             tx_auth_info: tx.auth_info,
+            tx_call_format: tx.call.format,
             tags: Tags::new(),
+            max_events: u32::MAX,
             max_messages: remaining_messages,
             messages: Vec::new(),
             values: &mut self.values,
             tx_values: BTreeMap::new(),
+            tracer: self.tracer.clone(),
             _runtime: PhantomData,
         };
         f(tx_ctx, tx.call)
@@ -490,6 +686,18 @@ impl<'a, R: runtime::Runtime, S: NestedStore> BatchContext for RuntimeBatchConte
     }
 }
 
+/// Outcome of a function run in a speculative child context.
+pub(crate) struct Speculation {
+    /// State changes together with the keys read from the parent state.
+    pub(crate) changes: storage::SpeculativeChanges,
+    /// Emitted tags.
+    block_tags: Tags,
+    /// Emitted messages.
+    messages: Vec<(roothash::Message, MessageEventHookInvocation)>,
+    /// Per-context values remaining in the child context.
+    values: BTreeMap<&'static str, Box<dyn Any>>,
+}
+
 /// Per-transaction/method dispatch sub-context.
 pub struct RuntimeTxContext<'round, 'store, R: runtime::Runtime, S: Store> {
     mode: Mode,
@@ -509,9 +717,13 @@ pub struct RuntimeTxContext<'round, 'store, R: runtime::Runtime, S: Store> {
     tx_size: u32,
     /// Transaction authentication info.
     tx_auth_info: transaction::AuthInfo,
+    /// Transaction call format.
+    tx_call_format: transaction::CallFormat,
 
     /// Emitted tags.
     tags: Tags,
+    /// Maximum number of events that can be emitted.
+    max_events: u32,
 
     /// Maximum number of messages that can be emitted.
     max_messages: u32,
@@ -524,6 +736,9 @@ pub struct RuntimeTxContext<'round, 'store, R: runtime::Runtime, S: Store> {
     /// Per-transaction values.
     tx_values: BTreeMap<&'static str, Box<dyn Any>>,
 
+    /// Execution tracer.
+    tracer: Option<trace::Tracer>,
+
     _runtime: PhantomData<R>,
 }
 
@@ -569,14 +784,31 @@ impl<'round, 'store, R: runtime::Runtime, S: Store> Context
         self.epoch
     }
 
-    fn emit_event<E: Event>(&mut self, event: E) {
-        self.tags.push(event.into_tag());
-    }
-
     fn emit_tag(&mut self, tag: Tag) {
+        if let Some(tracer) = &self.tracer {
+            tracer.record(trace::Operation::EmitEvent {
+                key: tag.key.clone(),
+                value: tag.value.clone(),
+            });
+        }
+
         self.tags.push(tag);
     }
 
+    fn remaining_events(&self) -> u32 {
+        self.max_events
+            .saturating_sub(self.tags.len().try_into().unwrap_or(u32::MAX))
+    }
+
+    fn limit_max_events(&mut self, max_events: u32) -> Result<(), Error> {
+        if max_events > self.max_events {
+            return Err(Error::TooManyEvents);
+        }
+
+        self.max_events = max_events;
+        Ok(())
+    }
+
     fn io_ctx(&self) -> IoContext {
         IoContext::create_child(&self.io_ctx)
     }
@@ -594,6 +826,10 @@ impl<'round, 'store, R: runtime::Runtime, S: Store> Context
         self.max_messages.saturating_sub(self.messages.len() as u32)
     }
 
+    fn tracer(&self) -> Option<&trace::Tracer> {
+        self.tracer.as_ref()
+    }
+
     fn limit_max_messages(&mut self, max_messages: u32) -> Result<(), Error> {
         if max_messages > self.max_messages {
             return Err(Error::OutOfMessageSlots);
@@ -610,6 +846,7 @@ impl<'round, 'store, R: runtime::Runtime, S: Store> Context
         ) -> Rs,
     {
         let remaining_messages = self.remaining_messages();
+        let remaining_events = self.remaining_events();
         // Create a store wrapped by an overlay store so any state changes don't leak.
         let store = storage::OverlayStore::new((&mut self.store) as &mut dyn Store);
 
@@ -627,12 +864,17 @@ impl<'round, 'store, R: runtime::Runtime, S: Store> Context
                 .logger
                 .new(o!("ctx" => "dispatch", "mode" => Into::<&'static str>::into(&mode))),
             block_tags: Tags::new(),
+            max_events: match mode {
+                Mode::SimulateTx => self.max_events,
+                _ => remaining_events,
+            },
             max_messages: match mode {
                 Mode::SimulateTx => self.max_messages,
                 _ => remaining_messages,
             },
             messages: Vec::new(),
             values: BTreeMap::new(),
+            tracer: None,
             _runtime: PhantomData,
         };
         f(child_ctx)
@@ -648,6 +890,10 @@ impl<R: runtime::Runtime, S: Store> TxContext for RuntimeTxContext<'_, '_, R, S>
         &self.tx_auth_info
     }
 
+    fn tx_call_format(&self) -> transaction::CallFormat {
+        self.tx_call_format
+    }
+
     fn tx_value<V: Any>(&mut self, key: &'static str) -> ContextValue<'_, V> {
         ContextValue::new(self.tx_values.entry(key))
     }
@@ -662,6 +908,12 @@ impl<R: runtime::Runtime, S: Store> TxContext for RuntimeTxContext<'_, '_, R, S>
             return Err(Error::OutOfMessageSlots);
         }
 
+        if let Some(tracer) = &self.tracer {
+            tracer.record(trace::Operation::EmitMessage {
+                message: msg.clone(),
+            });
+        }
+
         self.messages.push((msg, hook));
 
         Ok(())
@@ -791,6 +1043,35 @@ mod test {
         assert_eq!(y, None);
     }
 
+    #[test]
+    fn test_execution_mode() {
+        let mut mock = Mock::default();
+
+        {
+            let ctx = mock.create_ctx_for_runtime::<mock::EmptyRuntime>(Mode::CheckTx);
+            assert_eq!(ctx.execution_mode(), ExecutionMode::Check);
+            assert!(ctx.is_check_only());
+            assert!(!ctx.is_simulation());
+        }
+
+        {
+            let ctx = mock.create_ctx_for_runtime::<mock::EmptyRuntime>(Mode::SimulateTx);
+            assert_eq!(ctx.execution_mode(), ExecutionMode::Simulate);
+            assert!(!ctx.is_check_only());
+            assert!(ctx.is_simulation());
+        }
+
+        let mut ctx = mock.create_ctx_for_runtime::<mock::EmptyRuntime>(Mode::ExecuteTx);
+        assert_eq!(ctx.execution_mode(), ExecutionMode::Execute);
+        assert!(!ctx.is_check_only());
+        assert!(!ctx.is_simulation());
+
+        // Simulations are always in simulation mode, regardless of the parent context.
+        ctx.with_simulation(|sim_ctx| {
+            assert_eq!(sim_ctx.execution_mode(), ExecutionMode::Simulate);
+        });
+    }
+
     #[test]
     #[should_panic]
     fn test_value_type_change() {
@@ -822,6 +1103,7 @@ mod test {
                     gas: 1000,
                     consensus_messages: 0,
                 },
+                features: 0,
             },
         };
         ctx.with_tx(0, tx.clone(), |mut tx_ctx, _call| {
@@ -883,6 +1165,7 @@ mod test {
                     gas: 1000,
                     consensus_messages: 0,
                 },
+                features: 0,
             },
         };
         ctx.with_tx(0, tx, |mut tx_ctx, _call| {
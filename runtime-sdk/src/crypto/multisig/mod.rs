@@ -74,6 +74,10 @@ impl Config {
 
     /// Checks that the configuration and signature set are acceptable.
     /// Returns vectors of public keys and signatures for batch verification of included signatures.
+    ///
+    /// Only the weights of signers that actually provided a signature count towards the threshold.
+    /// As each key can only appear once in a valid configuration, a key can never be counted more
+    /// than once.
     pub fn batch(
         &self,
         signature_set: &SignatureSet,
@@ -82,12 +86,16 @@ impl Config {
         if signature_set.len() != self.signers.len() {
             return Err(Error::InvalidSignatureSet);
         }
-        let mut total = 0;
+        let mut total: u64 = 0;
         let mut public_keys = vec![];
         let mut signatures = vec![];
         for (signer, signature_o) in self.signers.iter().zip(signature_set.iter()) {
             if let Some(signature) = signature_o {
-                total += signer.weight;
+                // As validate_basic rejects configurations whose total weight overflows, this can
+                // not overflow. Should it nevertheless, the configuration is rejected as invalid.
+                total = total
+                    .checked_add(signer.weight)
+                    .ok_or(Error::InvalidConfig)?;
                 public_keys.push(signer.public_key.clone());
                 signatures.push(signature.clone());
             }
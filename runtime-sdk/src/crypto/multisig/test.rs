@@ -1,6 +1,6 @@
 use crate::{crypto::signature::Signature, testing::keys};
 
-use super::{Config, Signer};
+use super::{Config, Error, Signer};
 
 #[test]
 fn test_config_validate_basic() {
@@ -159,3 +159,114 @@ fn test_config_batch() {
         ])
         .expect_err("too many signature slots");
 }
+
+#[test]
+fn test_config_batch_threshold() {
+    let config = Config {
+        signers: vec![
+            Signer {
+                public_key: keys::alice::pk(),
+                weight: 2,
+            },
+            Signer {
+                public_key: keys::bob::pk(),
+                weight: 3,
+            },
+            Signer {
+                public_key: keys::charlie::pk(),
+                weight: 5,
+            },
+        ],
+        threshold: 5,
+    };
+    let dummy_sig_a = Signature::from(vec![97]);
+    let dummy_sig_b = Signature::from(vec![98]);
+    let dummy_sig_c = Signature::from(vec![99]);
+
+    // Exactly meeting the threshold.
+    config
+        .batch(&[Some(dummy_sig_a.clone()), Some(dummy_sig_b.clone()), None])
+        .expect("weight 2 + 3 should meet threshold 5");
+    config
+        .batch(&[None, None, Some(dummy_sig_c.clone())])
+        .expect("weight 5 should meet threshold 5");
+
+    // Exceeding the threshold.
+    config
+        .batch(&[None, Some(dummy_sig_b.clone()), Some(dummy_sig_c.clone())])
+        .expect("weight 3 + 5 should exceed threshold 5");
+
+    // Failing the threshold.
+    let err = config
+        .batch(&[Some(dummy_sig_a.clone()), None, None])
+        .expect_err("weight 2 should not meet threshold 5");
+    assert!(matches!(err, Error::InsufficientWeight));
+    let err = config
+        .batch(&[None, Some(dummy_sig_b.clone()), None])
+        .expect_err("weight 3 should not meet threshold 5");
+    assert!(matches!(err, Error::InsufficientWeight));
+
+    // The order of signers in the configuration should not affect the outcome.
+    let reordered = Config {
+        signers: vec![
+            config.signers[2].clone(),
+            config.signers[0].clone(),
+            config.signers[1].clone(),
+        ],
+        threshold: 5,
+    };
+    reordered
+        .batch(&[None, Some(dummy_sig_a.clone()), Some(dummy_sig_b.clone())])
+        .expect("reordered weight 2 + 3 should meet threshold 5");
+    reordered
+        .batch(&[None, Some(dummy_sig_a.clone()), None])
+        .expect_err("reordered weight 2 should not meet threshold 5");
+
+    // Attempting to count the same key twice should be rejected.
+    let duplicate = Config {
+        signers: vec![
+            Signer {
+                public_key: keys::alice::pk(),
+                weight: 3,
+            },
+            Signer {
+                public_key: keys::alice::pk(),
+                weight: 3,
+            },
+        ],
+        threshold: 5,
+    };
+    let err = duplicate
+        .batch(&[Some(dummy_sig_a.clone()), Some(dummy_sig_a.clone())])
+        .expect_err("duplicate key should not be counted twice");
+    assert!(matches!(err, Error::InvalidConfig));
+}
+
+#[test]
+fn test_config_batch_weight_overflow() {
+    let config = Config {
+        signers: vec![
+            Signer {
+                public_key: keys::alice::pk(),
+                weight: u64::MAX,
+            },
+            Signer {
+                public_key: keys::bob::pk(),
+                weight: 1,
+            },
+        ],
+        threshold: 1,
+    };
+    let dummy_sig_a = Signature::from(vec![97]);
+    let dummy_sig_b = Signature::from(vec![98]);
+
+    // Weights that overflow should be rejected with an error instead of wrapping or saturating.
+    let err = config
+        .batch(&[Some(dummy_sig_a.clone()), Some(dummy_sig_b)])
+        .expect_err("overflowing weights should be rejected");
+    assert!(matches!(err, Error::InvalidConfig));
+    let err = config
+        .batch(&[Some(dummy_sig_a), None])
+        .expect_err("configuration with overflowing total weight should be rejected");
+    assert!(matches!(err, Error::InvalidConfig));
+}
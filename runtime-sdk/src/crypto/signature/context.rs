@@ -67,12 +67,13 @@ pub fn set_chain_context(runtime_id: Namespace, consensus_chain_context: &str) {
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use super::*;
 
-    static TEST_GUARD: Lazy<Mutex<()>> = Lazy::new(Default::default);
+    /// Guard serializing tests that configure the global chain domain separation context.
+    pub(crate) static TEST_GUARD: Lazy<Mutex<()>> = Lazy::new(Default::default);
 
-    fn reset_chain_context() {
+    pub(crate) fn reset_chain_context() {
         *CHAIN_CONTEXT.lock().unwrap() = None;
     }
 
@@ -1,23 +1,28 @@
 //! Transaction dispatcher.
 use std::{
+    any::Any,
     collections::{BTreeMap, BTreeSet},
     convert::TryInto,
     marker::PhantomData,
-    sync::{atomic::AtomicBool, Arc},
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use anyhow::anyhow;
-use slog::error;
+use slog::{debug, error, warn};
 use thiserror::Error;
+use tokio::runtime::Handle as TokioHandle;
 
 use oasis_core_runtime::{
     self,
+    common::crypto::hash::Hash,
+    consensus::roothash,
     protocol::HostInfo,
     storage::mkvs,
     transaction::{
         self,
         dispatcher::{ExecuteBatchResult, ExecuteTxResult},
-        tags::Tags,
+        tags::{Tag, Tags},
         types::TxnBatch,
     },
     types::{CheckTxMetadata, CheckTxResult, BATCH_WEIGHT_LIMIT_QUERY_METHOD},
@@ -25,15 +30,19 @@ use oasis_core_runtime::{
 
 use crate::{
     callformat,
-    context::{BatchContext, Context, RuntimeBatchContext, TxContext},
+    context::{BatchContext, Context, ExecutionMode, RuntimeBatchContext, Speculation, TxContext},
     error::{Error as _, RuntimeError},
+    event::{self, Event as _},
     keymanager::{KeyManagerClient, KeyManagerError},
-    module::{self, AuthHandler, BlockHandler, MethodHandler},
+    module::{
+        self, AuthHandler, BlockHandler, EventHandler, InvariantHandler, MethodHandler, Module as _,
+    },
     modules,
     modules::core::API as _,
+    receipt,
     runtime::Runtime,
     storage,
-    storage::Prefix,
+    storage::{NestedStore, Prefix},
     types,
     types::transaction::{AuthProof, Transaction, TransactionWeight},
 };
@@ -41,6 +50,216 @@ use crate::{
 /// Unique module name.
 const MODULE_NAME: &str = "dispatcher";
 
+/// Context key for the observer of state written by transactions.
+const CONTEXT_KEY_STATE_OBSERVER: &str = "dispatcher.StateObserver";
+
+/// Observer notified after each executed transaction commits, with the hash of the transaction
+/// and the state keys it has written (inserted or removed) in ascending order.
+pub type StateObserver = Arc<dyn Fn(&Hash, &[Vec<u8>]) + Send + Sync>;
+
+/// Context key for the observer of transaction execution.
+const CONTEXT_KEY_EXECUTION_OBSERVER: &str = "dispatcher.ExecutionObserver";
+
+/// Context key for the handle of the Tokio runtime driving asynchronous queries.
+const CONTEXT_KEY_RUNTIME_HANDLE: &str = "dispatcher.RuntimeHandle";
+
+/// Observer notified of the execution of each transaction, e.g. for profiling purposes.
+///
+/// Observers are purely informational and must not affect execution in any way.
+pub trait ExecutionObserver: Send + Sync {
+    /// Called before the transaction at the given index in the batch is dispatched.
+    fn on_tx_start(&self, _index: usize) {}
+
+    /// Called after the transaction at the given index in the batch has been dispatched, with
+    /// its result, the amount of gas it used and the time that its dispatch took.
+    fn on_tx_end(
+        &self,
+        _index: usize,
+        _result: &module::CallResult,
+        _gas_used: u64,
+        _elapsed: Duration,
+    ) {
+    }
+}
+
+/// Converts the length of an encoded transaction into its size.
+fn encoded_tx_size(len: usize) -> Result<u32, modules::core::Error> {
+    len.try_into()
+        .map_err(|_| modules::core::Error::MalformedTransaction(anyhow!("transaction too large")))
+}
+
+/// Checks that a CBOR-encoded value does not nest containers deeper than `max_depth` and that
+/// none of its strings or containers declare more elements than there are bytes left in the
+/// input.
+///
+/// This is performed before decoding untrusted inputs so that hostile encodings are rejected
+/// without the decoder recursing into them or preallocating for them.
+fn check_cbor_limits(data: &[u8], max_depth: u16) -> Result<(), anyhow::Error> {
+    // Number of items remaining in each of the enclosing containers, `None` in case of an
+    // indefinite-length container. The outermost entry stands for the top-level value.
+    let mut stack: Vec<Option<u64>> = vec![Some(1)];
+    let mut pos = 0usize;
+
+    while let Some(remaining) = stack.last_mut() {
+        if *remaining == Some(0) {
+            stack.pop();
+            continue;
+        }
+
+        let initial = *data
+            .get(pos)
+            .ok_or_else(|| anyhow!("unexpected end of input"))?;
+        pos += 1;
+        let major = initial >> 5;
+        let info = initial & 0x1f;
+
+        if major == 7 && info == 31 {
+            // Break terminating an indefinite-length container.
+            if remaining.is_some() {
+                return Err(anyhow!("unexpected break"));
+            }
+            stack.pop();
+            continue;
+        }
+        if let Some(n) = remaining {
+            *n -= 1;
+        }
+
+        let arg = match info {
+            0..=23 => Some(info as u64),
+            24..=27 => {
+                let len = 1usize << (info - 24);
+                let bytes = data
+                    .get(pos..pos + len)
+                    .ok_or_else(|| anyhow!("unexpected end of input"))?;
+                pos += len;
+                Some(bytes.iter().fold(0u64, |acc, b| acc << 8 | *b as u64))
+            }
+            31 if (2..=5).contains(&major) => None,
+            _ => return Err(anyhow!("invalid additional information {}", info)),
+        };
+
+        let left = (data.len() - pos) as u64;
+        let items = match (major, arg) {
+            (2, Some(len)) | (3, Some(len)) => {
+                if len > left {
+                    return Err(anyhow!("string length exceeds input"));
+                }
+                pos += len as usize;
+                continue;
+            }
+            (4, Some(n)) => Some(n),
+            (5, Some(n)) => Some(
+                n.checked_mul(2)
+                    .ok_or_else(|| anyhow!("collection length exceeds input"))?,
+            ),
+            (6, _) => Some(1),
+            (2..=5, None) => None,
+            _ => continue,
+        };
+        if matches!(items, Some(n) if n > left) {
+            return Err(anyhow!("collection length exceeds input"));
+        }
+        if stack.len() > max_depth as usize {
+            return Err(anyhow!("maximum nesting depth of {} exceeded", max_depth));
+        }
+        stack.push(items);
+    }
+
+    Ok(())
+}
+
+/// Extracts the message from the payload of a caught panic.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        return (*msg).to_owned();
+    }
+    if let Some(msg) = payload.downcast_ref::<String>() {
+        return msg.clone();
+    }
+    "unknown panic".to_owned()
+}
+
+/// Sorts transactions into the order in which they should be scheduled, given a function
+/// returning the priority and hash of each transaction.
+///
+/// Transactions are ordered by decreasing priority. Transactions with the same priority are
+/// ordered by increasing transaction hash so that all nodes agree on the resulting order
+/// regardless of the order in which the transactions were received.
+pub fn sort_by_priority<T, F>(txs: &mut [T], key: F)
+where
+    F: Fn(&T) -> (u64, Hash),
+{
+    txs.sort_by(|a, b| {
+        let (a_priority, a_hash) = key(a);
+        let (b_priority, b_hash) = key(b);
+        b_priority
+            .cmp(&a_priority)
+            .then_with(|| a_hash.as_ref().cmp(b_hash.as_ref()))
+    });
+}
+
+/// Partitions a batch into groups of transactions that do not conflict with each other, given
+/// the storage prefixes that each transaction may access (as declared via `prefetch`).
+///
+/// Two transactions conflict in case one of them may access a key under a prefix of the other,
+/// and conflicting transactions always end up in the same group. Transactions whose prefixes are
+/// not known conflict with all other transactions. Each group lists the indices of its
+/// transactions in increasing order and groups are ordered by their first transaction, so the
+/// result only depends on the order of the batch.
+pub fn partition_by_prefixes(prefixes: &[Option<BTreeSet<Prefix>>]) -> Vec<Vec<usize>> {
+    if prefixes.iter().any(Option::is_none) {
+        return vec![(0..prefixes.len()).collect()];
+    }
+
+    fn find(parents: &mut [usize], mut idx: usize) -> usize {
+        while parents[idx] != idx {
+            parents[idx] = parents[parents[idx]];
+            idx = parents[idx];
+        }
+        idx
+    }
+
+    // Visiting all prefixes in lexicographic order, any prefix covering the current one is still
+    // on the stack of prefixes covering the previously visited one.
+    let mut sorted: Vec<(&[u8], usize)> = prefixes
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, prefixes)| {
+            prefixes
+                .iter()
+                .flatten()
+                .map(move |prefix| (AsRef::<[u8]>::as_ref(prefix), idx))
+        })
+        .collect();
+    sorted.sort_unstable();
+
+    let mut parents: Vec<usize> = (0..prefixes.len()).collect();
+    let mut stack: Vec<(&[u8], usize)> = Vec::new();
+    for (prefix, idx) in sorted {
+        while matches!(stack.last(), Some((covering, _)) if !prefix.starts_with(covering)) {
+            stack.pop();
+        }
+        if let Some((_, other)) = stack.last() {
+            let (a, b) = (find(&mut parents, idx), find(&mut parents, *other));
+            parents[a.max(b)] = a.min(b);
+        }
+        stack.push((prefix, idx));
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of_root: BTreeMap<usize, usize> = BTreeMap::new();
+    for idx in 0..prefixes.len() {
+        let root = find(&mut parents, idx);
+        let group = *group_of_root.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(idx);
+    }
+    groups
+}
+
 /// Error emitted by the dispatch process. Note that this indicates an error in the dispatch
 /// process itself and should not be used for any transaction-related errors.
 #[derive(Error, Debug, oasis_runtime_sdk_macros::Error)]
@@ -49,9 +268,14 @@ pub enum Error {
     #[sdk_error(code = 1)]
     Aborted,
 
-    #[error("malformed transaction in batch: {0}")]
+    #[error("malformed transaction {index} ({size} bytes) in batch: {err}")]
     #[sdk_error(code = 2)]
-    MalformedTransactionInBatch(#[source] anyhow::Error),
+    MalformedTransactionInBatch {
+        index: usize,
+        size: usize,
+        #[source]
+        err: anyhow::Error,
+    },
 
     #[error("query aborted: {0}")]
     #[sdk_error(code = 3)]
@@ -60,6 +284,35 @@ pub enum Error {
     #[error("key manager failure: {0}")]
     #[sdk_error(code = 4)]
     KeyManagerFailure(#[from] KeyManagerError),
+
+    #[error("invariant violation: {0}")]
+    #[sdk_error(code = 5)]
+    InvariantViolation(#[source] modules::core::Error),
+}
+
+/// A transaction of a batch that has been decoded but not yet executed.
+#[derive(Clone, Debug)]
+pub struct DecodedTx {
+    /// Index of the transaction in the batch.
+    pub index: usize,
+    /// Size of the encoded transaction.
+    pub size: u32,
+    /// Hash of the encoded transaction.
+    pub hash: Hash,
+    /// Decoded transaction.
+    pub tx: Transaction,
+}
+
+/// A transaction that has been executed in a speculative child context.
+struct SpeculativeTx {
+    /// Result of executing the transaction.
+    result: Result<(ExecuteTxResult, Hash), Error>,
+    /// Amount of gas used by the batch after executing the transaction.
+    batch_gas_used: u64,
+    /// Fees accumulated after executing the transaction.
+    fees: modules::accounts::FeeAccumulator,
+    /// Outcome of the speculation that is yet to be applied.
+    speculation: Speculation,
 }
 
 /// Result of dispatching a transaction.
@@ -74,6 +327,8 @@ pub struct DispatchResult {
     pub weights: BTreeMap<TransactionWeight, u64>,
     /// Call format metadata.
     pub call_format_metadata: callformat::Metadata,
+    /// Amount of gas used by the transaction.
+    pub gas_used: u64,
 }
 
 impl DispatchResult {
@@ -84,6 +339,7 @@ impl DispatchResult {
             priority: 0,
             weights: BTreeMap::new(),
             call_format_metadata,
+            gas_used: 0,
         }
     }
 }
@@ -94,10 +350,49 @@ impl From<module::CallResult> for DispatchResult {
     }
 }
 
+/// Result of a transaction executed in replay mode, as stored in state.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+struct ReplayedTx {
+    output: Vec<u8>,
+    tags: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl From<&ExecuteTxResult> for ReplayedTx {
+    fn from(result: &ExecuteTxResult) -> Self {
+        Self {
+            output: result.output.clone(),
+            tags: result
+                .tags
+                .iter()
+                .map(|tag| (tag.key.clone(), tag.value.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl From<ReplayedTx> for ExecuteTxResult {
+    fn from(replayed: ReplayedTx) -> Self {
+        Self {
+            output: replayed.output,
+            tags: replayed
+                .tags
+                .into_iter()
+                .map(|(key, value)| Tag::new(key, value))
+                .collect(),
+        }
+    }
+}
+
 /// The runtime dispatcher.
 pub struct Dispatcher<R: Runtime> {
     host_info: HostInfo,
     key_manager: Option<KeyManagerClient>,
+    state_observer: Option<StateObserver>,
+    execution_observer: Option<Arc<dyn ExecutionObserver>>,
+    /// Handle of the host's Tokio runtime, used to drive asynchronous queries.
+    runtime_handle: Option<TokioHandle>,
+    /// Next nonces of signers of transactions that have passed checks on this node.
+    pending_nonces: Arc<Mutex<modules::accounts::PendingNonces>>,
     _runtime: PhantomData<R>,
 }
 
@@ -106,29 +401,48 @@ impl<R: Runtime> Dispatcher<R> {
     ///
     /// Note that the dispatcher is fully static and the constructor is only needed so that the
     /// instance can be used directly with the dispatcher system provided by Oasis Core.
-    pub(super) fn new(host_info: HostInfo, key_manager: Option<KeyManagerClient>) -> Self {
+    pub(super) fn new(
+        host_info: HostInfo,
+        key_manager: Option<KeyManagerClient>,
+        runtime_handle: Option<TokioHandle>,
+    ) -> Self {
         Self {
             host_info,
             key_manager,
+            state_observer: R::state_observer(),
+            execution_observer: R::execution_observer(),
+            runtime_handle,
+            pending_nonces: Default::default(),
             _runtime: PhantomData,
         }
     }
 
+    /// Notify the given observer of the state written by transactions executed in the given
+    /// context.
+    pub fn set_state_observer<C: Context>(ctx: &mut C, observer: StateObserver) {
+        ctx.value(CONTEXT_KEY_STATE_OBSERVER).set(observer);
+    }
+
+    /// Notify the given observer of the execution of transactions dispatched in the given
+    /// context.
+    pub fn set_execution_observer<C: Context>(ctx: &mut C, observer: Arc<dyn ExecutionObserver>) {
+        ctx.value(CONTEXT_KEY_EXECUTION_OBSERVER).set(observer);
+    }
+
+    /// Drive asynchronous queries dispatched in the given context on the given Tokio runtime.
+    ///
+    /// The handle must not belong to a runtime that is executing on the current thread, as
+    /// queries block the current thread until they complete.
+    pub fn set_runtime_handle<C: Context>(ctx: &mut C, handle: TokioHandle) {
+        ctx.value(CONTEXT_KEY_RUNTIME_HANDLE).set(handle);
+    }
+
     /// Decode a runtime transaction.
     pub fn decode_tx<C: Context>(
         ctx: &mut C,
         tx: &[u8],
     ) -> Result<types::transaction::Transaction, modules::core::Error> {
-        // TODO: Check against transaction size limit.
-
-        // Deserialize transaction.
-        let utx: types::transaction::UnverifiedTransaction = cbor::from_slice(tx)
-            .map_err(|e| modules::core::Error::MalformedTransaction(e.into()))?;
-
-        // Perform any checks before signature verification.
-        R::Modules::approve_unverified_tx(ctx, &utx)?;
-
-        match utx.1.as_slice() {
+        Self::decode_tx_with(ctx, tx, |ctx, utx| match utx.1.as_slice() {
             [AuthProof::Module(scheme)] => {
                 R::Modules::decode_tx(ctx, scheme, &utx.0)?.ok_or_else(|| {
                     modules::core::Error::MalformedTransaction(anyhow!(
@@ -138,9 +452,147 @@ impl<R: Runtime> Dispatcher<R> {
                 })
             }
             _ => utx
-                .verify()
+                .verify_with_contexts(R::signature_context_for)
                 .map_err(|e| modules::core::Error::MalformedTransaction(e.into())),
+        })
+    }
+
+    /// Decode a runtime transaction that is part of an atomic group, returning its encoded size
+    /// together with the decoded transaction.
+    ///
+    /// The `bodies` are the signed bodies of all transactions in the group, in order. Grouped
+    /// transactions must be signed using the signature context bound to the group, module-controlled
+    /// decoding is not supported.
+    pub fn decode_grouped_tx<C: Context>(
+        ctx: &mut C,
+        tx: &[u8],
+        bodies: &[&[u8]],
+    ) -> Result<(u32, types::transaction::Transaction), modules::core::Error> {
+        let tx_size = encoded_tx_size(tx.len())?;
+        let tx = Self::decode_tx_with(ctx, tx, |_ctx, utx| {
+            if utx.1.iter().any(|p| matches!(p, AuthProof::Module(_))) {
+                return Err(modules::core::Error::MalformedTransaction(anyhow!(
+                    "grouped transactions must be signed"
+                )));
+            }
+            utx.verify_for_atomic_group(bodies)
+                .map_err(|e| modules::core::Error::MalformedTransaction(e.into()))
+        })?;
+        Ok((tx_size, tx))
+    }
+
+    fn decode_tx_with<C, F>(
+        ctx: &mut C,
+        tx: &[u8],
+        verify: F,
+    ) -> Result<types::transaction::Transaction, modules::core::Error>
+    where
+        C: Context,
+        F: FnOnce(
+            &mut C,
+            types::transaction::UnverifiedTransaction,
+        ) -> Result<types::transaction::Transaction, modules::core::Error>,
+    {
+        // Check against transaction size limit.
+        if let Some(max_tx_size) = modules::core::Module::params(ctx.runtime_state()).max_tx_size {
+            if tx.len() > max_tx_size as usize {
+                return Err(modules::core::Error::MalformedTransaction(anyhow!(
+                    "transaction too large ({} bytes, limit {})",
+                    tx.len(),
+                    max_tx_size
+                )));
+            }
+        }
+
+        // Deserialize transaction.
+        check_cbor_limits(tx, R::MAX_CBOR_NESTING_DEPTH)
+            .map_err(modules::core::Error::MalformedTransaction)?;
+        let utx: types::transaction::UnverifiedTransaction = cbor::from_slice(tx)
+            .map_err(|e| modules::core::Error::MalformedTransaction(e.into()))?;
+        // The body is itself encoded and is decoded separately below.
+        check_cbor_limits(&utx.0, R::MAX_CBOR_NESTING_DEPTH)
+            .map_err(modules::core::Error::MalformedTransaction)?;
+
+        // Perform any checks before signature verification.
+        R::Modules::approve_unverified_tx(ctx, &utx)?;
+
+        let tx = verify(ctx, utx)?;
+
+        // Reject transactions requesting features that the runtime does not support.
+        let unsupported = tx.auth_info.features & !R::SUPPORTED_TX_FEATURES;
+        if unsupported != 0 {
+            return Err(modules::core::Error::UnsupportedFeature(unsupported));
+        }
+
+        Ok(tx)
+    }
+
+    /// Decode all transactions in a batch, in the order in which they appear in the batch.
+    ///
+    /// It is an error to include a malformed transaction in a batch, so decoding fails in case
+    /// any of the transactions cannot be decoded.
+    pub fn decode_batch<C: BatchContext>(
+        ctx: &mut C,
+        batch: &TxnBatch,
+    ) -> Result<Vec<DecodedTx>, Error> {
+        batch
+            .iter()
+            .enumerate()
+            .map(|(index, raw)| {
+                let malformed = |err: modules::core::Error| Error::MalformedTransactionInBatch {
+                    index,
+                    size: raw.len(),
+                    err: err.into(),
+                };
+                let size = encoded_tx_size(raw.len()).map_err(malformed)?;
+                let hash = Hash::digest_bytes(raw);
+                let tx = Self::decode_tx(ctx, raw).map_err(malformed)?;
+
+                Ok(DecodedTx {
+                    index,
+                    size,
+                    hash,
+                    tx,
+                })
+            })
+            .collect()
+    }
+
+    /// Order the decoded transactions of a batch for execution.
+    ///
+    /// In case priority ordering is enabled, the transactions are first sorted by priority. Modules
+    /// may only reorder the transactions as results must be reported for every transaction in the
+    /// batch, so the batch is aborted in case the ordering is not a permutation of the batch.
+    fn order_batch<C: BatchContext>(ctx: &mut C, txs: &mut [DecodedTx]) -> Result<(), Error> {
+        if R::PRIORITY_ORDERING {
+            // The priority of a transaction is the gas price of its fee, same as the priority
+            // assigned during authentication.
+            sort_by_priority(txs, |decoded| {
+                let gas_price = decoded.tx.auth_info.fee.gas_price();
+                (gas_price.try_into().unwrap_or(u64::MAX), decoded.hash)
+            });
         }
+        R::Modules::order_batch(ctx, txs);
+
+        let mut seen = vec![false; txs.len()];
+        for tx in txs.iter() {
+            match seen.get_mut(tx.index) {
+                Some(seen) if !*seen => *seen = true,
+                _ => return Err(Error::Aborted),
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode a transaction that is being checked, returning its encoded size together with the
+    /// decoded transaction.
+    pub fn decode_tx_for_check<C: Context>(
+        ctx: &mut C,
+        tx: &[u8],
+    ) -> Result<(u32, types::transaction::Transaction), modules::core::Error> {
+        let tx_size = encoded_tx_size(tx.len())?;
+        let tx = Self::decode_tx(ctx, tx)?;
+        Ok((tx_size, tx))
     }
 
     /// Run the dispatch steps inside a transaction context. This includes the before call hooks
@@ -165,8 +617,43 @@ impl<R: Runtime> Dispatcher<R> {
     pub fn dispatch_tx<C: BatchContext>(
         ctx: &mut C,
         tx_size: u32,
+        tx_hash: Hash,
+        tx: types::transaction::Transaction,
+        index: usize,
+    ) -> Result<DispatchResult, Error> {
+        let mut gas_used = 0;
+        let observer = match ctx
+            .value::<Arc<dyn ExecutionObserver>>(CONTEXT_KEY_EXECUTION_OBSERVER)
+            .get()
+            .cloned()
+        {
+            Some(observer) => observer,
+            None => {
+                let mut result =
+                    Self::dispatch_tx_unobserved(ctx, tx_size, tx_hash, tx, index, &mut gas_used)?;
+                result.gas_used = gas_used;
+                return Ok(result);
+            }
+        };
+
+        observer.on_tx_start(index);
+        let start = Instant::now();
+        let mut result =
+            Self::dispatch_tx_unobserved(ctx, tx_size, tx_hash, tx, index, &mut gas_used)?;
+        observer.on_tx_end(index, &result.result, gas_used, start.elapsed());
+        result.gas_used = gas_used;
+
+        Ok(result)
+    }
+
+    /// Dispatch a runtime transaction in the given context, recording the gas used by the call.
+    fn dispatch_tx_unobserved<C: BatchContext>(
+        ctx: &mut C,
+        tx_size: u32,
+        tx_hash: Hash,
         tx: types::transaction::Transaction,
         index: usize,
+        gas_used: &mut u64,
     ) -> Result<DispatchResult, Error> {
         // Run pre-processing hooks.
         if let Err(err) = R::Modules::authenticate_tx(ctx, &tx) {
@@ -174,6 +661,8 @@ impl<R: Runtime> Dispatcher<R> {
         }
 
         let (result, messages) = ctx.with_tx(tx_size, tx, |mut ctx, call| {
+            modules::core::Module::set_tx_hash(&mut ctx, tx_hash);
+
             // Decode call based on specified call format.
             let (call, call_format_metadata) = match callformat::decode_call(&ctx, call, index) {
                 Ok(Some(result)) => result,
@@ -188,6 +677,7 @@ impl<R: Runtime> Dispatcher<R> {
             };
 
             let result = Self::dispatch_tx_call(&mut ctx, call);
+            *gas_used = modules::core::Module::used_tx_gas(&mut ctx);
             if !result.is_success() {
                 return (
                     DispatchResult::new(result, call_format_metadata),
@@ -195,12 +685,33 @@ impl<R: Runtime> Dispatcher<R> {
                 );
             }
 
+            // Refund the fee for any unused gas.
+            if let Err(err) = modules::core::Module::refund_unused_gas(&mut ctx) {
+                return (
+                    DispatchResult::new(err.into_call_result(), call_format_metadata),
+                    Vec::new(),
+                );
+            }
+
             // Load priority, weights.
             let priority = modules::core::Module::take_priority(&mut ctx);
             let weights = modules::core::Module::take_weights(&mut ctx);
 
+            // Collect the written keys in case anyone is observing them.
+            let observer = match ctx.execution_mode() {
+                ExecutionMode::Execute => ctx
+                    .value::<StateObserver>(CONTEXT_KEY_STATE_OBSERVER)
+                    .get()
+                    .cloned(),
+                ExecutionMode::Check | ExecutionMode::Simulate => None,
+            };
+            let observed = observer.map(|observer| (observer, ctx.runtime_state().written_keys()));
+
             // Commit store and return emitted tags and messages.
             let (tags, messages) = ctx.commit();
+            if let Some((observer, keys)) = observed {
+                observer(&tx_hash, &keys);
+            }
 
             (
                 DispatchResult {
@@ -209,6 +720,7 @@ impl<R: Runtime> Dispatcher<R> {
                     priority,
                     weights,
                     call_format_metadata,
+                    gas_used: 0,
                 },
                 messages,
             )
@@ -230,17 +742,24 @@ impl<R: Runtime> Dispatcher<R> {
     pub fn check_tx<C: BatchContext>(
         ctx: &mut C,
         tx_size: u32,
+        tx_hash: Hash,
         tx: Transaction,
     ) -> Result<CheckTxResult, Error> {
-        let dispatch = Self::dispatch_tx(ctx, tx_size, tx, usize::MAX)?;
+        let signer_info = tx.auth_info.signer_info.clone();
+        let dispatch = Self::dispatch_tx(ctx, tx_size, tx_hash, tx, usize::MAX)?;
         match dispatch.result {
-            module::CallResult::Ok(_) => Ok(CheckTxResult {
-                error: Default::default(),
-                meta: Some(CheckTxMetadata {
-                    priority: dispatch.priority,
-                    weights: Some(dispatch.weights),
-                }),
-            }),
+            module::CallResult::Ok(_) => {
+                // Only transactions that have passed all checks may advance pending nonces.
+                modules::accounts::Module::record_pending_nonces(ctx, &signer_info);
+
+                Ok(CheckTxResult {
+                    error: Default::default(),
+                    meta: Some(CheckTxMetadata {
+                        priority: dispatch.priority,
+                        weights: Some(dispatch.weights),
+                    }),
+                })
+            }
 
             module::CallResult::Failed {
                 module,
@@ -260,34 +779,352 @@ impl<R: Runtime> Dispatcher<R> {
     }
 
     /// Execute the given transaction.
+    ///
+    /// In case `Runtime::EMIT_RECEIPT_TAGS` is enabled, the result includes the core `GasUsed`
+    /// event with the amount of gas used by the transaction and the last tag of the result is the
+    /// core `EventsRoot` event committing to all of the other emitted tags.
     pub fn execute_tx<C: BatchContext>(
         ctx: &mut C,
         tx_size: u32,
+        tx_hash: Hash,
+        tx: Transaction,
+        index: usize,
+    ) -> Result<ExecuteTxResult, Error> {
+        Self::execute_tx_with_events_root(ctx, tx_size, tx_hash, tx, index)
+            .map(|(result, _)| result)
+    }
+
+    /// Execute the given transaction in replay mode.
+    ///
+    /// This is meant for tooling (e.g. indexer backfill) that re-dispatches transactions whose
+    /// effects may already be committed. The result of each transaction executed this way is
+    /// stored in state keyed by its hash. In case a result for the given hash already exists, the
+    /// transaction is not executed again and the stored result is returned instead, so no state
+    /// changes are applied and no events are delivered to module event handlers.
+    ///
+    /// # Determinism
+    ///
+    /// Replay mode writes to runtime state and skips execution based on that state, so it changes
+    /// the resulting state root compared to regular execution. It must therefore never be mixed
+    /// with regular execution for rounds that are committed by the network and should only be used
+    /// on a local copy of state. Since transactions are identified by their hash only, replaying
+    /// the same transaction at a different position or against different state still returns the
+    /// originally stored result.
+    pub fn replay_tx<C: BatchContext>(
+        ctx: &mut C,
+        tx_size: u32,
+        tx_hash: Hash,
         tx: Transaction,
         index: usize,
     ) -> Result<ExecuteTxResult, Error> {
-        let dispatch_result = Self::dispatch_tx(ctx, tx_size, tx, index)?;
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &modules::core::MODULE_NAME);
+        let replayed = storage::TypedStore::new(storage::PrefixStore::new(
+            store,
+            &modules::core::state::REPLAYED_TXS,
+        ));
+        if let Some(result) = replayed.get::<_, ReplayedTx>(tx_hash) {
+            return Ok(result.into());
+        }
+
+        let result = Self::execute_tx(ctx, tx_size, tx_hash, tx, index)?;
+
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &modules::core::MODULE_NAME);
+        let mut replayed = storage::TypedStore::new(storage::PrefixStore::new(
+            store,
+            &modules::core::state::REPLAYED_TXS,
+        ));
+        replayed.insert(tx_hash, ReplayedTx::from(&result));
+
+        Ok(result)
+    }
+
+    /// Execute the given transaction, returning its result together with its events root. The
+    /// events root is only computed in case receipt tags are enabled.
+    fn execute_tx_with_events_root<C: BatchContext>(
+        ctx: &mut C,
+        tx_size: u32,
+        tx_hash: Hash,
+        tx: Transaction,
+        index: usize,
+    ) -> Result<(ExecuteTxResult, Hash), Error> {
+        let dispatch_result = Self::dispatch_tx(ctx, tx_size, tx_hash, tx, index)?;
+        Self::handle_events(ctx, &dispatch_result.tags);
+
         let output: types::transaction::CallResult = callformat::encode_result(
             ctx,
             dispatch_result.result,
             dispatch_result.call_format_metadata,
         );
 
-        Ok(ExecuteTxResult {
-            output: cbor::to_vec(output),
-            tags: dispatch_result.tags,
-        })
+        let mut tags = dispatch_result.tags;
+        let mut events_root = Hash::default();
+        if R::EMIT_RECEIPT_TAGS {
+            tags.push(
+                modules::core::Event::GasUsed {
+                    amount: dispatch_result.gas_used,
+                }
+                .into_tag(),
+            );
+            events_root = receipt::events_root(&tags);
+            tags.push(modules::core::Event::EventsRoot { root: events_root }.into_tag());
+        }
+
+        Ok((
+            ExecuteTxResult {
+                output: cbor::to_vec(output),
+                tags,
+            },
+            events_root,
+        ))
+    }
+
+    /// Deliver events emitted by an executed transaction to module event handlers.
+    fn handle_events<C: BatchContext>(ctx: &mut C, tags: &[Tag]) {
+        for tag in tags {
+            if let Some((module_name, code)) = event::parse_event_key(&tag.key) {
+                R::Modules::handle_event(ctx, module_name, code, &tag.value);
+            }
+        }
+    }
+
+    /// Partition the given transactions into groups that do not conflict with each other, based
+    /// on the prefixes that they declare for prefetching (see `partition_by_prefixes`).
+    fn partition_batch(txs: &[DecodedTx]) -> Vec<Vec<usize>> {
+        let prefixes: Vec<_> = txs
+            .iter()
+            .map(|decoded| {
+                let mut prefixes = BTreeSet::new();
+                match R::Modules::prefetch(
+                    &mut prefixes,
+                    &decoded.tx.call.method,
+                    decoded.tx.call.body.clone(),
+                    &decoded.tx.auth_info,
+                ) {
+                    module::DispatchResult::Handled(Ok(())) => Some(prefixes),
+                    // Prefixes of transactions that fail to prefetch or do not implement it are
+                    // not known.
+                    _ => None,
+                }
+            })
+            .collect();
+        partition_by_prefixes(&prefixes)
+    }
+
+    /// Execute the given groups of transactions optimistically, see
+    /// `Runtime::PARTITIONED_EXECUTION`.
+    ///
+    /// The transactions of each group (given as indices into `txs`) are executed one after
+    /// another on top of the state at the start of the batch, independently of the other groups.
+    /// A speculation is only applied in case it did not read any state written by another group,
+    /// otherwise the transaction is executed again, and so are all later transactions of its
+    /// group as they were executed on top of the discarded speculation.
+    ///
+    /// Returns the results in execution order, each together with the index of the transaction
+    /// in the batch, and the number of speculations that have been discarded.
+    fn execute_txs_speculatively<S: NestedStore>(
+        ctx: &mut RuntimeBatchContext<'_, R, S>,
+        txs: Vec<DecodedTx>,
+        groups: &[Vec<usize>],
+    ) -> Result<(Vec<(usize, (ExecuteTxResult, Hash))>, usize), Error> {
+        // The gas used by the batch and the accumulated fees are kept outside of the context while
+        // the transactions are executed so that each speculation can start from scratch.
+        let mut batch_gas_used = modules::core::Module::take_used_batch_gas(ctx);
+        let mut fees = modules::accounts::Module::take_fee_accumulator(ctx);
+        let max_batch_gas = modules::core::Module::params(ctx.runtime_state()).max_batch_gas;
+        let remaining_events = ctx.remaining_events();
+        let remaining_messages = ctx.remaining_messages();
+
+        // Any other values (e.g., observers) would not be visible to the speculations, so the
+        // transactions must be executed directly in the context in that case.
+        let mut sequential = ctx.has_values();
+        let mut speculations: Vec<Option<SpeculativeTx>> =
+            std::iter::repeat_with(|| None).take(txs.len()).collect();
+        let mut group_of = vec![0; txs.len()];
+        for (group, indices) in groups.iter().enumerate() {
+            for &idx in indices {
+                group_of[idx] = group;
+            }
+            if sequential {
+                continue;
+            }
+
+            ctx.with_speculation(|ctx| {
+                for &idx in indices {
+                    let speculated = Self::speculate_tx(ctx, &txs[idx], 0, Default::default());
+                    // Later transactions of the group are executed on top of this one.
+                    speculated.speculation.changes.apply(ctx.runtime_state());
+                    speculations[idx] = Some(speculated);
+                }
+            });
+        }
+
+        // Keys written by the applied speculations of each group, followed by the keys written by
+        // transactions that have been executed again.
+        let mut written = vec![BTreeSet::new(); groups.len() + 1];
+        let mut invalidated = vec![false; groups.len()];
+        let mut results = Vec::with_capacity(txs.len());
+        let mut reexecuted = 0;
+        for (idx, (decoded, speculation)) in txs.into_iter().zip(speculations).enumerate() {
+            let group = group_of[idx];
+            let (result, speculation, writer) = match speculation {
+                // A speculation is only valid in case nothing it depends on has changed since it
+                // started. As the transaction can use at most its gas limit, it cannot tell the
+                // difference in batch gas usage while its limit fits into the remaining batch gas.
+                Some(SpeculativeTx {
+                    result: Ok(result),
+                    batch_gas_used: tx_batch_gas_used,
+                    fees: tx_fees,
+                    speculation,
+                }) if !sequential
+                    && !invalidated[group]
+                    && !ctx.has_values()
+                    && ctx.remaining_events() == remaining_events
+                    && ctx.remaining_messages() == remaining_messages
+                    && decoded.tx.auth_info.fee.gas
+                        <= max_batch_gas.saturating_sub(batch_gas_used)
+                    && !written.iter().enumerate().any(|(writer, keys)| {
+                        writer != group && speculation.changes.reads_any(keys)
+                    }) =>
+                {
+                    batch_gas_used = batch_gas_used.saturating_add(tx_batch_gas_used);
+                    fees.merge(tx_fees);
+                    (result, speculation, group)
+                }
+                speculation if sequential || ctx.has_values() => {
+                    if speculation.is_some() {
+                        reexecuted += 1;
+                    }
+                    sequential = true;
+
+                    modules::core::Module::set_used_batch_gas(ctx, batch_gas_used);
+                    modules::accounts::Module::merge_fee_accumulator(
+                        ctx,
+                        std::mem::take(&mut fees),
+                    );
+                    let result = Self::execute_tx_with_events_root(
+                        ctx,
+                        decoded.size,
+                        decoded.hash,
+                        decoded.tx,
+                        decoded.index,
+                    )?;
+                    batch_gas_used = modules::core::Module::take_used_batch_gas(ctx);
+                    fees = modules::accounts::Module::take_fee_accumulator(ctx);
+
+                    results.push((decoded.index, result));
+                    continue;
+                }
+                _ => {
+                    // Executing the transaction again against the current state is equivalent to
+                    // executing it directly in the context.
+                    reexecuted += 1;
+                    invalidated[group] = true;
+                    let executed = Self::speculate_tx(
+                        ctx,
+                        &decoded,
+                        batch_gas_used,
+                        std::mem::take(&mut fees),
+                    );
+                    batch_gas_used = executed.batch_gas_used;
+                    fees = executed.fees;
+                    (executed.result?, executed.speculation, groups.len())
+                }
+            };
+
+            written[writer].extend(speculation.changes.writes.keys().cloned());
+            ctx.apply_speculation(speculation);
+            results.push((decoded.index, result));
+        }
+
+        modules::core::Module::set_used_batch_gas(ctx, batch_gas_used);
+        modules::accounts::Module::merge_fee_accumulator(ctx, fees);
+
+        Ok((results, reexecuted))
+    }
+
+    /// Execute the given transaction in a speculative child context, starting from the given
+    /// amount of gas used by the batch and accumulated fees.
+    fn speculate_tx<S: NestedStore>(
+        ctx: &mut RuntimeBatchContext<'_, R, S>,
+        decoded: &DecodedTx,
+        batch_gas_used: u64,
+        fees: modules::accounts::FeeAccumulator,
+    ) -> SpeculativeTx {
+        let ((result, batch_gas_used, fees), speculation) = ctx.with_speculation(|ctx| {
+            if batch_gas_used > 0 {
+                modules::core::Module::set_used_batch_gas(ctx, batch_gas_used);
+            }
+            modules::accounts::Module::merge_fee_accumulator(ctx, fees);
+
+            let result = Self::execute_tx_with_events_root(
+                ctx,
+                decoded.size,
+                decoded.hash,
+                decoded.tx.clone(),
+                decoded.index,
+            );
+
+            // Priorities and weights are only used when checking transactions.
+            modules::core::Module::take_priority(ctx);
+            modules::core::Module::take_weights(ctx);
+
+            (
+                result,
+                modules::core::Module::take_used_batch_gas(ctx),
+                modules::accounts::Module::take_fee_accumulator(ctx),
+            )
+        });
+
+        SpeculativeTx {
+            result,
+            batch_gas_used,
+            fees,
+            speculation,
+        }
+    }
+
+    /// Check invariants of all modules in case the configured invariant check interval requires
+    /// it at the round being executed.
+    fn maybe_check_invariants<C: BatchContext>(ctx: &mut C) -> Result<(), Error> {
+        let interval = modules::core::Module::params(ctx.runtime_state()).invariant_check_interval;
+        // The round being executed follows the last runtime block.
+        let round = ctx.runtime_header().round.saturating_add(1);
+        if interval == 0 || round % interval != 0 {
+            return Ok(());
+        }
+
+        R::Modules::check_invariants(ctx).map_err(Error::InvariantViolation)
     }
 
     /// Prefetch prefixes for the given transaction.
+    ///
+    /// Transactions declaring more than `R::MAX_PREFETCH_PREFIXES_PER_TX` prefixes are rejected
+    /// without adding any of their prefixes.
     pub fn prefetch_tx(
         prefixes: &mut BTreeSet<Prefix>,
         tx: types::transaction::Transaction,
     ) -> Result<(), RuntimeError> {
-        match R::Modules::prefetch(prefixes, &tx.call.method, tx.call.body, &tx.auth_info) {
-            module::DispatchResult::Handled(r) => r,
-            module::DispatchResult::Unhandled(_) => Ok(()), // Unimplemented prefetch is allowed.
+        let mut tx_prefixes = BTreeSet::new();
+        match R::Modules::prefetch(
+            &mut tx_prefixes,
+            &tx.call.method,
+            tx.call.body,
+            &tx.auth_info,
+        ) {
+            module::DispatchResult::Handled(r) => r?,
+            module::DispatchResult::Unhandled(_) => {} // Unimplemented prefetch is allowed.
+        }
+
+        if tx_prefixes.len() > usize::from(R::MAX_PREFETCH_PREFIXES_PER_TX) {
+            return Err(modules::core::Error::TooManyPrefetchPrefixes(
+                R::MAX_PREFETCH_PREFIXES_PER_TX,
+                tx_prefixes.len(),
+            )
+            .into());
         }
+        prefixes.extend(tx_prefixes);
+
+        Ok(())
     }
 
     fn handle_last_round_messages<C: Context>(ctx: &mut C) -> Result<(), modules::core::Error> {
@@ -302,20 +1139,37 @@ impl<R: Runtime> Dispatcher<R> {
             .unwrap_or_default();
 
         for event in message_events {
-            let handler = handlers
-                .remove(&event.index)
-                .ok_or(modules::core::Error::MessageHandlerMissing(event.index))?;
+            let index = event.index;
+            let handler = match handlers.remove(&index) {
+                Some(handler) => handler,
+                None => {
+                    Self::handle_dangling_message(
+                        ctx,
+                        index,
+                        None,
+                        modules::core::Error::MessageHandlerMissing(index),
+                    )?;
+                    continue;
+                }
+            };
             let hook_name = handler.hook_name.clone();
 
-            R::Modules::dispatch_message_result(
+            let result = R::Modules::dispatch_message_result(
                 ctx,
                 &hook_name,
                 types::message::MessageResult {
                     event,
                     context: handler.payload,
                 },
-            )
-            .ok_or(modules::core::Error::InvalidMethod(hook_name))?;
+            );
+            if result.is_none() {
+                Self::handle_dangling_message(
+                    ctx,
+                    index,
+                    Some(hook_name.clone()),
+                    modules::core::Error::InvalidMethod(hook_name),
+                )?;
+            }
         }
 
         if !handlers.is_empty() {
@@ -326,22 +1180,94 @@ impl<R: Runtime> Dispatcher<R> {
         Ok(())
     }
 
-    fn save_emitted_message_handlers<S: storage::Store>(
-        store: S,
-        handlers: Vec<types::message::MessageEventHookInvocation>,
+    /// Handle the result of a message for which no handler could be found. Unless the runtime
+    /// allows dangling messages, the given error is returned. Otherwise the message is recorded
+    /// as an event, if the event limit permits.
+    fn handle_dangling_message<C: Context>(
+        ctx: &mut C,
+        index: u32,
+        hook_name: Option<String>,
+        err: modules::core::Error,
+    ) -> Result<(), modules::core::Error> {
+        if !R::ALLOW_DANGLING_MESSAGES {
+            return Err(err);
+        }
+
+        warn!(ctx.get_logger("dispatcher"), "ignoring dangling message";
+            "index" => index,
+            "hook_name" => ?hook_name,
+            "err" => ?err,
+        );
+        ctx.emit_event(modules::core::Event::DanglingMessage { index, hook_name });
+        Ok(())
+    }
+
+    /// Commit the batch context, returning the emitted block tags together with the emitted
+    /// messages and their handlers.
+    ///
+    /// Messages are ordered by emission: messages of earlier executed transactions come first and
+    /// messages of the same transaction are ordered by the calls to `emit_message`, regardless of
+    /// which module emitted them. The handler of each message is keyed by the index of the
+    /// message in the returned messages, which is the index that the consensus layer reports
+    /// the message result under in the next round.
+    ///
+    /// In case any messages have been emitted, a `MessagesEmitted` event summarizing them is
+    /// included in the block tags.
+    fn commit_batch<C: BatchContext>(
+        ctx: C,
+    ) -> (
+        Tags,
+        Vec<roothash::Message>,
+        BTreeMap<u32, types::message::MessageEventHookInvocation>,
     ) {
-        let message_handlers: BTreeMap<u32, types::message::MessageEventHookInvocation> = handlers
+        let (mut block_tags, messages) = ctx.commit();
+        let (messages, handlers): (Vec<_>, BTreeMap<_, _>) = messages
             .into_iter()
             .enumerate()
-            .map(|(idx, h)| (idx as u32, h))
-            .collect();
-
-        let mut store = storage::TypedStore::new(storage::PrefixStore::new(
-            store,
-            &modules::core::MODULE_NAME,
-        ));
-        store.insert(&modules::core::state::MESSAGE_HANDLERS, message_handlers);
-    }
+            .map(|(index, (message, handler))| {
+                let index: u32 = index.try_into().expect("message index should fit into u32");
+                (message, (index, handler))
+            })
+            .unzip();
+        assert_eq!(
+            messages.len(),
+            handlers.len(),
+            "every emitted message should have exactly one handler"
+        );
+
+        if !messages.is_empty() {
+            // The summary is added after the context has been committed so that it is not
+            // subject to the event limit.
+            block_tags.push(
+                modules::core::Event::MessagesEmitted {
+                    count: messages.len().try_into().unwrap_or(u32::MAX),
+                    handlers: handlers
+                        .values()
+                        .map(|handler| handler.hook_name.clone())
+                        .collect(),
+                }
+                .into_tag(),
+            );
+        }
+
+        (block_tags, messages, handlers)
+    }
+
+    /// Persist the handlers of the messages emitted in this round so that they can be invoked
+    /// once the results are reported in the next round.
+    ///
+    /// Handlers must be keyed by the global index of their message within the batch as returned
+    /// by `commit_batch` and not by any per-transaction index.
+    fn save_emitted_message_handlers<S: storage::Store>(
+        store: S,
+        handlers: BTreeMap<u32, types::message::MessageEventHookInvocation>,
+    ) {
+        let mut store = storage::TypedStore::new(storage::PrefixStore::new(
+            store,
+            &modules::core::MODULE_NAME,
+        ));
+        store.insert(&modules::core::state::MESSAGE_HANDLERS, handlers);
+    }
 
     /// Process the given runtime query.
     pub fn dispatch_query<C: BatchContext>(
@@ -349,27 +1275,72 @@ impl<R: Runtime> Dispatcher<R> {
         method: &str,
         args: Vec<u8>,
     ) -> Result<Vec<u8>, RuntimeError> {
+        check_cbor_limits(&args, R::MAX_CBOR_NESTING_DEPTH)
+            .map_err(modules::core::Error::InvalidArgument)?;
         let args = cbor::from_slice(&args)
             .map_err(|err| modules::core::Error::InvalidArgument(err.into()))?;
 
-        // Catch any panics that occur during query dispatch.
-        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            // Perform state migrations if required.
-            R::migrate(ctx);
+        // Child contexts do not inherit values, so fetch the runtime handle upfront.
+        let runtime_handle = ctx
+            .value::<TokioHandle>(CONTEXT_KEY_RUNTIME_HANDLE)
+            .get()
+            .cloned();
+
+        // Queries are executed in a child context which is never committed so that any state
+        // changes are discarded, including partial ones made by queries that panic.
+        let mode = ctx.mode();
+        ctx.with_child(mode, |mut ctx| {
+            let query = || -> Result<cbor::Value, RuntimeError> {
+                // Perform state migrations if required.
+                R::migrate_checked(&mut ctx)?;
+
+                // Execute the query.
+                let max_messages = ctx.remaining_messages();
+                let result = match method {
+                    // Internal methods.
+                    BATCH_WEIGHT_LIMIT_QUERY_METHOD => {
+                        let block_weight_limits = R::Modules::get_block_weight_limits(&mut ctx);
+                        Ok(cbor::to_value(block_weight_limits))
+                    }
+                    // Runtime methods. Queries are dispatched synchronously so that their handlers may
+                    // block (e.g., on key manager requests), only queries that are not handled that
+                    // way are driven to completion asynchronously.
+                    _ => match R::Modules::dispatch_query(&mut ctx, method, args) {
+                        module::DispatchResult::Handled(result) => result,
+                        module::DispatchResult::Unhandled(args)
+                            if R::Modules::has_async_queries() =>
+                        {
+                            let handle = runtime_handle.as_ref().ok_or_else(|| {
+                                Error::QueryAborted(
+                                    "no runtime available for asynchronous queries".to_string(),
+                                )
+                            })?;
+                            handle
+                                .block_on(R::Modules::dispatch_query_async(&mut ctx, method, args))
+                                .ok_or_else(|| modules::core::Error::InvalidMethod(method.into()))?
+                        }
+                        module::DispatchResult::Unhandled(_) => {
+                            Err(modules::core::Error::InvalidMethod(method.into()).into())
+                        }
+                    },
+                };
+                debug_assert_eq!(
+                    ctx.remaining_messages(),
+                    max_messages,
+                    "queries must not emit messages"
+                );
+                result
+            };
 
-            // Execute the query.
-            match method {
-                // Internal methods.
-                BATCH_WEIGHT_LIMIT_QUERY_METHOD => {
-                    let block_weight_limits = R::Modules::get_block_weight_limits(ctx);
-                    Ok(cbor::to_value(block_weight_limits))
-                }
-                // Runtime methods.
-                _ => R::Modules::dispatch_query(ctx, method, args)
-                    .ok_or_else(|| modules::core::Error::InvalidMethod(method.into()))?,
+            if !R::CATCH_QUERY_PANICS {
+                return query();
             }
-        }))
-        .map_err(|err| -> RuntimeError { Error::QueryAborted(format!("{:?}", err)).into() })?
+
+            // Catch any panics that occur during query dispatch.
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(query)).map_err(
+                |err| -> RuntimeError { Error::QueryAborted(panic_message(&*err)).into() },
+            )?
+        })
         .map(cbor::to_vec)
     }
 }
@@ -396,28 +1367,30 @@ impl<R: Runtime + Send + Sync> transaction::dispatcher::Dispatcher for Dispatche
             );
 
         // Perform state migrations if required.
-        R::migrate(&mut ctx);
+        R::migrate_checked(&mut ctx)?;
 
-        let mut txs = Vec::with_capacity(batch.len());
-        let mut prefixes: BTreeSet<Prefix> = BTreeSet::new();
-        for tx in batch.iter() {
-            let tx_size = tx.len().try_into().map_err(|_| {
-                Error::MalformedTransactionInBatch(anyhow!("transaction too large"))
-            })?;
-            // It is an error to include a malformed transaction in a batch. So instead of only
-            // reporting a failed execution result, we fail the whole batch. This will make the compute
-            // node vote for failure and the round will fail.
-            //
-            // Correct proposers should only include transactions which have passed check_tx.
-            let tx = Self::decode_tx(&mut ctx, tx)
-                .map_err(|err| Error::MalformedTransactionInBatch(err.into()))?;
-            txs.push((tx_size, tx.clone()));
-
-            if prefetch_enabled {
-                Self::prefetch_tx(&mut prefixes, tx)?;
-            }
+        if let Some(observer) = &self.state_observer {
+            Self::set_state_observer(&mut ctx, observer.clone());
+        }
+        if let Some(observer) = &self.execution_observer {
+            Self::set_execution_observer(&mut ctx, observer.clone());
         }
+
+        // It is an error to include a malformed transaction in a batch. So instead of only
+        // reporting a failed execution result, we fail the whole batch. This will make the compute
+        // node vote for failure and the round will fail.
+        //
+        // Correct proposers should only include transactions which have passed check_tx.
+        let mut txs = Self::decode_batch(&mut ctx, batch)?;
         if prefetch_enabled {
+            let mut prefixes: BTreeSet<Prefix> = BTreeSet::new();
+            for decoded in &txs {
+                Self::prefetch_tx(&mut prefixes, decoded.tx.clone())?;
+            }
+            debug!(ctx.get_logger("dispatcher"), "prefetching prefixes";
+                "count" => prefixes.len(),
+                "limit" => R::PREFETCH_LIMIT,
+            );
             ctx.runtime_state()
                 .prefetch_prefixes(prefixes.into_iter().collect(), R::PREFETCH_LIMIT);
         }
@@ -428,21 +1401,64 @@ impl<R: Runtime + Send + Sync> transaction::dispatcher::Dispatcher for Dispatche
         // Run begin block hooks.
         R::Modules::begin_block(&mut ctx);
 
-        // Execute the batch.
-        let mut results = Vec::with_capacity(batch.len());
-        for (index, (tx_size, tx)) in txs.into_iter().enumerate() {
-            results.push(Self::execute_tx(&mut ctx, tx_size, tx, index)?);
+        // Determine the order in which the transactions are executed.
+        Self::order_batch(&mut ctx, &mut txs)?;
+
+        // Execute the batch. Results are reported in the order of the transactions in the batch,
+        // regardless of the order in which they were executed.
+        let mut results: Vec<Option<(ExecuteTxResult, Hash)>> =
+            std::iter::repeat_with(|| None).take(batch.len()).collect();
+        let groups = if R::PARTITIONED_EXECUTION {
+            Self::partition_batch(&txs)
+        } else {
+            Vec::new()
+        };
+        if groups.len() > 1 {
+            let group_count = groups.len();
+            let (executed, reexecuted) = Self::execute_txs_speculatively(&mut ctx, txs, &groups)?;
+            debug!(ctx.get_logger("dispatcher"), "executed partitioned batch";
+                "count" => executed.len(),
+                "groups" => group_count,
+                "reexecuted" => reexecuted,
+            );
+            for (index, result) in executed {
+                results[index] = Some(result);
+            }
+        } else {
+            for decoded in txs {
+                let result = Self::execute_tx_with_events_root(
+                    &mut ctx,
+                    decoded.size,
+                    decoded.hash,
+                    decoded.tx,
+                    decoded.index,
+                )?;
+                results[decoded.index] = Some(result);
+            }
+        }
+        let (results, events_roots): (Vec<_>, Vec<_>) = results
+            .into_iter()
+            .map(|result| result.expect("all transactions should be executed"))
+            .unzip();
+
+        // Commit to the events of all transactions in the batch.
+        if R::EMIT_RECEIPT_TAGS {
+            ctx.emit_event(modules::core::Event::BlockEventsRoot {
+                root: receipt::block_events_root(&events_roots),
+            });
         }
 
         // Run end block hooks.
         R::Modules::end_block(&mut ctx);
 
+        // Check invariants in case this is required at the current round.
+        Self::maybe_check_invariants(&mut ctx)?;
+
         // Query block weight limits for next round.
         let block_weight_limits = R::Modules::get_block_weight_limits(&mut ctx);
 
         // Commit the context and retrieve the emitted messages.
-        let (block_tags, messages) = ctx.commit();
-        let (messages, handlers) = messages.into_iter().unzip();
+        let (block_tags, messages, handlers) = Self::commit_batch(ctx);
 
         let state = storage::MKVSStore::new(rt_ctx.io_ctx.clone(), &mut rt_ctx.runtime_state);
         Self::save_emitted_message_handlers(state, handlers);
@@ -476,28 +1492,41 @@ impl<R: Runtime + Send + Sync> transaction::dispatcher::Dispatcher for Dispatche
             );
 
         // Perform state migrations if required.
-        R::migrate(&mut ctx);
+        R::migrate_checked(&mut ctx)?;
+
+        // Track pending nonces of checked transactions.
+        modules::accounts::Module::set_pending_nonces(&mut ctx, self.pending_nonces.clone());
 
         // Prefetch.
         let mut txs: Vec<Result<_, RuntimeError>> = Vec::with_capacity(batch.len());
         let mut prefixes: BTreeSet<Prefix> = BTreeSet::new();
-        for tx in batch.iter() {
-            let tx_size = tx.len().try_into().map_err(|_| {
-                Error::MalformedTransactionInBatch(anyhow!("transaction too large"))
-            })?;
-            let res = match Self::decode_tx(&mut ctx, tx) {
-                Ok(tx) => {
+        for (index, tx) in batch.iter().enumerate() {
+            // A malformed transaction should only fail its own check and not the whole batch.
+            let tx_hash = Hash::digest_bytes(tx);
+            let res = match Self::decode_tx_for_check(&mut ctx, tx) {
+                Ok((tx_size, tx)) => {
                     if prefetch_enabled {
-                        Self::prefetch_tx(&mut prefixes, tx.clone()).map(|_| (tx_size, tx))
+                        Self::prefetch_tx(&mut prefixes, tx.clone()).map(|_| (tx_size, tx_hash, tx))
                     } else {
-                        Ok((tx_size, tx))
+                        Ok((tx_size, tx_hash, tx))
                     }
                 }
-                Err(err) => Err(err.into()),
+                Err(err) => {
+                    debug!(ctx.get_logger("dispatcher"), "malformed transaction in batch";
+                        "index" => index,
+                        "size" => tx.len(),
+                        "err" => ?err,
+                    );
+                    Err(err.into())
+                }
             };
             txs.push(res);
         }
         if prefetch_enabled {
+            debug!(ctx.get_logger("dispatcher"), "prefetching prefixes";
+                "count" => prefixes.len(),
+                "limit" => R::PREFETCH_LIMIT,
+            );
             ctx.runtime_state()
                 .prefetch_prefixes(prefixes.into_iter().collect(), R::PREFETCH_LIMIT);
         }
@@ -506,7 +1535,9 @@ impl<R: Runtime + Send + Sync> transaction::dispatcher::Dispatcher for Dispatche
         let mut results = Vec::with_capacity(batch.len());
         for tx in txs.into_iter() {
             match tx {
-                Ok((tx_size, tx)) => results.push(Self::check_tx(&mut ctx, tx_size, tx)?),
+                Ok((tx_size, tx_hash, tx)) => {
+                    results.push(Self::check_tx(&mut ctx, tx_size, tx_hash, tx)?)
+                }
                 Err(err) => results.push(CheckTxResult {
                     error: err,
                     meta: None,
@@ -539,6 +1570,1663 @@ impl<R: Runtime + Send + Sync> transaction::dispatcher::Dispatcher for Dispatche
                 key_manager,
             );
 
+        // Make pending nonces of checked transactions available to queries.
+        modules::accounts::Module::set_pending_nonces(&mut ctx, self.pending_nonces.clone());
+        if let Some(handle) = &self.runtime_handle {
+            Self::set_runtime_handle(&mut ctx, handle.clone());
+        }
+
         Self::dispatch_query(&mut ctx, method, args)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use io_context::Context as IoContext;
+
+    use oasis_core_runtime::consensus::state::ConsensusState;
+
+    use super::*;
+    use crate::{
+        context::Mode,
+        core::{common::version::Version, storage::mkvs::Iterator as _},
+        module::{BlockHandler as _, ParametersHandler as _},
+        modules::{accounts, core},
+        storage::Store as _,
+        testing::{keys, mock},
+        types::{
+            address::{Address, SignatureAddressSpec},
+            token, transaction,
+        },
+    };
+
+    /// Module that decodes plain transactions using a test-only encoding scheme.
+    struct TestDecoderModule;
+
+    impl TestDecoderModule {
+        const SCHEME: &'static str = "test";
+        const METHOD_NOOP: &'static str = "test.Noop";
+        const METHOD_INCREMENT: &'static str = "test.Increment";
+        const METHOD_PANIC: &'static str = "test.Panic";
+        const METHOD_ASYNC: &'static str = "test.Async";
+        const METHOD_BLOCKING: &'static str = "test.Blocking";
+        const METHOD_PREFETCH: &'static str = "test.Prefetch";
+        const KEY_INVARIANT_CHECKS: &'static [u8] = b"invariant_checks";
+        const KEY_COUNTER: &'static [u8] = b"counter";
+        const KEY_MESSAGE_RESULTS: &'static [u8] = b"message_results";
+        const HOOK_MESSAGE_RESULT: &'static str = "testdecoder.MessageResult";
+
+        fn invariant_checks<C: Context>(ctx: &mut C) -> u64 {
+            storage::TypedStore::new(storage::PrefixStore::new(ctx.runtime_state(), &Self::NAME))
+                .get(Self::KEY_INVARIANT_CHECKS)
+                .unwrap_or_default()
+        }
+
+        fn counter<C: Context>(ctx: &mut C) -> u64 {
+            storage::TypedStore::new(storage::PrefixStore::new(ctx.runtime_state(), &Self::NAME))
+                .get(Self::KEY_COUNTER)
+                .unwrap_or_default()
+        }
+
+        /// Message results handled so far as pairs of message index and handler context.
+        fn message_results<C: Context>(ctx: &mut C) -> Vec<(u32, String)> {
+            storage::TypedStore::new(storage::PrefixStore::new(ctx.runtime_state(), &Self::NAME))
+                .get(Self::KEY_MESSAGE_RESULTS)
+                .unwrap_or_default()
+        }
+    }
+
+    impl module::Module for TestDecoderModule {
+        const NAME: &'static str = "testdecoder";
+        type Error = std::convert::Infallible;
+        type Event = ();
+        type Parameters = ();
+    }
+
+    impl module::MethodHandler for TestDecoderModule {
+        fn prefetch(
+            prefixes: &mut BTreeSet<Prefix>,
+            method: &str,
+            body: cbor::Value,
+            _auth_info: &transaction::AuthInfo,
+        ) -> module::DispatchResult<cbor::Value, Result<(), RuntimeError>> {
+            match method {
+                // Declares the given number of distinct prefixes.
+                Self::METHOD_PREFETCH => {
+                    let count: u64 = cbor::from_value(body).unwrap();
+                    prefixes.extend((0..count).map(|i| Prefix::from(i.to_be_bytes().to_vec())));
+                    module::DispatchResult::Handled(Ok(()))
+                }
+                _ => module::DispatchResult::Unhandled(body),
+            }
+        }
+
+        fn dispatch_call<C: TxContext>(
+            ctx: &mut C,
+            method: &str,
+            body: cbor::Value,
+        ) -> module::DispatchResult<cbor::Value, module::CallResult> {
+            match method {
+                Self::METHOD_NOOP => module::DispatchResult::Handled(module::CallResult::Ok(
+                    cbor::Value::Simple(cbor::SimpleValue::NullValue),
+                )),
+                Self::METHOD_INCREMENT => {
+                    let counter = Self::counter(ctx) + 1;
+                    storage::TypedStore::new(storage::PrefixStore::new(
+                        ctx.runtime_state(),
+                        &Self::NAME,
+                    ))
+                    .insert(Self::KEY_COUNTER, counter);
+                    module::DispatchResult::Handled(module::CallResult::Ok(cbor::to_value(counter)))
+                }
+                _ => module::DispatchResult::Unhandled(body),
+            }
+        }
+
+        fn dispatch_query<C: Context>(
+            ctx: &mut C,
+            method: &str,
+            args: cbor::Value,
+        ) -> module::DispatchResult<cbor::Value, Result<cbor::Value, RuntimeError>> {
+            match method {
+                Self::METHOD_PANIC => {
+                    // Modify state before panicking.
+                    storage::TypedStore::new(storage::PrefixStore::new(
+                        ctx.runtime_state(),
+                        &Self::NAME,
+                    ))
+                    .insert(Self::KEY_COUNTER, 42u64);
+                    panic!("query panicked: {}", 42);
+                }
+                // Blocks on a future, as the key manager client does.
+                Self::METHOD_BLOCKING => {
+                    let runtime = tokio::runtime::Builder::new_current_thread()
+                        .build()
+                        .unwrap();
+                    module::DispatchResult::Handled(Ok(cbor::to_value(
+                        runtime.block_on(async { 42u64 }),
+                    )))
+                }
+                _ => module::DispatchResult::Unhandled(args),
+            }
+        }
+
+        fn dispatch_query_async<'a, C: Context + 'a>(
+            ctx: &'a mut C,
+            method: &'a str,
+            args: cbor::Value,
+        ) -> module::BoxFuture<
+            'a,
+            module::DispatchResult<cbor::Value, Result<cbor::Value, RuntimeError>>,
+        >
+        where
+            Self: 'a,
+        {
+            if method != Self::METHOD_ASYNC {
+                return Box::pin(std::future::ready(Self::dispatch_query(ctx, method, args)));
+            }
+
+            Box::pin(async move {
+                // Hold the context across an await point.
+                let before = Self::counter(ctx);
+                tokio::task::yield_now().await;
+                let after = Self::counter(ctx);
+                module::DispatchResult::Handled(Ok(cbor::to_value(before + after)))
+            })
+        }
+
+        fn has_async_queries() -> bool {
+            true
+        }
+
+        fn dispatch_message_result<C: Context>(
+            ctx: &mut C,
+            handler_name: &str,
+            result: types::message::MessageResult,
+        ) -> module::DispatchResult<types::message::MessageResult, ()> {
+            if handler_name != Self::HOOK_MESSAGE_RESULT {
+                return module::DispatchResult::Unhandled(result);
+            }
+
+            let mut results = Self::message_results(ctx);
+            results.push((
+                result.event.index,
+                cbor::from_value(result.context).expect("context should be valid"),
+            ));
+            storage::TypedStore::new(storage::PrefixStore::new(ctx.runtime_state(), &Self::NAME))
+                .insert(Self::KEY_MESSAGE_RESULTS, results);
+            module::DispatchResult::Handled(())
+        }
+    }
+    impl module::BlockHandler for TestDecoderModule {}
+    impl module::AuthHandler for TestDecoderModule {
+        fn decode_tx<C: Context>(
+            _ctx: &mut C,
+            scheme: &str,
+            body: &[u8],
+        ) -> Result<Option<Transaction>, modules::core::Error> {
+            if scheme != Self::SCHEME {
+                return Ok(None);
+            }
+            let tx = cbor::from_slice(body)
+                .map_err(|e| modules::core::Error::MalformedTransaction(e.into()))?;
+            Ok(Some(tx))
+        }
+    }
+    impl module::MigrationHandler for TestDecoderModule {
+        type Genesis = ();
+    }
+    impl module::EventHandler for TestDecoderModule {}
+    impl module::InvariantHandler for TestDecoderModule {
+        fn check_invariants<C: Context>(ctx: &mut C) -> Result<(), modules::core::Error> {
+            // Count invocations so that the cadence of checks can be observed.
+            let mut store = storage::TypedStore::new(storage::PrefixStore::new(
+                ctx.runtime_state(),
+                &Self::NAME,
+            ));
+            let count: u64 = store.get(Self::KEY_INVARIANT_CHECKS).unwrap_or_default();
+            store.insert(Self::KEY_INVARIANT_CHECKS, count + 1);
+            Ok(())
+        }
+    }
+
+    struct TestRuntime;
+
+    const TEST_FEATURE: u64 = 1 << 0;
+
+    impl Runtime for TestRuntime {
+        const VERSION: Version = Version::new(0, 0, 0);
+        const SUPPORTED_TX_FEATURES: u64 = TEST_FEATURE;
+
+        type Modules = (core::Module, TestDecoderModule);
+
+        fn genesis_state() -> (core::Genesis, ()) {
+            (
+                core::Genesis {
+                    parameters: core::Parameters {
+                        max_batch_gas: u64::MAX,
+                        max_tx_signers: 8,
+                        max_multisig_signers: 8,
+                        min_gas_price: {
+                            let mut mgp = BTreeMap::new();
+                            mgp.insert(token::Denomination::NATIVE, 0);
+                            mgp
+                        },
+                        ..Default::default()
+                    },
+                },
+                (),
+            )
+        }
+    }
+
+    struct PriorityRuntime;
+
+    impl Runtime for PriorityRuntime {
+        const VERSION: Version = Version::new(0, 0, 0);
+        const PRIORITY_ORDERING: bool = true;
+
+        type Modules = (core::Module, TestDecoderModule);
+
+        fn genesis_state() -> (core::Genesis, ()) {
+            TestRuntime::genesis_state()
+        }
+    }
+
+    /// Module whose parameters are never initialized.
+    struct UninitializedModule;
+
+    #[derive(Debug, Default, cbor::Encode, cbor::Decode)]
+    struct UninitializedParameters {
+        value: u64,
+    }
+
+    impl module::Parameters for UninitializedParameters {
+        type Error = std::convert::Infallible;
+    }
+
+    impl module::Module for UninitializedModule {
+        const NAME: &'static str = "uninitialized";
+        type Error = std::convert::Infallible;
+        type Event = ();
+        type Parameters = UninitializedParameters;
+    }
+
+    impl module::MethodHandler for UninitializedModule {}
+    impl module::BlockHandler for UninitializedModule {}
+    impl module::AuthHandler for UninitializedModule {}
+    impl module::MigrationHandler for UninitializedModule {
+        type Genesis = ();
+    }
+    impl module::EventHandler for UninitializedModule {}
+    impl module::InvariantHandler for UninitializedModule {}
+
+    struct StrictRuntime;
+
+    impl Runtime for StrictRuntime {
+        const VERSION: Version = Version::new(0, 0, 0);
+        const STRICT_PARAMETERS: bool = true;
+
+        type Modules = (core::Module, TestDecoderModule, UninitializedModule);
+
+        fn genesis_state() -> (core::Genesis, (), ()) {
+            (TestRuntime::genesis_state().0, (), ())
+        }
+    }
+
+    /// Runtime with accounts that executes partitioned batches.
+    struct SpeculativeRuntime;
+
+    impl SpeculativeRuntime {
+        fn signers() -> Vec<(SignatureAddressSpec, Address)> {
+            vec![
+                (keys::alice::sigspec(), keys::alice::address()),
+                (keys::bob::sigspec(), keys::bob::address()),
+                (keys::charlie::sigspec(), keys::charlie::address()),
+                (keys::dave::sigspec(), keys::dave::address()),
+            ]
+        }
+    }
+
+    impl Runtime for SpeculativeRuntime {
+        const VERSION: Version = Version::new(0, 0, 0);
+        const PARTITIONED_EXECUTION: bool = true;
+
+        type Modules = (core::Module, accounts::Module);
+
+        fn genesis_state() -> (core::Genesis, accounts::Genesis) {
+            let signers = Self::signers();
+            (
+                core::Genesis {
+                    parameters: core::Parameters {
+                        max_batch_gas: 20_000,
+                        ..TestRuntime::genesis_state().0.parameters
+                    },
+                },
+                accounts::Genesis {
+                    parameters: accounts::Parameters {
+                        gas_costs: accounts::GasCosts {
+                            tx_transfer: 1_000,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    balances: signers
+                        .iter()
+                        .map(|(_, address)| {
+                            let mut denominations = BTreeMap::new();
+                            denominations.insert(token::Denomination::NATIVE, 1_000_000);
+                            (*address, denominations)
+                        })
+                        .collect(),
+                    total_supplies: {
+                        let mut total_supplies = BTreeMap::new();
+                        total_supplies.insert(
+                            token::Denomination::NATIVE,
+                            1_000_000 * signers.len() as u128,
+                        );
+                        total_supplies
+                    },
+                    ..Default::default()
+                },
+            )
+        }
+    }
+
+    struct ReceiptRuntime;
+
+    impl Runtime for ReceiptRuntime {
+        const VERSION: Version = Version::new(0, 0, 0);
+        const EMIT_RECEIPT_TAGS: bool = true;
+
+        type Modules = (core::Module, TestDecoderModule);
+
+        fn genesis_state() -> (core::Genesis, ()) {
+            TestRuntime::genesis_state()
+        }
+    }
+
+    struct LenientRuntime;
+
+    impl Runtime for LenientRuntime {
+        const VERSION: Version = Version::new(0, 0, 0);
+        const ALLOW_DANGLING_MESSAGES: bool = true;
+
+        type Modules = (core::Module, TestDecoderModule);
+
+        fn genesis_state() -> (core::Genesis, ()) {
+            TestRuntime::genesis_state()
+        }
+    }
+
+    fn encode_tx(tx: &transaction::Transaction) -> Vec<u8> {
+        cbor::to_vec(transaction::UnverifiedTransaction(
+            cbor::to_vec(tx.clone()),
+            vec![AuthProof::Module(TestDecoderModule::SCHEME.to_owned())],
+        ))
+    }
+
+    /// Decode the given transactions as if they formed a batch.
+    fn decode_txs(txs: Vec<transaction::Transaction>) -> Vec<DecodedTx> {
+        txs.into_iter()
+            .enumerate()
+            .map(|(index, tx)| {
+                let encoded = encode_tx(&tx);
+                DecodedTx {
+                    index,
+                    size: encoded.len() as u32,
+                    hash: Hash::digest_bytes(&encoded),
+                    tx,
+                }
+            })
+            .collect()
+    }
+
+    /// Outcome of executing a batch with `SpeculativeRuntime`.
+    struct SpeculativeOutcome {
+        /// Index, output, tags and events root of each executed transaction.
+        results: Vec<(usize, Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>, Hash)>,
+        batch_gas_used: u64,
+        /// State at the end of the block.
+        state: Vec<(Vec<u8>, Vec<u8>)>,
+        /// Number of discarded speculations.
+        reexecuted: usize,
+    }
+
+    /// Execute the given transactions with `SpeculativeRuntime`, speculatively in the given groups
+    /// or one after another in case no groups are given.
+    fn execute_speculative_batch(
+        txs: &[DecodedTx],
+        groups: Option<&[Vec<usize>]>,
+    ) -> SpeculativeOutcome {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<SpeculativeRuntime>(Mode::ExecuteTx);
+        SpeculativeRuntime::migrate(&mut ctx);
+
+        let (results, reexecuted) = match groups {
+            Some(groups) => Dispatcher::<SpeculativeRuntime>::execute_txs_speculatively(
+                &mut ctx,
+                txs.to_vec(),
+                groups,
+            )
+            .expect("batch should not be aborted"),
+            None => {
+                let results = txs
+                    .iter()
+                    .cloned()
+                    .map(|decoded| {
+                        let result = Dispatcher::<SpeculativeRuntime>::execute_tx_with_events_root(
+                            &mut ctx,
+                            decoded.size,
+                            decoded.hash,
+                            decoded.tx,
+                            decoded.index,
+                        )
+                        .expect("batch should not be aborted");
+                        (decoded.index, result)
+                    })
+                    .collect();
+                (results, 0)
+            }
+        };
+        let results: Vec<_> = results
+            .into_iter()
+            .map(|(index, (result, events_root))| {
+                let tags: Vec<_> = result
+                    .tags
+                    .into_iter()
+                    .map(|tag| (tag.key, tag.value))
+                    .collect();
+                (index, result.output, tags, events_root)
+            })
+            .collect();
+        let batch_gas_used = core::Module::take_used_batch_gas(&mut ctx);
+
+        // Fees are only disbursed at the end of the block.
+        <SpeculativeRuntime as Runtime>::Modules::end_block(&mut ctx);
+        let state: Vec<_> = {
+            let mut it = ctx.runtime_state().iter();
+            it.rewind();
+            it.collect()
+        };
+
+        SpeculativeOutcome {
+            results,
+            batch_gas_used,
+            state,
+            reexecuted,
+        }
+    }
+
+    #[test]
+    fn test_check_batch_partial_failures() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::CheckTx);
+        TestRuntime::migrate(&mut ctx);
+
+        let mut tx = mock::transaction();
+        tx.call.method = TestDecoderModule::METHOD_NOOP.to_owned();
+        tx.auth_info.fee.consensus_messages = 0;
+        tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(
+            keys::alice::sigspec(),
+            0,
+        )];
+        let good = encode_tx(&tx);
+
+        let batch = vec![good.clone(), b"malformed".to_vec(), good.clone()];
+        let results: Vec<_> = batch
+            .iter()
+            .map(|tx| Dispatcher::<TestRuntime>::decode_tx_for_check(&mut ctx, tx))
+            .collect();
+
+        // Only the malformed transaction should fail.
+        assert_eq!(results.len(), 3);
+        let (tx_size, decoded) = results[0].as_ref().expect("good transaction should decode");
+        assert_eq!(*tx_size as usize, good.len());
+        assert_eq!(decoded.call.method, TestDecoderModule::METHOD_NOOP);
+        assert!(matches!(
+            results[1],
+            Err(modules::core::Error::MalformedTransaction(_))
+        ));
+        assert!(results[2].is_ok(), "good transaction should decode");
+
+        // Each decoded transaction should pass its own check.
+        for res in results.into_iter().flatten() {
+            let (tx_size, tx) = res;
+            let tx_hash = Hash::digest_bytes(&good);
+            let result = Dispatcher::<TestRuntime>::check_tx(&mut ctx, tx_size, tx_hash, tx)
+                .expect("check_tx should not fail the batch");
+            assert_eq!(result.error.code, 0, "good transaction should pass check");
+        }
+
+        // Oversized transactions should fail with a per-transaction error.
+        let err = encoded_tx_size(usize::MAX).expect_err("oversized transaction should fail");
+        assert!(matches!(err, modules::core::Error::MalformedTransaction(_)));
+        let err: RuntimeError = err.into();
+        assert_eq!(err.module, modules::core::MODULE_NAME);
+    }
+
+    #[test]
+    fn test_check_batch() {
+        use oasis_core_runtime::transaction::dispatcher::Dispatcher as _;
+
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::CheckTx);
+        TestRuntime::migrate(&mut ctx);
+        drop(ctx);
+
+        let mut tx = mock::transaction();
+        tx.call.method = TestDecoderModule::METHOD_NOOP.to_owned();
+        tx.auth_info.fee.consensus_messages = 0;
+        tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(
+            keys::alice::sigspec(),
+            0,
+        )];
+        let good = encode_tx(&tx);
+        let batch = TxnBatch::new(vec![good.clone(), b"malformed".to_vec(), good]);
+
+        let dispatcher = Dispatcher::<TestRuntime>::new(mock.host_info.clone(), None, None);
+        let consensus_state = ConsensusState::new(
+            mkvs::Tree::make()
+                .with_root_type(mkvs::RootType::State)
+                .new(Box::new(mkvs::sync::NoopReadSyncer)),
+        );
+        let rt_ctx = oasis_core_runtime::transaction::Context::new(
+            IoContext::background().freeze(),
+            consensus_state,
+            mock.mkvs.as_mut(),
+            &mock.runtime_header,
+            mock.epoch,
+            &mock.runtime_round_results,
+            mock.max_messages,
+            true,
+        );
+        let results = dispatcher
+            .check_batch(rt_ctx, &batch)
+            .expect("malformed transactions should not fail the batch");
+
+        // Only the malformed transaction should fail its check.
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results[0].error.code, 0,
+            "good transaction should pass check"
+        );
+        assert_eq!(results[1].error.module, modules::core::MODULE_NAME);
+        assert_eq!(
+            results[1].error.code, 1,
+            "malformed transaction should fail with MalformedTransaction"
+        );
+        assert!(results[1].meta.is_none());
+        assert_eq!(
+            results[2].error.code, 0,
+            "good transaction should pass check"
+        );
+    }
+
+    #[test]
+    fn test_check_tx_pending_nonces() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::CheckTx);
+        TestRuntime::migrate(&mut ctx);
+
+        let pending_nonces: Arc<Mutex<modules::accounts::PendingNonces>> = Default::default();
+        modules::accounts::Module::set_pending_nonces(&mut ctx, pending_nonces.clone());
+        let pending_nonce = || {
+            pending_nonces
+                .lock()
+                .unwrap()
+                .get(&keys::alice::address(), 0, 0)
+        };
+
+        let mut tx = mock::transaction();
+        tx.auth_info.fee.consensus_messages = 0;
+        tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(
+            keys::alice::sigspec(),
+            5,
+        )];
+
+        fn check<C: BatchContext>(ctx: &mut C, tx: &transaction::Transaction) -> CheckTxResult {
+            let encoded = encode_tx(tx);
+            Dispatcher::<TestRuntime>::check_tx(
+                ctx,
+                encoded.len() as u32,
+                Hash::digest_bytes(&encoded),
+                tx.clone(),
+            )
+            .expect("check_tx should not fail the batch")
+        }
+
+        // Transactions failing their check should not advance the pending nonce.
+        tx.call.method = "test.Unknown".to_owned();
+        let result = check(&mut ctx, &tx);
+        assert_ne!(result.error.code, 0, "unknown method should fail check");
+        assert_eq!(pending_nonce(), None);
+
+        // Transactions passing their check should advance the pending nonce.
+        tx.call.method = TestDecoderModule::METHOD_NOOP.to_owned();
+        let result = check(&mut ctx, &tx);
+        assert_eq!(result.error.code, 0, "good transaction should pass check");
+        assert_eq!(pending_nonce(), Some(6));
+    }
+
+    #[test]
+    fn test_invariant_check_interval() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        TestRuntime::migrate(&mut ctx);
+
+        // By default invariants are only checked on demand.
+        Dispatcher::<TestRuntime>::maybe_check_invariants(&mut ctx)
+            .expect("invariant checks should succeed");
+        assert_eq!(TestDecoderModule::invariant_checks(&mut ctx), 0);
+
+        let mut params = core::Module::params(ctx.runtime_state());
+        params.invariant_check_interval = 3;
+        core::Module::set_params(ctx.runtime_state(), params);
+
+        // Execute rounds 1 to 9, invariants should be checked in rounds 3, 6 and 9.
+        let mut checked = vec![];
+        for round in 1..=9 {
+            mock.runtime_header.round = round - 1;
+            let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+            let before = TestDecoderModule::invariant_checks(&mut ctx);
+            Dispatcher::<TestRuntime>::maybe_check_invariants(&mut ctx)
+                .expect("invariant checks should succeed");
+            if TestDecoderModule::invariant_checks(&mut ctx) > before {
+                checked.push(round);
+            }
+        }
+        assert_eq!(checked, vec![3, 6, 9]);
+    }
+
+    fn replay_batch<C: BatchContext>(ctx: &mut C, batch: &[Vec<u8>]) -> Vec<ExecuteTxResult> {
+        batch
+            .iter()
+            .enumerate()
+            .map(|(index, raw)| {
+                let tx_size = encoded_tx_size(raw.len()).unwrap();
+                let tx_hash = Hash::digest_bytes(raw);
+                let tx = Dispatcher::<TestRuntime>::decode_tx(ctx, raw)
+                    .expect("transaction should decode");
+                Dispatcher::<TestRuntime>::replay_tx(ctx, tx_size, tx_hash, tx, index)
+                    .expect("replay should succeed")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_replay_tx() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        TestRuntime::migrate(&mut ctx);
+
+        let batch: Vec<_> = (0..2)
+            .map(|nonce| {
+                let mut tx = mock::transaction();
+                tx.call.method = TestDecoderModule::METHOD_INCREMENT.to_owned();
+                tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(
+                    keys::alice::sigspec(),
+                    nonce,
+                )];
+                encode_tx(&tx)
+            })
+            .collect();
+
+        let first = replay_batch(&mut ctx, &batch);
+        assert_eq!(TestDecoderModule::counter(&mut ctx), 2);
+
+        // Replaying the same batch again should not apply its effects again.
+        let second = replay_batch(&mut ctx, &batch);
+        assert_eq!(
+            TestDecoderModule::counter(&mut ctx),
+            2,
+            "state should not be double-applied"
+        );
+
+        // The stored results should be returned instead.
+        assert_eq!(first.len(), second.len());
+        for (first, second) in first.iter().zip(second.iter()) {
+            assert_eq!(first.output, second.output);
+            assert_eq!(
+                ReplayedTx::from(first).tags,
+                ReplayedTx::from(second).tags,
+                "tags should be preserved"
+            );
+        }
+        let output: types::transaction::CallResult =
+            cbor::from_slice(&second[1].output).expect("output should be well-formed");
+        assert!(matches!(
+            output,
+            types::transaction::CallResult::Ok(value) if value == cbor::to_value(2u64)
+        ));
+    }
+
+    #[test]
+    fn test_receipt_tags() {
+        let mut tx = mock::transaction();
+        tx.call.method = TestDecoderModule::METHOD_INCREMENT.to_owned();
+        tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(
+            keys::alice::sigspec(),
+            0,
+        )];
+        let raw = encode_tx(&tx);
+        let tx_size = encoded_tx_size(raw.len()).unwrap();
+        let tx_hash = Hash::digest_bytes(&raw);
+        let is_core_tag = |tag: &Tag, code: u32| {
+            event::parse_event_key(&tag.key) == Some((modules::core::MODULE_NAME, code))
+        };
+
+        // Receipt tags should not be emitted by default.
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        TestRuntime::migrate(&mut ctx);
+        let result =
+            Dispatcher::<TestRuntime>::execute_tx(&mut ctx, tx_size, tx_hash, tx.clone(), 0)
+                .expect("transaction execution should not abort");
+        assert!(
+            !result
+                .tags
+                .iter()
+                .any(|tag| is_core_tag(tag, 1) || is_core_tag(tag, 5)),
+            "receipt tags should not be emitted by default"
+        );
+
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<ReceiptRuntime>(Mode::ExecuteTx);
+        ReceiptRuntime::migrate(&mut ctx);
+        let result = Dispatcher::<ReceiptRuntime>::execute_tx(&mut ctx, tx_size, tx_hash, tx, 0)
+            .expect("transaction execution should not abort");
+        let (last, rest) = result
+            .tags
+            .split_last()
+            .expect("receipt tags should be emitted");
+        let events_root = modules::core::Event::EventsRoot {
+            root: receipt::events_root(rest),
+        }
+        .into_tag();
+        assert!(is_core_tag(last, 1), "last tag should be the events root");
+        assert_eq!(last.value, events_root.value);
+        assert!(
+            rest.iter().any(|tag| is_core_tag(tag, 5)),
+            "gas used event should be emitted"
+        );
+    }
+
+    #[test]
+    fn test_tx_features() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::CheckTx);
+        TestRuntime::migrate(&mut ctx);
+
+        let mut tx = mock::transaction();
+        tx.call.method = TestDecoderModule::METHOD_NOOP.to_owned();
+
+        // Supported features should be accepted.
+        tx.auth_info.features = TEST_FEATURE;
+        let decoded = Dispatcher::<TestRuntime>::decode_tx(&mut ctx, &encode_tx(&tx))
+            .expect("supported feature should be accepted");
+        assert_eq!(decoded.auth_info.features, TEST_FEATURE);
+
+        // Unsupported features should be rejected.
+        tx.auth_info.features = TEST_FEATURE | 1 << 5;
+        let err = Dispatcher::<TestRuntime>::decode_tx(&mut ctx, &encode_tx(&tx))
+            .expect_err("unsupported feature should be rejected");
+        assert!(matches!(
+            err,
+            modules::core::Error::UnsupportedFeature(features) if features == 1 << 5
+        ));
+    }
+
+    #[test]
+    fn test_query_panic() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::CheckTx);
+        TestRuntime::migrate(&mut ctx);
+
+        let err = Dispatcher::<TestRuntime>::dispatch_query(
+            &mut ctx,
+            TestDecoderModule::METHOD_PANIC,
+            cbor::to_vec(()),
+        )
+        .expect_err("panicking query should fail");
+        assert_eq!(err.module, MODULE_NAME);
+        assert_eq!(err.code, 3);
+        assert_eq!(err.message, "query aborted: query panicked: 42");
+
+        // State changes made by the query should be discarded.
+        assert_eq!(TestDecoderModule::counter(&mut ctx), 0);
+    }
+
+    #[test]
+    fn test_query_async() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::CheckTx);
+        TestRuntime::migrate(&mut ctx);
+        storage::TypedStore::new(storage::PrefixStore::new(
+            ctx.runtime_state(),
+            &TestDecoderModule::NAME,
+        ))
+        .insert(TestDecoderModule::KEY_COUNTER, 21u64);
+
+        // Asynchronous handlers need a runtime to drive them.
+        let err = Dispatcher::<TestRuntime>::dispatch_query(
+            &mut ctx,
+            TestDecoderModule::METHOD_ASYNC,
+            cbor::to_vec(()),
+        )
+        .expect_err("async query without a runtime should fail");
+        assert_eq!(err.module, MODULE_NAME);
+        assert_eq!(err.code, 3, "query should fail with QueryAborted");
+
+        // Asynchronous handlers should be awaited by the dispatcher.
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        Dispatcher::<TestRuntime>::set_runtime_handle(&mut ctx, runtime.handle().clone());
+        let result = Dispatcher::<TestRuntime>::dispatch_query(
+            &mut ctx,
+            TestDecoderModule::METHOD_ASYNC,
+            cbor::to_vec(()),
+        )
+        .expect("async query should succeed");
+        let result: u64 = cbor::from_slice(&result).unwrap();
+        assert_eq!(result, 42);
+
+        // Synchronous handlers should still be reachable.
+        let result =
+            Dispatcher::<TestRuntime>::dispatch_query(&mut ctx, "core.SafeMode", cbor::to_vec(()))
+                .expect("sync query should succeed");
+        let safe_mode: bool = cbor::from_slice(&result).unwrap();
+        assert!(!safe_mode);
+    }
+
+    #[test]
+    fn test_query_blocking() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::CheckTx);
+        TestRuntime::migrate(&mut ctx);
+
+        // Synchronous handlers should not be dispatched from within a future, so they may block.
+        let result = Dispatcher::<TestRuntime>::dispatch_query(
+            &mut ctx,
+            TestDecoderModule::METHOD_BLOCKING,
+            cbor::to_vec(()),
+        )
+        .expect("blocking query should succeed");
+        let result: u64 = cbor::from_slice(&result).unwrap();
+        assert_eq!(result, 42);
+
+        let err = Dispatcher::<TestRuntime>::dispatch_query(
+            &mut ctx,
+            "core.CallDataPublicKey",
+            cbor::to_vec(()),
+        )
+        .expect_err("key manager should not be available");
+        assert_eq!(err.module, modules::core::MODULE_NAME);
+        assert_eq!(err.code, 34, "query should fail with KeyManagerUnavailable");
+
+        // Batched queries should be dispatched synchronously as well.
+        let result = Dispatcher::<TestRuntime>::dispatch_query(
+            &mut ctx,
+            "core.BatchQuery",
+            cbor::to_vec(core::types::BatchQuery {
+                queries: vec![
+                    core::types::BatchQueryItem {
+                        method: TestDecoderModule::METHOD_BLOCKING.to_owned(),
+                        args: cbor::Value::Simple(cbor::SimpleValue::NullValue),
+                    },
+                    core::types::BatchQueryItem {
+                        method: "core.CallDataPublicKey".to_owned(),
+                        args: cbor::Value::Simple(cbor::SimpleValue::NullValue),
+                    },
+                ],
+            }),
+        )
+        .expect("batch query should succeed");
+        let response: core::types::BatchQueryResponse = cbor::from_slice(&result).unwrap();
+        assert!(matches!(
+            &response.results[0],
+            transaction::CallResult::Ok(value) if value == &cbor::to_value(42u64)
+        ));
+        assert!(matches!(
+            &response.results[1],
+            transaction::CallResult::Failed { code: 34, .. }
+        ));
+    }
+
+    #[test]
+    fn test_max_tx_size() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::CheckTx);
+        TestRuntime::migrate(&mut ctx);
+
+        let mut tx = mock::transaction();
+        tx.call.method = TestDecoderModule::METHOD_NOOP.to_owned();
+        let encoded = encode_tx(&tx);
+
+        let mut params = core::Module::params(ctx.runtime_state());
+        params.max_tx_size = Some(encoded.len() as u32);
+        core::Module::set_params(ctx.runtime_state(), params);
+
+        // Transactions within the limit should be accepted.
+        Dispatcher::<TestRuntime>::decode_tx(&mut ctx, &encoded)
+            .expect("transaction within the size limit should be accepted");
+
+        // Oversized blobs should be rejected before being decoded.
+        let blob = vec![0xff; encoded.len() + 1];
+        let err = Dispatcher::<TestRuntime>::decode_tx(&mut ctx, &blob)
+            .expect_err("oversized transaction should be rejected");
+        assert!(matches!(err, modules::core::Error::MalformedTransaction(_)));
+        assert!(err.to_string().contains("transaction too large"));
+    }
+
+    #[test]
+    fn test_prefetch_tx_limit() {
+        let prefetch = |prefixes: &mut BTreeSet<Prefix>, count: u64| {
+            let mut tx = mock::transaction();
+            tx.call.method = TestDecoderModule::METHOD_PREFETCH.to_owned();
+            tx.call.body = cbor::to_value(count);
+            Dispatcher::<TestRuntime>::prefetch_tx(prefixes, tx)
+        };
+
+        let mut prefixes = BTreeSet::new();
+        prefetch(
+            &mut prefixes,
+            TestRuntime::MAX_PREFETCH_PREFIXES_PER_TX.into(),
+        )
+        .expect("prefetch at the limit should succeed");
+        assert_eq!(
+            prefixes.len(),
+            usize::from(TestRuntime::MAX_PREFETCH_PREFIXES_PER_TX)
+        );
+
+        // Transactions over-declaring prefixes should be rejected without adding any prefixes.
+        let mut prefixes = BTreeSet::new();
+        let err = prefetch(
+            &mut prefixes,
+            u64::from(TestRuntime::MAX_PREFETCH_PREFIXES_PER_TX) + 1,
+        )
+        .expect_err("prefetch over the limit should fail");
+        assert_eq!(err.module, core::MODULE_NAME);
+        assert_eq!(err.code, 35);
+        assert!(prefixes.is_empty());
+    }
+
+    #[test]
+    fn test_decode_batch() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        TestRuntime::migrate(&mut ctx);
+
+        let mut tx = mock::transaction();
+        tx.call.method = TestDecoderModule::METHOD_NOOP.to_owned();
+        let first = encode_tx(&tx);
+        tx.call.method = TestDecoderModule::METHOD_INCREMENT.to_owned();
+        let second = encode_tx(&tx);
+
+        let batch = TxnBatch::new(vec![first.clone(), second.clone()]);
+        let mut txs = Dispatcher::<TestRuntime>::decode_batch(&mut ctx, &batch)
+            .expect("batch should be decoded");
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].index, 0);
+        assert_eq!(txs[0].size as usize, first.len());
+        assert_eq!(txs[0].hash, Hash::digest_bytes(&first));
+        assert_eq!(txs[0].tx.call.method, TestDecoderModule::METHOD_NOOP);
+        assert_eq!(txs[1].index, 1);
+        assert_eq!(txs[1].tx.call.method, TestDecoderModule::METHOD_INCREMENT);
+
+        // By default the batch order should be kept.
+        Dispatcher::<TestRuntime>::order_batch(&mut ctx, &mut txs)
+            .expect("ordering should succeed");
+        assert_eq!(
+            txs.iter().map(|tx| tx.index).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+
+        // Orderings that are not a permutation of the batch should abort it.
+        txs[1] = txs[0].clone();
+        assert!(matches!(
+            Dispatcher::<TestRuntime>::order_batch(&mut ctx, &mut txs),
+            Err(Error::Aborted)
+        ));
+
+        // Any malformed transaction should fail the whole batch.
+        let batch = TxnBatch::new(vec![first, vec![0xff]]);
+        assert!(matches!(
+            Dispatcher::<TestRuntime>::decode_batch(&mut ctx, &batch),
+            Err(Error::MalformedTransactionInBatch {
+                index: 1,
+                size: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cbor_nesting_limit() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::CheckTx);
+        TestRuntime::migrate(&mut ctx);
+
+        // Arrays of a single element nested the given number of times.
+        let nested = |depth: usize| {
+            let mut data = vec![0x81; depth];
+            data.push(0x00);
+            data
+        };
+
+        assert!(check_cbor_limits(&nested(64), 64).is_ok());
+        assert!(check_cbor_limits(&nested(65), 64).is_err());
+        // Containers claiming more elements than there are bytes should be rejected.
+        assert!(
+            check_cbor_limits(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], 64).is_err()
+        );
+        assert!(check_cbor_limits(&[0x5a, 0xff, 0xff, 0xff, 0xff], 64).is_err());
+        // Indefinite-length containers must be terminated.
+        assert!(check_cbor_limits(&[0x9f, 0x00, 0xff], 64).is_ok());
+        assert!(check_cbor_limits(&[0x9f, 0x00], 64).is_err());
+        assert!(check_cbor_limits(&[0x81, 0xff], 64).is_err());
+
+        // Deeply nested transactions should be rejected before decoding.
+        let err = Dispatcher::<TestRuntime>::decode_tx(&mut ctx, &nested(10_000))
+            .expect_err("deeply nested transaction should be rejected");
+        assert!(matches!(err, modules::core::Error::MalformedTransaction(_)));
+
+        // The same holds for the separately encoded transaction body.
+        let utx = cbor::to_vec(transaction::UnverifiedTransaction(
+            nested(10_000),
+            vec![AuthProof::Module(TestDecoderModule::SCHEME.to_owned())],
+        ));
+        let err = Dispatcher::<TestRuntime>::decode_tx(&mut ctx, &utx)
+            .expect_err("deeply nested transaction body should be rejected");
+        assert!(matches!(err, modules::core::Error::MalformedTransaction(_)));
+
+        // Deeply nested query arguments should be rejected as invalid.
+        let err =
+            Dispatcher::<TestRuntime>::dispatch_query(&mut ctx, "core.Parameters", nested(10_000))
+                .expect_err("deeply nested query arguments should be rejected");
+        assert_eq!(err.module, core::MODULE_NAME);
+        assert_eq!(err.code, 10);
+    }
+
+    #[test]
+    fn test_commit_batch_message_summary() {
+        let mut mock = mock::Mock::default();
+
+        // A batch without messages should not report a summary.
+        let ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        let (tags, messages, _) = Dispatcher::<TestRuntime>::commit_batch(ctx);
+        assert!(messages.is_empty());
+        assert!(
+            tags.is_empty(),
+            "no summary should be emitted without messages"
+        );
+
+        // Perform a withdrawal which emits a single consensus message.
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        let messages = ctx.with_tx(0, mock::transaction(), |mut tx_ctx, _call| {
+            let amount = token::BaseUnits::new(
+                1_000,
+                modules::consensus::Parameters::default().consensus_denomination,
+            );
+            <modules::consensus::Module as modules::consensus::API>::withdraw(
+                &mut tx_ctx,
+                keys::alice::address(),
+                &amount,
+                types::message::MessageEventHookInvocation::new("test.Withdrawn".to_owned(), 0),
+            )
+            .expect("withdraw should succeed");
+
+            let (_, messages) = tx_ctx.commit();
+            messages
+        });
+        ctx.emit_messages(messages)
+            .expect("message slots should be available");
+
+        let (tags, messages, handlers) = Dispatcher::<TestRuntime>::commit_batch(ctx);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(handlers.len(), 1);
+        assert_eq!(tags.len(), 1, "summary should be emitted");
+        assert_eq!(tags[0].key, b"core\x00\x00\x00\x03"); // core.MessagesEmitted (code = 3) event
+
+        #[derive(Debug, cbor::Decode)]
+        struct MessagesEmittedEvent {
+            count: u32,
+            handlers: Vec<String>,
+        }
+        let event: MessagesEmittedEvent = cbor::from_slice(&tags[0].value).unwrap();
+        assert_eq!(event.count, 1);
+        assert_eq!(event.handlers, vec!["test.Withdrawn".to_owned()]);
+    }
+
+    #[test]
+    fn test_message_handler_ordering() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        let amount = token::BaseUnits::new(
+            1_000,
+            modules::consensus::Parameters::default().consensus_denomination,
+        );
+
+        // Emit messages with handlers of two different modules from multiple transactions.
+        let hooks = vec![
+            vec!["first.Withdrawn", "second.Transferred", "first.Transferred"],
+            vec!["second.Withdrawn"],
+        ];
+        for hooks in hooks {
+            let messages = ctx.with_tx(0, mock::transaction(), |mut tx_ctx, _call| {
+                for hook in hooks {
+                    let hook = types::message::MessageEventHookInvocation::new(hook.to_owned(), 0);
+                    if hook.hook_name.ends_with("Withdrawn") {
+                        <modules::consensus::Module as modules::consensus::API>::withdraw(
+                            &mut tx_ctx,
+                            keys::alice::address(),
+                            &amount,
+                            hook,
+                        )
+                        .expect("withdraw should succeed");
+                    } else {
+                        <modules::consensus::Module as modules::consensus::API>::transfer(
+                            &mut tx_ctx,
+                            keys::alice::address(),
+                            &amount,
+                            hook,
+                        )
+                        .expect("transfer should succeed");
+                    }
+                }
+
+                let (_, messages) = tx_ctx.commit();
+                messages
+            });
+            ctx.emit_messages(messages)
+                .expect("message slots should be available");
+        }
+
+        // Handlers should be keyed by the index of their message in emission order.
+        let (_, messages, handlers) = Dispatcher::<TestRuntime>::commit_batch(ctx);
+        assert_eq!(messages.len(), 4);
+        let expected = vec![
+            (0, "first.Withdrawn"),
+            (1, "second.Transferred"),
+            (2, "first.Transferred"),
+            (3, "second.Withdrawn"),
+        ];
+        let actual: Vec<_> = handlers
+            .iter()
+            .map(|(index, handler)| (*index, handler.hook_name.as_str()))
+            .collect();
+        assert_eq!(actual, expected);
+
+        // The same mapping should be persisted for the next round.
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        Dispatcher::<TestRuntime>::save_emitted_message_handlers(ctx.runtime_state(), handlers);
+        let stored: BTreeMap<u32, types::message::MessageEventHookInvocation> =
+            storage::TypedStore::new(storage::PrefixStore::new(
+                ctx.runtime_state(),
+                &core::MODULE_NAME,
+            ))
+            .get(&core::state::MESSAGE_HANDLERS)
+            .unwrap();
+        let stored: Vec<_> = stored
+            .iter()
+            .map(|(index, handler)| (*index, handler.hook_name.as_str()))
+            .collect();
+        assert_eq!(stored, expected);
+    }
+
+    #[test]
+    fn test_message_handlers_next_round() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        let amount = token::BaseUnits::new(
+            1_000,
+            modules::consensus::Parameters::default().consensus_denomination,
+        );
+
+        // Each of the two transactions emits a single message, with its own handler context.
+        for context in ["first", "second"] {
+            let messages = ctx.with_tx(0, mock::transaction(), |mut tx_ctx, _call| {
+                <modules::consensus::Module as modules::consensus::API>::withdraw(
+                    &mut tx_ctx,
+                    keys::alice::address(),
+                    &amount,
+                    types::message::MessageEventHookInvocation::new(
+                        TestDecoderModule::HOOK_MESSAGE_RESULT.to_owned(),
+                        context.to_owned(),
+                    ),
+                )
+                .expect("withdraw should succeed");
+
+                let (_, messages) = tx_ctx.commit();
+                messages
+            });
+            ctx.emit_messages(messages)
+                .expect("message slots should be available");
+        }
+
+        // Message indices should be global across the batch and not restart for each transaction.
+        let (_, messages, handlers) = Dispatcher::<TestRuntime>::commit_batch(ctx);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(handlers.keys().copied().collect::<Vec<_>>(), vec![0, 1]);
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        Dispatcher::<TestRuntime>::save_emitted_message_handlers(ctx.runtime_state(), handlers);
+        drop(ctx);
+
+        // In the next round, results should resolve to the handler context of the message with
+        // the same index, regardless of the order in which results are reported.
+        mock.runtime_round_results.messages = [1, 0]
+            .iter()
+            .map(|&index| roothash::MessageEvent {
+                module: "staking".to_owned(),
+                code: 0,
+                index,
+            })
+            .collect();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        Dispatcher::<TestRuntime>::handle_last_round_messages(&mut ctx)
+            .expect("all message results should be handled");
+        assert_eq!(
+            TestDecoderModule::message_results(&mut ctx),
+            vec![(1, "second".to_owned()), (0, "first".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_dangling_messages() {
+        let mut mock = mock::Mock::default();
+        // Message 0 has no registered handler and message 1 has a handler which doesn't exist
+        // (e.g. because it has been renamed during an upgrade).
+        mock.runtime_round_results.messages = (0..2)
+            .map(|index| roothash::MessageEvent {
+                module: "staking".to_owned(),
+                code: 0,
+                index,
+            })
+            .collect();
+
+        fn setup<R: Runtime>(
+            mock: &mut mock::Mock,
+        ) -> RuntimeBatchContext<'_, R, storage::MKVSStore<&mut dyn mkvs::MKVS>> {
+            let mut ctx = mock.create_ctx_for_runtime::<R>(Mode::ExecuteTx);
+            let mut handlers = BTreeMap::new();
+            handlers.insert(
+                1u32,
+                types::message::MessageEventHookInvocation::new("test.Renamed".to_owned(), 0),
+            );
+            storage::TypedStore::new(storage::PrefixStore::new(
+                ctx.runtime_state(),
+                &core::MODULE_NAME,
+            ))
+            .insert(&core::state::MESSAGE_HANDLERS, handlers);
+            ctx
+        }
+
+        // By default, dangling messages should abort the block.
+        let mut ctx = setup::<TestRuntime>(&mut mock);
+        let result = Dispatcher::<TestRuntime>::handle_last_round_messages(&mut ctx);
+        assert!(matches!(result, Err(core::Error::MessageHandlerMissing(0))));
+        drop(ctx);
+
+        // When allowed, dangling messages should only be recorded.
+        let mut ctx = setup::<LenientRuntime>(&mut mock);
+        Dispatcher::<LenientRuntime>::handle_last_round_messages(&mut ctx)
+            .expect("dangling messages should be allowed");
+        let (tags, _) = ctx.commit();
+        assert_eq!(tags.len(), 2, "dangling messages should be recorded");
+
+        #[derive(Debug, cbor::Decode)]
+        struct DanglingMessageEvent {
+            index: u32,
+            hook_name: Option<String>,
+        }
+        let hook_names = [None, Some("test.Renamed")];
+        for (index, (tag, hook_name)) in tags.iter().zip(hook_names).enumerate() {
+            assert_eq!(tag.key, b"core\x00\x00\x00\x04"); // core.DanglingMessage (code = 4) event
+            let event: DanglingMessageEvent = cbor::from_slice(&tag.value).unwrap();
+            assert_eq!(event.index, index as u32);
+            assert_eq!(event.hook_name.as_deref(), hook_name);
+        }
+
+        // Dangling messages should still be allowed when they cannot all be recorded.
+        let mut ctx = setup::<LenientRuntime>(&mut mock);
+        ctx.limit_max_events(1).unwrap();
+        Dispatcher::<LenientRuntime>::handle_last_round_messages(&mut ctx)
+            .expect("dangling messages should be allowed when events are limited");
+        let (tags, _) = ctx.commit();
+        assert_eq!(
+            tags.len(),
+            1,
+            "only the first dangling message should be recorded"
+        );
+    }
+
+    #[test]
+    fn test_sort_by_priority() {
+        let txs: Vec<(u64, Hash)> = vec![
+            (10, Hash::digest_bytes(b"a")),
+            (20, Hash::digest_bytes(b"b")),
+            (10, Hash::digest_bytes(b"c")),
+            (10, Hash::digest_bytes(b"d")),
+            (5, Hash::digest_bytes(b"e")),
+            (10, Hash::digest_bytes(b"f")),
+        ];
+
+        let mut sorted = txs.clone();
+        sort_by_priority(&mut sorted, |tx| *tx);
+
+        // Transactions should be ordered by decreasing priority.
+        let priorities: Vec<_> = sorted.iter().map(|(priority, _)| *priority).collect();
+        assert_eq!(priorities, vec![20, 10, 10, 10, 10, 5]);
+
+        // Transactions with equal priority should be ordered by hash.
+        for pair in sorted[1..5].windows(2) {
+            assert!(
+                pair[0].1.as_ref() < pair[1].1.as_ref(),
+                "equal-priority transactions should be ordered by hash"
+            );
+        }
+
+        // The order should not depend on the order in which transactions were received.
+        for rotation in 1..txs.len() {
+            let mut rotated = txs.clone();
+            rotated.rotate_left(rotation);
+            rotated.reverse();
+            sort_by_priority(&mut rotated, |tx| *tx);
+            assert_eq!(rotated, sorted, "order should be deterministic");
+        }
+    }
+
+    #[test]
+    fn test_order_batch_by_priority() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<PriorityRuntime>(Mode::ExecuteTx);
+        PriorityRuntime::migrate(&mut ctx);
+
+        let decoded = |index: usize, gas_price: u128| {
+            let mut tx = mock::transaction();
+            tx.call.method = TestDecoderModule::METHOD_NOOP.to_owned();
+            tx.call.body = cbor::to_value(index as u64);
+            tx.auth_info.fee.amount = token::BaseUnits::new(
+                gas_price * u128::from(tx.auth_info.fee.gas),
+                token::Denomination::NATIVE,
+            );
+            let encoded = encode_tx(&tx);
+            DecodedTx {
+                index,
+                size: encoded.len() as u32,
+                hash: Hash::digest_bytes(&encoded),
+                tx,
+            }
+        };
+        let batch = vec![
+            decoded(0, 1),
+            decoded(1, 5),
+            decoded(2, 1),
+            decoded(3, 5),
+            decoded(4, 1),
+            decoded(5, 10),
+        ];
+
+        let mut txs = batch.clone();
+        Dispatcher::<PriorityRuntime>::order_batch(&mut ctx, &mut txs)
+            .expect("ordering should succeed");
+
+        // Transactions should be executed in order of decreasing priority.
+        let priorities: Vec<_> = txs
+            .iter()
+            .map(|decoded| decoded.tx.auth_info.fee.gas_price())
+            .collect();
+        assert_eq!(priorities, vec![10, 5, 5, 1, 1, 1]);
+
+        // Transactions with equal priority should be ordered by hash.
+        for pair in txs.windows(2) {
+            if pair[0].tx.auth_info.fee.gas_price() == pair[1].tx.auth_info.fee.gas_price() {
+                assert!(
+                    pair[0].hash.as_ref() < pair[1].hash.as_ref(),
+                    "equal-priority transactions should be ordered by hash"
+                );
+            }
+        }
+
+        // The order should not depend on the batch order.
+        let order: Vec<_> = txs.iter().map(|decoded| decoded.hash).collect();
+        let mut reversed = batch;
+        reversed.reverse();
+        Dispatcher::<PriorityRuntime>::order_batch(&mut ctx, &mut reversed)
+            .expect("ordering should succeed");
+        assert_eq!(
+            reversed
+                .iter()
+                .map(|decoded| decoded.hash)
+                .collect::<Vec<_>>(),
+            order,
+            "order should be deterministic"
+        );
+    }
+
+    #[test]
+    fn test_partition_by_prefixes() {
+        let prefixes = |prefixes: &[&[u8]]| -> Option<BTreeSet<Prefix>> {
+            Some(prefixes.iter().map(|p| Prefix::from(p.to_vec())).collect())
+        };
+
+        let groups = partition_by_prefixes(&[
+            prefixes(&[b"accounts/alice"]),
+            prefixes(&[b"accounts/bob"]),
+            prefixes(&[b"keyvalue/foo"]),
+            prefixes(&[b"accounts"]),
+            prefixes(&[b"keyvalue/bar"]),
+            prefixes(&[b"names/x", b"keyvalue/foo/baz"]),
+            prefixes(&[]),
+        ]);
+        assert_eq!(groups, vec![vec![0, 1, 3], vec![2, 5], vec![4], vec![6]]);
+
+        // Transactions with unknown prefixes conflict with all other transactions.
+        let groups = partition_by_prefixes(&[prefixes(&[b"a"]), None, prefixes(&[b"b"])]);
+        assert_eq!(groups, vec![vec![0, 1, 2]]);
+
+        assert!(partition_by_prefixes(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_missing_params() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<TestRuntime>(Mode::ExecuteTx);
+        TestRuntime::migrate(&mut ctx);
+
+        // Modules without parameters should never be reported as missing.
+        let mut missing = vec![];
+        <TestRuntime as Runtime>::Modules::missing_params(ctx.runtime_state(), &mut missing);
+        assert!(missing.is_empty(), "all parameters should be initialized");
+
+        let mut missing = vec![];
+        <StrictRuntime as Runtime>::Modules::missing_params(ctx.runtime_state(), &mut missing);
+        assert_eq!(missing, vec![UninitializedModule::NAME]);
+    }
+
+    #[test]
+    fn test_strict_params() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx_for_runtime::<StrictRuntime>(Mode::ExecuteTx);
+        let err = StrictRuntime::migrate_checked(&mut ctx)
+            .expect_err("uninitialized parameters should be rejected");
+        assert!(matches!(err, core::Error::UninitializedParameters(_)));
+        assert_eq!(
+            err.to_string(),
+            "parameters of modules [\"uninitialized\"] have not been initialized"
+        );
+    }
+
+    #[test]
+    fn test_speculative_execution() {
+        let signers = SpeculativeRuntime::signers();
+
+        // Mix transfers between signers, which depend on each other, with transfers to fresh
+        // accounts, transfers exceeding the balance of the signer and transfers with fees. Each
+        // signer sends a sequence of transactions so that later ones depend on the nonces updated
+        // by the earlier ones. The batch gas limit is exhausted towards the end of the batch.
+        let mut txs = vec![];
+        for round in 0..6u64 {
+            for (i, (sigspec, _)) in signers.iter().enumerate() {
+                let seq = round * signers.len() as u64 + i as u64;
+                let to = if seq % 3 == 0 {
+                    signers[(i + 1) % signers.len()].1
+                } else {
+                    Address::new(b"test", 0, &seq.to_be_bytes())
+                };
+                let amount = if seq % 7 == 6 {
+                    10_000_000
+                } else {
+                    1_000 * u128::from(round + 1)
+                };
+
+                let mut tx = mock::transaction();
+                tx.call.method = "accounts.Transfer".to_owned();
+                tx.call.body = cbor::to_value(accounts::types::Transfer {
+                    to,
+                    amount: token::BaseUnits::new(amount, token::Denomination::NATIVE),
+                });
+                tx.auth_info.signer_info =
+                    vec![transaction::SignerInfo::new_sigspec(sigspec.clone(), round)];
+                tx.auth_info.fee = transaction::Fee {
+                    amount: token::BaseUnits::new(
+                        if seq % 2 == 0 { 100 } else { 0 },
+                        token::Denomination::NATIVE,
+                    ),
+                    gas: 2_000,
+                    consensus_messages: 0,
+                };
+                txs.push(tx);
+            }
+        }
+        // Replaying a transaction fails due to its nonce.
+        txs.push(txs[0].clone());
+        let txs = decode_txs(txs);
+
+        let expected = execute_speculative_batch(&txs, None);
+        let groups: Vec<_> = (0..txs.len()).map(|idx| vec![idx]).collect();
+        let outcome = execute_speculative_batch(&txs, Some(&groups));
+        assert_eq!(
+            outcome.results, expected.results,
+            "results should match sequential execution"
+        );
+        assert_eq!(outcome.batch_gas_used, expected.batch_gas_used);
+        assert!(
+            outcome.state == expected.state,
+            "state should match sequential execution"
+        );
+
+        // The batch should contain both successful and failed transactions.
+        let outcomes: Vec<_> = outcome
+            .results
+            .iter()
+            .map(|(_, output, _, _)| {
+                cbor::from_slice::<types::transaction::CallResult>(output)
+                    .unwrap()
+                    .is_success()
+            })
+            .collect();
+        assert!(outcomes.iter().any(|success| *success));
+        assert!(outcomes.iter().any(|success| !*success));
+
+        // Independent transactions should be applied as speculated, while the others need to be
+        // executed again.
+        assert!(
+            outcome.reexecuted > 0,
+            "dependent transactions should be executed again"
+        );
+        assert!(
+            outcome.reexecuted < txs.len(),
+            "independent transactions should not be executed again"
+        );
+    }
+
+    #[test]
+    fn test_partitioned_execution() {
+        let signers = SpeculativeRuntime::signers();
+
+        // Alice and Bob as well as Charlie and Dave transfer tokens to each other, so the batch can
+        // be split into two groups. Some of the transfers exceed the balance of the signer.
+        let mut txs = vec![];
+        for round in 0..3u64 {
+            for (i, (sigspec, _)) in signers.iter().enumerate() {
+                let amount = if round == 1 && i % 2 == 0 {
+                    10_000_000
+                } else {
+                    1_000 * u128::from(round + 1)
+                };
+
+                let mut tx = mock::transaction();
+                tx.call.method = "accounts.Transfer".to_owned();
+                tx.call.body = cbor::to_value(accounts::types::Transfer {
+                    to: signers[i ^ 1].1,
+                    amount: token::BaseUnits::new(amount, token::Denomination::NATIVE),
+                });
+                tx.auth_info.signer_info =
+                    vec![transaction::SignerInfo::new_sigspec(sigspec.clone(), round)];
+                tx.auth_info.fee = transaction::Fee {
+                    amount: token::BaseUnits::new(
+                        if (round + i as u64) % 2 == 0 { 100 } else { 0 },
+                        token::Denomination::NATIVE,
+                    ),
+                    gas: 2_000,
+                    consensus_messages: 0,
+                };
+                txs.push(tx);
+            }
+        }
+        let txs = decode_txs(txs);
+
+        let groups = Dispatcher::<SpeculativeRuntime>::partition_batch(&txs);
+        assert_eq!(
+            groups,
+            vec![vec![0, 1, 4, 5, 8, 9], vec![2, 3, 6, 7, 10, 11]],
+            "transfers between different accounts should not conflict"
+        );
+
+        let expected = execute_speculative_batch(&txs, None);
+        let outcome = execute_speculative_batch(&txs, Some(&groups));
+        assert_eq!(
+            outcome.results, expected.results,
+            "results should match sequential execution"
+        );
+        assert_eq!(outcome.batch_gas_used, expected.batch_gas_used);
+        assert!(
+            outcome.state == expected.state,
+            "state should match sequential execution"
+        );
+        assert_eq!(outcome.reexecuted, 0, "groups should not conflict");
+
+        // Groups that do conflict (e.g., due to incomplete prefix declarations) should be detected
+        // and still yield the same results.
+        let groups: Vec<Vec<usize>> = vec![
+            (0..txs.len()).step_by(2).collect(),
+            (1..txs.len()).step_by(2).collect(),
+        ];
+        let outcome = execute_speculative_batch(&txs, Some(&groups));
+        assert_eq!(
+            outcome.results, expected.results,
+            "results should match sequential execution"
+        );
+        assert_eq!(outcome.batch_gas_used, expected.batch_gas_used);
+        assert!(
+            outcome.state == expected.state,
+            "state should match sequential execution"
+        );
+        assert!(
+            outcome.reexecuted > 0,
+            "conflicting transactions should be executed again"
+        );
+
+        // Batches with transactions whose prefixes are not known cannot be partitioned.
+        let mut unknown = txs.clone();
+        unknown[5].tx.call.method = "test.Unknown".to_owned();
+        assert_eq!(
+            Dispatcher::<SpeculativeRuntime>::partition_batch(&unknown),
+            vec![(0..txs.len()).collect::<Vec<_>>()]
+        );
+    }
+}
@@ -56,6 +56,17 @@ pub trait Error: std::error::Error {
     {
         Err(self)
     }
+
+    /// Error codes (together with their names) that this error type can produce on its own,
+    /// excluding any codes forwarded from other error types.
+    ///
+    /// This is used to populate the error registry which allows clients to decode errors.
+    fn known_codes() -> Vec<(u32, &'static str)>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
 }
 
 impl Error for std::convert::Infallible {
@@ -172,6 +183,17 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_known_codes() {
+        assert_eq!(
+            ChildError::known_codes(),
+            vec![(1, "Error1"), (2, "Error2")]
+        );
+        // Transparent variants should not be included.
+        assert_eq!(ParentError::known_codes(), vec![(1, "NotForwarded")]);
+        assert!(ParentParentError::known_codes().is_empty());
+    }
+
     #[test]
     fn test_error_sources_3() {
         let err = ParentParentError::Nested(ParentError::Nested(ChildError::Error1));
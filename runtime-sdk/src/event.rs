@@ -1,4 +1,6 @@
 //! Event types for runtimes.
+use std::convert::TryInto;
+
 use oasis_core_runtime::transaction::tags::Tag;
 
 /// An event emitted by the runtime.
@@ -65,3 +67,16 @@ pub fn tag_for_event(module_name: &str, code: u32, value: Vec<u8>) -> Tag {
         value,
     )
 }
+
+/// Split the key of a tag generated by [`tag_for_event`] into the module name and event code.
+///
+/// Returns `None` if the key is not a well-formed event key.
+pub fn parse_event_key(key: &[u8]) -> Option<(&str, u32)> {
+    if key.len() < 4 {
+        return None;
+    }
+    let (module_name, code) = key.split_at(key.len() - 4);
+    let module_name = std::str::from_utf8(module_name).ok()?;
+    let code = u32::from_be_bytes(code.try_into().ok()?);
+    Some((module_name, code))
+}
@@ -12,9 +12,11 @@ pub mod event;
 pub mod keymanager;
 pub mod module;
 pub mod modules;
+pub mod receipt;
 pub mod runtime;
 pub mod storage;
 pub mod testing;
+pub mod trace;
 pub mod types;
 
 pub use crate::{
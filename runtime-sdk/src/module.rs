@@ -1,7 +1,10 @@
 //! Runtime modules.
 use std::{
+    any::TypeId,
     collections::{BTreeMap, BTreeSet},
     fmt::Debug,
+    future::Future,
+    pin::Pin,
 };
 
 use impl_trait_for_tuples::impl_for_tuples;
@@ -20,6 +23,12 @@ use crate::{
     },
 };
 
+/// A boxed future returned by asynchronous method handlers.
+///
+/// The future is not required to be `Send` as dispatch contexts are bound to the thread that
+/// performs the dispatch.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
 /// Result of invoking the method handler.
 pub enum DispatchResult<B, R> {
     Handled(R),
@@ -178,6 +187,38 @@ pub trait MethodHandler {
         DispatchResult::Unhandled(args)
     }
 
+    /// Dispatch a query asynchronously. Queries submitted to the runtime are dispatched via
+    /// `dispatch_query` first, and only those not handled there are dispatched via this method in
+    /// modules reporting `has_async_queries`. Handlers which need to wait for I/O-bound work
+    /// should override both instead of blocking in `dispatch_query`.
+    ///
+    /// The context is exclusively borrowed for the lifetime of the returned future, so the
+    /// context and any stores created over its state may be held across await points. The
+    /// context is a child context that is discarded once the query completes, which means that
+    /// no state changes survive the query. The future is polled on the dispatching thread using
+    /// the host's Tokio runtime (the query fails in case there is none) and must not move the
+    /// context or anything borrowed from it to other tasks.
+    ///
+    /// Queries handled only here are not reachable via nested dispatch, e.g. `core.BatchQuery`.
+    fn dispatch_query_async<'a, C: Context + 'a>(
+        ctx: &'a mut C,
+        method: &'a str,
+        args: cbor::Value,
+    ) -> BoxFuture<'a, DispatchResult<cbor::Value, Result<cbor::Value, error::RuntimeError>>>
+    where
+        Self: 'a,
+    {
+        // Default implementation delegates to the synchronous dispatch.
+        Box::pin(std::future::ready(Self::dispatch_query(ctx, method, args)))
+    }
+
+    /// Whether the module overrides `dispatch_query_async` to handle queries that are not handled
+    /// by `dispatch_query`.
+    fn has_async_queries() -> bool {
+        // Default implementation only handles queries synchronously.
+        false
+    }
+
     /// Dispatch a message result.
     fn dispatch_message_result<C: Context>(
         _ctx: &mut C,
@@ -187,9 +228,23 @@ pub trait MethodHandler {
         // Default implementation indicates that the query was not handled.
         DispatchResult::Unhandled(result)
     }
+
+    /// Register any error codes the module can emit into the error registry.
+    ///
+    /// Modules can use `register_error_codes` to register the codes of their error type.
+    fn register_error_codes(_registry: &mut modules::core::types::ErrorRegistry) {
+        // Default implementation doesn't register any error codes.
+    }
+
+    /// Register any optional capabilities provided by the module (e.g. `consensus-deposits`) so
+    /// that clients can discover them.
+    fn register_capabilities(_capabilities: &mut modules::core::types::Capabilities) {
+        // Default implementation doesn't register any capabilities.
+    }
 }
 
 #[impl_for_tuples(30)]
+#[tuple_types_custom_trait_bound(Module + MethodHandler)]
 impl MethodHandler for Tuple {
     fn prefetch(
         prefixes: &mut BTreeSet<Prefix>,
@@ -213,11 +268,17 @@ impl MethodHandler for Tuple {
         method: &str,
         body: cbor::Value,
     ) -> DispatchResult<cbor::Value, CallResult> {
-        // Return on first handler that can handle the method.
+        let disabled = modules::core::Module::disabled_modules(ctx);
+
+        // Return on first enabled handler that can handle the method.
         for_tuples!( #(
-            let body = match Tuple::dispatch_call::<C>(ctx, method, body) {
-                DispatchResult::Handled(result) => return DispatchResult::Handled(result),
-                DispatchResult::Unhandled(body) => body,
+            let body = if disabled.contains(Tuple::NAME) {
+                body
+            } else {
+                match Tuple::dispatch_call::<C>(ctx, method, body) {
+                    DispatchResult::Handled(result) => return DispatchResult::Handled(result),
+                    DispatchResult::Unhandled(body) => body,
+                }
             };
         )* );
 
@@ -229,17 +290,60 @@ impl MethodHandler for Tuple {
         method: &str,
         args: cbor::Value,
     ) -> DispatchResult<cbor::Value, Result<cbor::Value, error::RuntimeError>> {
-        // Return on first handler that can handle the method.
+        let disabled = modules::core::Module::disabled_modules(ctx);
+
+        // Return on first enabled handler that can handle the method.
         for_tuples!( #(
-            let args = match Tuple::dispatch_query::<C>(ctx, method, args) {
-                DispatchResult::Handled(result) => return DispatchResult::Handled(result),
-                DispatchResult::Unhandled(args) => args,
+            let args = if disabled.contains(Tuple::NAME) {
+                args
+            } else {
+                match Tuple::dispatch_query::<C>(ctx, method, args) {
+                    DispatchResult::Handled(result) => return DispatchResult::Handled(result),
+                    DispatchResult::Unhandled(args) => args,
+                }
             };
         )* );
 
         DispatchResult::Unhandled(args)
     }
 
+    fn dispatch_query_async<'a, C: Context + 'a>(
+        ctx: &'a mut C,
+        method: &'a str,
+        args: cbor::Value,
+    ) -> BoxFuture<'a, DispatchResult<cbor::Value, Result<cbor::Value, error::RuntimeError>>>
+    where
+        Self: 'a,
+    {
+        Box::pin(async move {
+            let disabled = modules::core::Module::disabled_modules(ctx);
+
+            // Return on first enabled handler that can handle the method.
+            for_tuples!( #(
+                let args = if disabled.contains(Tuple::NAME) {
+                    args
+                } else {
+                    // Only modules with asynchronous queries need their futures to be awaited.
+                    let result = if Tuple::has_async_queries() {
+                        Tuple::dispatch_query_async::<C>(ctx, method, args).await
+                    } else {
+                        Tuple::dispatch_query::<C>(ctx, method, args)
+                    };
+                    match result {
+                        DispatchResult::Handled(result) => return DispatchResult::Handled(result),
+                        DispatchResult::Unhandled(args) => args,
+                    }
+                };
+            )* );
+
+            DispatchResult::Unhandled(args)
+        })
+    }
+
+    fn has_async_queries() -> bool {
+        [false, for_tuples!( #( Tuple::has_async_queries() ),* )].contains(&true)
+    }
+
     fn dispatch_message_result<C: Context>(
         ctx: &mut C,
         handler_name: &str,
@@ -255,6 +359,34 @@ impl MethodHandler for Tuple {
 
         DispatchResult::Unhandled(result)
     }
+
+    fn register_error_codes(registry: &mut modules::core::types::ErrorRegistry) {
+        for_tuples!( #( Tuple::register_error_codes(registry); )* );
+    }
+
+    fn register_capabilities(capabilities: &mut modules::core::types::Capabilities) {
+        for_tuples!( #( Tuple::register_capabilities(capabilities); )* );
+    }
+}
+
+/// Registers the error codes of the given module's error type under the module's name.
+pub fn register_error_codes<M: Module>(registry: &mut modules::core::types::ErrorRegistry) {
+    let codes = <M::Error as error::Error>::known_codes();
+    if codes.is_empty() {
+        return;
+    }
+
+    registry
+        .entry(M::NAME.to_owned())
+        .or_default()
+        .extend(
+            codes
+                .into_iter()
+                .map(|(code, name)| modules::core::types::ErrorCode {
+                    code,
+                    name: name.to_owned(),
+                }),
+        );
 }
 
 /// Authentication handler.
@@ -384,6 +516,59 @@ impl MigrationHandler for Tuple {
     }
 }
 
+/// A migration of module state from one version to the next.
+pub type Migration<C> = fn(&mut C);
+
+/// Run the migrations required to bring module state from the given version to the module's
+/// current version.
+///
+/// The migration at index `i` migrates state from version `i + 1` to version `i + 2`, so there
+/// must be exactly `M::VERSION - 1` migrations. Migrations are run in order and the stored module
+/// version is only updated once all of them have completed.
+///
+/// Returns true in case metadata has been changed.
+pub fn run_migrations<M: Module, C: Context>(
+    ctx: &mut C,
+    meta: &mut modules::core::types::Metadata,
+    from: u32,
+    migrations: &[Migration<C>],
+) -> bool {
+    assert_eq!(
+        migrations.len() as u32,
+        M::VERSION - 1,
+        "module {} must provide a migration for each version",
+        M::NAME,
+    );
+    if from == 0 || from >= M::VERSION {
+        // Nothing to migrate.
+        return false;
+    }
+
+    for migration in &migrations[(from - 1) as usize..] {
+        migration(ctx);
+    }
+    meta.versions.insert(M::NAME.to_owned(), M::VERSION);
+    true
+}
+
+/// Parameters handler.
+pub trait ParametersHandler {
+    /// Collect the names of all modules whose parameters have not been initialized.
+    fn missing_params<S: Store>(store: &mut S, missing: &mut Vec<&'static str>);
+}
+
+#[impl_for_tuples(30)]
+#[tuple_types_custom_trait_bound(Module)]
+impl ParametersHandler for Tuple {
+    fn missing_params<S: Store>(store: &mut S, missing: &mut Vec<&'static str>) {
+        for_tuples!( #(
+            if !Tuple::params_initialized(&mut *store) {
+                missing.push(Tuple::NAME);
+            }
+        )* );
+    }
+}
+
 /// Block handler.
 pub trait BlockHandler {
     /// Perform any common actions at the start of the block (before any transactions have been
@@ -392,6 +577,12 @@ pub trait BlockHandler {
         // Default implementation doesn't do anything.
     }
 
+    /// Determine the order in which the decoded transactions of a batch are executed. This is
+    /// invoked after `begin_block` and before any transactions have been executed.
+    fn order_batch<C: Context>(_ctx: &mut C, _txs: &mut [dispatcher::DecodedTx]) {
+        // Default implementation keeps the batch order.
+    }
+
     /// Perform any common actions at the end of the block (after all transactions have been
     /// executed).
     fn end_block<C: Context>(_ctx: &mut C) {
@@ -405,13 +596,34 @@ pub trait BlockHandler {
 }
 
 #[impl_for_tuples(30)]
+#[tuple_types_custom_trait_bound(Module + BlockHandler)]
 impl BlockHandler for Tuple {
     fn begin_block<C: Context>(ctx: &mut C) {
-        for_tuples!( #( Tuple::begin_block(ctx); )* );
+        // Hooks of disabled modules are skipped.
+        let disabled = modules::core::Module::disabled_modules(ctx);
+        for_tuples!( #(
+            if !disabled.contains(Tuple::NAME) {
+                Tuple::begin_block(ctx);
+            }
+        )* );
+    }
+
+    fn order_batch<C: Context>(ctx: &mut C, txs: &mut [dispatcher::DecodedTx]) {
+        let disabled = modules::core::Module::disabled_modules(ctx);
+        for_tuples!( #(
+            if !disabled.contains(Tuple::NAME) {
+                Tuple::order_batch(ctx, txs);
+            }
+        )* );
     }
 
     fn end_block<C: Context>(ctx: &mut C) {
-        for_tuples!( #( Tuple::end_block(ctx); )* );
+        let disabled = modules::core::Module::disabled_modules(ctx);
+        for_tuples!( #(
+            if !disabled.contains(Tuple::NAME) {
+                Tuple::end_block(ctx);
+            }
+        )* );
     }
 
     // Ignore let and return for the empty tuple case.
@@ -427,6 +639,32 @@ impl BlockHandler for Tuple {
     }
 }
 
+/// Event handler.
+///
+/// Allows modules to react to events emitted by other modules without polling state.
+pub trait EventHandler {
+    /// Handle an event emitted by a successfully executed transaction.
+    ///
+    /// Events are delivered synchronously during execution of the same block, right after the
+    /// emitting transaction has been committed and before the next transaction is executed. Each
+    /// event is delivered in emission order and, for each event, to modules in the order in which
+    /// they are defined in the runtime. Implementations should ignore events they are not
+    /// interested in based on the emitting module name and event code.
+    ///
+    /// Handlers are invoked in the batch context so any state changes are persisted. Events
+    /// emitted by handlers are not delivered to any handlers, preventing reentrancy.
+    fn handle_event<C: Context>(_ctx: &mut C, _module_name: &str, _code: u32, _value: &[u8]) {
+        // Default implementation doesn't do anything.
+    }
+}
+
+#[impl_for_tuples(30)]
+impl EventHandler for Tuple {
+    fn handle_event<C: Context>(ctx: &mut C, module_name: &str, code: u32, value: &[u8]) {
+        for_tuples!( #( Tuple::handle_event(ctx, module_name, code, value); )* );
+    }
+}
+
 /// Invariant handler.
 pub trait InvariantHandler {
     /// Check invariants.
@@ -469,12 +707,29 @@ pub trait Module {
         store.get(Self::Parameters::STORE_KEY).unwrap_or_default()
     }
 
+    /// Whether the module's parameters have been initialized. Modules without any parameters are
+    /// always considered initialized.
+    fn params_initialized<S: Store>(store: S) -> bool {
+        if TypeId::of::<Self::Parameters>() == TypeId::of::<()>() {
+            return true;
+        }
+        let store = storage::PrefixStore::new(store, &Self::NAME);
+        store.get(Self::Parameters::STORE_KEY).is_some()
+    }
+
     /// Set the module's parameters.
     fn set_params<S: Store>(store: S, params: Self::Parameters) {
         let store = storage::PrefixStore::new(store, &Self::NAME);
         let mut store = storage::TypedStore::new(store);
         store.insert(Self::Parameters::STORE_KEY, params);
     }
+
+    /// Return the parameters of another module.
+    ///
+    /// This is useful for modules that need to read the configuration of their dependencies.
+    fn params_of<M: Module, S: Store>(store: S) -> M::Parameters {
+        M::params(store)
+    }
 }
 
 /// Parameters for a runtime module.
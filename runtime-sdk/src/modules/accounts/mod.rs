@@ -2,6 +2,7 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
     convert::TryInto,
+    sync::{Arc, Mutex},
 };
 
 use num_traits::Zero;
@@ -14,13 +15,13 @@ use crate::{
     error, module,
     module::{CallResult, Module as _, Parameters as _},
     modules,
-    modules::core::{Error as CoreError, Module as Core, API as _},
+    modules::core::{Error as CoreError, Module as Core, NonceConsumption, API as _},
     storage,
     storage::Prefix,
     types::{
         address::{Address, SignatureAddressSpec},
         token,
-        transaction::{AuthInfo, Transaction},
+        transaction::{AddressSpec, AuthInfo, Call, SignerInfo, Transaction},
     },
 };
 
@@ -77,12 +78,29 @@ pub enum Event {
         owner: Address,
         amount: token::BaseUnits,
     },
+
+    #[sdk_event(code = 4)]
+    Convert {
+        owner: Address,
+        burned: token::BaseUnits,
+        minted: token::BaseUnits,
+    },
+
+    /// Fees of a denomination collected in the previous block, split into the part that has been
+    /// burned and the part that has been retained (disbursed or moved to the common pool).
+    #[sdk_event(code = 5)]
+    FeeSummary {
+        burned: token::BaseUnits,
+        retained: token::BaseUnits,
+    },
 }
 
 /// Gas costs.
 #[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
 pub struct GasCosts {
     pub tx_transfer: u64,
+    #[cbor(optional, default)]
+    pub tx_convert: u64,
 }
 
 #[allow(clippy::trivially_copy_pass_by_ref)]
@@ -104,6 +122,31 @@ pub struct Parameters {
 
     #[cbor(optional, default, skip_serializing_if = "BTreeMap::is_empty")]
     pub denomination_infos: BTreeMap<token::Denomination, types::DenominationInfo>,
+
+    /// Conversions between denominations supported by the Convert call.
+    #[cbor(optional, default, skip_serializing_if = "Vec::is_empty")]
+    pub conversions: Vec<types::Conversion>,
+
+    /// Fraction of collected fees that is burned instead of being disbursed. A zero numerator
+    /// disables burning.
+    #[cbor(optional, default)]
+    pub fee_burn_numerator: u64,
+    #[cbor(optional, default)]
+    pub fee_burn_denominator: u64,
+}
+
+impl Parameters {
+    /// Amount of the given fee that should be burned.
+    fn fee_burn_amount(&self, fee: u128) -> u128 {
+        if self.fee_burn_numerator.is_zero() || self.fee_burn_denominator.is_zero() {
+            return 0;
+        }
+        let numerator: u128 = self.fee_burn_numerator.into();
+        let denominator: u128 = self.fee_burn_denominator.into();
+        // Split the computation to avoid overflows. As the numerator is at most the denominator,
+        // neither of the terms can overflow.
+        (fee / denominator) * numerator + (fee % denominator) * numerator / denominator
+    }
 }
 
 /// Errors emitted during rewards parameter validation.
@@ -111,19 +154,53 @@ pub struct Parameters {
 pub enum ParameterValidationError {
     #[error("debug option used: {0}")]
     DebugOptionUsed(String),
+
+    #[error("invalid conversion: {0}")]
+    InvalidConversion(String),
+
+    #[error("invalid fee burn rate")]
+    InvalidFeeBurnRate,
 }
 
 impl module::Parameters for Parameters {
     type Error = ParameterValidationError;
 
-    #[cfg(not(feature = "unsafe-allow-debug"))]
     fn validate_basic(&self) -> Result<(), Self::Error> {
-        if self.debug_disable_nonce_check {
+        if cfg!(not(feature = "unsafe-allow-debug")) && self.debug_disable_nonce_check {
             return Err(ParameterValidationError::DebugOptionUsed(
                 "debug_disable_nonce_check".to_string(),
             ));
         }
 
+        let mut pairs = BTreeSet::new();
+        for conversion in &self.conversions {
+            if conversion.from == conversion.to {
+                return Err(ParameterValidationError::InvalidConversion(format!(
+                    "conversion from {} to itself",
+                    conversion.from
+                )));
+            }
+            if conversion.numerator == 0 || conversion.denominator == 0 {
+                return Err(ParameterValidationError::InvalidConversion(format!(
+                    "zero rate for conversion from {} to {}",
+                    conversion.from, conversion.to
+                )));
+            }
+            if !pairs.insert((&conversion.from, &conversion.to)) {
+                return Err(ParameterValidationError::InvalidConversion(format!(
+                    "duplicate conversion from {} to {}",
+                    conversion.from, conversion.to
+                )));
+            }
+        }
+
+        if !self.fee_burn_numerator.is_zero()
+            && (self.fee_burn_denominator.is_zero()
+                || self.fee_burn_numerator > self.fee_burn_denominator)
+        {
+            return Err(ParameterValidationError::InvalidFeeBurnRate);
+        }
+
         Ok(())
     }
 }
@@ -148,7 +225,14 @@ pub trait API {
     ) -> Result<(), Error>;
 
     /// Mint new tokens, increasing the total supply.
-    fn mint<C: Context>(ctx: &mut C, to: Address, amount: &token::BaseUnits) -> Result<(), Error>;
+    ///
+    /// The minter is the module `M` performing the mint, which must be allowed to mint the given
+    /// denomination by the core mint allowlist.
+    fn mint<M: module::Module, C: Context>(
+        ctx: &mut C,
+        to: Address,
+        amount: &token::BaseUnits,
+    ) -> Result<(), Error>;
 
     /// Burn existing tokens, decreasing the total supply.
     fn burn<C: Context>(ctx: &mut C, from: Address, amount: &token::BaseUnits)
@@ -251,6 +335,70 @@ pub static ADDRESS_COMMON_POOL: Lazy<Address> =
 pub static ADDRESS_FEE_ACCUMULATOR: Lazy<Address> =
     Lazy::new(|| Address::from_module(MODULE_NAME, "fee-accumulator"));
 
+/// Number of rounds after which a pending nonce is considered stale, e.g. because the transaction
+/// that it was observed in has been dropped from the mempool.
+const PENDING_NONCE_MAX_AGE: u64 = 10;
+/// Maximum number of accounts whose pending nonces are tracked.
+const PENDING_NONCE_MAX_ACCOUNTS: usize = 10_000;
+
+/// Context key for the pending nonces tracked by the node.
+const CONTEXT_KEY_PENDING_NONCES: &str = "accounts.PendingNonces";
+
+/// Next nonces of accounts with transactions that have passed checks on a node, together with the
+/// round in which they were last observed.
+///
+/// This is node-local state which must never be used in anything but queries. Entries expire after
+/// a few rounds and only a bounded number of accounts is tracked, evicting the least recently
+/// observed ones first.
+#[derive(Debug, Default)]
+pub struct PendingNonces {
+    nonces: BTreeMap<Address, (u64, u64)>,
+    last_pruned_round: u64,
+}
+
+impl PendingNonces {
+    /// Record the next nonce of the given account, observed in the given round.
+    fn record(&mut self, address: Address, next: u64, round: u64) {
+        let entry = self.nonces.entry(address).or_insert((next, round));
+        *entry = (entry.0.max(next), round);
+
+        self.prune(round);
+    }
+
+    /// Next nonce of the given account in case it is ahead of the committed nonce and has not yet
+    /// expired at the given round.
+    pub fn get(&self, address: &Address, committed: u64, round: u64) -> Option<u64> {
+        match self.nonces.get(address) {
+            Some((pending, seen))
+                if *pending > committed && round <= seen.saturating_add(PENDING_NONCE_MAX_AGE) =>
+            {
+                Some(*pending)
+            }
+            _ => None,
+        }
+    }
+
+    /// Remove expired entries and evict the least recently observed entries in case too many
+    /// accounts are tracked.
+    fn prune(&mut self, round: u64) {
+        if round > self.last_pruned_round {
+            self.nonces
+                .retain(|_, (_, seen)| round <= seen.saturating_add(PENDING_NONCE_MAX_AGE));
+            self.last_pruned_round = round;
+        }
+
+        while self.nonces.len() > PENDING_NONCE_MAX_ACCOUNTS {
+            let oldest = self
+                .nonces
+                .iter()
+                .min_by_key(|(_, (_, seen))| *seen)
+                .map(|(address, _)| *address)
+                .expect("pending nonces should not be empty");
+            self.nonces.remove(&oldest);
+        }
+    }
+}
+
 /// This is needed to properly iterate over the BALANCES map.
 #[derive(Clone, PartialEq, PartialOrd, Eq, Ord)]
 struct AddressWithDenomination(Address, token::Denomination);
@@ -380,7 +528,7 @@ impl Module {
 
 /// A fee accumulator that stores fees from all transactions in a block.
 #[derive(Default)]
-struct FeeAccumulator {
+pub(crate) struct FeeAccumulator {
     total_fees: BTreeMap<token::Denomination, u128>,
 }
 
@@ -406,6 +554,13 @@ impl FeeAccumulator {
         *current -= fee.amount();
         Ok(())
     }
+
+    /// Add all fees from the given accumulator.
+    pub(crate) fn merge(&mut self, other: FeeAccumulator) {
+        for (denomination, amount) in other.total_fees {
+            *self.total_fees.entry(denomination).or_default() += amount;
+        }
+    }
 }
 
 /// Context key for the fee accumulator.
@@ -428,16 +583,22 @@ impl API for Module {
         Self::add_amount(ctx.runtime_state(), to, amount)?;
 
         // Emit a transfer event.
-        ctx.emit_event(Event::Transfer {
+        ctx.try_emit_event(Event::Transfer {
             from,
             to,
             amount: amount.clone(),
-        });
+        })?;
 
         Ok(())
     }
 
-    fn mint<C: Context>(ctx: &mut C, to: Address, amount: &token::BaseUnits) -> Result<(), Error> {
+    fn mint<M: module::Module, C: Context>(
+        ctx: &mut C,
+        to: Address,
+        amount: &token::BaseUnits,
+    ) -> Result<(), Error> {
+        Core::ensure_mint_allowed::<M, _>(ctx, amount.denomination())?;
+
         // Add to destination account.
         Self::add_amount(ctx.runtime_state(), to, amount)?;
 
@@ -445,10 +606,10 @@ impl API for Module {
         Self::inc_total_supply(ctx.runtime_state(), amount)?;
 
         // Emit a mint event.
-        ctx.emit_event(Event::Mint {
+        ctx.try_emit_event(Event::Mint {
             owner: to,
             amount: amount.clone(),
-        });
+        })?;
 
         Ok(())
     }
@@ -466,10 +627,10 @@ impl API for Module {
             .expect("target account had enough balance so total supply should not underflow");
 
         // Emit a burn event.
-        ctx.emit_event(Event::Burn {
+        ctx.try_emit_event(Event::Burn {
             owner: from,
             amount: amount.clone(),
-        });
+        })?;
 
         Ok(())
     }
@@ -640,24 +801,39 @@ impl API for Module {
         ctx: &mut C,
         tx: &Transaction,
     ) -> Result<(), modules::core::Error> {
-        // Fetch information about each signer.
-        let mut store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        Self::increment_nonces(
+            ctx.runtime_state(),
+            tx.auth_info
+                .signer_info
+                .iter()
+                .map(|si| si.address_spec.address()),
+        );
+        Ok(())
+    }
+}
+
+impl Module {
+    /// Whether the nonces of a transaction should only be consumed in case its call succeeds.
+    ///
+    /// Nonces of transactions paying a fee are always consumed during authentication, as they
+    /// could otherwise be replayed to charge the fee again.
+    fn defers_nonces(params: &modules::core::Parameters, auth_info: &AuthInfo) -> bool {
+        params.nonce_consumption == NonceConsumption::OnSuccessIfFree
+            && auth_info.fee.amount.amount().is_zero()
+    }
+
+    /// Increment the nonces of the given accounts.
+    fn increment_nonces<S: storage::Store>(state: S, addresses: impl IntoIterator<Item = Address>) {
+        let mut store = storage::PrefixStore::new(state, &MODULE_NAME);
         let mut accounts =
             storage::TypedStore::new(storage::PrefixStore::new(&mut store, &state::ACCOUNTS));
-        for si in tx.auth_info.signer_info.iter() {
-            let address = si.address_spec.address();
+        for address in addresses {
             let mut account: types::Account = accounts.get(&address).unwrap_or_default();
-
-            // Update nonce.
-            // TODO: Could support an option to defer this.
             account.nonce += 1;
             accounts.insert(&address, account);
         }
-        Ok(())
     }
-}
 
-impl Module {
     fn tx_transfer<C: TxContext>(ctx: &mut C, body: types::Transfer) -> Result<(), Error> {
         let params = Self::params(ctx.runtime_state());
 
@@ -668,13 +844,184 @@ impl Module {
 
         Core::use_tx_gas(ctx, params.gas_costs.tx_transfer)?;
 
-        Self::transfer(ctx, ctx.tx_caller_address(), body.to, &body.amount)?;
+        // Reject transfers exceeding the configured limits.
+        let from = ctx.tx_caller_address();
+        Core::check_transfer_limits(ctx, from, &body.amount)?;
+
+        Self::transfer(ctx, from, body.to, &body.amount)?;
+
+        Ok(())
+    }
+
+    fn tx_convert<C: TxContext>(
+        ctx: &mut C,
+        body: types::Convert,
+    ) -> Result<types::ConvertResult, Error> {
+        let params = Self::params(ctx.runtime_state());
+
+        Core::use_tx_gas(ctx, params.gas_costs.tx_convert)?;
+
+        let conversion = params
+            .conversions
+            .iter()
+            .find(|c| c.from == body.from && c.to == body.to)
+            .ok_or(Error::Forbidden)?;
+        let (burned, minted) = conversion
+            .amounts(body.amount)
+            .ok_or(Error::InvalidArgument)?;
+        let result = types::ConvertResult { burned, minted };
+
+        if ctx.is_check_only() {
+            return Ok(result);
+        }
+
+        // Burning and minting keeps the total supplies consistent with the balances.
+        let owner = ctx.tx_caller_address();
+        let burned = token::BaseUnits::new(burned, body.from);
+        let minted = token::BaseUnits::new(minted, body.to);
+        Self::burn(ctx, owner, &burned)?;
+        Self::mint::<Self, _>(ctx, owner, &minted)?;
+
+        ctx.try_emit_event(Event::Convert {
+            owner,
+            burned,
+            minted,
+        })?;
+
+        Ok(result)
+    }
+
+    /// Convert tokens of the fee fallback denomination configured in the core module in case the
+    /// payer's balance does not cover the given fee, minting just enough to cover it.
+    ///
+    /// The rate of the matching conversion supported by the Convert call is used. Nothing is
+    /// converted in case there is no such conversion or the fallback balance is insufficient as
+    /// well, leaving it to the fee charge to fail.
+    fn convert_for_fee<C: Context>(
+        ctx: &mut C,
+        payer: Address,
+        fee: &token::BaseUnits,
+    ) -> Result<(), modules::core::Error> {
+        if ctx.is_simulation() {
+            return Ok(());
+        }
+        let fallback = match Core::params(ctx.runtime_state()).fee_fallback_denomination {
+            Some(fallback) if &fallback != fee.denomination() => fallback,
+            _ => return Ok(()),
+        };
+
+        let balance = Self::get_balance(ctx.runtime_state(), payer, fee.denomination().clone())
+            .map_err(|_| modules::core::Error::InsufficientFeeBalance)?;
+        let missing = fee.amount().saturating_sub(balance);
+        if missing == 0 {
+            return Ok(());
+        }
+
+        let params = Self::params(ctx.runtime_state());
+        let conversion = match params
+            .conversions
+            .iter()
+            .find(|c| c.from == fallback && &c.to == fee.denomination())
+        {
+            Some(conversion) => conversion,
+            None => return Ok(()),
+        };
+        // Smallest amount of the fallback denomination that converts to at least the missing
+        // amount.
+        let (burned, minted) = match missing
+            .checked_mul(conversion.denominator)
+            .map(|product| {
+                product / conversion.numerator + (product % conversion.numerator != 0) as u128
+            })
+            .and_then(|amount| conversion.amounts(amount))
+        {
+            Some(amounts) => amounts,
+            None => return Ok(()),
+        };
+        let fallback_balance = Self::get_balance(ctx.runtime_state(), payer, fallback.clone())
+            .map_err(|_| modules::core::Error::InsufficientFeeBalance)?;
+        if fallback_balance < burned {
+            return Ok(());
+        }
+
+        let burned = token::BaseUnits::new(burned, fallback);
+        let minted = token::BaseUnits::new(minted, fee.denomination().clone());
+        Self::burn(ctx, payer, &burned)
+            .map_err(|_| modules::core::Error::InsufficientFeeBalance)?;
+        Self::mint::<Self, _>(ctx, payer, &minted)
+            .map_err(|err| modules::core::Error::InvalidArgument(err.into()))?;
+
+        ctx.try_emit_event(Event::Convert {
+            owner: payer,
+            burned,
+            minted,
+        })?;
 
         Ok(())
     }
 
     fn query_nonce<C: Context>(ctx: &mut C, args: types::NonceQuery) -> Result<u64, Error> {
-        Self::get_nonce(ctx.runtime_state(), args.address)
+        let nonce = Self::get_nonce(ctx.runtime_state(), args.address)?;
+        if !args.pending {
+            return Ok(nonce);
+        }
+
+        // Pending nonces are only available in case the node tracks them.
+        let pending_nonces = match ctx
+            .value::<Arc<Mutex<PendingNonces>>>(CONTEXT_KEY_PENDING_NONCES)
+            .get()
+        {
+            Some(pending_nonces) => pending_nonces.clone(),
+            None => return Ok(nonce),
+        };
+        let round = ctx.runtime_header().round;
+        let pending = pending_nonces
+            .lock()
+            .unwrap()
+            .get(&args.address, nonce, round);
+        Ok(pending.unwrap_or(nonce))
+    }
+
+    /// Track the next nonces of signers of transactions that pass checks in the given context in
+    /// the given node-local structure, making them available to pending nonce queries.
+    ///
+    /// This must never be enabled in contexts used for executing transactions.
+    pub fn set_pending_nonces<C: Context>(ctx: &mut C, pending_nonces: Arc<Mutex<PendingNonces>>) {
+        ctx.value(CONTEXT_KEY_PENDING_NONCES).set(pending_nonces);
+    }
+
+    /// Take the fees accumulated in the given context so far.
+    pub(crate) fn take_fee_accumulator<C: Context>(ctx: &mut C) -> FeeAccumulator {
+        ctx.value::<FeeAccumulator>(CONTEXT_KEY_FEE_ACCUMULATOR)
+            .take()
+            .unwrap_or_default()
+    }
+
+    /// Add the given fees to the fees accumulated in the given context.
+    pub(crate) fn merge_fee_accumulator<C: Context>(ctx: &mut C, fees: FeeAccumulator) {
+        if fees.total_fees.is_empty() {
+            return;
+        }
+        ctx.value::<FeeAccumulator>(CONTEXT_KEY_FEE_ACCUMULATOR)
+            .or_default()
+            .merge(fees);
+    }
+
+    /// Record the next nonces of the signers of a transaction that has passed checks, in case
+    /// pending nonces are tracked in the given context.
+    pub fn record_pending_nonces<C: Context>(ctx: &mut C, signer_info: &[SignerInfo]) {
+        let pending_nonces = match ctx
+            .value::<Arc<Mutex<PendingNonces>>>(CONTEXT_KEY_PENDING_NONCES)
+            .get()
+        {
+            Some(pending_nonces) => pending_nonces.clone(),
+            None => return,
+        };
+        let round = ctx.runtime_header().round;
+        let mut pending_nonces = pending_nonces.lock().unwrap();
+        for si in signer_info {
+            pending_nonces.record(si.address_spec.address(), si.nonce.saturating_add(1), round);
+        }
     }
 
     fn query_addresses<C: Context>(
@@ -753,6 +1100,7 @@ impl module::MethodHandler for Module {
     ) -> module::DispatchResult<cbor::Value, CallResult> {
         match method {
             "accounts.Transfer" => module::dispatch_call(ctx, body, Self::tx_transfer),
+            "accounts.Convert" => module::dispatch_call(ctx, body, Self::tx_convert),
             _ => module::DispatchResult::Unhandled(body),
         }
     }
@@ -772,6 +1120,10 @@ impl module::MethodHandler for Module {
             _ => module::DispatchResult::Unhandled(args),
         }
     }
+
+    fn register_error_codes(registry: &mut modules::core::types::ErrorRegistry) {
+        module::register_error_codes::<Self>(registry)
+    }
 }
 
 impl Module {
@@ -874,6 +1226,7 @@ impl module::AuthHandler for Module {
         if !tx.auth_info.fee.amount.amount().is_zero() {
             let payee = payee.expect("at least one signer is always present");
 
+            Self::convert_for_fee(ctx, payee, &tx.auth_info.fee.amount)?;
             Self::move_into_fee_accumulator(ctx, payee, &tx.auth_info.fee.amount)?;
 
             // TODO: Emit event that fee has been paid.
@@ -882,8 +1235,37 @@ impl module::AuthHandler for Module {
             // Bump transaction priority.
             Core::add_priority(ctx, gas_price.try_into().unwrap_or(u64::MAX))?;
         }
+
+        // When nonces of free transactions should only be consumed on success, they are updated
+        // in the transaction context instead so that the update is reverted together with a
+        // failed call.
+        if Self::defers_nonces(&Core::params(ctx.runtime_state()), &tx.auth_info) {
+            return Ok(());
+        }
         Self::update_signer_nonces(ctx, tx)
     }
+
+    fn before_handle_call<C: TxContext>(
+        ctx: &mut C,
+        _call: &Call,
+    ) -> Result<(), modules::core::Error> {
+        let params = Core::params(ctx.runtime_state());
+        if !Self::defers_nonces(&params, ctx.tx_auth_info()) {
+            return Ok(());
+        }
+
+        // Internal callers (e.g. contracts or scheduled calls) are not authenticated and do not
+        // have nonces.
+        let signers: Vec<Address> = ctx
+            .tx_auth_info()
+            .signer_info
+            .iter()
+            .filter(|si| !matches!(si.address_spec, AddressSpec::Internal(_)))
+            .map(|si| si.address_spec.address())
+            .collect();
+        Self::increment_nonces(ctx.runtime_state(), signers);
+        Ok(())
+    }
 }
 
 impl module::BlockHandler for Module {
@@ -903,6 +1285,28 @@ impl module::BlockHandler for Module {
             .expect("sub_amount must succeed");
         }
 
+        // Burn the configured fraction of fees and account for how much was burned and retained.
+        let params = Self::params(ctx.runtime_state());
+        for (denom, fee) in previous_fees.iter_mut() {
+            if fee.is_zero() {
+                continue;
+            }
+
+            let burned = token::BaseUnits::new(params.fee_burn_amount(*fee), denom.clone());
+            if !burned.amount().is_zero() {
+                // Fees have already been drained from the fee accumulator, so only the total
+                // supply needs to be adjusted.
+                Self::dec_total_supply(ctx.runtime_state(), &burned)
+                    .expect("fees are part of the total supply");
+            }
+            *fee -= burned.amount();
+
+            ctx.emit_event(Event::FeeSummary {
+                burned,
+                retained: token::BaseUnits::new(*fee, denom.clone()),
+            });
+        }
+
         // Disburse transaction fees to entities controlling all the good nodes in the committee.
         let addrs: Vec<Address> = ctx
             .runtime_round_results()
@@ -969,6 +1373,7 @@ impl module::BlockHandler for Module {
     }
 }
 
+impl module::EventHandler for Module {}
 impl module::InvariantHandler for Module {
     /// Check invariants.
     fn check_invariants<C: Context>(ctx: &mut C) -> Result<(), CoreError> {
@@ -2,24 +2,28 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
     iter::FromIterator,
+    sync::{Arc, Mutex},
 };
 
 use anyhow::anyhow;
 
 use crate::{
     context::{BatchContext, Context},
-    module::{AuthHandler, BlockHandler, InvariantHandler, MethodHandler},
+    module::{
+        AuthHandler, BlockHandler, InvariantHandler, MethodHandler, Module as _, Parameters as _,
+    },
     modules::core,
     testing::{keys, mock},
     types::{
+        address::Address,
         token::{BaseUnits, Denomination},
         transaction,
     },
 };
 
 use super::{
-    types::*, Error, Genesis, Module as Accounts, Parameters, ADDRESS_COMMON_POOL,
-    ADDRESS_FEE_ACCUMULATOR, API as _,
+    types::*, Error, Genesis, Module as Accounts, Parameters, PendingNonces, ADDRESS_COMMON_POOL,
+    ADDRESS_FEE_ACCUMULATOR, API as _, PENDING_NONCE_MAX_ACCOUNTS,
 };
 
 #[test]
@@ -232,6 +236,7 @@ fn test_api_tx_transfer_disabled() {
                 gas: 1000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
 
@@ -262,6 +267,7 @@ fn test_prefetch() {
             gas: 1000,
             consensus_messages: 0,
         },
+        features: 0,
     };
 
     let tx = transaction::Transaction {
@@ -392,6 +398,60 @@ fn test_api_transfer() {
     });
 }
 
+#[test]
+fn test_api_mint_allowlist() {
+    type ConsensusAccounts =
+        crate::modules::consensus_accounts::Module<Accounts, crate::modules::consensus::Module>;
+    type Rewards = crate::modules::rewards::Module<Accounts>;
+
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    let consensus: Denomination = "TEST".parse().unwrap();
+    core::Module::set_params(
+        ctx.runtime_state(),
+        core::Parameters {
+            mint_allowlist: {
+                let mut allowlist = BTreeMap::new();
+                allowlist.insert(
+                    consensus.clone(),
+                    BTreeSet::from_iter(vec![ConsensusAccounts::NAME.to_owned()]),
+                );
+                allowlist
+            },
+            ..Default::default()
+        },
+    );
+
+    // Modules that are not allowlisted should not be able to mint the consensus denomination.
+    let result = Accounts::mint::<Rewards, _>(
+        &mut ctx,
+        keys::alice::address(),
+        &BaseUnits::new(1_000, consensus.clone()),
+    );
+    assert!(matches!(result, Err(Error::Core(core::Error::Forbidden))));
+
+    // Allowlisted modules should be able to mint the consensus denomination.
+    Accounts::mint::<ConsensusAccounts, _>(
+        &mut ctx,
+        keys::alice::address(),
+        &BaseUnits::new(1_000, consensus.clone()),
+    )
+    .expect("mint by an allowlisted module should succeed");
+
+    // Denominations that are not listed can be minted by any module.
+    Accounts::mint::<Rewards, _>(
+        &mut ctx,
+        keys::alice::address(),
+        &BaseUnits::new(1_000, Denomination::NATIVE),
+    )
+    .expect("mint of an unrestricted denomination should succeed");
+
+    let balances = Accounts::get_balances(ctx.runtime_state(), keys::alice::address()).unwrap();
+    assert_eq!(balances.balances[&consensus], 1_000);
+    assert_eq!(balances.balances[&Denomination::NATIVE], 1_000);
+}
+
 #[test]
 fn test_authenticate_tx() {
     let mut mock = mock::Mock::default();
@@ -419,6 +479,7 @@ fn test_authenticate_tx() {
                 gas: 1000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
 
@@ -453,6 +514,309 @@ fn test_authenticate_tx() {
     assert!(matches!(result, Err(core::Error::InsufficientFeeBalance)));
 }
 
+#[test]
+fn test_authenticate_tx_fee_conversion() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    init_accounts(&mut ctx);
+
+    let wrapped: Denomination = "WNAT".parse().unwrap();
+    let mut params = Accounts::params(ctx.runtime_state());
+    // Three native tokens per wrapped token.
+    params.conversions = vec![Conversion {
+        from: wrapped.clone(),
+        to: Denomination::NATIVE,
+        numerator: 3,
+        denominator: 1,
+    }];
+    Accounts::set_params(ctx.runtime_state(), params);
+    core::Module::set_params(
+        ctx.runtime_state(),
+        core::Parameters {
+            fee_fallback_denomination: Some(wrapped.clone()),
+            ..Default::default()
+        },
+    );
+
+    Accounts::set_balance(
+        ctx.runtime_state(),
+        keys::bob::address(),
+        &BaseUnits::new(100, Denomination::NATIVE),
+    );
+    Accounts::set_total_supply(
+        ctx.runtime_state(),
+        &BaseUnits::new(1_000_100, Denomination::NATIVE),
+    );
+    Accounts::set_balance(
+        ctx.runtime_state(),
+        keys::bob::address(),
+        &BaseUnits::new(1_000, wrapped.clone()),
+    );
+    Accounts::set_total_supply(ctx.runtime_state(), &BaseUnits::new(1_000, wrapped.clone()));
+
+    let tx = |nonce, fee| transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "accounts.Transfer".to_owned(),
+            body: cbor::to_value(Transfer {
+                to: keys::alice::address(),
+                amount: BaseUnits::new(0, Denomination::NATIVE),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::bob::sigspec(),
+                nonce,
+            )],
+            fee: transaction::Fee {
+                amount: BaseUnits::new(fee, Denomination::NATIVE),
+                gas: 1000,
+                consensus_messages: 0,
+            },
+            features: 0,
+        },
+    };
+    fn balance<C: Context>(ctx: &mut C, denomination: &Denomination) -> u128 {
+        Accounts::get_balance(
+            ctx.runtime_state(),
+            keys::bob::address(),
+            denomination.clone(),
+        )
+        .unwrap()
+    }
+
+    // The missing part of the fee should be covered by converting wrapped tokens.
+    Accounts::authenticate_tx(&mut ctx, &tx(0, 1_000))
+        .expect("transaction authentication should succeed");
+    assert_eq!(balance(&mut ctx, &Denomination::NATIVE), 0);
+    assert_eq!(
+        balance(&mut ctx, &wrapped),
+        700,
+        "only the missing amount should be converted"
+    );
+    let ts = Accounts::get_total_supplies(ctx.runtime_state()).unwrap();
+    assert_eq!(ts[&Denomination::NATIVE], 1_000_100 + 900);
+    assert_eq!(ts[&wrapped], 700);
+
+    // Neither balance suffices, nothing should be converted.
+    Accounts::set_balance(
+        ctx.runtime_state(),
+        keys::bob::address(),
+        &BaseUnits::new(100, Denomination::NATIVE),
+    );
+    let result = Accounts::authenticate_tx(&mut ctx, &tx(1, 5_000));
+    assert!(matches!(result, Err(core::Error::InsufficientFeeBalance)));
+    assert_eq!(balance(&mut ctx, &Denomination::NATIVE), 100);
+    assert_eq!(balance(&mut ctx, &wrapped), 700);
+
+    let (tags, _) = ctx.commit();
+    let converts: Vec<_> = tags
+        .iter()
+        .filter(|tag| tag.key == b"accounts\x00\x00\x00\x04") // accounts.Convert (code = 4) event
+        .collect();
+    assert_eq!(converts.len(), 1, "one conversion event should be emitted");
+}
+
+#[test]
+fn test_nonce_consumption() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    init_accounts(&mut ctx);
+
+    let mut tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "accounts.Transfer".to_owned(),
+            body: cbor::to_value(Transfer {
+                to: keys::bob::address(),
+                // More than the available balance so that the call fails.
+                amount: BaseUnits::new(2_000_000, Denomination::NATIVE),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: BaseUnits::new(1_000, Denomination::NATIVE),
+                gas: 1000,
+                consensus_messages: 0,
+            },
+            features: 0,
+        },
+    };
+    // Authenticate and execute the transaction, only committing the call on success.
+    fn dispatch<C: BatchContext>(ctx: &mut C, tx: &transaction::Transaction) -> bool {
+        Accounts::authenticate_tx(ctx, tx).expect("transaction authentication should succeed");
+        ctx.with_tx(0, tx.clone(), |mut tx_ctx, call| {
+            Accounts::before_handle_call(&mut tx_ctx, &call)
+                .expect("before_handle_call should succeed");
+            let result = Accounts::tx_transfer(&mut tx_ctx, cbor::from_value(call.body).unwrap());
+            if result.is_ok() {
+                tx_ctx.commit();
+            }
+            result.is_ok()
+        })
+    }
+    fn nonce<C: Context>(ctx: &mut C) -> u64 {
+        Accounts::get_nonce(ctx.runtime_state(), keys::alice::address())
+            .expect("get_nonce should succeed")
+    }
+
+    // By default, nonces are consumed even when the call fails.
+    assert!(!dispatch(&mut ctx, &tx), "transfer should fail");
+    assert_eq!(nonce(&mut ctx), 1, "nonce should be consumed on failure");
+
+    // When consumed only on success for free transactions, failed transactions paying a fee
+    // should still consume the nonce so that they can not be replayed.
+    core::Module::set_params(
+        ctx.runtime_state(),
+        core::Parameters {
+            nonce_consumption: core::NonceConsumption::OnSuccessIfFree,
+            ..Default::default()
+        },
+    );
+    tx.auth_info.signer_info[0].nonce = 1;
+    assert!(!dispatch(&mut ctx, &tx), "transfer should fail");
+    assert_eq!(
+        nonce(&mut ctx),
+        2,
+        "nonce should be consumed on failure when paying a fee"
+    );
+
+    // The fee should have been charged for both failed transactions.
+    let bals = Accounts::get_balances(ctx.runtime_state(), keys::alice::address())
+        .expect("get_balances should succeed");
+    assert_eq!(bals.balances[&Denomination::NATIVE], 998_000);
+
+    // Failed free transactions should leave the nonce unchanged.
+    tx.auth_info.signer_info[0].nonce = 2;
+    tx.auth_info.fee.amount = BaseUnits::new(0, Denomination::NATIVE);
+    assert!(!dispatch(&mut ctx, &tx), "transfer should fail");
+    assert_eq!(
+        nonce(&mut ctx),
+        2,
+        "nonce should not be consumed on failure when free"
+    );
+
+    // The same nonce can be reused and is consumed once the call succeeds.
+    tx.call.body = cbor::to_value(Transfer {
+        to: keys::bob::address(),
+        amount: BaseUnits::new(1_000, Denomination::NATIVE),
+    });
+    assert!(dispatch(&mut ctx, &tx), "transfer should succeed");
+    assert_eq!(nonce(&mut ctx), 3, "nonce should be consumed on success");
+}
+
+#[test]
+fn test_query_pending_nonce() {
+    let address = Address::from_module("test", "pending-nonce");
+    let signer_info = |nonce| {
+        vec![transaction::SignerInfo {
+            address_spec: transaction::AddressSpec::Internal(transaction::CallerAddress::Address(
+                address,
+            )),
+            nonce,
+        }]
+    };
+    fn query<C: Context>(ctx: &mut C, address: Address, pending: bool) -> u64 {
+        Accounts::query_nonce(ctx, NonceQuery { address, pending }).expect("query should succeed")
+    }
+
+    let mut mock = mock::Mock::default();
+    let pending_nonces: Arc<Mutex<PendingNonces>> = Default::default();
+
+    // Pending nonces should be ignored in case the node does not track them.
+    {
+        let mut ctx = mock.create_check_ctx();
+        Accounts::record_pending_nonces(&mut ctx, &signer_info(0));
+        assert_eq!(query(&mut ctx, address, true), 0);
+    }
+
+    // Queue transactions on a node that tracks pending nonces.
+    {
+        let mut ctx = mock.create_check_ctx();
+        Accounts::set_pending_nonces(&mut ctx, pending_nonces.clone());
+        for nonce in 0..2 {
+            Accounts::record_pending_nonces(&mut ctx, &signer_info(nonce));
+        }
+    }
+
+    {
+        let mut ctx = mock.create_check_ctx();
+        Accounts::set_pending_nonces(&mut ctx, pending_nonces.clone());
+        assert_eq!(
+            query(&mut ctx, address, false),
+            0,
+            "committed nonce should not change"
+        );
+        assert_eq!(
+            query(&mut ctx, address, true),
+            2,
+            "pending nonce should be advanced"
+        );
+    }
+
+    // Pending nonces should expire.
+    mock.runtime_header.round = 100;
+    {
+        let mut ctx = mock.create_check_ctx();
+        Accounts::set_pending_nonces(&mut ctx, pending_nonces.clone());
+        assert_eq!(
+            query(&mut ctx, address, true),
+            0,
+            "stale pending nonce should be ignored"
+        );
+
+        // Expired entries should be pruned once new nonces are recorded.
+        let other = Address::from_module("test", "pending-nonce-other");
+        Accounts::record_pending_nonces(
+            &mut ctx,
+            &[transaction::SignerInfo {
+                address_spec: transaction::AddressSpec::Internal(
+                    transaction::CallerAddress::Address(other),
+                ),
+                nonce: 0,
+            }],
+        );
+        assert_eq!(
+            pending_nonces.lock().unwrap().nonces.len(),
+            1,
+            "stale pending nonces should be pruned"
+        );
+    }
+}
+
+#[test]
+fn test_pending_nonces_bounded() {
+    let mut pending_nonces = PendingNonces::default();
+    let address = |i: usize| Address::from_module("test", &format!("pending-nonce-{}", i));
+
+    // The first account is observed before all of the others.
+    for i in 0..=PENDING_NONCE_MAX_ACCOUNTS {
+        pending_nonces.record(address(i), 1, if i == 0 { 0 } else { 1 });
+    }
+    assert_eq!(
+        pending_nonces.nonces.len(),
+        PENDING_NONCE_MAX_ACCOUNTS,
+        "number of tracked accounts should be bounded"
+    );
+    assert_eq!(
+        pending_nonces.get(&address(0), 0, 1),
+        None,
+        "least recently observed account should be evicted"
+    );
+    assert_eq!(
+        pending_nonces.get(&address(PENDING_NONCE_MAX_ACCOUNTS), 0, 1),
+        Some(1)
+    );
+}
+
 #[test]
 fn test_tx_transfer() {
     let mut mock = mock::Mock::default();
@@ -480,6 +844,7 @@ fn test_tx_transfer() {
                 gas: 1000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
 
@@ -518,6 +883,330 @@ fn test_tx_transfer() {
     });
 }
 
+#[test]
+fn test_tx_transfer_limits() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    init_accounts(&mut ctx);
+
+    core::Module::set_params(
+        ctx.runtime_state(),
+        core::Parameters {
+            max_batch_gas: u64::MAX,
+            transfer_limits: {
+                let mut limits = BTreeMap::new();
+                limits.insert(Denomination::NATIVE, 1_000);
+                limits
+            },
+            ..Default::default()
+        },
+    );
+
+    let transfer_tx = |amount| transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "accounts.Transfer".to_owned(),
+            body: cbor::to_value(Transfer {
+                to: keys::bob::address(),
+                amount: BaseUnits::new(amount, Denomination::NATIVE),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 0,
+            },
+            features: 0,
+        },
+    };
+
+    // Transfers exactly at the limit should be allowed.
+    ctx.with_tx(0, transfer_tx(1_000), |mut tx_ctx, call| {
+        Accounts::tx_transfer(&mut tx_ctx, cbor::from_value(call.body).unwrap())
+            .expect("transfer at the limit should succeed");
+        tx_ctx.commit();
+    });
+
+    // Transfers above the limit should be rejected.
+    ctx.with_tx(0, transfer_tx(1_001), |mut tx_ctx, call| {
+        let result = Accounts::tx_transfer(&mut tx_ctx, cbor::from_value(call.body).unwrap());
+        assert!(matches!(
+            result,
+            Err(Error::Core(core::Error::TransferLimitExceeded(
+                1_000, 1_001
+            )))
+        ));
+    });
+
+    // Only the transfer at the limit should have been performed.
+    let bals = Accounts::get_balances(ctx.runtime_state(), keys::bob::address())
+        .expect("get_balances should succeed");
+    assert_eq!(
+        bals.balances[&Denomination::NATIVE],
+        1_000,
+        "balance in destination account should be correct"
+    );
+
+    drop(ctx);
+
+    // Transfers should also be limited per account within a block.
+    mock.runtime_header.round += 1;
+    let mut ctx = mock.create_ctx();
+    core::Module::set_params(
+        ctx.runtime_state(),
+        core::Parameters {
+            max_batch_gas: u64::MAX,
+            transfer_limits: {
+                let mut limits = BTreeMap::new();
+                limits.insert(Denomination::NATIVE, 1_000);
+                limits
+            },
+            block_transfer_limits: {
+                let mut limits = BTreeMap::new();
+                limits.insert(Denomination::NATIVE, 1_500);
+                limits
+            },
+            ..Default::default()
+        },
+    );
+
+    ctx.with_tx(0, transfer_tx(1_000), |mut tx_ctx, call| {
+        Accounts::tx_transfer(&mut tx_ctx, cbor::from_value(call.body).unwrap())
+            .expect("transfer within the block limit should succeed");
+        tx_ctx.commit();
+    });
+    ctx.with_tx(0, transfer_tx(600), |mut tx_ctx, call| {
+        let result = Accounts::tx_transfer(&mut tx_ctx, cbor::from_value(call.body).unwrap());
+        assert!(matches!(
+            result,
+            Err(Error::Core(core::Error::TransferLimitExceeded(
+                1_500, 1_600
+            )))
+        ));
+    });
+
+    // Other accounts should not be affected by the transfers of Alice.
+    let mut tx = transfer_tx(1_000);
+    tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(
+        keys::bob::sigspec(),
+        0,
+    )];
+    tx.call.body = cbor::to_value(Transfer {
+        to: keys::alice::address(),
+        amount: BaseUnits::new(1_000, Denomination::NATIVE),
+    });
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        Accounts::tx_transfer(&mut tx_ctx, cbor::from_value(call.body).unwrap())
+            .expect("transfer by another account should succeed");
+        tx_ctx.commit();
+    });
+
+    // Transfers performed internally by modules should not be limited.
+    Accounts::transfer(
+        &mut ctx,
+        keys::alice::address(),
+        keys::bob::address(),
+        &BaseUnits::new(600, Denomination::NATIVE),
+    )
+    .expect("internal transfer should not be limited");
+    drop(ctx);
+
+    // The block limit should apply afresh in the next block.
+    mock.runtime_header.round += 1;
+    let mut ctx = mock.create_ctx();
+    ctx.with_tx(0, transfer_tx(1_000), |mut tx_ctx, call| {
+        Accounts::tx_transfer(&mut tx_ctx, cbor::from_value(call.body).unwrap())
+            .expect("transfer within the limit of the next block should succeed");
+        tx_ctx.commit();
+    });
+    let bals = Accounts::get_balances(ctx.runtime_state(), keys::bob::address())
+        .expect("get_balances should succeed");
+    assert_eq!(bals.balances[&Denomination::NATIVE], 2_600);
+}
+
+#[test]
+fn test_conversion_amounts() {
+    let conversion = Conversion {
+        from: Denomination::NATIVE,
+        to: "WNAT".parse().unwrap(),
+        numerator: 2,
+        denominator: 3,
+    };
+
+    assert_eq!(conversion.amounts(3), Some((3, 2)), "exact conversion");
+    assert_eq!(
+        conversion.amounts(4),
+        Some((3, 2)),
+        "unconverted remainder should not be burned"
+    );
+    assert_eq!(
+        conversion.amounts(5),
+        Some((5, 3)),
+        "fractional remainder should be lost"
+    );
+    assert_eq!(conversion.amounts(1), None, "nothing should be minted");
+    assert_eq!(conversion.amounts(u128::MAX), None, "overflow");
+}
+
+#[test]
+fn test_tx_convert() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    init_accounts(&mut ctx);
+
+    let wrapped: Denomination = "WNAT".parse().unwrap();
+    let mut params = Accounts::params(ctx.runtime_state());
+    // One wrapped token per three native tokens.
+    params.conversions = vec![Conversion {
+        from: Denomination::NATIVE,
+        to: wrapped.clone(),
+        numerator: 1,
+        denominator: 3,
+    }];
+    Accounts::set_params(ctx.runtime_state(), params);
+
+    let convert_tx = |from: &Denomination, to: &Denomination, amount| transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "accounts.Convert".to_owned(),
+            body: cbor::to_value(Convert {
+                from: from.clone(),
+                to: to.clone(),
+                amount,
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 0,
+            },
+            features: 0,
+        },
+    };
+
+    // Exact conversion.
+    ctx.with_tx(
+        0,
+        convert_tx(&Denomination::NATIVE, &wrapped, 999),
+        |mut tx_ctx, call| {
+            let result = Accounts::tx_convert(&mut tx_ctx, cbor::from_value(call.body).unwrap())
+                .expect("exact conversion should succeed");
+            assert_eq!(
+                result,
+                ConvertResult {
+                    burned: 999,
+                    minted: 333
+                }
+            );
+            tx_ctx.commit();
+        },
+    );
+
+    // Conversion with rounding loss.
+    ctx.with_tx(
+        0,
+        convert_tx(&Denomination::NATIVE, &wrapped, 1000),
+        |mut tx_ctx, call| {
+            let result = Accounts::tx_convert(&mut tx_ctx, cbor::from_value(call.body).unwrap())
+                .expect("conversion with remainder should succeed");
+            assert_eq!(
+                result,
+                ConvertResult {
+                    burned: 999,
+                    minted: 333
+                },
+                "remainder should be left with the owner"
+            );
+            tx_ctx.commit();
+        },
+    );
+
+    // Conversions which would not mint anything should be rejected.
+    ctx.with_tx(
+        0,
+        convert_tx(&Denomination::NATIVE, &wrapped, 2),
+        |mut tx_ctx, call| {
+            let result = Accounts::tx_convert(&mut tx_ctx, cbor::from_value(call.body).unwrap());
+            assert!(matches!(result, Err(Error::InvalidArgument)));
+        },
+    );
+
+    // Unsupported conversions should be rejected.
+    ctx.with_tx(
+        0,
+        convert_tx(&wrapped, &Denomination::NATIVE, 100),
+        |mut tx_ctx, call| {
+            let result = Accounts::tx_convert(&mut tx_ctx, cbor::from_value(call.body).unwrap());
+            assert!(matches!(result, Err(Error::Forbidden)));
+        },
+    );
+
+    let bals = Accounts::get_balances(ctx.runtime_state(), keys::alice::address())
+        .expect("get_balances should succeed");
+    assert_eq!(bals.balances[&Denomination::NATIVE], 1_000_000 - 2 * 999);
+    assert_eq!(bals.balances[&wrapped], 2 * 333);
+
+    let total_supplies = Accounts::get_total_supplies(ctx.runtime_state())
+        .expect("get_total_supplies should succeed");
+    assert_eq!(total_supplies[&Denomination::NATIVE], 1_000_000 - 2 * 999);
+    assert_eq!(total_supplies[&wrapped], 2 * 333);
+
+    assert!(
+        Accounts::check_invariants(&mut ctx).is_ok(),
+        "invariants should hold after conversions"
+    );
+}
+
+#[test]
+fn test_conversion_params_validation() {
+    let conversion = |from: &str, to: &str, numerator, denominator| Conversion {
+        from: from.parse().unwrap(),
+        to: to.parse().unwrap(),
+        numerator,
+        denominator,
+    };
+    let params = |conversions| Parameters {
+        conversions,
+        ..Default::default()
+    };
+
+    params(vec![
+        conversion("", "WNAT", 1, 1),
+        conversion("WNAT", "", 1, 1),
+    ])
+    .validate_basic()
+    .expect("valid conversions should be accepted");
+    params(vec![conversion("WNAT", "WNAT", 1, 1)])
+        .validate_basic()
+        .expect_err("conversion to itself");
+    params(vec![conversion("", "WNAT", 0, 1)])
+        .validate_basic()
+        .expect_err("zero numerator");
+    params(vec![conversion("", "WNAT", 1, 0)])
+        .validate_basic()
+        .expect_err("zero denominator");
+    params(vec![
+        conversion("", "WNAT", 1, 1),
+        conversion("", "WNAT", 2, 1),
+    ])
+    .validate_basic()
+    .expect_err("duplicate conversion");
+}
+
 #[test]
 fn test_fee_disbursement() {
     let mut mock = mock::Mock::default();
@@ -553,6 +1242,7 @@ fn test_fee_disbursement() {
                 gas: 1000,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
 
@@ -626,6 +1316,158 @@ fn test_fee_disbursement() {
     );
 }
 
+#[test]
+fn test_fee_burn() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    init_accounts(&mut ctx);
+    Accounts::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            fee_burn_numerator: 1,
+            fee_burn_denominator: 4,
+            ..Default::default()
+        },
+    );
+
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "accounts.Transfer".to_owned(),
+            body: cbor::to_value(Transfer {
+                to: keys::bob::address(),
+                amount: Default::default(),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: BaseUnits::new(1_001, Denomination::NATIVE),
+                gas: 1000,
+                consensus_messages: 0,
+            },
+            features: 0,
+        },
+    };
+
+    // Collect fees in one block and process them at the end of the next one.
+    Accounts::authenticate_tx(&mut ctx, &tx).expect("transaction authentication should succeed");
+    Accounts::end_block(&mut ctx);
+    Accounts::end_block(&mut ctx);
+
+    // A quarter of the fees (rounded down) should be burned and the rest retained.
+    let ts = Accounts::get_total_supplies(ctx.runtime_state())
+        .expect("get_total_supplies should succeed");
+    assert_eq!(
+        ts[&Denomination::NATIVE],
+        999_750,
+        "burned fees should reduce the total supply"
+    );
+    let bals = Accounts::get_balances(ctx.runtime_state(), *ADDRESS_COMMON_POOL)
+        .expect("get_balances should succeed");
+    assert_eq!(
+        bals.balances[&Denomination::NATIVE],
+        751,
+        "retained fees should be moved to the common pool"
+    );
+    Accounts::check_invariants(&mut ctx).expect("invariants should hold after burning fees");
+
+    // The summary should match the collected fees.
+    #[derive(Debug, cbor::Decode)]
+    struct FeeSummaryEvent {
+        burned: BaseUnits,
+        retained: BaseUnits,
+    }
+    let (tags, _) = ctx.commit();
+    let summaries: Vec<FeeSummaryEvent> = tags
+        .iter()
+        .filter(|tag| tag.key == b"accounts\x00\x00\x00\x05") // accounts.FeeSummary (code = 5) event
+        .map(|tag| cbor::from_slice(&tag.value).unwrap())
+        .collect();
+    assert_eq!(summaries.len(), 1, "a single summary should be emitted");
+    assert_eq!(
+        summaries[0].burned,
+        BaseUnits::new(250, Denomination::NATIVE)
+    );
+    assert_eq!(
+        summaries[0].retained,
+        BaseUnits::new(751, Denomination::NATIVE)
+    );
+    assert_eq!(
+        summaries[0].burned.amount() + summaries[0].retained.amount(),
+        1_001,
+        "summary should account for all collected fees"
+    );
+}
+
+#[test]
+fn test_fee_burn_events_limited() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    init_accounts(&mut ctx);
+    Accounts::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            fee_burn_numerator: 1,
+            fee_burn_denominator: 4,
+            ..Default::default()
+        },
+    );
+
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "accounts.Transfer".to_owned(),
+            body: cbor::to_value(Transfer {
+                to: keys::bob::address(),
+                amount: Default::default(),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: BaseUnits::new(1_001, Denomination::NATIVE),
+                gas: 1000,
+                consensus_messages: 0,
+            },
+            features: 0,
+        },
+    };
+
+    // Fees should still be processed when the fee summary cannot be emitted.
+    Accounts::authenticate_tx(&mut ctx, &tx).expect("transaction authentication should succeed");
+    Accounts::end_block(&mut ctx);
+    ctx.limit_max_events(0).unwrap();
+    Accounts::end_block(&mut ctx);
+
+    let bals = Accounts::get_balances(ctx.runtime_state(), *ADDRESS_COMMON_POOL)
+        .expect("get_balances should succeed");
+    assert_eq!(
+        bals.balances[&Denomination::NATIVE],
+        751,
+        "retained fees should be moved to the common pool"
+    );
+    Accounts::check_invariants(&mut ctx).expect("invariants should hold after burning fees");
+
+    let (tags, _) = ctx.commit();
+    assert!(
+        !tags
+            .iter()
+            .any(|tag| tag.key == b"accounts\x00\x00\x00\x05"), // accounts.FeeSummary (code = 5) event
+        "no fee summary should be emitted"
+    );
+}
+
 #[test]
 fn test_query_addresses() {
     let mut mock = mock::Mock::default();
@@ -1208,3 +2050,34 @@ fn test_query_denomination_info() {
     )
     .unwrap_err();
 }
+
+#[test]
+fn test_params_of() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    core::Module::set_params(
+        ctx.runtime_state(),
+        core::Parameters {
+            max_batch_gas: 10_000,
+            max_tx_signers: 8,
+            ..Default::default()
+        },
+    );
+
+    // The core module's parameters should be readable from the accounts module.
+    let params = Accounts::params_of::<core::Module, _>(ctx.runtime_state());
+    assert_eq!(params.max_batch_gas, 10_000);
+    assert_eq!(params.max_tx_signers, 8);
+
+    // Reading a module's own parameters should be equivalent to calling params.
+    Accounts::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            transfers_disabled: true,
+            ..Default::default()
+        },
+    );
+    let params = Accounts::params_of::<Accounts, _>(ctx.runtime_state());
+    assert!(params.transfers_disabled);
+}
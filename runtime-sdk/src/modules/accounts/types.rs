@@ -12,6 +12,55 @@ pub struct Transfer {
     pub amount: token::BaseUnits,
 }
 
+/// Convert call.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct Convert {
+    pub from: token::Denomination,
+    pub to: token::Denomination,
+    pub amount: u128,
+}
+
+/// Result of the Convert call.
+#[derive(Clone, Debug, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct ConvertResult {
+    /// Amount of the source denomination that has been burned.
+    pub burned: u128,
+    /// Amount of the target denomination that has been minted.
+    pub minted: u128,
+}
+
+/// A supported conversion between two denominations at a fixed rate.
+///
+/// Converting `amount` units of `from` yields `amount * numerator / denominator` units of `to`,
+/// rounded down.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct Conversion {
+    pub from: token::Denomination,
+    pub to: token::Denomination,
+    pub numerator: u128,
+    pub denominator: u128,
+}
+
+impl Conversion {
+    /// Compute the amounts burned and minted when converting the given amount.
+    ///
+    /// As the minted amount is rounded down, only the smallest amount that still yields the same
+    /// minted amount is burned and the rest is left with the owner. This means that at most the
+    /// value of less than one unit of the target denomination is lost in any conversion.
+    ///
+    /// Returns `None` in case the computation overflows or the conversion would not mint anything.
+    pub fn amounts(&self, amount: u128) -> Option<(u128, u128)> {
+        let minted = amount.checked_mul(self.numerator)? / self.denominator;
+        if minted == 0 {
+            return None;
+        }
+        // Since minted * denominator <= amount * numerator this cannot overflow.
+        let product = minted * self.denominator;
+        let burned = product / self.numerator + (product % self.numerator != 0) as u128;
+        Some((burned, minted))
+    }
+}
+
 /// Account metadata.
 #[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
 pub struct Account {
@@ -25,6 +74,15 @@ pub struct Account {
 #[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
 pub struct NonceQuery {
     pub address: Address,
+
+    /// Whether to report the nonce following any transactions of the account that are still
+    /// pending in the mempool instead of the committed one.
+    ///
+    /// This is best-effort and node-local: it only accounts for transactions that passed checks
+    /// on the queried node during the last few rounds, and may be ahead of the actual next nonce
+    /// in case such transactions are later dropped.
+    #[cbor(optional, default)]
+    pub pending: bool,
 }
 
 /// Arguments for the Addresses query.
@@ -1,13 +1,14 @@
 //! Consensus module.
 //!
 //! Low level consensus module for communicating with the consensus layer.
-use std::str::FromStr;
+use std::{collections::BTreeSet, convert::TryInto, str::FromStr};
 
 use thiserror::Error;
 
 use oasis_core_runtime::{
     common::versioned::Versioned,
     consensus::{
+        address::Address as ConsensusAddress,
         roothash::{Message, StakingMessage},
         staking,
         staking::Account as ConsensusAccount,
@@ -40,6 +41,16 @@ const MODULE_NAME: &str = "consensus";
 pub struct Parameters {
     pub consensus_denomination: token::Denomination,
     pub consensus_scaling_factor: u64,
+
+    /// Additional runtime denominations that map to the consensus layer token. Amounts in any of
+    /// these denominations can be deposited and withdrawn the same as the consensus denomination.
+    ///
+    /// All of these denominations are backed by the same consensus layer token, so they are
+    /// freely interchangeable: tokens withdrawn in one denomination can be deposited back in any
+    /// other. They should therefore only be used for denominations that are meant to be
+    /// equivalent to the consensus denomination.
+    #[cbor(optional, default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub allowed_denominations: BTreeSet<token::Denomination>,
 }
 
 impl Default for Parameters {
@@ -47,6 +58,7 @@ impl Default for Parameters {
         Self {
             consensus_denomination: token::Denomination::from_str("TEST").unwrap(),
             consensus_scaling_factor: 1,
+            allowed_denominations: BTreeSet::new(),
         }
     }
 }
@@ -152,17 +164,41 @@ pub trait API {
     /// Returns consensus token denomination.
     fn consensus_denomination<C: Context>(ctx: &mut C) -> Result<token::Denomination, Error>;
 
+    /// Returns all runtime token denominations that map to the consensus layer token, including
+    /// the consensus token denomination.
+    fn consensus_denominations<C: Context>(
+        ctx: &mut C,
+    ) -> Result<BTreeSet<token::Denomination>, Error>;
+
     /// Ensures transaction signer is consensus compatible.
     fn ensure_compatible_tx_signer<C: TxContext>(ctx: &C) -> Result<(), Error>;
 
     /// Query consensus account info.
     fn account<C: Context>(ctx: &C, addr: Address) -> Result<ConsensusAccount, Error>;
 
-    /// Convert runtime amount to consensus amount, scaling as needed.
-    fn amount_from_consensus<C: Context>(ctx: &mut C, amount: u128) -> Result<u128, Error>;
+    /// Query the allowance that the given consensus account granted to the beneficiary. The
+    /// amount is in consensus base units.
+    fn allowance<C: Context>(ctx: &C, owner: Address, beneficiary: Address) -> Result<u128, Error>;
+
+    /// Convert consensus amount to runtime amount in the given denomination, scaling as needed.
+    ///
+    /// Fails with `InvalidDenomination` in case the denomination does not map to the consensus
+    /// layer token.
+    fn amount_from_consensus<C: Context>(
+        ctx: &mut C,
+        denomination: &token::Denomination,
+        amount: u128,
+    ) -> Result<u128, Error>;
 
-    /// Convert consensus amount to runtime amount, scaling as needed.
-    fn amount_to_consensus<C: Context>(ctx: &mut C, amount: u128) -> Result<u128, Error>;
+    /// Convert runtime amount in the given denomination to consensus amount, scaling as needed.
+    ///
+    /// Fails with `InvalidDenomination` in case the denomination does not map to the consensus
+    /// layer token.
+    fn amount_to_consensus<C: Context>(
+        ctx: &mut C,
+        denomination: &token::Denomination,
+        amount: u128,
+    ) -> Result<u128, Error>;
 }
 
 pub struct Module;
@@ -172,7 +208,7 @@ impl Module {
         ctx: &mut C,
         denomination: &token::Denomination,
     ) -> Result<(), Error> {
-        if denomination != &Self::consensus_denomination(ctx)? {
+        if !Self::consensus_denominations(ctx)?.contains(denomination) {
             return Err(Error::InvalidDenomination);
         }
 
@@ -191,8 +227,7 @@ impl API for Module {
         amount: &token::BaseUnits,
         hook: MessageEventHookInvocation,
     ) -> Result<(), Error> {
-        Self::ensure_consensus_denomination(ctx, amount.denomination())?;
-        let amount = Self::amount_to_consensus(ctx, amount.amount())?;
+        let amount = Self::amount_to_consensus(ctx, amount.denomination(), amount.amount())?;
 
         Core::add_weight(ctx, TransactionWeight::ConsensusMessages, 1)?;
 
@@ -220,8 +255,7 @@ impl API for Module {
         amount: &token::BaseUnits,
         hook: MessageEventHookInvocation,
     ) -> Result<(), Error> {
-        Self::ensure_consensus_denomination(ctx, amount.denomination())?;
-        let amount = Self::amount_to_consensus(ctx, amount.amount())?;
+        let amount = Self::amount_to_consensus(ctx, amount.denomination(), amount.amount())?;
 
         Core::add_weight(ctx, TransactionWeight::ConsensusMessages, 1)?;
 
@@ -249,8 +283,7 @@ impl API for Module {
         amount: &token::BaseUnits,
         hook: MessageEventHookInvocation,
     ) -> Result<(), Error> {
-        Self::ensure_consensus_denomination(ctx, amount.denomination())?;
-        let amount = Self::amount_to_consensus(ctx, amount.amount())?;
+        let amount = Self::amount_to_consensus(ctx, amount.denomination(), amount.amount())?;
 
         Core::add_weight(ctx, TransactionWeight::ConsensusMessages, 1)?;
 
@@ -303,6 +336,15 @@ impl API for Module {
         Ok(params.consensus_denomination)
     }
 
+    fn consensus_denominations<C: Context>(
+        ctx: &mut C,
+    ) -> Result<BTreeSet<token::Denomination>, Error> {
+        let params = Self::params(ctx.runtime_state());
+        let mut denominations = params.allowed_denominations;
+        denominations.insert(params.consensus_denomination);
+        Ok(denominations)
+    }
+
     fn ensure_compatible_tx_signer<C: TxContext>(ctx: &C) -> Result<(), Error> {
         match ctx.tx_auth_info().signer_info[0].address_spec {
             AddressSpec::Signature(SignatureAddressSpec::Ed25519(_)) => Ok(()),
@@ -317,7 +359,25 @@ impl API for Module {
             .map_err(Error::InternalStateError)
     }
 
-    fn amount_from_consensus<C: Context>(ctx: &mut C, amount: u128) -> Result<u128, Error> {
+    fn allowance<C: Context>(ctx: &C, owner: Address, beneficiary: Address) -> Result<u128, Error> {
+        let account = Self::account(ctx, owner)?;
+        let beneficiary: ConsensusAddress = beneficiary.into();
+        account
+            .general
+            .allowances
+            .get(&beneficiary)
+            .cloned()
+            .unwrap_or_default()
+            .try_into()
+            .map_err(|_| Error::AmountNotRepresentable)
+    }
+
+    fn amount_from_consensus<C: Context>(
+        ctx: &mut C,
+        denomination: &token::Denomination,
+        amount: u128,
+    ) -> Result<u128, Error> {
+        Self::ensure_consensus_denomination(ctx, denomination)?;
         let params = Self::params(ctx.runtime_state());
         let scaling_factor = params.consensus_scaling_factor;
         amount
@@ -325,7 +385,12 @@ impl API for Module {
             .ok_or(Error::AmountNotRepresentable)
     }
 
-    fn amount_to_consensus<C: Context>(ctx: &mut C, amount: u128) -> Result<u128, Error> {
+    fn amount_to_consensus<C: Context>(
+        ctx: &mut C,
+        denomination: &token::Denomination,
+        amount: u128,
+    ) -> Result<u128, Error> {
+        Self::ensure_consensus_denomination(ctx, denomination)?;
         let params = Self::params(ctx.runtime_state());
         let scaling_factor = params.consensus_scaling_factor;
         let scaled = amount
@@ -363,6 +428,10 @@ impl module::MethodHandler for Module {
             _ => module::DispatchResult::Unhandled(args),
         }
     }
+
+    fn register_error_codes(registry: &mut modules::core::types::ErrorRegistry) {
+        module::register_error_codes::<Self>(registry)
+    }
 }
 
 impl Module {
@@ -413,4 +482,5 @@ impl module::AuthHandler for Module {}
 
 impl module::BlockHandler for Module {}
 
+impl module::EventHandler for Module {}
 impl module::InvariantHandler for Module {}
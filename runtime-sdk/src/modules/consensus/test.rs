@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{collections::BTreeSet, str::FromStr};
 
 use oasis_core_runtime::{
     common::{quantity::Quantity, versioned::Versioned},
@@ -19,7 +19,7 @@ use crate::{
     },
 };
 
-use super::{Genesis, Parameters, API as _};
+use super::{Error, Genesis, Parameters, API as _};
 
 #[test]
 fn test_api_transfer_invalid_denomination() {
@@ -396,6 +396,18 @@ fn test_api_account() {
     )
 }
 
+#[test]
+fn test_api_allowance() {
+    let mut mock = mock::Mock::default();
+    let ctx = mock.create_ctx();
+
+    // TODO: prepare mock consensus state.
+
+    let allowance = Consensus::allowance(&ctx, keys::alice::address(), keys::bob::address())
+        .expect("query should succeed");
+    assert_eq!(allowance, 0, "allowance should be zero");
+}
+
 #[test]
 fn test_api_scaling() {
     let mut mock = mock::Mock::default();
@@ -408,38 +420,129 @@ fn test_api_scaling() {
             ..Default::default()
         },
     );
+    let denomination = Consensus::consensus_denomination(&mut ctx).unwrap();
 
     // Not representable.
-    Consensus::amount_to_consensus(&mut ctx, 100).unwrap_err();
-    Consensus::amount_to_consensus(&mut ctx, 1100).unwrap_err();
-    Consensus::amount_to_consensus(&mut ctx, 2500).unwrap_err();
-    Consensus::amount_to_consensus(&mut ctx, 2500).unwrap_err();
-    Consensus::amount_to_consensus(&mut ctx, 1_000_250).unwrap_err();
-    Consensus::amount_to_consensus(&mut ctx, 1_000_001).unwrap_err();
+    Consensus::amount_to_consensus(&mut ctx, &denomination, 100).unwrap_err();
+    Consensus::amount_to_consensus(&mut ctx, &denomination, 1100).unwrap_err();
+    Consensus::amount_to_consensus(&mut ctx, &denomination, 2500).unwrap_err();
+    Consensus::amount_to_consensus(&mut ctx, &denomination, 2500).unwrap_err();
+    Consensus::amount_to_consensus(&mut ctx, &denomination, 1_000_250).unwrap_err();
+    Consensus::amount_to_consensus(&mut ctx, &denomination, 1_000_001).unwrap_err();
     // Scaling.
-    assert_eq!(Consensus::amount_to_consensus(&mut ctx, 0).unwrap(), 0);
-    assert_eq!(Consensus::amount_to_consensus(&mut ctx, 1000).unwrap(), 1);
-    assert_eq!(Consensus::amount_to_consensus(&mut ctx, 2000).unwrap(), 2);
     assert_eq!(
-        Consensus::amount_to_consensus(&mut ctx, 1_000_000).unwrap(),
+        Consensus::amount_to_consensus(&mut ctx, &denomination, 0).unwrap(),
+        0
+    );
+    assert_eq!(
+        Consensus::amount_to_consensus(&mut ctx, &denomination, 1000).unwrap(),
+        1
+    );
+    assert_eq!(
+        Consensus::amount_to_consensus(&mut ctx, &denomination, 2000).unwrap(),
+        2
+    );
+    assert_eq!(
+        Consensus::amount_to_consensus(&mut ctx, &denomination, 1_000_000).unwrap(),
         1000
     );
     assert_eq!(
-        Consensus::amount_to_consensus(&mut ctx, 1_234_000).unwrap(),
+        Consensus::amount_to_consensus(&mut ctx, &denomination, 1_234_000).unwrap(),
         1234
     );
-    assert_eq!(Consensus::amount_from_consensus(&mut ctx, 0).unwrap(), 0);
-    assert_eq!(Consensus::amount_from_consensus(&mut ctx, 1).unwrap(), 1000);
     assert_eq!(
-        Consensus::amount_from_consensus(&mut ctx, 10).unwrap(),
+        Consensus::amount_from_consensus(&mut ctx, &denomination, 0).unwrap(),
+        0
+    );
+    assert_eq!(
+        Consensus::amount_from_consensus(&mut ctx, &denomination, 1).unwrap(),
+        1000
+    );
+    assert_eq!(
+        Consensus::amount_from_consensus(&mut ctx, &denomination, 10).unwrap(),
         10_000
     );
     assert_eq!(
-        Consensus::amount_from_consensus(&mut ctx, 1000).unwrap(),
+        Consensus::amount_from_consensus(&mut ctx, &denomination, 1000).unwrap(),
         1_000_000
     );
 }
 
+#[test]
+fn test_api_allowed_denominations() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    let other = Denomination::from_str("OTHER").unwrap();
+    Consensus::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            consensus_scaling_factor: 1_000,
+            allowed_denominations: vec![other.clone()].into_iter().collect(),
+            ..Default::default()
+        },
+    );
+
+    let denominations = Consensus::consensus_denominations(&mut ctx).unwrap();
+    let expected: BTreeSet<_> = vec![Denomination::from_str("TEST").unwrap(), other.clone()]
+        .into_iter()
+        .collect();
+    assert_eq!(denominations, expected);
+
+    // Allowed denominations are scaled the same as the consensus denomination.
+    assert_eq!(
+        Consensus::amount_to_consensus(&mut ctx, &other, 2000).unwrap(),
+        2
+    );
+    assert_eq!(
+        Consensus::amount_from_consensus(&mut ctx, &other, 2).unwrap(),
+        2000
+    );
+    assert!(matches!(
+        Consensus::amount_to_consensus(&mut ctx, &Denomination::NATIVE, 2000),
+        Err(Error::InvalidDenomination)
+    ));
+    assert!(matches!(
+        Consensus::amount_from_consensus(&mut ctx, &Denomination::NATIVE, 2),
+        Err(Error::InvalidDenomination)
+    ));
+
+    ctx.with_tx(0, mock::transaction(), |mut tx_ctx, _call| {
+        let hook_name = "test_event_handler";
+        Consensus::withdraw(
+            &mut tx_ctx,
+            keys::alice::address(),
+            &BaseUnits::new(2_000, other.clone()),
+            MessageEventHookInvocation::new(hook_name.to_string(), 0),
+        )
+        .expect("withdraw in an allowed denomination should succeed");
+        assert!(matches!(
+            Consensus::withdraw(
+                &mut tx_ctx,
+                keys::alice::address(),
+                &BaseUnits::new(2_000, Denomination::NATIVE),
+                MessageEventHookInvocation::new(hook_name.to_string(), 0),
+            ),
+            Err(Error::InvalidDenomination)
+        ));
+
+        let (_, msgs) = tx_ctx.commit();
+        assert_eq!(1, msgs.len(), "one message should be emitted");
+        let (msg, _) = msgs.first().unwrap();
+        assert_eq!(
+            &Message::Staking(Versioned::new(
+                0,
+                StakingMessage::Withdraw(staking::Withdraw {
+                    from: keys::alice::address().into(),
+                    amount: Quantity::from(2u128),
+                })
+            )),
+            msg,
+            "emitted message should match"
+        );
+    });
+}
+
 #[test]
 fn test_query_parameters() {
     let mut mock = mock::Mock::default();
@@ -448,6 +551,7 @@ fn test_query_parameters() {
     let params = Parameters {
         consensus_denomination: Denomination::NATIVE,
         consensus_scaling_factor: 1_000,
+        ..Default::default()
     };
     Consensus::set_params(ctx.runtime_state(), params.clone());
 
@@ -468,6 +572,7 @@ fn test_init_bad_scaling_factor_1() {
                 consensus_denomination: Denomination::NATIVE,
                 // Zero scaling factor is invalid.
                 consensus_scaling_factor: 0,
+                ..Default::default()
             },
             ..Default::default()
         },
@@ -487,6 +592,7 @@ fn test_init_bad_scaling_factor_2() {
                 consensus_denomination: Denomination::NATIVE,
                 // Scaling factor that is not a power of 10 is invalid.
                 consensus_scaling_factor: 1230,
+                ..Default::default()
             },
             ..Default::default()
         },
@@ -2,22 +2,29 @@
 //!
 //! This module allows consensus transfers in and out of the runtime account,
 //! while keeping track of amount deposited per account.
-use std::{collections::BTreeSet, convert::TryInto};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::{TryFrom, TryInto},
+};
 
 use once_cell::sync::Lazy;
+use slog::error;
 use thiserror::Error;
 
-use oasis_core_runtime::consensus::staking::Account as ConsensusAccount;
+use oasis_core_runtime::consensus::{
+    beacon::EpochTime,
+    staking::{Account as ConsensusAccount, EscrowAccount, SharePool},
+};
 
 use crate::{
-    context::{Context, TxContext},
+    context::{Context, ExecutionMode, TxContext},
     error, module,
     module::{CallResult, Module as _},
     modules,
     modules::core::{Error as CoreError, Module as Core, API as _},
-    storage::Prefix,
+    storage::{self, Prefix},
     types::{
-        address::Address,
+        address::{Address, SignatureAddressSpec},
         message::{MessageEvent, MessageEventHookInvocation, MessageResult},
         token,
         transaction::{AuthInfo, TransactionWeight},
@@ -31,6 +38,12 @@ pub mod types;
 /// Unique module name.
 const MODULE_NAME: &str = "consensus_accounts";
 
+/// Maximum size (in bytes) of a deposit or withdrawal memo.
+pub const MAX_MEMO_SIZE: usize = 64;
+
+/// Size (in bytes) of the nonce prefixing the keys of pending withdrawals.
+const NONCE_SIZE: usize = 8;
+
 #[derive(Error, Debug, oasis_runtime_sdk_macros::Error)]
 pub enum Error {
     #[error("invalid argument")]
@@ -45,6 +58,22 @@ pub enum Error {
     #[sdk_error(code = 3)]
     InsufficientWithdrawBalance,
 
+    #[error("add escrow: insufficient runtime balance")]
+    #[sdk_error(code = 4)]
+    InsufficientEscrowBalance,
+
+    #[error("reclaim escrow: insufficient escrowed shares")]
+    #[sdk_error(code = 5)]
+    InsufficientEscrowShares,
+
+    #[error("forbidden by policy")]
+    #[sdk_error(code = 6)]
+    Forbidden,
+
+    #[error("deposit: insufficient consensus allowance (allowance: {0})")]
+    #[sdk_error(code = 7)]
+    InsufficientDepositAllowance(u128),
+
     #[error("consensus: {0}")]
     #[sdk_error(transparent)]
     Consensus(#[from] modules::consensus::Error),
@@ -59,12 +88,36 @@ pub enum Error {
 pub struct GasCosts {
     pub tx_deposit: u64,
     pub tx_withdraw: u64,
+    #[cbor(optional, default)]
+    pub tx_add_escrow: u64,
+    #[cbor(optional, default)]
+    pub tx_reclaim_escrow: u64,
+
+    #[cbor(optional, default)]
+    pub query_balance: u64,
+    #[cbor(optional, default)]
+    pub query_consensus_account: u64,
 }
 
 /// Parameters for the consensus module.
 #[derive(Clone, Default, Debug, cbor::Encode, cbor::Decode)]
 pub struct Parameters {
     pub gas_costs: GasCosts,
+
+    /// Whether withdrawals to accounts other than the sender's are forbidden.
+    #[cbor(optional, default)]
+    pub disable_delegated_withdraw: bool,
+
+    /// Whether deposits should be checked against the allowance that the depositor granted to the
+    /// runtime account when checking transactions. This requires an extra consensus state read.
+    #[cbor(optional, default)]
+    pub check_deposit_allowance: bool,
+
+    /// Debonding interval of the consensus layer staking service (in epochs). Reclaimed escrow is
+    /// released once this many epochs have passed since the reclaim has been processed. In case
+    /// this is not set, reclaiming escrow is not allowed.
+    #[cbor(optional)]
+    pub debonding_interval: Option<EpochTime>,
 }
 
 impl module::Parameters for Parameters {
@@ -83,6 +136,8 @@ pub enum Event {
         amount: token::BaseUnits,
         #[cbor(optional)]
         error: Option<types::ConsensusError>,
+        #[cbor(optional)]
+        memo: Option<Vec<u8>>,
     },
 
     #[sdk_event(code = 2)]
@@ -93,7 +148,60 @@ pub enum Event {
         amount: token::BaseUnits,
         #[cbor(optional)]
         error: Option<types::ConsensusError>,
+        #[cbor(optional)]
+        memo: Option<Vec<u8>>,
     },
+
+    #[sdk_event(code = 3)]
+    AddEscrow {
+        from: Address,
+        nonce: u64,
+        to: Address,
+        amount: token::BaseUnits,
+        #[cbor(optional)]
+        error: Option<types::ConsensusError>,
+    },
+
+    /// The consensus layer does not report how many tokens the reclaimed shares were worth, so
+    /// the event carries the epoch in which the reclaim was processed. The tokens are released
+    /// once the debonding period counted from that epoch ends.
+    #[sdk_event(code = 4)]
+    ReclaimEscrow {
+        from: Address,
+        nonce: u64,
+        to: Address,
+        shares: u128,
+        epoch: EpochTime,
+        #[cbor(optional)]
+        error: Option<types::ConsensusError>,
+    },
+
+    /// Emitted when a withdrawal has been submitted and the tokens are pending withdrawal. It
+    /// always precedes the terminal `Withdraw` event with the same `from` and `nonce`, which is
+    /// emitted once the consensus layer transfer succeeds or fails.
+    #[sdk_event(code = 5)]
+    WithdrawInitiated {
+        from: Address,
+        nonce: u64,
+        to: Address,
+        amount: token::BaseUnits,
+    },
+}
+
+/// State schema constants.
+pub mod state {
+    /// Map of (account address, nonce, denomination) to amounts of withdrawals pending in the
+    /// consensus layer.
+    pub const PENDING_WITHDRAWALS: &[u8] = &[0x01];
+    /// Map of (account address, escrow account address) to escrows made through the runtime.
+    pub const ESCROWS: &[u8] = &[0x02];
+    /// Map of escrow account addresses to reclaimed escrows that are debonding, in the order in
+    /// which they have been reclaimed.
+    pub const DEBONDING_ESCROWS: &[u8] = &[0x03];
+    /// Map of (account address, denomination) to the cumulative amount deposited into them.
+    pub const DEPOSITED: &[u8] = &[0x04];
+    /// Map of denominations to the cumulative amount deposited into all accounts.
+    pub const TOTAL_DEPOSITED: &[u8] = &[0x05];
 }
 
 /// Genesis state for the consensus module.
@@ -110,12 +218,15 @@ pub trait API {
     ///
     /// * `nonce`: A caller-provided sequence number that will help identify the success/fail events.
     ///   When called from a deposit transaction, we use the signer nonce.
+    /// * `memo`: An optional opaque reference (at most [`MAX_MEMO_SIZE`] bytes) that is included
+    ///   in the success/fail events.
     fn deposit<C: TxContext>(
         ctx: &mut C,
         from: Address,
         nonce: u64,
         to: Address,
         amount: token::BaseUnits,
+        memo: Option<Vec<u8>>,
     ) -> Result<(), Error>;
 
     /// Transfer from runtime account to consensus staking account.
@@ -124,17 +235,52 @@ pub trait API {
     ///
     /// * `nonce`: A caller-provided sequence number that will help identify the success/fail events.
     ///   When called from a withdraw transaction, we use the signer nonce.
+    /// * `memo`: An optional opaque reference (at most [`MAX_MEMO_SIZE`] bytes) that is included
+    ///   in the success/fail events.
     fn withdraw<C: TxContext>(
         ctx: &mut C,
         from: Address,
         nonce: u64,
         to: Address,
         amount: token::BaseUnits,
+        memo: Option<Vec<u8>>,
+    ) -> Result<(), Error>;
+
+    /// Escrow tokens from a runtime account to a consensus staking account.
+    ///
+    /// # Arguments
+    ///
+    /// * `nonce`: A caller-provided sequence number that will help identify the success/fail events.
+    ///   When called from an add escrow transaction, we use the signer nonce.
+    fn add_escrow<C: TxContext>(
+        ctx: &mut C,
+        from: Address,
+        to: Address,
+        amount: token::BaseUnits,
+        nonce: u64,
     ) -> Result<(), Error>;
 
-    // TODO:
-    //  - Add/reclaim deposited escrow.
-    //      - need a way to get escrow events in runtime: https://github.com/oasisprotocol/oasis-core/issues/3862
+    /// Reclaim shares escrowed by a runtime account from a consensus staking account.
+    ///
+    /// # Arguments
+    ///
+    /// * `nonce`: A caller-provided sequence number that will help identify the success/fail events.
+    ///   When called from a reclaim escrow transaction, we use the signer nonce.
+    fn reclaim_escrow<C: TxContext>(
+        ctx: &mut C,
+        from: Address,
+        to: Address,
+        shares: u128,
+        nonce: u64,
+    ) -> Result<(), Error>;
+
+    /// Release reclaimed escrow once its debonding period ends.
+    ///
+    /// The amount of tokens that the debonded shares were worth when returned to the runtime
+    /// account is credited to the account that has reclaimed the shares. This is called at the end
+    /// of each block for all reclaims whose debonding period has ended. In case of failure, any
+    /// state changes should be discarded.
+    fn debonding_end<C: Context>(ctx: &mut C, end: types::DebondingEnd) -> Result<(), Error>;
 }
 
 pub struct Module<Accounts: modules::accounts::API, Consensus: modules::consensus::API> {
@@ -146,8 +292,14 @@ pub struct Module<Accounts: modules::accounts::API, Consensus: modules::consensu
 pub static ADDRESS_PENDING_WITHDRAWAL: Lazy<Address> =
     Lazy::new(|| Address::from_module(MODULE_NAME, "pending-withdrawal"));
 
+/// Module's address that has the tokens escrowed in the consensus layer.
+pub static ADDRESS_ESCROW: Lazy<Address> =
+    Lazy::new(|| Address::from_module(MODULE_NAME, "escrow"));
+
 const CONSENSUS_TRANSFER_HANDLER: &str = "consensus.TransferFromRuntime";
 const CONSENSUS_WITHDRAW_HANDLER: &str = "consensus.WithdrawIntoRuntime";
+const CONSENSUS_ESCROW_HANDLER: &str = "consensus.EscrowFromRuntime";
+const CONSENSUS_RECLAIM_ESCROW_HANDLER: &str = "consensus.ReclaimEscrowIntoRuntime";
 
 impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> API
     for Module<Accounts, Consensus>
@@ -158,13 +310,21 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> API
         nonce: u64,
         to: Address,
         amount: token::BaseUnits,
+        memo: Option<Vec<u8>>,
     ) -> Result<(), Error> {
-        if ctx.is_check_only() {
-            // In case this is not check only this weight will be emitted from Consensus::withdraw
-            // below, same as the amount conversion check.
-            Core::add_weight(ctx, TransactionWeight::ConsensusMessages, 1)?;
-            Consensus::amount_to_consensus(ctx, amount.amount())?;
-            return Ok(());
+        Self::ensure_valid_memo(&memo)?;
+
+        match ctx.execution_mode() {
+            ExecutionMode::Check => {
+                // In case this is not check only this weight will be emitted from Consensus::withdraw
+                // below, same as the amount conversion check.
+                Core::add_weight(ctx, TransactionWeight::ConsensusMessages, 1)?;
+                Consensus::amount_to_consensus(ctx, amount.denomination(), amount.amount())?;
+                return Ok(());
+            }
+            // Simulations emit the message as well, it is discarded together with the
+            // simulation context.
+            ExecutionMode::Simulate | ExecutionMode::Execute => {}
         }
 
         // XXX: could check consensus state if allowance for the runtime account
@@ -184,6 +344,7 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> API
                     nonce,
                     address: to,
                     amount: amount.clone(),
+                    memo,
                 },
             ),
         )?;
@@ -197,19 +358,32 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> API
         nonce: u64,
         to: Address,
         amount: token::BaseUnits,
+        memo: Option<Vec<u8>>,
     ) -> Result<(), Error> {
-        if ctx.is_check_only() {
-            // In case this is not check only this weight will be emitted from Consensus::transfer
-            // below, same as the amount conversion check.
-            Core::add_weight(ctx, TransactionWeight::ConsensusMessages, 1)?;
-            Consensus::amount_to_consensus(ctx, amount.amount())?;
-            return Ok(());
+        Self::ensure_valid_memo(&memo)?;
+
+        match ctx.execution_mode() {
+            ExecutionMode::Check => {
+                // In case this is not check only this weight will be emitted from Consensus::transfer
+                // below, same as the amount conversion check.
+                Core::add_weight(ctx, TransactionWeight::ConsensusMessages, 1)?;
+                Consensus::amount_to_consensus(ctx, amount.denomination(), amount.amount())?;
+                return Ok(());
+            }
+            ExecutionMode::Simulate | ExecutionMode::Execute => {}
         }
 
         // Transfer the given amount to the module's withdrawal account to make sure the tokens
         // remain available until actually withdrawn.
         Accounts::transfer(ctx, from, *ADDRESS_PENDING_WITHDRAWAL, &amount)
             .map_err(|_| Error::InsufficientWithdrawBalance)?;
+        ctx.try_emit_event(Event::WithdrawInitiated {
+            from,
+            nonce,
+            to,
+            amount: amount.clone(),
+        })?;
+        Self::add_pending_withdrawal(ctx, from, nonce, &amount);
 
         // Transfer out of runtime account and update the account state if successful.
         Consensus::transfer(
@@ -223,17 +397,185 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> API
                     nonce,
                     address: from,
                     amount: amount.clone(),
+                    memo,
+                },
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    fn add_escrow<C: TxContext>(
+        ctx: &mut C,
+        from: Address,
+        to: Address,
+        amount: token::BaseUnits,
+        nonce: u64,
+    ) -> Result<(), Error> {
+        // Reclaimed escrow is returned in the consensus denomination, so only that can be escrowed.
+        if amount.denomination() != &Consensus::consensus_denomination(ctx)? {
+            return Err(Error::InvalidDenomination);
+        }
+
+        match ctx.execution_mode() {
+            ExecutionMode::Check => {
+                // In case this is not check only this weight will be emitted from Consensus::escrow
+                // below, same as the amount conversion check.
+                Core::add_weight(ctx, TransactionWeight::ConsensusMessages, 1)?;
+                Consensus::amount_to_consensus(ctx, amount.denomination(), amount.amount())?;
+                return Ok(());
+            }
+            ExecutionMode::Simulate | ExecutionMode::Execute => {}
+        }
+
+        // Transfer the given amount to the module's escrow account, the tokens remain there for
+        // as long as they are escrowed in the consensus layer.
+        Accounts::transfer(ctx, from, *ADDRESS_ESCROW, &amount)
+            .map_err(|_| Error::InsufficientEscrowBalance)?;
+
+        // Escrow from the runtime account and update the account state if successful.
+        Consensus::escrow(
+            ctx,
+            to,
+            &amount,
+            MessageEventHookInvocation::new(
+                CONSENSUS_ESCROW_HANDLER.to_string(),
+                types::ConsensusEscrowContext {
+                    address: from,
+                    nonce,
+                    to,
+                    amount: amount.clone(),
                 },
             ),
         )?;
 
         Ok(())
     }
+
+    fn reclaim_escrow<C: TxContext>(
+        ctx: &mut C,
+        from: Address,
+        to: Address,
+        shares: u128,
+        nonce: u64,
+    ) -> Result<(), Error> {
+        // Reclaimed escrow can only be released once the debonding interval is known.
+        let params = Self::params(ctx.runtime_state());
+        if params.debonding_interval.is_none() {
+            return Err(Error::Forbidden);
+        }
+
+        // The shares are held by the runtime account on behalf of all its accounts, so make sure
+        // the account does not reclaim more than it has escrowed.
+        let mut escrow = Self::get_escrow(ctx, to, from);
+        escrow.reclaimed_shares = escrow
+            .reclaimed_shares
+            .checked_add(shares)
+            .filter(|reclaimed| *reclaimed <= escrow.shares)
+            .ok_or(Error::InsufficientEscrowShares)?;
+
+        match ctx.execution_mode() {
+            ExecutionMode::Check => {
+                // In case this is not check only this weight will be emitted from
+                // Consensus::reclaim_escrow below.
+                Core::add_weight(ctx, TransactionWeight::ConsensusMessages, 1)?;
+                return Ok(());
+            }
+            ExecutionMode::Simulate | ExecutionMode::Execute => {}
+        }
+
+        // Reserve the shares so they cannot be reclaimed again while the message is pending.
+        Self::set_escrow(ctx, to, from, escrow);
+
+        // Reclaim into the runtime account and update the account state if successful.
+        Consensus::reclaim_escrow(
+            ctx,
+            from,
+            shares,
+            MessageEventHookInvocation::new(
+                CONSENSUS_RECLAIM_ESCROW_HANDLER.to_string(),
+                types::ConsensusReclaimEscrowContext {
+                    from,
+                    nonce,
+                    address: to,
+                    shares,
+                },
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    fn debonding_end<C: Context>(ctx: &mut C, end: types::DebondingEnd) -> Result<(), Error> {
+        // The debonded shares are matched against the oldest reclaim of the same number of shares.
+        let mut debonding = Self::get_debonding_escrows(ctx, end.escrow);
+        let index = debonding
+            .iter()
+            .position(|d| d.shares == end.shares)
+            .ok_or(Error::InvalidArgument)?;
+        let reclaimed = debonding.remove(index);
+
+        let denomination = Consensus::consensus_denomination(ctx)?;
+        let amount = Consensus::amount_from_consensus(ctx, &denomination, end.amount)?;
+
+        // Release the reclaimed shares together with the proportional part of the escrowed tokens
+        // backing them. The shares may be worth more (due to rewards) or less (due to slashing)
+        // once debonded, so the difference is minted or burned respectively and the account is
+        // credited exactly the amount returned to the runtime account.
+        let mut escrow = Self::get_escrow(ctx, reclaimed.address, end.escrow);
+        let principal = escrow
+            .amount
+            .checked_mul(reclaimed.shares)
+            .and_then(|amount| amount.checked_div(escrow.shares))
+            .ok_or(Error::InsufficientEscrowShares)?;
+        escrow.amount -= principal.min(escrow.amount);
+        escrow.shares -= reclaimed.shares.min(escrow.shares);
+        escrow.reclaimed_shares = escrow
+            .reclaimed_shares
+            .checked_sub(reclaimed.shares)
+            .ok_or(Error::InsufficientEscrowShares)?;
+
+        Accounts::transfer(
+            ctx,
+            *ADDRESS_ESCROW,
+            reclaimed.address,
+            &token::BaseUnits::new(principal.min(amount), denomination.clone()),
+        )
+        .map_err(|_| Error::InsufficientEscrowBalance)?;
+        if amount > principal {
+            Accounts::mint::<Self, _>(
+                ctx,
+                reclaimed.address,
+                &token::BaseUnits::new(amount - principal, denomination),
+            )
+            .map_err(|_| Error::InvalidArgument)?;
+        } else if principal > amount {
+            Accounts::burn(
+                ctx,
+                *ADDRESS_ESCROW,
+                &token::BaseUnits::new(principal - amount, denomination),
+            )
+            .map_err(|_| Error::InsufficientEscrowBalance)?;
+        }
+
+        Self::set_escrow(ctx, reclaimed.address, end.escrow, escrow);
+        Self::set_debonding_escrows(ctx, end.escrow, debonding);
+
+        Ok(())
+    }
 }
 
 impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API>
     Module<Accounts, Consensus>
 {
+    /// Ensure the given memo is not too large.
+    fn ensure_valid_memo(memo: &Option<Vec<u8>>) -> Result<(), Error> {
+        match memo {
+            Some(memo) if memo.len() > MAX_MEMO_SIZE => Err(Error::InvalidArgument),
+            _ => Ok(()),
+        }
+    }
+
     /// Deposit in the runtime.
     fn tx_deposit<C: TxContext>(ctx: &mut C, body: types::Deposit) -> Result<(), Error> {
         let params = Self::params(ctx.runtime_state());
@@ -244,7 +586,77 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API>
 
         let address = signer.address_spec.address();
         let nonce = signer.nonce;
-        Self::deposit(ctx, address, nonce, body.to.unwrap_or(address), body.amount)
+        if params.check_deposit_allowance && ctx.is_check_only() {
+            Self::ensure_deposit_allowance(ctx, address, &body.amount)?;
+        }
+
+        Self::deposit(
+            ctx,
+            address,
+            nonce,
+            body.to.unwrap_or(address),
+            body.amount,
+            body.memo,
+        )
+    }
+
+    /// Ensure that the allowance granted to the runtime account by the given consensus account
+    /// covers the given amount.
+    ///
+    /// The consensus state may be outdated by the time the withdrawal is processed, so this only
+    /// rejects deposits that would clearly fail early.
+    fn ensure_deposit_allowance<C: Context>(
+        ctx: &mut C,
+        from: Address,
+        amount: &token::BaseUnits,
+    ) -> Result<(), Error> {
+        let amount = Consensus::amount_to_consensus(ctx, amount.denomination(), amount.amount())?;
+        let runtime_address = Address::from_runtime_id(ctx.runtime_id());
+        let allowance = Consensus::allowance(ctx, from, runtime_address)?;
+        if allowance < amount {
+            return Err(Error::InsufficientDepositAllowance(allowance));
+        }
+
+        Ok(())
+    }
+
+    /// Deposit a batch in the runtime.
+    fn tx_deposit_batch<C: TxContext>(ctx: &mut C, body: types::DepositBatch) -> Result<(), Error> {
+        let deposits = body.0;
+        if deposits.is_empty() {
+            return Err(Error::InvalidArgument);
+        }
+
+        // Each deposit emits a consensus message. As messages are not emitted in check-only mode,
+        // make sure enough of them have been declared upfront so oversized batches are rejected
+        // before being included in a block.
+        if ctx.is_check_only() && deposits.len() > ctx.remaining_messages() as usize {
+            return Err(CoreError::OutOfMessageSlots.into());
+        }
+
+        let params = Self::params(ctx.runtime_state());
+        let signer = &ctx.tx_auth_info().signer_info[0];
+        Consensus::ensure_compatible_tx_signer(ctx)?;
+
+        let address = signer.address_spec.address();
+        let nonce = signer.nonce;
+        for (index, deposit) in deposits.into_iter().enumerate() {
+            Core::use_tx_gas(ctx, params.gas_costs.tx_deposit)?;
+
+            let nonce = nonce
+                .checked_add(index as u64)
+                .ok_or(Error::InvalidArgument)?;
+            Self::deposit(
+                ctx,
+                address,
+                nonce,
+                deposit.to.unwrap_or(address),
+                deposit.amount,
+                deposit.memo,
+            )?;
+        }
+
+        Ok(())
     }
 
     /// Withdraw from the runtime.
@@ -254,23 +666,77 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API>
 
         // Signer.
         let signer = &ctx.tx_auth_info().signer_info[0];
-        if body.to.is_none() {
-            // If no `to` field is specified, i.e. withdrawing to the transaction sender's account,
-            // only allow the consensus-compatible single-Ed25519-key signer type. Otherwise, the
-            // tokens would get stuck in an account that you can't sign for on the consensus layer.
+        let address = signer.address_spec.address();
+        let nonce = signer.nonce;
+
+        let to = body.to.unwrap_or(address);
+        if to == address {
+            // When withdrawing to the transaction sender's account, only allow the
+            // consensus-compatible single-Ed25519-key signer type. Otherwise, the tokens would get
+            // stuck in an account that you can't sign for on the consensus layer.
             Consensus::ensure_compatible_tx_signer(ctx)?;
+        } else {
+            if params.disable_delegated_withdraw {
+                return Err(Error::Forbidden);
+            }
+            Self::ensure_consensus_representable(to, body.to_spec.as_ref())?;
         }
 
+        Self::withdraw(ctx, address, nonce, to, body.amount, body.memo)
+    }
+
+    /// Ensure that tokens withdrawn to the given address can be controlled on the consensus layer.
+    ///
+    /// Addresses are hashes so it is not possible to tell how an arbitrary address was derived.
+    /// Instead the caller must provide the address specification of the destination, which must
+    /// be a single Ed25519 key (the only signer type supported by the consensus layer) and must
+    /// derive to the given address. This also rules out the addresses of the runtime itself and
+    /// of its modules, as those are not derived from any key.
+    fn ensure_consensus_representable(
+        to: Address,
+        to_spec: Option<&SignatureAddressSpec>,
+    ) -> Result<(), Error> {
+        match to_spec {
+            Some(spec @ SignatureAddressSpec::Ed25519(_)) if Address::from_sigspec(spec) == to => {
+                Ok(())
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    /// Add escrow from the runtime.
+    fn tx_add_escrow<C: TxContext>(ctx: &mut C, body: types::AddEscrow) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        Core::use_tx_gas(ctx, params.gas_costs.tx_add_escrow)?;
+
+        let signer = &ctx.tx_auth_info().signer_info[0];
         let address = signer.address_spec.address();
         let nonce = signer.nonce;
-        Self::withdraw(ctx, address, nonce, body.to.unwrap_or(address), body.amount)
+        Self::add_escrow(ctx, address, body.to, body.amount, nonce)
+    }
+
+    /// Reclaim escrow into the runtime.
+    fn tx_reclaim_escrow<C: TxContext>(
+        ctx: &mut C,
+        body: types::ReclaimEscrow,
+    ) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        Core::use_tx_gas(ctx, params.gas_costs.tx_reclaim_escrow)?;
+
+        let signer = &ctx.tx_auth_info().signer_info[0];
+        let address = signer.address_spec.address();
+        let nonce = signer.nonce;
+        Self::reclaim_escrow(ctx, body.from, address, body.shares, nonce)
     }
 
     fn query_balance<C: Context>(
         ctx: &mut C,
         args: types::BalanceQuery,
     ) -> Result<types::AccountBalance, Error> {
-        let denomination = Consensus::consensus_denomination(ctx)?;
+        let params = Self::params(ctx.runtime_state());
+        Core::use_query_gas(ctx, params.gas_costs.query_balance)?;
+
+        let denomination = Self::query_denomination(ctx, args.denomination)?;
         let balances = Accounts::get_balances(ctx.runtime_state(), args.address)
             .map_err(|_| Error::InvalidArgument)?;
         let balance = balances
@@ -281,13 +747,238 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API>
         Ok(types::AccountBalance { balance })
     }
 
+    fn query_pending_withdrawal<C: Context>(
+        ctx: &mut C,
+        args: types::PendingWithdrawalQuery,
+    ) -> Result<types::PendingWithdrawal, Error> {
+        let denomination = Self::query_denomination(ctx, args.denomination)?;
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let pending = storage::PrefixStore::new(store, &state::PENDING_WITHDRAWALS);
+        let account = storage::TypedStore::new(storage::PrefixStore::new(pending, &args.address));
+        let amount = account
+            .iter::<Vec<u8>, u128>()
+            .filter(|(key, _)| key.get(NONCE_SIZE..) == Some(denomination.as_ref()))
+            .try_fold(0u128, |total, (_, amount)| total.checked_add(amount))
+            .ok_or(Error::InvalidArgument)?;
+        Ok(types::PendingWithdrawal { amount })
+    }
+
+    fn query_deposited<C: Context>(
+        ctx: &mut C,
+        args: types::DepositedQuery,
+    ) -> Result<token::BaseUnits, Error> {
+        let denomination = Self::query_denomination(ctx, args.denomination)?;
+        let amount = Self::get_deposited(ctx, args.address, &denomination);
+        Ok(token::BaseUnits::new(amount, denomination))
+    }
+
+    /// Cumulative amount deposited into all accounts. As with the per-account amounts, withdrawals
+    /// do not reduce it.
+    fn query_total_deposited<C: Context>(
+        ctx: &mut C,
+        args: types::TotalDepositedQuery,
+    ) -> Result<token::BaseUnits, Error> {
+        let denomination = Self::query_denomination(ctx, args.denomination)?;
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let total_deposited =
+            storage::TypedStore::new(storage::PrefixStore::new(store, &state::TOTAL_DEPOSITED));
+        let amount: u128 = total_deposited.get(&denomination).unwrap_or_default();
+        Ok(token::BaseUnits::new(amount, denomination))
+    }
+
     fn query_consensus_account<C: Context>(
         ctx: &mut C,
         args: types::ConsensusAccountQuery,
     ) -> Result<ConsensusAccount, Error> {
+        let params = Self::params(ctx.runtime_state());
+        Core::use_query_gas(ctx, params.gas_costs.query_consensus_account)?;
+
         Consensus::account(ctx, args.address).map_err(|_| Error::InvalidArgument)
     }
 
+    /// Denomination to query, the consensus denomination in case none is given.
+    fn query_denomination<C: Context>(
+        ctx: &mut C,
+        denomination: Option<token::Denomination>,
+    ) -> Result<token::Denomination, Error> {
+        match denomination {
+            Some(denomination) => {
+                if !Consensus::consensus_denominations(ctx)?.contains(&denomination) {
+                    return Err(Error::InvalidDenomination);
+                }
+                Ok(denomination)
+            }
+            None => Ok(Consensus::consensus_denomination(ctx)?),
+        }
+    }
+
+    /// Cumulative amount (in runtime base units) of the given denomination deposited into the
+    /// given account.
+    fn get_deposited<C: Context>(
+        ctx: &mut C,
+        address: Address,
+        denomination: &token::Denomination,
+    ) -> u128 {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let deposited = storage::PrefixStore::new(store, &state::DEPOSITED);
+        let account = storage::TypedStore::new(storage::PrefixStore::new(deposited, &address));
+        account.get(denomination).unwrap_or_default()
+    }
+
+    /// Record a successful deposit into the given account.
+    fn add_deposited<C: Context>(ctx: &mut C, address: Address, amount: &token::BaseUnits) {
+        let total = Self::get_deposited(ctx, address, amount.denomination())
+            .checked_add(amount.amount())
+            .expect("deposited amount should not overflow");
+
+        let mut store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let deposited = storage::PrefixStore::new(&mut store, &state::DEPOSITED);
+        let mut account = storage::TypedStore::new(storage::PrefixStore::new(deposited, &address));
+        account.insert(amount.denomination(), total);
+
+        let mut total_deposited =
+            storage::TypedStore::new(storage::PrefixStore::new(store, &state::TOTAL_DEPOSITED));
+        let total: u128 = total_deposited
+            .get(amount.denomination())
+            .unwrap_or_default();
+        total_deposited.insert(
+            amount.denomination(),
+            total
+                .checked_add(amount.amount())
+                .expect("total deposited amount should not overflow"),
+        );
+    }
+
+    /// Key of a pending withdrawal within the pending withdrawals of an account.
+    fn pending_withdrawal_key(nonce: u64, denomination: &token::Denomination) -> Vec<u8> {
+        [&nonce.to_be_bytes()[..], denomination.as_ref()].concat()
+    }
+
+    /// Record an amount pending withdrawal for the given account and nonce.
+    fn add_pending_withdrawal<C: Context>(
+        ctx: &mut C,
+        address: Address,
+        nonce: u64,
+        amount: &token::BaseUnits,
+    ) {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let pending = storage::PrefixStore::new(store, &state::PENDING_WITHDRAWALS);
+        let mut account = storage::TypedStore::new(storage::PrefixStore::new(pending, &address));
+        let key = Self::pending_withdrawal_key(nonce, amount.denomination());
+        // Multiple withdrawals may share the same nonce when not made via transactions.
+        let existing: u128 = account.get(&key).unwrap_or_default();
+        account.insert(
+            &key,
+            existing
+                .checked_add(amount.amount())
+                .expect("pending withdrawal amount should not overflow"),
+        );
+    }
+
+    /// Remove an amount pending withdrawal for the given account and nonce.
+    fn remove_pending_withdrawal<C: Context>(
+        ctx: &mut C,
+        address: Address,
+        nonce: u64,
+        amount: &token::BaseUnits,
+    ) {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let pending = storage::PrefixStore::new(store, &state::PENDING_WITHDRAWALS);
+        let mut account = storage::TypedStore::new(storage::PrefixStore::new(pending, &address));
+        let key = Self::pending_withdrawal_key(nonce, amount.denomination());
+        // Withdrawals made before pending amounts were tracked have no entry.
+        let remaining = account
+            .get::<_, u128>(&key)
+            .unwrap_or_default()
+            .saturating_sub(amount.amount());
+        if remaining == 0 {
+            account.remove(&key);
+        } else {
+            account.insert(&key, remaining);
+        }
+    }
+
+    /// Escrow made by the given account to the given consensus staking account.
+    fn get_escrow<C: Context>(ctx: &mut C, address: Address, to: Address) -> types::AccountEscrow {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let escrows = storage::PrefixStore::new(store, &state::ESCROWS);
+        let account = storage::TypedStore::new(storage::PrefixStore::new(escrows, &address));
+        account.get(to).unwrap_or_default()
+    }
+
+    /// Escrow of the given consensus staking account.
+    fn consensus_escrow<C: Context>(ctx: &C, address: Address) -> EscrowAccount {
+        Consensus::account(ctx, address)
+            .expect("consensus state should be available")
+            .escrow
+    }
+
+    /// Convert an amount of tokens (in consensus base units) into shares of the given pool at the
+    /// pool's current exchange rate. Empty pools issue a share for each base unit.
+    fn shares_for_amount(pool: &SharePool, amount: u128) -> u128 {
+        let balance: u128 = pool.balance.clone().try_into().unwrap_or(u128::MAX);
+        let total_shares: u128 = pool.total_shares.clone().try_into().unwrap_or(u128::MAX);
+        if balance == 0 || total_shares == 0 {
+            return amount;
+        }
+        amount.saturating_mul(total_shares) / balance
+    }
+
+    /// Convert shares of the given pool into an amount of tokens (in consensus base units) at the
+    /// pool's current exchange rate.
+    fn amount_for_shares(pool: &SharePool, shares: u128) -> u128 {
+        let balance: u128 = pool.balance.clone().try_into().unwrap_or(u128::MAX);
+        let total_shares: u128 = pool.total_shares.clone().try_into().unwrap_or(u128::MAX);
+        if total_shares == 0 {
+            return 0;
+        }
+        shares.saturating_mul(balance) / total_shares
+    }
+
+    /// Update the escrow made by the given account to the given consensus staking account.
+    fn set_escrow<C: Context>(
+        ctx: &mut C,
+        address: Address,
+        to: Address,
+        escrow: types::AccountEscrow,
+    ) {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let escrows = storage::PrefixStore::new(store, &state::ESCROWS);
+        let mut account = storage::TypedStore::new(storage::PrefixStore::new(escrows, &address));
+        if escrow == Default::default() {
+            account.remove(to);
+        } else {
+            account.insert(to, escrow);
+        }
+    }
+
+    /// Reclaimed escrows from the given escrow account that are debonding.
+    fn get_debonding_escrows<C: Context>(
+        ctx: &mut C,
+        escrow: Address,
+    ) -> Vec<types::DebondingEscrow> {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let debonding =
+            storage::TypedStore::new(storage::PrefixStore::new(store, &state::DEBONDING_ESCROWS));
+        debonding.get(escrow).unwrap_or_default()
+    }
+
+    /// Update the reclaimed escrows from the given escrow account that are debonding.
+    fn set_debonding_escrows<C: Context>(
+        ctx: &mut C,
+        escrow: Address,
+        escrows: Vec<types::DebondingEscrow>,
+    ) {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut debonding =
+            storage::TypedStore::new(storage::PrefixStore::new(store, &state::DEBONDING_ESCROWS));
+        if escrows.is_empty() {
+            debonding.remove(escrow);
+        } else {
+            debonding.insert(escrow, escrows);
+        }
+    }
+
     fn message_result_transfer<C: Context>(
         ctx: &mut C,
         me: MessageEvent,
@@ -302,6 +993,7 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API>
                 &context.amount,
             )
             .expect("should have enough balance");
+            Self::remove_pending_withdrawal(ctx, context.address, context.nonce, &context.amount);
 
             // Emit withdraw failed event.
             ctx.emit_event(Event::Withdraw {
@@ -310,6 +1002,7 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API>
                 to: context.to,
                 amount: context.amount.clone(),
                 error: Some(me.into()),
+                memo: context.memo.clone(),
             });
             return;
         }
@@ -317,6 +1010,7 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API>
         // Burn the withdrawn tokens.
         Accounts::burn(ctx, *ADDRESS_PENDING_WITHDRAWAL, &context.amount)
             .expect("should have enough balance");
+        Self::remove_pending_withdrawal(ctx, context.address, context.nonce, &context.amount);
 
         // Emit withdraw successful event.
         ctx.emit_event(Event::Withdraw {
@@ -325,6 +1019,7 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API>
             to: context.to,
             amount: context.amount.clone(),
             error: None,
+            memo: context.memo.clone(),
         });
     }
 
@@ -341,12 +1036,14 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API>
                 to: context.address,
                 amount: context.amount.clone(),
                 error: Some(me.into()),
+                memo: context.memo.clone(),
             });
             return;
         }
 
         // Update runtime state.
-        Accounts::mint(ctx, context.address, &context.amount).unwrap();
+        Accounts::mint::<Self, _>(ctx, context.address, &context.amount).unwrap();
+        Self::add_deposited(ctx, context.address, &context.amount);
 
         // Emit deposit successful event.
         ctx.emit_event(Event::Deposit {
@@ -355,6 +1052,107 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API>
             to: context.address,
             amount: context.amount.clone(),
             error: None,
+            memo: context.memo.clone(),
+        });
+    }
+
+    fn message_result_escrow<C: Context>(
+        ctx: &mut C,
+        me: MessageEvent,
+        context: types::ConsensusEscrowContext,
+    ) {
+        if !me.is_success() {
+            // Escrow failed, refund the balance.
+            Accounts::transfer(ctx, *ADDRESS_ESCROW, context.address, &context.amount)
+                .expect("should have enough balance");
+
+            // Emit add escrow failed event.
+            ctx.emit_event(Event::AddEscrow {
+                from: context.address,
+                nonce: context.nonce,
+                to: context.to,
+                amount: context.amount.clone(),
+                error: Some(me.into()),
+            });
+            return;
+        }
+
+        // Record the escrow together with the shares issued for it, the tokens remain in the
+        // module's escrow account. Escrowing does not change the exchange rate of the active
+        // escrow pool, so it can be used to determine the number of issued shares.
+        let pool = Self::consensus_escrow(ctx, context.to).active;
+        let amount = Consensus::amount_to_consensus(
+            ctx,
+            context.amount.denomination(),
+            context.amount.amount(),
+        )
+        .expect("escrowed amount should be representable");
+        let mut escrow = Self::get_escrow(ctx, context.address, context.to);
+        escrow.amount = escrow
+            .amount
+            .checked_add(context.amount.amount())
+            .expect("escrowed amount should not overflow");
+        escrow.shares = escrow
+            .shares
+            .checked_add(Self::shares_for_amount(&pool, amount))
+            .expect("escrowed shares should not overflow");
+        Self::set_escrow(ctx, context.address, context.to, escrow);
+
+        // Emit add escrow successful event.
+        ctx.emit_event(Event::AddEscrow {
+            from: context.address,
+            nonce: context.nonce,
+            to: context.to,
+            amount: context.amount.clone(),
+            error: None,
+        });
+    }
+
+    fn message_result_reclaim_escrow<C: Context>(
+        ctx: &mut C,
+        me: MessageEvent,
+        context: types::ConsensusReclaimEscrowContext,
+    ) {
+        let epoch = ctx.epoch();
+        if !me.is_success() {
+            // Reclaim failed, release the reserved shares.
+            let mut escrow = Self::get_escrow(ctx, context.address, context.from);
+            escrow.reclaimed_shares = escrow.reclaimed_shares.saturating_sub(context.shares);
+            Self::set_escrow(ctx, context.address, context.from, escrow);
+
+            // Emit reclaim escrow failed event.
+            ctx.emit_event(Event::ReclaimEscrow {
+                from: context.from,
+                nonce: context.nonce,
+                to: context.address,
+                shares: context.shares,
+                epoch,
+                error: Some(me.into()),
+            });
+            return;
+        }
+
+        // The reclaimed shares are debonding in the consensus layer. They remain reserved and the
+        // tokens backing them remain escrowed until the debonding ends. Reclaiming does not change
+        // the exchange rate of the active escrow pool, so it can be used to value the shares.
+        let pool = Self::consensus_escrow(ctx, context.from).active;
+        let mut debonding = Self::get_debonding_escrows(ctx, context.from);
+        debonding.push(types::DebondingEscrow {
+            address: context.address,
+            shares: context.shares,
+            amount: Self::amount_for_shares(&pool, context.shares),
+            epoch,
+        });
+        Self::set_debonding_escrows(ctx, context.from, debonding);
+
+        // Emit reclaim escrow successful event.
+        ctx.emit_event(Event::ReclaimEscrow {
+            from: context.from,
+            nonce: context.nonce,
+            to: context.address,
+            shares: context.shares,
+            epoch,
+            error: None,
         });
     }
 }
@@ -363,7 +1161,7 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> modul
     for Module<Accounts, Consensus>
 {
     const NAME: &'static str = MODULE_NAME;
-    const VERSION: u32 = 1;
+    const VERSION: u32 = 2;
     type Error = Error;
     type Event = Event;
     type Parameters = Parameters;
@@ -380,12 +1178,12 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> modul
         auth_info: &AuthInfo,
     ) -> module::DispatchResult<cbor::Value, Result<(), error::RuntimeError>> {
         match method {
-            "consensus.Deposit" => {
+            "consensus.Deposit" | "consensus.DepositBatch" | "consensus.ReclaimEscrow" => {
                 // Nothing to prefetch.
                 module::DispatchResult::Handled(Ok(()))
             }
-            "consensus.Withdraw" => {
-                // Prefetch withdrawing account balance.
+            "consensus.Withdraw" | "consensus.AddEscrow" => {
+                // Prefetch withdrawing/escrowing account balance.
                 let addr = auth_info.signer_info[0].address_spec.address();
                 prefixes.insert(Prefix::from(
                     [
@@ -408,7 +1206,10 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> modul
     ) -> module::DispatchResult<cbor::Value, CallResult> {
         match method {
             "consensus.Deposit" => module::dispatch_call(ctx, body, Self::tx_deposit),
+            "consensus.DepositBatch" => module::dispatch_call(ctx, body, Self::tx_deposit_batch),
             "consensus.Withdraw" => module::dispatch_call(ctx, body, Self::tx_withdraw),
+            "consensus.AddEscrow" => module::dispatch_call(ctx, body, Self::tx_add_escrow),
+            "consensus.ReclaimEscrow" => module::dispatch_call(ctx, body, Self::tx_reclaim_escrow),
             _ => module::DispatchResult::Unhandled(body),
         }
     }
@@ -421,6 +1222,13 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> modul
         match method {
             "consensus.Balance" => module::dispatch_query(ctx, args, Self::query_balance),
             "consensus.Account" => module::dispatch_query(ctx, args, Self::query_consensus_account),
+            "consensus.PendingWithdrawal" => {
+                module::dispatch_query(ctx, args, Self::query_pending_withdrawal)
+            }
+            "consensus.Deposited" => module::dispatch_query(ctx, args, Self::query_deposited),
+            "consensus.TotalDeposited" => {
+                module::dispatch_query(ctx, args, Self::query_total_deposited)
+            }
             _ => module::DispatchResult::Unhandled(args),
         }
     }
@@ -447,9 +1255,69 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> modul
                 );
                 module::DispatchResult::Handled(())
             }
+            CONSENSUS_ESCROW_HANDLER => {
+                Self::message_result_escrow(
+                    ctx,
+                    result.event,
+                    cbor::from_value(result.context).expect("invalid message handler context"),
+                );
+                module::DispatchResult::Handled(())
+            }
+            CONSENSUS_RECLAIM_ESCROW_HANDLER => {
+                Self::message_result_reclaim_escrow(
+                    ctx,
+                    result.event,
+                    cbor::from_value(result.context).expect("invalid message handler context"),
+                );
+                module::DispatchResult::Handled(())
+            }
             _ => module::DispatchResult::Unhandled(result),
         }
     }
+
+    fn register_error_codes(registry: &mut modules::core::types::ErrorRegistry) {
+        module::register_error_codes::<Self>(registry)
+    }
+
+    fn register_capabilities(capabilities: &mut modules::core::types::Capabilities) {
+        capabilities.insert("consensus-deposits".to_owned());
+        capabilities.insert("consensus-withdrawals".to_owned());
+        capabilities.insert("consensus-escrows".to_owned());
+    }
+}
+
+impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API>
+    Module<Accounts, Consensus>
+{
+    /// Migrate state from version 1 to version 2.
+    fn migrate_v1_to_v2<C: Context>(ctx: &mut C) {
+        // Escrow operations were not charged for in version 1, make them cost the same as a
+        // withdrawal unless configured otherwise.
+        let mut params = Self::params(ctx.runtime_state());
+        if params.gas_costs.tx_add_escrow == 0 {
+            params.gas_costs.tx_add_escrow = params.gas_costs.tx_withdraw;
+        }
+        if params.gas_costs.tx_reclaim_escrow == 0 {
+            params.gas_costs.tx_reclaim_escrow = params.gas_costs.tx_withdraw;
+        }
+        Self::set_params(ctx.runtime_state(), params);
+
+        // Withdrawals that are still in flight keep their pending amounts, only drop any entries
+        // that no longer track an amount.
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut pending = storage::TypedStore::new(storage::PrefixStore::new(
+            store,
+            &state::PENDING_WITHDRAWALS,
+        ));
+        let empty: Vec<Vec<u8>> = pending
+            .iter::<Vec<u8>, u128>()
+            .filter(|(_, amount)| *amount == 0)
+            .map(|(key, _)| key)
+            .collect();
+        for key in empty {
+            pending.remove(key);
+        }
+    }
 }
 
 impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> module::MigrationHandler
@@ -471,8 +1339,8 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> modul
             return true;
         }
 
-        // Migrations are not supported.
-        false
+        // Perform migration.
+        module::run_migrations::<Self, _>(ctx, meta, version, &[Self::migrate_v1_to_v2])
     }
 }
 
@@ -483,6 +1351,59 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> modul
 
 impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> module::BlockHandler
     for Module<Accounts, Consensus>
+{
+    fn end_block<C: Context>(ctx: &mut C) {
+        let debonding_interval = match Self::params(ctx.runtime_state()).debonding_interval {
+            Some(debonding_interval) => debonding_interval,
+            None => return,
+        };
+
+        // Release reclaimed escrow whose debonding period has ended. As the reclaim is processed
+        // after it has been executed in the consensus layer, its debonding has ended by then.
+        let epoch = ctx.epoch();
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let debonding =
+            storage::TypedStore::new(storage::PrefixStore::new(store, &state::DEBONDING_ESCROWS));
+        let ended: Vec<types::DebondingEnd> = debonding
+            .iter::<Address, Vec<types::DebondingEscrow>>()
+            .flat_map(|(escrow, reclaims)| {
+                reclaims
+                    .into_iter()
+                    .filter(|d| d.epoch.saturating_add(debonding_interval) <= epoch)
+                    .map(move |d| types::DebondingEnd {
+                        escrow,
+                        shares: d.shares,
+                        amount: d.amount,
+                    })
+            })
+            .collect();
+
+        for end in ended {
+            // Release in a child context so that a failure does not leave partial state behind.
+            let result = ctx.with_child(ctx.mode(), |mut ctx| {
+                Self::debonding_end(&mut ctx, end.clone())?;
+                Ok::<_, Error>(ctx.commit().0)
+            });
+            match result {
+                Ok(tags) => {
+                    for tag in tags {
+                        ctx.emit_tag(tag);
+                    }
+                }
+                Err(err) => {
+                    error!(ctx.get_logger("consensus_accounts"), "failed to release escrow";
+                        "escrow" => %end.escrow,
+                        "shares" => end.shares,
+                        "err" => ?err,
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> module::EventHandler
+    for Module<Accounts, Consensus>
 {
 }
 
@@ -491,11 +1412,17 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> modul
 {
     /// Check invariants.
     fn check_invariants<C: Context>(ctx: &mut C) -> Result<(), CoreError> {
-        // Total supply of the designated consensus layer token denomination
-        // should be less than or equal to the balance of the runtime's general
-        // account in the consensus layer.
+        // All runtime token denominations that map to the consensus layer token are backed by the
+        // runtime's general account in the consensus layer, so their combined total supply should
+        // be less than or equal to its balance, plus whatever the runtime has escrowed (as
+        // escrowed tokens remain part of the total supply). As all these denominations share the
+        // same scaling factor, the amounts are compared in runtime base units.
+        //
+        // The supplies are deliberately not checked per denomination, as mapped denominations are
+        // fungible: tokens withdrawn in one of them can be deposited back in any other.
 
-        let den = Consensus::consensus_denomination(ctx).unwrap();
+        let denominations = Consensus::consensus_denominations(ctx).unwrap();
+        let consensus_denomination = Consensus::consensus_denomination(ctx).unwrap();
         #[allow(clippy::or_fun_call)]
         let ts = Accounts::get_total_supplies(ctx.runtime_state()).or(Err(
             CoreError::InvariantViolation("unable to get total supplies".to_string()),
@@ -505,25 +1432,111 @@ impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API> modul
         let rt_acct = Consensus::account(ctx, rt_addr).unwrap_or_default();
         let rt_ga_balance = rt_acct.general.balance;
         let rt_ga_balance: u128 = rt_ga_balance.try_into().unwrap_or(u128::MAX);
+        let rt_ga_balance =
+            Consensus::amount_from_consensus(ctx, &consensus_denomination, rt_ga_balance).map_err(
+                |_| {
+                    CoreError::InvariantViolation(
+                        "runtime's consensus balance is not representable".to_string(),
+                    )
+                },
+            )?;
 
-        let rt_ga_balance = Consensus::amount_from_consensus(ctx, rt_ga_balance).map_err(|_| {
-            CoreError::InvariantViolation(
-                "runtime's consensus balance is not representable".to_string(),
-            )
-        })?;
+        let mut total_supply = 0u128;
+        let mut escrowed = 0u128;
+        for den in &denominations {
+            // Having no total supply also satisfies above invariant.
+            let supply = ts.get(den).copied().unwrap_or_default();
+            total_supply = total_supply.saturating_add(supply);
 
-        match ts.get(&den) {
-            Some(total_supply) => {
-                if total_supply <= &rt_ga_balance {
-                    Ok(())
-                } else {
-                    Err(CoreError::InvariantViolation(
-                        "total supply is greater than runtime's general account balance"
-                            .to_string(),
-                    ))
-                }
+            let balance = Accounts::get_balance(ctx.runtime_state(), *ADDRESS_ESCROW, den.clone())
+                .map_err(|_| {
+                    CoreError::InvariantViolation("unable to get escrowed balance".to_string())
+                })?;
+            escrowed = escrowed.saturating_add(balance);
+        }
+        if total_supply > rt_ga_balance.saturating_add(escrowed) {
+            return Err(CoreError::InvariantViolation(
+                "total supply of consensus denominations is greater than runtime's general account and escrowed balance"
+                    .to_string(),
+            ));
+        }
+
+        // Tokens held by the pending withdrawal account should match the amounts of outstanding
+        // withdrawals in each denomination, as they are only held until the withdrawal is either
+        // refunded or burned.
+        let mut outstanding: BTreeMap<token::Denomination, u128> = BTreeMap::new();
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let pending = storage::TypedStore::new(storage::PrefixStore::new(
+            store,
+            &state::PENDING_WITHDRAWALS,
+        ));
+        for (key, amount) in pending.iter::<Vec<u8>, u128>() {
+            let den = key
+                .get(Address::SIZE + NONCE_SIZE..)
+                .and_then(|den| token::Denomination::try_from(den).ok())
+                .ok_or_else(|| {
+                    CoreError::InvariantViolation("malformed pending withdrawal".to_string())
+                })?;
+            let total = outstanding.entry(den).or_default();
+            *total = total.checked_add(amount).ok_or_else(|| {
+                CoreError::InvariantViolation("outstanding withdrawals overflow".to_string())
+            })?;
+        }
+        let pending_denominations: BTreeSet<token::Denomination> = denominations
+            .iter()
+            .chain(outstanding.keys())
+            .cloned()
+            .collect();
+        for den in pending_denominations {
+            let balance = Accounts::get_balance(
+                ctx.runtime_state(),
+                *ADDRESS_PENDING_WITHDRAWAL,
+                den.clone(),
+            )
+            .map_err(|_| {
+                CoreError::InvariantViolation(
+                    "unable to get pending withdrawal balance".to_string(),
+                )
+            })?;
+            let amount = outstanding.get(&den).copied().unwrap_or_default();
+            if balance != amount {
+                return Err(CoreError::InvariantViolation(format!(
+                    "pending withdrawal balance of {} ({}) does not match outstanding withdrawals ({})",
+                    den, balance, amount
+                )));
             }
-            None => Ok(()), // Having no total supply also satisfies above invariant.
         }
+
+        // The per-account deposited amounts should add up to the total deposited amount of each
+        // denomination.
+        let mut store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let total_deposited: BTreeMap<token::Denomination, u128> = storage::TypedStore::new(
+            storage::PrefixStore::new(&mut store, &state::TOTAL_DEPOSITED),
+        )
+        .iter()
+        .collect();
+        let deposited =
+            storage::TypedStore::new(storage::PrefixStore::new(&mut store, &state::DEPOSITED));
+        let mut sums: BTreeMap<token::Denomination, u128> = BTreeMap::new();
+        for (key, amount) in deposited.iter::<Vec<u8>, u128>() {
+            let den = key
+                .get(Address::SIZE..)
+                .and_then(|den| token::Denomination::try_from(den).ok())
+                .ok_or_else(|| {
+                    CoreError::InvariantViolation("malformed deposited amount".to_string())
+                })?;
+            let sum = sums.entry(den).or_default();
+            *sum = sum.checked_add(amount).ok_or_else(|| {
+                CoreError::InvariantViolation("deposited amounts overflow".to_string())
+            })?;
+        }
+        if sums != total_deposited {
+            return Err(CoreError::InvariantViolation(
+                "per-account deposited amounts do not add up to the total deposited amount"
+                    .to_string(),
+            ));
+        }
+
+        Ok(())
     }
 }
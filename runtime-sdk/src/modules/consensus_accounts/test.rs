@@ -1,22 +1,27 @@
 use std::{collections::BTreeMap, str::FromStr};
 
 use anyhow::anyhow;
+use io_context::Context as IoContext;
 
 use oasis_core_runtime::{
-    common::versioned::Versioned,
+    common::{quantity::Quantity, versioned::Versioned},
     consensus::{
         roothash::{Message, StakingMessage},
         staking,
+        state::ConsensusState,
     },
+    storage::mkvs,
 };
 
 use crate::{
     context::BatchContext,
-    module::{MethodHandler, MigrationHandler},
+    module::{BlockHandler, InvariantHandler, MethodHandler, MigrationHandler, Module as _},
     modules::{
         accounts::{Genesis as AccountsGenesis, Module as Accounts, API},
-        consensus::{Error as ConsensusError, Module as Consensus},
-        core::types::Metadata,
+        consensus::{
+            Error as ConsensusError, Module as Consensus, Parameters as ConsensusParameters,
+        },
+        core::{types::Metadata, Module as Core, Parameters as CoreParameters, API as _},
     },
     testing::{keys, mock},
     types::{
@@ -27,8 +32,8 @@ use crate::{
 };
 
 use super::{
-    types::{Deposit, Withdraw},
-    Module, *,
+    types::{AddEscrow, Deposit, DepositBatch, ReclaimEscrow, Withdraw},
+    Module, API as _, *,
 };
 
 #[test]
@@ -43,6 +48,75 @@ fn test_init() {
     Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, genesis);
 }
 
+#[test]
+fn test_migrate_v1() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+    meta.versions.insert(MODULE_NAME.to_owned(), 1);
+
+    Module::<Accounts, Consensus>::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            gas_costs: GasCosts {
+                tx_deposit: 100,
+                tx_withdraw: 200,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    Module::<Accounts, Consensus>::add_pending_withdrawal(
+        &mut ctx,
+        keys::alice::address(),
+        1,
+        &BaseUnits::new(0, denom.clone()),
+    );
+    Module::<Accounts, Consensus>::add_pending_withdrawal(
+        &mut ctx,
+        keys::alice::address(),
+        2,
+        &BaseUnits::new(1_000, denom),
+    );
+
+    assert!(
+        Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, Default::default()),
+        "migration should be performed"
+    );
+    assert_eq!(meta.versions[MODULE_NAME], 2);
+
+    let params = Module::<Accounts, Consensus>::params(ctx.runtime_state());
+    assert_eq!(params.gas_costs.tx_deposit, 100);
+    assert_eq!(params.gas_costs.tx_add_escrow, 200);
+    assert_eq!(params.gas_costs.tx_reclaim_escrow, 200);
+
+    // In-flight withdrawals should be kept.
+    let pending = Module::<Accounts, Consensus>::query_pending_withdrawal(
+        &mut ctx,
+        types::PendingWithdrawalQuery {
+            address: keys::alice::address(),
+            denomination: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(pending.amount, 1_000);
+    let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+    let pending = storage::PrefixStore::new(store, &state::PENDING_WITHDRAWALS);
+    let account =
+        storage::TypedStore::new(storage::PrefixStore::new(pending, keys::alice::address()));
+    assert_eq!(account.iter::<Vec<u8>, u128>().count(), 1);
+
+    // Running again should not change anything.
+    assert!(!Module::<Accounts, Consensus>::init_or_migrate(
+        &mut ctx,
+        &mut meta,
+        Default::default()
+    ));
+}
+
 #[test]
 fn test_api_deposit_invalid_denomination() {
     let mut mock = mock::Mock::default();
@@ -64,6 +138,7 @@ fn test_api_deposit_invalid_denomination() {
                 // separate `to` account to make sure everything is hooked up to the right places.
                 to: Some(keys::bob::address()),
                 amount: BaseUnits::new(1_000, Denomination::NATIVE),
+                memo: None,
             }),
         },
         auth_info: transaction::AuthInfo {
@@ -76,6 +151,7 @@ fn test_api_deposit_invalid_denomination() {
                 gas: 1000,
                 consensus_messages: 1,
             },
+            features: 0,
         },
     };
 
@@ -113,6 +189,7 @@ fn test_api_deposit_incompatible_signer() {
                 // separate `to` account to make sure everything is hooked up to the right places.
                 to: Some(keys::bob::address()),
                 amount: BaseUnits::new(1_000, Denomination::from_str("TEST").unwrap()),
+                memo: None,
             }),
         },
         auth_info: transaction::AuthInfo {
@@ -125,6 +202,7 @@ fn test_api_deposit_incompatible_signer() {
                 gas: 1000,
                 consensus_messages: 1,
             },
+            features: 0,
         },
     };
 
@@ -164,6 +242,7 @@ fn test_api_deposit() {
                 // separate `to` account to make sure everything is hooked up to the right places.
                 to: Some(keys::bob::address()),
                 amount: BaseUnits::new(1_000, denom.clone()),
+                memo: None,
             }),
         },
         auth_info: transaction::AuthInfo {
@@ -176,6 +255,7 @@ fn test_api_deposit() {
                 gas: 1000,
                 consensus_messages: 1,
             },
+            features: 0,
         },
     };
 
@@ -196,6 +276,7 @@ fn test_api_deposit() {
                 StakingMessage::Withdraw(staking::Withdraw {
                     from: keys::alice::address().into(),
                     amount: 1_000u128.into(),
+                    memo: None,
                 })
             )),
             msg,
@@ -234,6 +315,26 @@ fn test_api_deposit() {
         "deposited balance should be minted"
     );
 
+    // Ensure the deposited amount is tracked for the receiving account only.
+    let deposited = Module::<Accounts, Consensus>::query_deposited(
+        &mut ctx,
+        types::DepositedQuery {
+            address: keys::bob::address(),
+            denomination: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(deposited, BaseUnits::new(1_000, denom.clone()));
+    let deposited = Module::<Accounts, Consensus>::query_deposited(
+        &mut ctx,
+        types::DepositedQuery {
+            address: keys::alice::address(),
+            denomination: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(deposited.amount(), 0);
+
     // Make sure events were emitted.
     let (tags, _) = ctx.commit();
     assert_eq!(tags.len(), 2, "deposit and mint events should be emitted");
@@ -260,97 +361,143 @@ fn test_api_deposit() {
 }
 
 #[test]
-fn test_api_withdraw_invalid_denomination() {
+fn test_api_deposit_batch() {
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
     let mut mock = mock::Mock::default();
-    let mut ctx = mock.create_ctx();
-    let mut meta = Metadata {
-        ..Default::default()
-    };
-    let genesis = Default::default();
-
-    Accounts::init_or_migrate(
-        &mut ctx,
-        &mut meta,
-        AccountsGenesis {
-            balances: {
-                let mut balances = BTreeMap::new();
-                // Alice.
-                balances.insert(keys::alice::address(), {
-                    let mut denominations = BTreeMap::new();
-                    denominations.insert(Denomination::NATIVE, 1_000_000);
-                    denominations
-                });
-                balances
-            },
-            total_supplies: {
-                let mut total_supplies = BTreeMap::new();
-                total_supplies.insert(Denomination::NATIVE, 1_000_000);
-                total_supplies
-            },
-            ..Default::default()
-        },
-    );
-    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, genesis);
 
-    let tx = transaction::Transaction {
+    let nonce = 123;
+    let tx = |consensus_messages| transaction::Transaction {
         version: 1,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
-            method: "consensus.Withdraw".to_owned(),
-            body: cbor::to_value(Withdraw {
-                // It's probably more common to withdraw into your own account, but we're using a
-                // separate `to` account to make sure everything is hooked up to the right places.
-                to: Some(keys::bob::address()),
-                amount: BaseUnits::new(1_000, Denomination::NATIVE),
-            }),
+            method: "consensus.DepositBatch".to_owned(),
+            body: cbor::to_value(DepositBatch(vec![
+                Deposit {
+                    to: None,
+                    amount: BaseUnits::new(1_000, denom.clone()),
+                    memo: None,
+                },
+                Deposit {
+                    to: Some(keys::bob::address()),
+                    amount: BaseUnits::new(2_000, denom.clone()),
+                    memo: None,
+                },
+            ])),
         },
         auth_info: transaction::AuthInfo {
             signer_info: vec![transaction::SignerInfo::new_sigspec(
                 keys::alice::sigspec(),
-                0,
+                nonce,
             )],
             fee: transaction::Fee {
                 amount: Default::default(),
                 gas: 1000,
-                consensus_messages: 1,
+                consensus_messages,
             },
+            features: 0,
         },
     };
 
-    ctx.with_tx(0, tx, |mut tx_ctx, call| {
-        let result = Module::<Accounts, Consensus>::tx_withdraw(
+    // Batches declaring too few consensus messages should be rejected in check-only mode.
+    {
+        let mut ctx = mock.create_check_ctx();
+        Module::<Accounts, Consensus>::init_or_migrate(
+            &mut ctx,
+            &mut Default::default(),
+            Default::default(),
+        );
+
+        ctx.with_tx(0, tx(1), |mut tx_ctx, call| {
+            tx_ctx.limit_max_messages(1).unwrap();
+            let result = Module::<Accounts, Consensus>::tx_deposit_batch(
+                &mut tx_ctx,
+                cbor::from_value(call.body).unwrap(),
+            );
+            assert!(matches!(
+                result,
+                Err(Error::Core(CoreError::OutOfMessageSlots))
+            ));
+        });
+
+        ctx.with_tx(0, tx(2), |mut tx_ctx, call| {
+            tx_ctx.limit_max_messages(2).unwrap();
+            Module::<Accounts, Consensus>::tx_deposit_batch(
+                &mut tx_ctx,
+                cbor::from_value(call.body).unwrap(),
+            )
+            .expect("deposit batch check should succeed");
+        });
+    }
+
+    // Each deposit should emit its own message with a per-entry nonce.
+    let mut ctx = mock.create_ctx();
+    Module::<Accounts, Consensus>::init_or_migrate(
+        &mut ctx,
+        &mut Default::default(),
+        Default::default(),
+    );
+    let msgs = ctx.with_tx(0, tx(2), |mut tx_ctx, call| {
+        Module::<Accounts, Consensus>::tx_deposit_batch(
             &mut tx_ctx,
             cbor::from_value(call.body).unwrap(),
         )
-        .unwrap_err();
-        assert!(matches!(
-            result,
-            Error::Consensus(ConsensusError::InvalidDenomination)
-        ));
+        .expect("deposit batch tx should succeed");
+
+        tx_ctx.commit().1
     });
+    assert_eq!(2, msgs.len(), "one message per deposit should be emitted");
+
+    let expected = vec![
+        (keys::alice::address(), 1_000u128),
+        (keys::bob::address(), 2_000u128),
+    ];
+    for (index, ((msg, hook), (to, amount))) in msgs.into_iter().zip(expected).enumerate() {
+        assert_eq!(
+            Message::Staking(Versioned::new(
+                0,
+                StakingMessage::Withdraw(staking::Withdraw {
+                    from: keys::alice::address().into(),
+                    amount: amount.into(),
+                    memo: None,
+                })
+            )),
+            msg,
+            "emitted message should match"
+        );
+        assert_eq!(CONSENSUS_WITHDRAW_HANDLER.to_string(), hook.hook_name);
+
+        let context: types::ConsensusWithdrawContext = cbor::from_value(hook.payload).unwrap();
+        assert_eq!(context.from, keys::alice::address());
+        assert_eq!(context.nonce, nonce + index as u64);
+        assert_eq!(context.address, to);
+        assert_eq!(context.amount, BaseUnits::new(amount, denom.clone()));
+    }
 }
 
 #[test]
-fn test_api_withdraw_insufficient_balance() {
+fn test_api_deposit_allowance() {
     let mut mock = mock::Mock::default();
-    let mut ctx = mock.create_ctx();
-    let mut meta = Metadata {
-        ..Default::default()
-    };
-    let genesis = Default::default();
-
-    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, genesis);
+    let mut ctx = mock.create_check_ctx();
+    Module::<Accounts, Consensus>::init_or_migrate(
+        &mut ctx,
+        &mut Default::default(),
+        Genesis {
+            parameters: Parameters {
+                check_deposit_allowance: true,
+                ..Default::default()
+            },
+        },
+    );
 
     let tx = transaction::Transaction {
         version: 1,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
-            method: "consensus.Withdraw".to_owned(),
-            body: cbor::to_value(Withdraw {
-                // It's probably more common to withdraw into your own account, but we're using a
-                // separate `to` account to make sure everything is hooked up to the right places.
-                to: Some(keys::bob::address()),
+            method: "consensus.Deposit".to_owned(),
+            body: cbor::to_value(Deposit {
+                to: None,
                 amount: BaseUnits::new(1_000, Denomination::from_str("TEST").unwrap()),
+                memo: None,
             }),
         },
         auth_info: transaction::AuthInfo {
@@ -363,43 +510,58 @@ fn test_api_withdraw_insufficient_balance() {
                 gas: 1000,
                 consensus_messages: 1,
             },
+            features: 0,
         },
     };
 
+    // The mock consensus state has no allowances, so deposits should be rejected early.
+    ctx.with_tx(0, tx.clone(), |mut tx_ctx, call| {
+        let result = Module::<Accounts, Consensus>::tx_deposit(
+            &mut tx_ctx,
+            cbor::from_value(call.body).unwrap(),
+        );
+        assert!(matches!(
+            result,
+            Err(Error::InsufficientDepositAllowance(0))
+        ));
+    });
+
+    // Without the pre-flight check, deposits should be accepted.
+    Module::<Accounts, Consensus>::set_params(ctx.runtime_state(), Default::default());
     ctx.with_tx(0, tx, |mut tx_ctx, call| {
-        let result = Module::<Accounts, Consensus>::tx_withdraw(
+        Module::<Accounts, Consensus>::tx_deposit(
             &mut tx_ctx,
             cbor::from_value(call.body).unwrap(),
         )
-        .unwrap_err();
-        assert!(matches!(result, Error::InsufficientWithdrawBalance));
+        .expect("deposit check should succeed");
     });
 }
 
 #[test]
-fn test_api_withdraw_incompatible_signer() {
+fn test_api_deposit_memo() {
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
     let mut mock = mock::Mock::default();
     let mut ctx = mock.create_ctx();
-    let mut meta = Metadata {
-        ..Default::default()
-    };
-    let genesis = Default::default();
-
-    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, genesis);
+    Module::<Accounts, Consensus>::init_or_migrate(
+        &mut ctx,
+        &mut Default::default(),
+        Default::default(),
+    );
 
-    let tx = transaction::Transaction {
+    let tx = |memo| transaction::Transaction {
         version: 1,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
-            method: "consensus.Withdraw".to_owned(),
-            body: cbor::to_value(Withdraw {
+            method: "consensus.Deposit".to_owned(),
+            body: cbor::to_value(Deposit {
                 to: None,
-                amount: BaseUnits::new(1_000, Denomination::from_str("TEST").unwrap()),
+                amount: BaseUnits::new(1_000, denom.clone()),
+                memo: Some(memo),
             }),
         },
         auth_info: transaction::AuthInfo {
             signer_info: vec![transaction::SignerInfo::new_sigspec(
-                keys::dave::sigspec(),
+                keys::alice::sigspec(),
                 0,
             )],
             fee: transaction::Fee {
@@ -407,81 +569,995 @@ fn test_api_withdraw_incompatible_signer() {
                 gas: 1000,
                 consensus_messages: 1,
             },
+            features: 0,
         },
     };
 
-    ctx.with_tx(0, tx, |mut tx_ctx, call| {
-        let result = Module::<Accounts, Consensus>::tx_withdraw(
+    // Memos over the size limit should be rejected.
+    ctx.with_tx(0, tx(vec![0xaa; MAX_MEMO_SIZE + 1]), |mut tx_ctx, call| {
+        let result = Module::<Accounts, Consensus>::tx_deposit(
+            &mut tx_ctx,
+            cbor::from_value(call.body).unwrap(),
+        );
+        assert!(matches!(result, Err(Error::InvalidArgument)));
+    });
+
+    // Memos should survive the message result round-trip.
+    let memo = vec![0xaa; MAX_MEMO_SIZE];
+    let hook = ctx.with_tx(0, tx(memo.clone()), |mut tx_ctx, call| {
+        Module::<Accounts, Consensus>::tx_deposit(
             &mut tx_ctx,
             cbor::from_value(call.body).unwrap(),
         )
-        .unwrap_err();
-        assert!(matches!(
-            result,
-            Error::Consensus(ConsensusError::ConsensusIncompatibleSigner)
-        ));
+        .expect("deposit tx should succeed");
+
+        let (_, mut msgs) = tx_ctx.commit();
+        msgs.pop().unwrap().1
     });
-}
+    let me = MessageEvent {
+        module: "staking".to_string(),
+        code: 1, // Any non-zero code is treated as an error.
+        index: 0,
+    };
+    Module::<Accounts, Consensus>::message_result_withdraw(
+        &mut ctx,
+        me,
+        cbor::from_value(hook.payload).unwrap(),
+    );
 
-fn test_api_withdraw(signer_sigspec: SignatureAddressSpec) {
-    let signer_address = Address::from_sigspec(&signer_sigspec);
+    // Failed deposits should not be counted as deposited.
+    let deposited = Module::<Accounts, Consensus>::query_deposited(
+        &mut ctx,
+        types::DepositedQuery {
+            address: keys::alice::address(),
+            denomination: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(deposited.amount(), 0);
 
-    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    let (tags, _) = ctx.commit();
+    assert_eq!(tags.len(), 1, "deposit event should be emitted");
+
+    #[derive(Debug, cbor::Decode)]
+    struct DepositEvent {
+        from: Address,
+        nonce: u64,
+        to: Address,
+        amount: token::BaseUnits,
+        #[cbor(optional)]
+        error: Option<types::ConsensusError>,
+        #[cbor(optional)]
+        memo: Option<Vec<u8>>,
+    }
+    let event: DepositEvent = cbor::from_slice(&tags[0].value).unwrap();
+    assert_eq!(event.from, keys::alice::address());
+    assert_eq!(event.nonce, 0);
+    assert_eq!(event.to, keys::alice::address());
+    assert_eq!(event.amount.amount(), 1_000);
+    assert!(event.error.is_some(), "deposit should fail");
+    assert_eq!(
+        event.memo,
+        Some(memo),
+        "memo should be included in the event"
+    );
+}
+
+#[test]
+fn test_api_deposit_allowed_denominations() {
+    let denom: Denomination = Denomination::from_str("OTHER").unwrap();
     let mut mock = mock::Mock::default();
     let mut ctx = mock.create_ctx();
     let mut meta = Metadata {
         ..Default::default()
     };
+    let genesis = Default::default();
 
-    Accounts::init_or_migrate(
-        &mut ctx,
-        &mut meta,
-        AccountsGenesis {
-            balances: {
-                let mut balances = BTreeMap::new();
-                // Alice.
-                balances.insert(signer_address, {
-                    let mut denominations = BTreeMap::new();
-                    denominations.insert(denom.clone(), 1_000_000);
-                    denominations
-                });
-                balances
-            },
-            total_supplies: {
-                let mut total_supplies = BTreeMap::new();
-                total_supplies.insert(denom.clone(), 1_000_000);
-                total_supplies
-            },
+    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, genesis);
+    Consensus::set_params(
+        ctx.runtime_state(),
+        ConsensusParameters {
+            allowed_denominations: vec![denom.clone()].into_iter().collect(),
             ..Default::default()
         },
     );
-    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, Default::default());
 
     let nonce = 123;
     let tx = transaction::Transaction {
         version: 1,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
-            method: "consensus.Withdraw".to_owned(),
-            body: cbor::to_value(Withdraw {
-                // It's probably more common to withdraw into your own account, but we're using a
-                // separate `to` account to make sure everything is hooked up to the right places.
-                to: Some(keys::bob::address()),
-                amount: BaseUnits::new(1_000_000, denom.clone()),
+            method: "consensus.Deposit".to_owned(),
+            body: cbor::to_value(Deposit {
+                to: None,
+                amount: BaseUnits::new(1_000, denom.clone()),
+                memo: None,
             }),
         },
         auth_info: transaction::AuthInfo {
-            signer_info: vec![transaction::SignerInfo::new_sigspec(signer_sigspec, nonce)],
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                nonce,
+            )],
             fee: transaction::Fee {
                 amount: Default::default(),
                 gas: 1000,
                 consensus_messages: 1,
             },
+            features: 0,
         },
     };
 
     let hook = ctx.with_tx(0, tx, |mut tx_ctx, call| {
-        Module::<Accounts, Consensus>::tx_withdraw(
+        Module::<Accounts, Consensus>::tx_deposit(
+            &mut tx_ctx,
+            cbor::from_value(call.body).unwrap(),
+        )
+        .expect("deposit tx in an allowed denomination should succeed");
+
+        let (_, mut msgs) = tx_ctx.commit();
+        assert_eq!(1, msgs.len(), "one message should be emitted");
+        let (_, hook) = msgs.pop().unwrap();
+        hook
+    });
+
+    // Simulate the message being processed, the deposit should be minted in its denomination.
+    let me = Default::default();
+    Module::<Accounts, Consensus>::message_result_withdraw(
+        &mut ctx,
+        me,
+        cbor::from_value(hook.payload).unwrap(),
+    );
+
+    let balance = Module::<Accounts, Consensus>::query_balance(
+        &mut ctx,
+        types::BalanceQuery {
+            address: keys::alice::address(),
+            denomination: Some(denom.clone()),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        balance.balance, 1_000u128,
+        "deposited balance should be minted"
+    );
+    let balance = Module::<Accounts, Consensus>::query_balance(
+        &mut ctx,
+        types::BalanceQuery {
+            address: keys::alice::address(),
+            denomination: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        balance.balance, 0,
+        "consensus denomination should be unaffected"
+    );
+    assert!(matches!(
+        Module::<Accounts, Consensus>::query_balance(
+            &mut ctx,
+            types::BalanceQuery {
+                address: keys::alice::address(),
+                denomination: Some(Denomination::NATIVE),
+            },
+        ),
+        Err(Error::InvalidDenomination)
+    ));
+
+    // Deposits should be tracked separately for each denomination.
+    let deposited = Module::<Accounts, Consensus>::query_deposited(
+        &mut ctx,
+        types::DepositedQuery {
+            address: keys::alice::address(),
+            denomination: Some(denom.clone()),
+        },
+    )
+    .unwrap();
+    assert_eq!(deposited, BaseUnits::new(1_000, denom.clone()));
+    let deposited = Module::<Accounts, Consensus>::query_deposited(
+        &mut ctx,
+        types::DepositedQuery {
+            address: keys::alice::address(),
+            denomination: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(deposited.amount(), 0);
+    let total_deposited = Module::<Accounts, Consensus>::query_total_deposited(
+        &mut ctx,
+        types::TotalDepositedQuery {
+            denomination: Some(denom.clone()),
+        },
+    )
+    .unwrap();
+    assert_eq!(total_deposited, BaseUnits::new(1_000, denom));
+    assert!(matches!(
+        Module::<Accounts, Consensus>::query_deposited(
+            &mut ctx,
+            types::DepositedQuery {
+                address: keys::alice::address(),
+                denomination: Some(Denomination::NATIVE),
+            },
+        ),
+        Err(Error::InvalidDenomination)
+    ));
+
+    // The invariant should cover every allowed denomination. As the mock consensus layer does
+    // not reflect the deposit, the runtime account does not cover the minted supply.
+    assert!(matches!(
+        Module::<Accounts, Consensus>::check_invariants(&mut ctx),
+        Err(CoreError::InvariantViolation(msg)) if msg.contains("total supply")
+    ));
+}
+
+/// Set the balance of the runtime's general account in the mock consensus layer.
+fn set_runtime_consensus_balance(mock: &mut mock::Mock, balance: u128) {
+    let address = Address::from_runtime_id(&mock.host_info.runtime_id);
+    let account = staking::Account {
+        general: staking::GeneralAccount {
+            balance: Quantity::from(balance),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    set_consensus_account(mock, address, account);
+}
+
+/// Set the active escrow pool of the given staking account in the mock consensus layer.
+fn set_consensus_escrow_pool(
+    mock: &mut mock::Mock,
+    address: Address,
+    balance: u128,
+    total_shares: u128,
+) {
+    let account = staking::Account {
+        escrow: staking::EscrowAccount {
+            active: staking::SharePool {
+                balance: Quantity::from(balance),
+                total_shares: Quantity::from(total_shares),
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    set_consensus_account(mock, address, account);
+}
+
+/// Replace the mock consensus layer state with one containing only the given staking account.
+fn set_consensus_account(mock: &mut mock::Mock, address: Address, account: staking::Account) {
+    let mut tree = mkvs::Tree::make()
+        .with_root_type(mkvs::RootType::State)
+        .new(Box::new(mkvs::sync::NoopReadSyncer));
+    // Staking accounts are keyed by their address, prefixed with 0x50.
+    tree.insert(
+        IoContext::background(),
+        &[&[0x50][..], address.as_ref()].concat(),
+        &cbor::to_vec(account),
+    )
+    .unwrap();
+    mock.consensus_state = ConsensusState::new(tree);
+}
+
+#[test]
+fn test_invariant_total_supply() {
+    let denom: Denomination = Denomination::from_str("OTHER").unwrap();
+    let mut mock = mock::Mock::default();
+    set_runtime_consensus_balance(&mut mock, 1_000);
+    let mut ctx = mock.create_ctx();
+
+    Module::<Accounts, Consensus>::init_or_migrate(
+        &mut ctx,
+        &mut Default::default(),
+        Default::default(),
+    );
+    Consensus::set_params(
+        ctx.runtime_state(),
+        ConsensusParameters {
+            allowed_denominations: vec![denom.clone()].into_iter().collect(),
+            ..Default::default()
+        },
+    );
+
+    Accounts::set_total_supply(
+        ctx.runtime_state(),
+        &BaseUnits::new(600, Denomination::from_str("TEST").unwrap()),
+    );
+    Accounts::set_total_supply(ctx.runtime_state(), &BaseUnits::new(400, denom.clone()));
+    Module::<Accounts, Consensus>::check_invariants(&mut ctx)
+        .expect("invariants should hold while the runtime account covers all denominations");
+
+    // Each denomination on its own is still covered, but not all of them together.
+    Accounts::set_total_supply(ctx.runtime_state(), &BaseUnits::new(600, denom));
+    assert!(matches!(
+        Module::<Accounts, Consensus>::check_invariants(&mut ctx),
+        Err(CoreError::InvariantViolation(msg)) if msg.contains("total supply")
+    ));
+}
+
+#[test]
+fn test_allowed_denominations_fungible() {
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    let other: Denomination = Denomination::from_str("OTHER").unwrap();
+    let mut mock = mock::Mock::default();
+    set_runtime_consensus_balance(&mut mock, 1_000);
+    let mut ctx = mock.create_ctx();
+
+    Accounts::init_or_migrate(
+        &mut ctx,
+        &mut Default::default(),
+        AccountsGenesis {
+            balances: {
+                let mut balances = BTreeMap::new();
+                balances.insert(keys::alice::address(), {
+                    let mut denominations = BTreeMap::new();
+                    denominations.insert(denom.clone(), 1_000);
+                    denominations
+                });
+                balances
+            },
+            total_supplies: {
+                let mut total_supplies = BTreeMap::new();
+                total_supplies.insert(denom.clone(), 1_000);
+                total_supplies
+            },
+            ..Default::default()
+        },
+    );
+    Module::<Accounts, Consensus>::init_or_migrate(
+        &mut ctx,
+        &mut Default::default(),
+        Default::default(),
+    );
+    Consensus::set_params(
+        ctx.runtime_state(),
+        ConsensusParameters {
+            allowed_denominations: vec![other.clone()].into_iter().collect(),
+            ..Default::default()
+        },
+    );
+
+    let mut tx = mock::transaction();
+    tx.auth_info.fee.consensus_messages = 1;
+
+    // Withdraw the consensus denomination out of the runtime.
+    let hook = ctx.with_tx(0, tx.clone(), |mut tx_ctx, _call| {
+        Module::<Accounts, Consensus>::withdraw(
+            &mut tx_ctx,
+            keys::alice::address(),
+            0,
+            keys::alice::address(),
+            BaseUnits::new(1_000, denom.clone()),
+            None,
+        )
+        .expect("withdraw should succeed");
+        let (_, mut msgs) = tx_ctx.commit();
+        msgs.pop().unwrap().1
+    });
+    Module::<Accounts, Consensus>::message_result_transfer(
+        &mut ctx,
+        Default::default(),
+        cbor::from_value(hook.payload).unwrap(),
+    );
+
+    // And deposit the same consensus tokens back in a different denomination.
+    let hook = ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        Module::<Accounts, Consensus>::deposit(
+            &mut tx_ctx,
+            keys::alice::address(),
+            1,
+            keys::alice::address(),
+            BaseUnits::new(1_000, other.clone()),
+            None,
+        )
+        .expect("deposit should succeed");
+        let (_, mut msgs) = tx_ctx.commit();
+        msgs.pop().unwrap().1
+    });
+    Module::<Accounts, Consensus>::message_result_withdraw(
+        &mut ctx,
+        Default::default(),
+        cbor::from_value(hook.payload).unwrap(),
+    );
+
+    // All mapped denominations are interchangeable, so the supply has moved from one to the
+    // other while the invariant, which covers them together, still holds.
+    let total_supplies = Accounts::get_total_supplies(ctx.runtime_state()).unwrap();
+    assert_eq!(total_supplies.get(&denom).copied().unwrap_or_default(), 0);
+    assert_eq!(total_supplies.get(&other).copied(), Some(1_000));
+    assert_eq!(
+        Accounts::get_balance(ctx.runtime_state(), keys::alice::address(), other).unwrap(),
+        1_000
+    );
+    Module::<Accounts, Consensus>::check_invariants(&mut ctx)
+        .expect("invariants should hold after converting between mapped denominations");
+}
+
+#[test]
+fn test_api_withdraw_invalid_denomination() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+    let genesis = Default::default();
+
+    Accounts::init_or_migrate(
+        &mut ctx,
+        &mut meta,
+        AccountsGenesis {
+            balances: {
+                let mut balances = BTreeMap::new();
+                // Alice.
+                balances.insert(keys::alice::address(), {
+                    let mut denominations = BTreeMap::new();
+                    denominations.insert(Denomination::NATIVE, 1_000_000);
+                    denominations
+                });
+                balances
+            },
+            total_supplies: {
+                let mut total_supplies = BTreeMap::new();
+                total_supplies.insert(Denomination::NATIVE, 1_000_000);
+                total_supplies
+            },
+            ..Default::default()
+        },
+    );
+    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, genesis);
+
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "consensus.Withdraw".to_owned(),
+            body: cbor::to_value(Withdraw {
+                // It's probably more common to withdraw into your own account, but we're using a
+                // separate `to` account to make sure everything is hooked up to the right places.
+                to: Some(keys::bob::address()),
+                to_spec: Some(keys::bob::sigspec()),
+                amount: BaseUnits::new(1_000, Denomination::NATIVE),
+                memo: None,
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 1,
+            },
+            features: 0,
+        },
+    };
+
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        let result = Module::<Accounts, Consensus>::tx_withdraw(
+            &mut tx_ctx,
+            cbor::from_value(call.body).unwrap(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            result,
+            Error::Consensus(ConsensusError::InvalidDenomination)
+        ));
+    });
+}
+
+#[test]
+fn test_api_withdraw_insufficient_balance() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+    let genesis = Default::default();
+
+    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, genesis);
+
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "consensus.Withdraw".to_owned(),
+            body: cbor::to_value(Withdraw {
+                // It's probably more common to withdraw into your own account, but we're using a
+                // separate `to` account to make sure everything is hooked up to the right places.
+                to: Some(keys::bob::address()),
+                to_spec: Some(keys::bob::sigspec()),
+                amount: BaseUnits::new(1_000, Denomination::from_str("TEST").unwrap()),
+                memo: None,
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 1,
+            },
+            features: 0,
+        },
+    };
+
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        let result = Module::<Accounts, Consensus>::tx_withdraw(
+            &mut tx_ctx,
+            cbor::from_value(call.body).unwrap(),
+        )
+        .unwrap_err();
+        assert!(matches!(result, Error::InsufficientWithdrawBalance));
+    });
+}
+
+#[test]
+fn test_api_withdraw_incompatible_signer() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+    let genesis = Default::default();
+
+    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, genesis);
+
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "consensus.Withdraw".to_owned(),
+            body: cbor::to_value(Withdraw {
+                to: None,
+                to_spec: None,
+                amount: BaseUnits::new(1_000, Denomination::from_str("TEST").unwrap()),
+                memo: None,
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::dave::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 1,
+            },
+            features: 0,
+        },
+    };
+
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        let result = Module::<Accounts, Consensus>::tx_withdraw(
+            &mut tx_ctx,
+            cbor::from_value(call.body).unwrap(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            result,
+            Error::Consensus(ConsensusError::ConsensusIncompatibleSigner)
+        ));
+    });
+}
+
+fn test_api_withdraw(signer_sigspec: SignatureAddressSpec) {
+    let signer_address = Address::from_sigspec(&signer_sigspec);
+
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+
+    Accounts::init_or_migrate(
+        &mut ctx,
+        &mut meta,
+        AccountsGenesis {
+            balances: {
+                let mut balances = BTreeMap::new();
+                // Alice.
+                balances.insert(signer_address, {
+                    let mut denominations = BTreeMap::new();
+                    denominations.insert(denom.clone(), 1_000_000);
+                    denominations
+                });
+                balances
+            },
+            total_supplies: {
+                let mut total_supplies = BTreeMap::new();
+                total_supplies.insert(denom.clone(), 1_000_000);
+                total_supplies
+            },
+            ..Default::default()
+        },
+    );
+    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, Default::default());
+
+    let nonce = 123;
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "consensus.Withdraw".to_owned(),
+            body: cbor::to_value(Withdraw {
+                // It's probably more common to withdraw into your own account, but we're using a
+                // separate `to` account to make sure everything is hooked up to the right places.
+                to: Some(keys::bob::address()),
+                to_spec: Some(keys::bob::sigspec()),
+                amount: BaseUnits::new(1_000_000, denom.clone()),
+                memo: None,
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(signer_sigspec, nonce)],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 1,
+            },
+            features: 0,
+        },
+    };
+
+    let (tx_tags, hook) = ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        Module::<Accounts, Consensus>::tx_withdraw(
+            &mut tx_ctx,
+            cbor::from_value(call.body).unwrap(),
+        )
+        .expect("withdraw tx should succeed");
+
+        let (tags, mut msgs) = tx_ctx.commit();
+        assert_eq!(1, msgs.len(), "one message should be emitted");
+        let (msg, hook) = msgs.pop().unwrap();
+
+        assert_eq!(
+            Message::Staking(Versioned::new(
+                0,
+                StakingMessage::Transfer(staking::Transfer {
+                    to: keys::bob::address().into(),
+                    amount: 1_000_000u128.into(),
+                })
+            )),
+            msg,
+            "emitted message should match"
+        );
+
+        assert_eq!(
+            CONSENSUS_TRANSFER_HANDLER.to_string(),
+            hook.hook_name,
+            "emitted hook should match"
+        );
+
+        (tags, hook)
+    });
+
+    // Make sure the withdrawal was reported as initiated.
+    assert_eq!(
+        tx_tags.len(),
+        2,
+        "transfer and withdraw initiated events should be emitted"
+    );
+    assert_eq!(tx_tags[0].key, b"accounts\x00\x00\x00\x01"); // accounts.Transfer (code = 1) event
+    assert_eq!(tx_tags[1].key, b"consensus_accounts\x00\x00\x00\x05"); // consensus_accounts.WithdrawInitiated (code = 5) event
+
+    // Decode withdraw initiated event.
+    #[derive(Debug, cbor::Decode)]
+    struct WithdrawInitiatedEvent {
+        from: Address,
+        nonce: u64,
+        to: Address,
+        amount: token::BaseUnits,
+    }
+    let initiated: WithdrawInitiatedEvent = cbor::from_slice(&tx_tags[1].value).unwrap();
+    assert_eq!(initiated.from, signer_address);
+    assert_eq!(initiated.nonce, nonce);
+    assert_eq!(initiated.to, keys::bob::address());
+    assert_eq!(initiated.amount.amount(), 1_000_000);
+    assert_eq!(initiated.amount.denomination(), &denom);
+
+    // Make sure that withdrawn balance is in the module's pending withdrawal account.
+    let balance =
+        Accounts::get_balance(ctx.runtime_state(), signer_address, denom.clone()).unwrap();
+    assert_eq!(balance, 0u128, "withdrawn balance should be locked");
+
+    let balance = Accounts::get_balance(
+        ctx.runtime_state(),
+        *ADDRESS_PENDING_WITHDRAWAL,
+        denom.clone(),
+    )
+    .unwrap();
+    assert_eq!(balance, 1_000_000u128, "withdrawn balance should be locked");
+
+    // Simulate the message being processed and make sure withdrawal is successfully completed.
+    let me = Default::default();
+    Module::<Accounts, Consensus>::message_result_transfer(
+        &mut ctx,
+        me,
+        cbor::from_value(hook.payload).unwrap(),
+    );
+
+    // Ensure runtime balance is updated.
+    let balance = Accounts::get_balance(
+        ctx.runtime_state(),
+        *ADDRESS_PENDING_WITHDRAWAL,
+        denom.clone(),
+    )
+    .unwrap();
+    assert_eq!(balance, 0u128, "withdrawn balance should be burned");
+    let balance =
+        Accounts::get_balance(ctx.runtime_state(), signer_address, denom.clone()).unwrap();
+    assert_eq!(balance, 0u128, "withdrawn balance should be burned");
+    let total_supplies = Accounts::get_total_supplies(ctx.runtime_state()).unwrap();
+    assert_eq!(total_supplies.len(), 1);
+    assert_eq!(
+        total_supplies[&denom], 0u128,
+        "withdrawn balance should be burned"
+    );
+
+    // Make sure events were emitted.
+    let (tags, _) = ctx.commit();
+    assert_eq!(tags.len(), 2, "withdraw and burn events should be emitted");
+    assert_eq!(tags[0].key, b"accounts\x00\x00\x00\x02"); // accounts.Burn (code = 2) event
+    assert_eq!(tags[1].key, b"consensus_accounts\x00\x00\x00\x02"); // consensus_accounts.Withdraw (code = 2) event
+
+    // Decode withdraw event.
+    #[derive(Debug, cbor::Decode)]
+    struct WithdrawEvent {
+        from: Address,
+        nonce: u64,
+        to: Address,
+        amount: token::BaseUnits,
+        #[cbor(optional)]
+        error: Option<types::ConsensusError>,
+    }
+    let event: WithdrawEvent = cbor::from_slice(&tags[1].value).unwrap();
+    assert_eq!(event.from, signer_address);
+    assert_eq!(
+        event.nonce, initiated.nonce,
+        "terminal event should match the initiated one"
+    );
+    assert_eq!(event.to, keys::bob::address());
+    assert_eq!(event.amount.amount(), 1_000_000);
+    assert_eq!(event.amount.denomination(), &denom);
+    assert_eq!(event.error, None);
+}
+
+#[test]
+fn test_api_withdraw_ed25519() {
+    test_api_withdraw(keys::alice::sigspec());
+}
+
+#[test]
+fn test_api_withdraw_secp256k1() {
+    test_api_withdraw(keys::dave::sigspec());
+}
+
+#[test]
+fn test_api_withdraw_destination() {
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+
+    Accounts::init_or_migrate(
+        &mut ctx,
+        &mut meta,
+        AccountsGenesis {
+            balances: {
+                let mut balances = BTreeMap::new();
+                for address in vec![keys::alice::address(), keys::dave::address()] {
+                    balances.insert(address, {
+                        let mut denominations = BTreeMap::new();
+                        denominations.insert(denom.clone(), 1_000_000);
+                        denominations
+                    });
+                }
+                balances
+            },
+            total_supplies: {
+                let mut total_supplies = BTreeMap::new();
+                total_supplies.insert(denom.clone(), 2_000_000);
+                total_supplies
+            },
+            ..Default::default()
+        },
+    );
+    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, Default::default());
+
+    let tx = |signer: SignatureAddressSpec,
+              to: Option<Address>,
+              to_spec: Option<SignatureAddressSpec>| transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "consensus.Withdraw".to_owned(),
+            body: cbor::to_value(Withdraw {
+                to,
+                to_spec,
+                amount: BaseUnits::new(1_000, denom.clone()),
+                memo: None,
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(signer, 0)],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 1,
+            },
+            features: 0,
+        },
+    };
+    fn withdraw<C: BatchContext>(ctx: &mut C, tx: transaction::Transaction) -> Result<(), Error> {
+        ctx.with_tx(0, tx, |mut tx_ctx, call| {
+            Module::<Accounts, Consensus>::tx_withdraw(
+                &mut tx_ctx,
+                cbor::from_value(call.body).unwrap(),
+            )
+        })
+    }
+
+    // A Secp256k1 sender can withdraw to an Ed25519 account.
+    withdraw(
+        &mut ctx,
+        tx(
+            keys::dave::sigspec(),
+            Some(keys::bob::address()),
+            Some(keys::bob::sigspec()),
+        ),
+    )
+    .expect("withdraw to an Ed25519 account should succeed");
+
+    // But not to its own account, even when it is given explicitly.
+    let result = withdraw(
+        &mut ctx,
+        tx(
+            keys::dave::sigspec(),
+            Some(keys::dave::address()),
+            Some(keys::dave::sigspec()),
+        ),
+    );
+    assert!(matches!(
+        result,
+        Err(Error::Consensus(
+            ConsensusError::ConsensusIncompatibleSigner
+        ))
+    ));
+
+    // Nor can anyone withdraw to a Secp256k1 account.
+    let result = withdraw(
+        &mut ctx,
+        tx(
+            keys::alice::sigspec(),
+            Some(keys::dave::address()),
+            Some(keys::dave::sigspec()),
+        ),
+    );
+    assert!(matches!(result, Err(Error::InvalidArgument)));
+
+    // The destination must be proven to be derived from an Ed25519 key.
+    let result = withdraw(
+        &mut ctx,
+        tx(keys::alice::sigspec(), Some(keys::bob::address()), None),
+    );
+    assert!(matches!(result, Err(Error::InvalidArgument)));
+    let result = withdraw(
+        &mut ctx,
+        tx(
+            keys::alice::sigspec(),
+            Some(keys::bob::address()),
+            Some(keys::charlie::sigspec()),
+        ),
+    );
+    assert!(matches!(result, Err(Error::InvalidArgument)));
+
+    // Module accounts can never be controlled on the consensus layer.
+    let result = withdraw(
+        &mut ctx,
+        tx(
+            keys::dave::sigspec(),
+            Some(*ADDRESS_PENDING_WITHDRAWAL),
+            None,
+        ),
+    );
+    assert!(matches!(result, Err(Error::InvalidArgument)));
+
+    // Delegated withdrawals can be disabled.
+    Module::<Accounts, Consensus>::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            disable_delegated_withdraw: true,
+            ..Default::default()
+        },
+    );
+    let result = withdraw(
+        &mut ctx,
+        tx(
+            keys::dave::sigspec(),
+            Some(keys::bob::address()),
+            Some(keys::bob::sigspec()),
+        ),
+    );
+    assert!(matches!(result, Err(Error::Forbidden)));
+    withdraw(
+        &mut ctx,
+        tx(keys::alice::sigspec(), Some(keys::alice::address()), None),
+    )
+    .expect("withdraw to own account should succeed");
+}
+
+#[test]
+fn test_api_withdraw_handler_failure() {
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+
+    Accounts::init_or_migrate(
+        &mut ctx,
+        &mut meta,
+        AccountsGenesis {
+            balances: {
+                let mut balances = BTreeMap::new();
+                // Alice.
+                balances.insert(keys::alice::address(), {
+                    let mut denominations = BTreeMap::new();
+                    denominations.insert(denom.clone(), 1_000_000);
+                    denominations
+                });
+                balances
+            },
+            total_supplies: {
+                let mut total_supplies = BTreeMap::new();
+                total_supplies.insert(denom.clone(), 1_000_000);
+                total_supplies
+            },
+            ..Default::default()
+        },
+    );
+    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, Default::default());
+
+    let nonce = 123;
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "consensus.Withdraw".to_owned(),
+            body: cbor::to_value(Withdraw {
+                // It's probably more common to withdraw into your own account, but we're using a
+                // separate `to` account to make sure everything is hooked up to the right places.
+                to: keys::bob::address().into(),
+                to_spec: Some(keys::bob::sigspec()),
+                amount: BaseUnits::new(1_000_000, denom.clone()),
+                memo: None,
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                nonce,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 1,
+            },
+            features: 0,
+        },
+    };
+
+    let hook = ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        Module::<Accounts, Consensus>::tx_withdraw(
             &mut tx_ctx,
             cbor::from_value(call.body).unwrap(),
         )
@@ -514,7 +1590,7 @@ fn test_api_withdraw(signer_sigspec: SignatureAddressSpec) {
 
     // Make sure that withdrawn balance is in the module's pending withdrawal account.
     let balance =
-        Accounts::get_balance(ctx.runtime_state(), signer_address, denom.clone()).unwrap();
+        Accounts::get_balance(ctx.runtime_state(), keys::alice::address(), denom.clone()).unwrap();
     assert_eq!(balance, 0u128, "withdrawn balance should be locked");
 
     let balance = Accounts::get_balance(
@@ -525,36 +1601,47 @@ fn test_api_withdraw(signer_sigspec: SignatureAddressSpec) {
     .unwrap();
     assert_eq!(balance, 1_000_000u128, "withdrawn balance should be locked");
 
-    // Simulate the message being processed and make sure withdrawal is successfully completed.
-    let me = Default::default();
+    // Simulate the message failing and make sure withdrawal amount is refunded.
+    let me = MessageEvent {
+        module: "staking".to_string(),
+        code: 1, // Any non-zero code is treated as an error.
+        index: 0,
+    };
     Module::<Accounts, Consensus>::message_result_transfer(
         &mut ctx,
         me,
         cbor::from_value(hook.payload).unwrap(),
     );
 
-    // Ensure runtime balance is updated.
+    // Ensure amount is refunded.
     let balance = Accounts::get_balance(
         ctx.runtime_state(),
         *ADDRESS_PENDING_WITHDRAWAL,
         denom.clone(),
     )
     .unwrap();
-    assert_eq!(balance, 0u128, "withdrawn balance should be burned");
+    assert_eq!(balance, 0u128, "withdrawn balance should be refunded");
     let balance =
-        Accounts::get_balance(ctx.runtime_state(), signer_address, denom.clone()).unwrap();
-    assert_eq!(balance, 0u128, "withdrawn balance should be burned");
+        Accounts::get_balance(ctx.runtime_state(), keys::alice::address(), denom.clone()).unwrap();
+    assert_eq!(
+        balance, 1_000_000u128,
+        "withdrawn balance should be refunded"
+    );
     let total_supplies = Accounts::get_total_supplies(ctx.runtime_state()).unwrap();
     assert_eq!(total_supplies.len(), 1);
     assert_eq!(
-        total_supplies[&denom], 0u128,
-        "withdrawn balance should be burned"
+        total_supplies[&denom], 1_000_000u128,
+        "withdrawn balance should be refunded"
     );
 
     // Make sure events were emitted.
     let (tags, _) = ctx.commit();
-    assert_eq!(tags.len(), 2, "withdraw and burn events should be emitted");
-    assert_eq!(tags[0].key, b"accounts\x00\x00\x00\x02"); // accounts.Burn (code = 2) event
+    assert_eq!(
+        tags.len(),
+        2,
+        "withdraw and transfer events should be emitted"
+    );
+    assert_eq!(tags[0].key, b"accounts\x00\x00\x00\x01"); // accounts.Transfer (code = 1) event
     assert_eq!(tags[1].key, b"consensus_accounts\x00\x00\x00\x02"); // consensus_accounts.Withdraw (code = 2) event
 
     // Decode withdraw event.
@@ -568,26 +1655,188 @@ fn test_api_withdraw(signer_sigspec: SignatureAddressSpec) {
         error: Option<types::ConsensusError>,
     }
     let event: WithdrawEvent = cbor::from_slice(&tags[1].value).unwrap();
-    assert_eq!(event.from, signer_address);
+    assert_eq!(event.from, keys::alice::address());
     assert_eq!(event.nonce, nonce);
     assert_eq!(event.to, keys::bob::address());
     assert_eq!(event.amount.amount(), 1_000_000);
     assert_eq!(event.amount.denomination(), &denom);
-    assert_eq!(event.error, None);
+    assert_eq!(
+        event.error,
+        Some(types::ConsensusError {
+            module: "staking".to_string(),
+            code: 1,
+        })
+    );
 }
 
 #[test]
-fn test_api_withdraw_ed25519() {
-    test_api_withdraw(keys::alice::sigspec());
+fn test_query_gas() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Core::set_params(
+        ctx.runtime_state(),
+        CoreParameters {
+            max_batch_gas: 1_000,
+            ..Default::default()
+        },
+    );
+    Module::<Accounts, Consensus>::init_or_migrate(
+        &mut ctx,
+        &mut Default::default(),
+        Genesis {
+            parameters: Parameters {
+                gas_costs: GasCosts {
+                    query_balance: 100,
+                    query_consensus_account: 200,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        },
+    );
+
+    // Regular queries are not metered.
+    Module::<Accounts, Consensus>::query_balance(
+        &mut ctx,
+        types::BalanceQuery {
+            address: keys::alice::address(),
+            denomination: None,
+        },
+    )
+    .expect("balance query should succeed");
+    assert_eq!(Core::remaining_batch_gas(&mut ctx), 1_000);
+
+    ctx.with_simulation(|mut sim_ctx| {
+        Module::<Accounts, Consensus>::query_balance(
+            &mut sim_ctx,
+            types::BalanceQuery {
+                address: keys::alice::address(),
+                denomination: None,
+            },
+        )
+        .expect("balance query should succeed");
+        assert_eq!(Core::remaining_batch_gas(&mut sim_ctx), 900);
+
+        // The consensus account query result does not matter, only the charged gas.
+        let _ = Module::<Accounts, Consensus>::query_consensus_account(
+            &mut sim_ctx,
+            types::ConsensusAccountQuery {
+                address: keys::alice::address(),
+            },
+        );
+        assert_eq!(Core::remaining_batch_gas(&mut sim_ctx), 700);
+    });
 }
 
 #[test]
-fn test_api_withdraw_secp256k1() {
-    test_api_withdraw(keys::dave::sigspec());
+fn test_query_total_deposited() {
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Module::<Accounts, Consensus>::init_or_migrate(
+        &mut ctx,
+        &mut Default::default(),
+        Default::default(),
+    );
+
+    fn deposit<C: Context>(ctx: &mut C, to: Address, amount: u128) {
+        Module::<Accounts, Consensus>::message_result_withdraw(
+            ctx,
+            Default::default(),
+            types::ConsensusWithdrawContext {
+                from: to,
+                nonce: 0,
+                address: to,
+                amount: BaseUnits::new(amount, Denomination::from_str("TEST").unwrap()),
+                memo: None,
+            },
+        );
+    }
+    fn total_deposited<C: Context>(ctx: &mut C) -> BaseUnits {
+        Module::<Accounts, Consensus>::query_total_deposited(ctx, Default::default()).unwrap()
+    }
+
+    assert_eq!(total_deposited(&mut ctx), BaseUnits::new(0, denom.clone()));
+
+    deposit(&mut ctx, keys::alice::address(), 1_000);
+    deposit(&mut ctx, keys::bob::address(), 2_000);
+    assert_eq!(
+        total_deposited(&mut ctx),
+        BaseUnits::new(3_000, denom.clone())
+    );
+
+    // Withdrawals should not reduce the total.
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "consensus.Withdraw".to_owned(),
+            body: cbor::to_value(Withdraw {
+                to: None,
+                to_spec: None,
+                amount: BaseUnits::new(500, denom.clone()),
+                memo: None,
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 1,
+            },
+            features: 0,
+        },
+    };
+    let hook = ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        Module::<Accounts, Consensus>::tx_withdraw(
+            &mut tx_ctx,
+            cbor::from_value(call.body).unwrap(),
+        )
+        .expect("withdraw tx should succeed");
+
+        let (_, mut msgs) = tx_ctx.commit();
+        msgs.pop().unwrap().1
+    });
+    Module::<Accounts, Consensus>::message_result_transfer(
+        &mut ctx,
+        Default::default(),
+        cbor::from_value(hook.payload).unwrap(),
+    );
+    assert_eq!(
+        total_deposited(&mut ctx),
+        BaseUnits::new(3_000, denom.clone())
+    );
+
+    deposit(&mut ctx, keys::alice::address(), 500);
+    assert_eq!(
+        total_deposited(&mut ctx),
+        BaseUnits::new(3_500, denom.clone())
+    );
+
+    // The total should match the sum of the per-account amounts.
+    let sum: u128 = vec![keys::alice::address(), keys::bob::address()]
+        .into_iter()
+        .map(|address| {
+            Module::<Accounts, Consensus>::query_deposited(
+                &mut ctx,
+                types::DepositedQuery {
+                    address,
+                    denomination: None,
+                },
+            )
+            .unwrap()
+            .amount()
+        })
+        .sum();
+    assert_eq!(sum, 3_500);
 }
 
 #[test]
-fn test_api_withdraw_handler_failure() {
+fn test_query_pending_withdrawal() {
     let denom: Denomination = Denomination::from_str("TEST").unwrap();
     let mut mock = mock::Mock::default();
     let mut ctx = mock.create_ctx();
@@ -619,142 +1868,217 @@ fn test_api_withdraw_handler_failure() {
     );
     Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, Default::default());
 
-    let nonce = 123;
+    fn withdraw<C: BatchContext>(ctx: &mut C, nonce: u64, amount: u128) -> cbor::Value {
+        let tx = transaction::Transaction {
+            version: 1,
+            call: transaction::Call {
+                format: transaction::CallFormat::Plain,
+                method: "consensus.Withdraw".to_owned(),
+                body: cbor::to_value(Withdraw {
+                    to: Some(keys::bob::address()),
+                    to_spec: Some(keys::bob::sigspec()),
+                    amount: BaseUnits::new(amount, Denomination::from_str("TEST").unwrap()),
+                    memo: None,
+                }),
+            },
+            auth_info: transaction::AuthInfo {
+                signer_info: vec![transaction::SignerInfo::new_sigspec(
+                    keys::alice::sigspec(),
+                    nonce,
+                )],
+                fee: transaction::Fee {
+                    amount: Default::default(),
+                    gas: 1000,
+                    consensus_messages: 1,
+                },
+                features: 0,
+            },
+        };
+        ctx.with_tx(0, tx, |mut tx_ctx, call| {
+            Module::<Accounts, Consensus>::tx_withdraw(
+                &mut tx_ctx,
+                cbor::from_value(call.body).unwrap(),
+            )
+            .expect("withdraw tx should succeed");
+
+            let (_, mut msgs) = tx_ctx.commit();
+            let (_, hook) = msgs.pop().unwrap();
+            hook.payload
+        })
+    }
+    fn pending_withdrawal<C: Context>(ctx: &mut C, address: Address) -> u128 {
+        Module::<Accounts, Consensus>::query_pending_withdrawal(
+            ctx,
+            types::PendingWithdrawalQuery {
+                address,
+                denomination: None,
+            },
+        )
+        .expect("pending withdrawal query should succeed")
+        .amount
+    }
+
+    // Concurrent withdrawals should be tracked separately.
+    let first = withdraw(&mut ctx, 1, 300_000);
+    let second = withdraw(&mut ctx, 2, 200_000);
+    assert_eq!(
+        pending_withdrawal(&mut ctx, keys::alice::address()),
+        500_000
+    );
+    assert_eq!(pending_withdrawal(&mut ctx, keys::bob::address()), 0);
+
+    // Successful withdrawals should no longer be pending.
+    Module::<Accounts, Consensus>::message_result_transfer(
+        &mut ctx,
+        Default::default(),
+        cbor::from_value(first).unwrap(),
+    );
+    assert_eq!(
+        pending_withdrawal(&mut ctx, keys::alice::address()),
+        200_000
+    );
+
+    // Failed withdrawals should no longer be pending after being refunded.
+    let me = MessageEvent {
+        module: "staking".to_string(),
+        code: 1,
+        index: 0,
+    };
+    Module::<Accounts, Consensus>::message_result_transfer(
+        &mut ctx,
+        me,
+        cbor::from_value(second).unwrap(),
+    );
+    assert_eq!(pending_withdrawal(&mut ctx, keys::alice::address()), 0);
+
+    let balance =
+        Accounts::get_balance(ctx.runtime_state(), keys::alice::address(), denom).unwrap();
+    assert_eq!(balance, 800_000u128, "failed withdrawal should be refunded");
+}
+
+#[test]
+fn test_invariant_pending_withdrawals() {
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+
+    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, Default::default());
+    // Do not record a total supply as the mock consensus layer has no runtime account balance.
+    Accounts::set_balance(
+        ctx.runtime_state(),
+        keys::alice::address(),
+        &BaseUnits::new(1_000, denom.clone()),
+    );
+
     let tx = transaction::Transaction {
         version: 1,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
             method: "consensus.Withdraw".to_owned(),
             body: cbor::to_value(Withdraw {
-                // It's probably more common to withdraw into your own account, but we're using a
-                // separate `to` account to make sure everything is hooked up to the right places.
-                to: keys::bob::address().into(),
-                amount: BaseUnits::new(1_000_000, denom.clone()),
+                to: Some(keys::bob::address()),
+                to_spec: Some(keys::bob::sigspec()),
+                amount: BaseUnits::new(400, denom.clone()),
+                memo: None,
             }),
         },
         auth_info: transaction::AuthInfo {
             signer_info: vec![transaction::SignerInfo::new_sigspec(
                 keys::alice::sigspec(),
-                nonce,
+                1,
             )],
             fee: transaction::Fee {
                 amount: Default::default(),
                 gas: 1000,
                 consensus_messages: 1,
             },
+            features: 0,
         },
     };
-
-    let hook = ctx.with_tx(0, tx, |mut tx_ctx, call| {
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
         Module::<Accounts, Consensus>::tx_withdraw(
             &mut tx_ctx,
             cbor::from_value(call.body).unwrap(),
         )
         .expect("withdraw tx should succeed");
-
-        let (_, mut msgs) = tx_ctx.commit();
-        assert_eq!(1, msgs.len(), "one message should be emitted");
-        let (msg, hook) = msgs.pop().unwrap();
-
-        assert_eq!(
-            Message::Staking(Versioned::new(
-                0,
-                StakingMessage::Transfer(staking::Transfer {
-                    to: keys::bob::address().into(),
-                    amount: 1_000_000u128.into(),
-                })
-            )),
-            msg,
-            "emitted message should match"
-        );
-
-        assert_eq!(
-            CONSENSUS_TRANSFER_HANDLER.to_string(),
-            hook.hook_name,
-            "emitted hook should match"
-        );
-
-        hook
+        tx_ctx.commit();
     });
+    Module::<Accounts, Consensus>::check_invariants(&mut ctx)
+        .expect("invariants should hold while the withdrawal is pending");
 
-    // Make sure that withdrawn balance is in the module's pending withdrawal account.
-    let balance =
-        Accounts::get_balance(ctx.runtime_state(), keys::alice::address(), denom.clone()).unwrap();
-    assert_eq!(balance, 0u128, "withdrawn balance should be locked");
-
-    let balance = Accounts::get_balance(
+    // Tokens leaving the pending withdrawal account without the withdrawal being resolved.
+    Accounts::set_balance(
         ctx.runtime_state(),
         *ADDRESS_PENDING_WITHDRAWAL,
-        denom.clone(),
-    )
-    .unwrap();
-    assert_eq!(balance, 1_000_000u128, "withdrawn balance should be locked");
-
-    // Simulate the message failing and make sure withdrawal amount is refunded.
-    let me = MessageEvent {
-        module: "staking".to_string(),
-        code: 1, // Any non-zero code is treated as an error.
-        index: 0,
-    };
-    Module::<Accounts, Consensus>::message_result_transfer(
-        &mut ctx,
-        me,
-        cbor::from_value(hook.payload).unwrap(),
+        &BaseUnits::new(300, denom.clone()),
     );
+    assert!(matches!(
+        Module::<Accounts, Consensus>::check_invariants(&mut ctx),
+        Err(CoreError::InvariantViolation(msg)) if msg.contains("pending withdrawal balance")
+    ));
 
-    // Ensure amount is refunded.
-    let balance = Accounts::get_balance(
+    // The withdrawal being resolved without the tokens leaving the pending withdrawal account.
+    Accounts::set_balance(
         ctx.runtime_state(),
         *ADDRESS_PENDING_WITHDRAWAL,
-        denom.clone(),
-    )
-    .unwrap();
-    assert_eq!(balance, 0u128, "withdrawn balance should be refunded");
-    let balance =
-        Accounts::get_balance(ctx.runtime_state(), keys::alice::address(), denom.clone()).unwrap();
-    assert_eq!(
-        balance, 1_000_000u128,
-        "withdrawn balance should be refunded"
+        &BaseUnits::new(400, denom.clone()),
     );
-    let total_supplies = Accounts::get_total_supplies(ctx.runtime_state()).unwrap();
-    assert_eq!(total_supplies.len(), 1);
-    assert_eq!(
-        total_supplies[&denom], 1_000_000u128,
-        "withdrawn balance should be refunded"
+    Module::<Accounts, Consensus>::check_invariants(&mut ctx).expect("invariants should hold");
+    Module::<Accounts, Consensus>::remove_pending_withdrawal(
+        &mut ctx,
+        keys::alice::address(),
+        1,
+        &BaseUnits::new(400, denom.clone()),
     );
-
-    // Make sure events were emitted.
-    let (tags, _) = ctx.commit();
-    assert_eq!(
-        tags.len(),
-        2,
-        "withdraw and transfer events should be emitted"
+    assert!(matches!(
+        Module::<Accounts, Consensus>::check_invariants(&mut ctx),
+        Err(CoreError::InvariantViolation(msg)) if msg.contains("pending withdrawal balance")
+    ));
+
+    // Pending withdrawals should be matched in their own denomination.
+    let other: Denomination = Denomination::from_str("OTHER").unwrap();
+    Consensus::set_params(
+        ctx.runtime_state(),
+        ConsensusParameters {
+            allowed_denominations: vec![other.clone()].into_iter().collect(),
+            ..Default::default()
+        },
     );
-    assert_eq!(tags[0].key, b"accounts\x00\x00\x00\x01"); // accounts.Transfer (code = 1) event
-    assert_eq!(tags[1].key, b"consensus_accounts\x00\x00\x00\x02"); // consensus_accounts.Withdraw (code = 2) event
-
-    // Decode withdraw event.
-    #[derive(Debug, cbor::Decode)]
-    struct WithdrawEvent {
-        from: Address,
-        nonce: u64,
-        to: Address,
-        amount: token::BaseUnits,
-        #[cbor(optional)]
-        error: Option<types::ConsensusError>,
-    }
-    let event: WithdrawEvent = cbor::from_slice(&tags[1].value).unwrap();
-    assert_eq!(event.from, keys::alice::address());
-    assert_eq!(event.nonce, nonce);
-    assert_eq!(event.to, keys::bob::address());
-    assert_eq!(event.amount.amount(), 1_000_000);
-    assert_eq!(event.amount.denomination(), &denom);
+    Module::<Accounts, Consensus>::add_pending_withdrawal(
+        &mut ctx,
+        keys::alice::address(),
+        1,
+        &BaseUnits::new(400, denom.clone()),
+    );
+    Module::<Accounts, Consensus>::check_invariants(&mut ctx).expect("invariants should hold");
+    Accounts::set_balance(
+        ctx.runtime_state(),
+        *ADDRESS_PENDING_WITHDRAWAL,
+        &BaseUnits::new(0, denom),
+    );
+    Accounts::set_balance(
+        ctx.runtime_state(),
+        *ADDRESS_PENDING_WITHDRAWAL,
+        &BaseUnits::new(400, other.clone()),
+    );
+    assert!(matches!(
+        Module::<Accounts, Consensus>::check_invariants(&mut ctx),
+        Err(CoreError::InvariantViolation(msg)) if msg.contains("pending withdrawal balance")
+    ));
     assert_eq!(
-        event.error,
-        Some(types::ConsensusError {
-            module: "staking".to_string(),
-            code: 1,
-        })
+        Module::<Accounts, Consensus>::query_pending_withdrawal(
+            &mut ctx,
+            types::PendingWithdrawalQuery {
+                address: keys::alice::address(),
+                denomination: Some(other),
+            },
+        )
+        .unwrap()
+        .amount,
+        0
     );
 }
 
@@ -798,6 +2122,7 @@ fn test_consensus_withdraw_handler() {
         nonce: 0,
         address: keys::alice::address(),
         amount: BaseUnits::new(1, denom.clone()),
+        memo: None,
     };
     Module::<Accounts, Consensus>::message_result_withdraw(&mut ctx, me, h_ctx);
 
@@ -809,6 +2134,381 @@ fn test_consensus_withdraw_handler() {
     )
 }
 
+#[test]
+fn test_api_escrow() {
+    const DEBONDING_INTERVAL: EpochTime = 14;
+
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    let mut mock = mock::Mock::default();
+    let reclaim_epoch = mock.epoch;
+    // Each share of the active escrow pool is worth two base units.
+    set_consensus_escrow_pool(&mut mock, keys::bob::address(), 2_000_000, 1_000_000);
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+
+    Accounts::init_or_migrate(
+        &mut ctx,
+        &mut meta,
+        AccountsGenesis {
+            balances: {
+                let mut balances = BTreeMap::new();
+                // Alice.
+                balances.insert(keys::alice::address(), {
+                    let mut denominations = BTreeMap::new();
+                    denominations.insert(denom.clone(), 1_000_000);
+                    denominations
+                });
+                balances
+            },
+            total_supplies: {
+                let mut total_supplies = BTreeMap::new();
+                total_supplies.insert(denom.clone(), 1_000_000);
+                total_supplies
+            },
+            ..Default::default()
+        },
+    );
+    Module::<Accounts, Consensus>::init_or_migrate(
+        &mut ctx,
+        &mut meta,
+        Genesis {
+            parameters: Parameters {
+                debonding_interval: Some(DEBONDING_INTERVAL),
+                ..Default::default()
+            },
+        },
+    );
+
+    fn call<C: BatchContext>(
+        ctx: &mut C,
+        method: &str,
+        body: cbor::Value,
+        nonce: u64,
+    ) -> Result<Vec<(Message, MessageEventHookInvocation)>, Error> {
+        let tx = transaction::Transaction {
+            version: 1,
+            call: transaction::Call {
+                format: transaction::CallFormat::Plain,
+                method: method.to_owned(),
+                body,
+            },
+            auth_info: transaction::AuthInfo {
+                signer_info: vec![transaction::SignerInfo::new_sigspec(
+                    keys::alice::sigspec(),
+                    nonce,
+                )],
+                fee: transaction::Fee {
+                    amount: Default::default(),
+                    gas: 1000,
+                    consensus_messages: 1,
+                },
+                features: 0,
+            },
+        };
+
+        ctx.with_tx(0, tx, |mut tx_ctx, call| {
+            let result = match call.method.as_str() {
+                "consensus.AddEscrow" => Module::<Accounts, Consensus>::tx_add_escrow(
+                    &mut tx_ctx,
+                    cbor::from_value(call.body).unwrap(),
+                ),
+                _ => Module::<Accounts, Consensus>::tx_reclaim_escrow(
+                    &mut tx_ctx,
+                    cbor::from_value(call.body).unwrap(),
+                ),
+            };
+            result.map(|_| tx_ctx.commit().1)
+        })
+    }
+
+    // Escrow more than is available.
+    let result = call(
+        &mut ctx,
+        "consensus.AddEscrow",
+        cbor::to_value(AddEscrow {
+            to: keys::bob::address(),
+            amount: BaseUnits::new(2_000_000, denom.clone()),
+        }),
+        0,
+    );
+    assert!(matches!(result, Err(Error::InsufficientEscrowBalance)));
+
+    // Escrow and make sure the balance is held by the module.
+    let mut msgs = call(
+        &mut ctx,
+        "consensus.AddEscrow",
+        cbor::to_value(AddEscrow {
+            to: keys::bob::address(),
+            amount: BaseUnits::new(600_000, denom.clone()),
+        }),
+        0,
+    )
+    .expect("add escrow tx should succeed");
+    assert_eq!(1, msgs.len(), "one message should be emitted");
+    let (msg, hook) = msgs.pop().unwrap();
+    assert_eq!(
+        Message::Staking(Versioned::new(
+            0,
+            StakingMessage::AddEscrow(staking::Escrow {
+                account: keys::bob::address().into(),
+                amount: 600_000u128.into(),
+            })
+        )),
+        msg,
+        "emitted message should match"
+    );
+    assert_eq!(CONSENSUS_ESCROW_HANDLER.to_string(), hook.hook_name);
+    let balance = Accounts::get_balance(ctx.runtime_state(), *ADDRESS_ESCROW, denom.clone())
+        .expect("get_balance should succeed");
+    assert_eq!(balance, 600_000, "escrowed balance should be held");
+
+    // Nothing can be reclaimed until the escrow has been processed.
+    let reclaim = |shares| {
+        cbor::to_value(ReclaimEscrow {
+            from: keys::bob::address(),
+            shares,
+        })
+    };
+    let result = call(&mut ctx, "consensus.ReclaimEscrow", reclaim(1), 1);
+    assert!(matches!(result, Err(Error::InsufficientEscrowShares)));
+
+    Module::<Accounts, Consensus>::message_result_escrow(
+        &mut ctx,
+        Default::default(),
+        cbor::from_value(hook.payload).unwrap(),
+    );
+    let balance = Accounts::get_balance(ctx.runtime_state(), *ADDRESS_ESCROW, denom.clone())
+        .expect("get_balance should succeed");
+    assert_eq!(balance, 600_000, "escrowed balance should remain held");
+    assert_eq!(
+        Module::<Accounts, Consensus>::get_escrow(
+            &mut ctx,
+            keys::alice::address(),
+            keys::bob::address()
+        ),
+        types::AccountEscrow {
+            amount: 600_000,
+            shares: 300_000,
+            reclaimed_shares: 0,
+        },
+        "shares should be issued at the exchange rate of the active escrow pool"
+    );
+
+    // Reclaiming more shares than were escrowed should fail.
+    let result = call(&mut ctx, "consensus.ReclaimEscrow", reclaim(300_001), 1);
+    assert!(matches!(result, Err(Error::InsufficientEscrowShares)));
+
+    // Reclaimed shares should be reserved while the message is pending.
+    let (msg, hook) = call(&mut ctx, "consensus.ReclaimEscrow", reclaim(200_000), 1)
+        .expect("reclaim escrow tx should succeed")
+        .pop()
+        .unwrap();
+    assert_eq!(
+        Message::Staking(Versioned::new(
+            0,
+            StakingMessage::ReclaimEscrow(staking::ReclaimEscrow {
+                account: keys::bob::address().into(),
+                shares: 200_000u128.into(),
+            })
+        )),
+        msg,
+        "emitted message should match"
+    );
+    let result = call(&mut ctx, "consensus.ReclaimEscrow", reclaim(100_001), 2);
+    assert!(matches!(result, Err(Error::InsufficientEscrowShares)));
+
+    // Simulate the reclaim failing and make sure the shares are released.
+    let me = MessageEvent {
+        module: "staking".to_string(),
+        code: 1, // Any non-zero code is treated as an error.
+        index: 0,
+    };
+    Module::<Accounts, Consensus>::message_result_reclaim_escrow(
+        &mut ctx,
+        me,
+        cbor::from_value(hook.payload.clone()).unwrap(),
+    );
+    let escrow = Module::<Accounts, Consensus>::get_escrow(
+        &mut ctx,
+        keys::alice::address(),
+        keys::bob::address(),
+    );
+    assert_eq!(
+        escrow.reclaimed_shares, 0,
+        "reserved shares should be released"
+    );
+
+    // Reclaim again and simulate the reclaim succeeding.
+    let (_, hook) = call(&mut ctx, "consensus.ReclaimEscrow", reclaim(200_000), 3)
+        .expect("reclaim escrow tx should succeed")
+        .pop()
+        .unwrap();
+    Module::<Accounts, Consensus>::message_result_reclaim_escrow(
+        &mut ctx,
+        Default::default(),
+        cbor::from_value(hook.payload).unwrap(),
+    );
+
+    // The tokens should remain escrowed while the reclaimed shares are debonding.
+    assert_eq!(
+        Module::<Accounts, Consensus>::get_escrow(
+            &mut ctx,
+            keys::alice::address(),
+            keys::bob::address()
+        ),
+        types::AccountEscrow {
+            amount: 600_000,
+            shares: 300_000,
+            reclaimed_shares: 200_000,
+        },
+        "reclaimed shares should remain reserved while debonding"
+    );
+    assert_eq!(
+        Module::<Accounts, Consensus>::get_debonding_escrows(&mut ctx, keys::bob::address()),
+        vec![types::DebondingEscrow {
+            address: keys::alice::address(),
+            shares: 200_000,
+            amount: 400_000,
+            epoch: reclaim_epoch,
+        }],
+        "reclaimed shares should be valued at the exchange rate of the active escrow pool"
+    );
+
+    // Debonding of shares that have not been reclaimed should be rejected.
+    let result = Module::<Accounts, Consensus>::debonding_end(
+        &mut ctx,
+        types::DebondingEnd {
+            escrow: keys::bob::address(),
+            shares: 100_000,
+            amount: 200_000,
+        },
+    );
+    assert!(matches!(result, Err(Error::InvalidArgument)));
+
+    // Make sure events were emitted.
+    let (tags, _) = ctx.commit();
+    let events: Vec<_> = tags
+        .iter()
+        .filter(|tag| tag.key.starts_with(b"consensus_accounts"))
+        .collect();
+    assert_eq!(
+        events.len(),
+        3,
+        "add escrow and reclaim events should be emitted"
+    );
+    assert_eq!(events[0].key, b"consensus_accounts\x00\x00\x00\x03"); // consensus_accounts.AddEscrow (code = 3) event
+    assert_eq!(events[2].key, b"consensus_accounts\x00\x00\x00\x04"); // consensus_accounts.ReclaimEscrow (code = 4) event
+
+    // Decode reclaim escrow event.
+    #[derive(Debug, cbor::Decode)]
+    struct ReclaimEscrowEvent {
+        from: Address,
+        nonce: u64,
+        to: Address,
+        shares: u128,
+        epoch: EpochTime,
+        #[cbor(optional)]
+        error: Option<types::ConsensusError>,
+    }
+    let event: ReclaimEscrowEvent = cbor::from_slice(&events[2].value).unwrap();
+    assert_eq!(event.from, keys::bob::address());
+    assert_eq!(event.nonce, 3);
+    assert_eq!(event.to, keys::alice::address());
+    assert_eq!(event.shares, 200_000);
+    assert_eq!(
+        event.epoch, mock.epoch,
+        "event should carry the reclaim epoch"
+    );
+    assert_eq!(event.error, None);
+
+    // Nothing should be released before the debonding period ends.
+    mock.epoch = reclaim_epoch + DEBONDING_INTERVAL - 1;
+    {
+        let mut ctx = mock.create_ctx();
+        Module::<Accounts, Consensus>::end_block(&mut ctx);
+        let balance =
+            Accounts::get_balance(ctx.runtime_state(), keys::alice::address(), denom.clone())
+                .expect("get_balance should succeed");
+        assert_eq!(
+            balance, 400_000,
+            "reclaimed tokens should not be credited while debonding"
+        );
+    }
+
+    // Once debonding ends, the reclaimed shares and the escrowed tokens backing them should be
+    // released and the account credited.
+    mock.epoch = reclaim_epoch + DEBONDING_INTERVAL;
+    {
+        let mut ctx = mock.create_ctx();
+        Module::<Accounts, Consensus>::end_block(&mut ctx);
+        assert_eq!(
+            Module::<Accounts, Consensus>::get_escrow(
+                &mut ctx,
+                keys::alice::address(),
+                keys::bob::address()
+            ),
+            types::AccountEscrow {
+                amount: 200_000,
+                shares: 100_000,
+                reclaimed_shares: 0,
+            },
+            "debonded shares should no longer be escrowed"
+        );
+        assert!(
+            Module::<Accounts, Consensus>::get_debonding_escrows(&mut ctx, keys::bob::address())
+                .is_empty(),
+            "debonding should only end once"
+        );
+        let balance = Accounts::get_balance(ctx.runtime_state(), *ADDRESS_ESCROW, denom.clone())
+            .expect("get_balance should succeed");
+        assert_eq!(balance, 200_000, "debonded balance should be released");
+        let balance =
+            Accounts::get_balance(ctx.runtime_state(), keys::alice::address(), denom.clone())
+                .expect("get_balance should succeed");
+        assert_eq!(balance, 800_000, "reclaimed amount should be credited");
+    }
+
+    // Escrow accrues rewards, so the remaining shares are worth more once reclaimed.
+    set_consensus_escrow_pool(&mut mock, keys::bob::address(), 2_500_000, 1_000_000);
+    {
+        let mut ctx = mock.create_ctx();
+        let (_, hook) = call(&mut ctx, "consensus.ReclaimEscrow", reclaim(100_000), 4)
+            .expect("reclaim escrow tx should succeed")
+            .pop()
+            .unwrap();
+        Module::<Accounts, Consensus>::message_result_reclaim_escrow(
+            &mut ctx,
+            Default::default(),
+            cbor::from_value(hook.payload).unwrap(),
+        );
+    }
+
+    // The rewards should be credited together with the escrowed tokens.
+    mock.epoch = reclaim_epoch + 2 * DEBONDING_INTERVAL;
+    let mut ctx = mock.create_ctx();
+    Module::<Accounts, Consensus>::end_block(&mut ctx);
+    assert_eq!(
+        Module::<Accounts, Consensus>::get_escrow(
+            &mut ctx,
+            keys::alice::address(),
+            keys::bob::address()
+        ),
+        Default::default(),
+        "all escrow should be released"
+    );
+    let balance = Accounts::get_balance(ctx.runtime_state(), *ADDRESS_ESCROW, denom.clone())
+        .expect("get_balance should succeed");
+    assert_eq!(balance, 0, "debonded balance should be released");
+    let balance = Accounts::get_balance(ctx.runtime_state(), keys::alice::address(), denom.clone())
+        .expect("get_balance should succeed");
+    assert_eq!(balance, 1_050_000, "rewards should be credited");
+    let ts = Accounts::get_total_supplies(ctx.runtime_state())
+        .expect("get_total_supplies should succeed");
+    assert_eq!(ts[&denom], 1_050_000, "rewards should be minted");
+}
+
 #[test]
 fn test_prefetch() {
     let mut mock = mock::Mock::default();
@@ -824,6 +2524,7 @@ fn test_prefetch() {
             gas: 1000,
             consensus_messages: 1,
         },
+        features: 0,
     };
 
     // Test withdraw.
@@ -836,7 +2537,9 @@ fn test_prefetch() {
                 // It's probably more common to withdraw into your own account, but we're using a
                 // separate `to` account to make sure everything is hooked up to the right places.
                 to: Some(keys::bob::address()),
+                to_spec: Some(keys::bob::sigspec()),
                 amount: BaseUnits::new(1_000, Denomination::NATIVE),
+                memo: None,
             }),
         },
         auth_info: auth_info.clone(),
@@ -868,6 +2571,7 @@ fn test_prefetch() {
                 // separate `to` account to make sure everything is hooked up to the right places.
                 to: Some(keys::bob::address()),
                 amount: BaseUnits::new(1_000, Denomination::NATIVE),
+                memo: None,
             }),
         },
         auth_info: auth_info.clone(),
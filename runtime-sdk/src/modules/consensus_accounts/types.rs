@@ -1,5 +1,11 @@
 //! Consensus module types.
-use crate::types::{address::Address, message::MessageEvent, token};
+use oasis_core_runtime::consensus::beacon::EpochTime;
+
+use crate::types::{
+    address::{Address, SignatureAddressSpec},
+    message::MessageEvent,
+    token,
+};
 
 /// Deposit into runtime call.
 /// Transfer from consensus staking to an account in this runtime.
@@ -10,8 +16,17 @@ pub struct Deposit {
     #[cbor(optional)]
     pub to: Option<Address>,
     pub amount: token::BaseUnits,
+    /// Opaque reference included in the emitted events (e.g., for reconciliation).
+    #[cbor(optional)]
+    pub memo: Option<Vec<u8>>,
 }
 
+/// Batch deposit into runtime call.
+/// Performs each deposit in order, as if each was a separate deposit transaction.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+#[cbor(transparent)]
+pub struct DepositBatch(pub Vec<Deposit>);
+
 /// Withdraw from runtime call.
 /// Transfer from an account in this runtime to consensus staking.
 /// The `to` address consensus staking account gets the tokens.
@@ -19,13 +34,42 @@ pub struct Deposit {
 pub struct Withdraw {
     #[cbor(optional)]
     pub to: Option<Address>,
+    /// Address specification of the `to` address, required when withdrawing to an account other
+    /// than the sender's. It must be an Ed25519 key, as only those can sign on the consensus layer.
+    #[cbor(optional)]
+    pub to_spec: Option<SignatureAddressSpec>,
     pub amount: token::BaseUnits,
+    /// Opaque reference included in the emitted events (e.g., for reconciliation).
+    #[cbor(optional)]
+    pub memo: Option<Vec<u8>>,
+}
+
+/// Add escrow call.
+/// Escrow tokens from an account in this runtime to a consensus staking account.
+/// The `to` address consensus staking account receives the escrow.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct AddEscrow {
+    pub to: Address,
+    pub amount: token::BaseUnits,
+}
+
+/// Reclaim escrow call.
+/// Reclaim shares previously escrowed through this runtime from a consensus staking account.
+/// The reclaimed tokens are subject to the consensus layer debonding period and are credited to
+/// the caller once it ends.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct ReclaimEscrow {
+    pub from: Address,
+    pub shares: u128,
 }
 
 /// Balance query.
 #[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
 pub struct BalanceQuery {
     pub address: Address,
+    /// Denomination of the balance. In case this is not set, the consensus denomination is used.
+    #[cbor(optional)]
+    pub denomination: Option<token::Denomination>,
 }
 
 /// Consensus account query.
@@ -39,6 +83,42 @@ pub struct AccountBalance {
     pub balance: u128,
 }
 
+/// Pending withdrawal query.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct PendingWithdrawalQuery {
+    pub address: Address,
+    /// Denomination of the withdrawals. In case this is not set, the consensus denomination is
+    /// used.
+    #[cbor(optional)]
+    pub denomination: Option<token::Denomination>,
+}
+
+/// Deposited amount query.
+///
+/// The result is the cumulative amount of successful deposits into the account. Withdrawals do
+/// not reduce it.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct DepositedQuery {
+    pub address: Address,
+    /// Denomination of the deposits. In case this is not set, the consensus denomination is used.
+    #[cbor(optional)]
+    pub denomination: Option<token::Denomination>,
+}
+
+/// Total deposited amount query.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct TotalDepositedQuery {
+    /// Denomination of the deposits. In case this is not set, the consensus denomination is used.
+    #[cbor(optional)]
+    pub denomination: Option<token::Denomination>,
+}
+
+/// Amount withdrawn from an account that is still pending in the consensus layer.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct PendingWithdrawal {
+    pub amount: u128,
+}
+
 /// Context for consensus transfer message handler.
 #[derive(Clone, Debug, cbor::Encode, cbor::Decode, Default)]
 pub struct ConsensusTransferContext {
@@ -48,6 +128,8 @@ pub struct ConsensusTransferContext {
     #[cbor(optional, default)]
     pub to: Address,
     pub amount: token::BaseUnits,
+    #[cbor(optional)]
+    pub memo: Option<Vec<u8>>,
 }
 
 /// Context for consensus withdraw message handler.
@@ -59,6 +141,65 @@ pub struct ConsensusWithdrawContext {
     pub nonce: u64,
     pub address: Address,
     pub amount: token::BaseUnits,
+    #[cbor(optional)]
+    pub memo: Option<Vec<u8>>,
+}
+
+/// Escrow made by an account in this runtime to a consensus staking account.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct AccountEscrow {
+    /// Amount of tokens escrowed (in runtime base units).
+    pub amount: u128,
+    /// Number of shares of the staking account's active escrow pool held for the escrowed tokens.
+    #[cbor(optional, default)]
+    pub shares: u128,
+    /// Number of shares that have been (or are being) reclaimed.
+    #[cbor(optional, default)]
+    pub reclaimed_shares: u128,
+}
+
+/// Escrow reclaimed by an account in this runtime that is debonding in the consensus layer.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct DebondingEscrow {
+    /// Account that has reclaimed the escrow.
+    pub address: Address,
+    /// Number of reclaimed shares.
+    pub shares: u128,
+    /// Amount of tokens (in consensus base units) that the reclaimed shares were worth at the
+    /// exchange rate of the staking account's active escrow pool.
+    #[cbor(optional, default)]
+    pub amount: u128,
+    /// Epoch in which the reclaim has been processed.
+    pub epoch: EpochTime,
+}
+
+/// End of the debonding period of escrow reclaimed through this runtime.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct DebondingEnd {
+    /// Consensus staking account from which the escrow has been reclaimed.
+    pub escrow: Address,
+    /// Number of debonded shares.
+    pub shares: u128,
+    /// Amount of tokens (in consensus base units) returned to the runtime account.
+    pub amount: u128,
+}
+
+/// Context for consensus add escrow message handler.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode, Default)]
+pub struct ConsensusEscrowContext {
+    pub address: Address,
+    pub nonce: u64,
+    pub to: Address,
+    pub amount: token::BaseUnits,
+}
+
+/// Context for consensus reclaim escrow message handler.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode, Default)]
+pub struct ConsensusReclaimEscrowContext {
+    pub from: Address,
+    pub nonce: u64,
+    pub address: Address,
+    pub shares: u128,
 }
 
 /// Error details from the consensus layer.
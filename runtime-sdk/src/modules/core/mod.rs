@@ -1,5 +1,8 @@
 //! Core definitions module.
-use std::{collections::BTreeMap, convert::TryInto};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryInto,
+};
 
 use anyhow::anyhow;
 use thiserror::Error;
@@ -9,9 +12,14 @@ pub use oasis_core_keymanager_api_common::KeyManagerError;
 use crate::{
     callformat,
     context::{BatchContext, Context, TxContext},
+    core::common::crypto::hash::Hash,
     dispatcher, error,
-    module::{self, InvariantHandler as _, Module as _},
+    module::{self, AuthHandler as _, InvariantHandler as _, MethodHandler as _, Module as _},
+    modules::accounts::{self, Module as Accounts, API as _},
+    storage::{self, Store as _},
+    trace,
     types::{
+        address::Address,
         token,
         transaction::{
             self, AddressSpec, AuthProof, Call, CallFormat, TransactionWeight,
@@ -110,6 +118,103 @@ pub enum Error {
     #[error("gas price too low")]
     #[sdk_error(code = 20)]
     GasPriceTooLow,
+
+    #[error("fee required")]
+    #[sdk_error(code = 21)]
+    FeeRequired,
+
+    #[error("too many events")]
+    #[sdk_error(code = 22)]
+    TooManyEvents,
+
+    #[error("transfer limit exceeded (limit: {0} wanted: {1})")]
+    #[sdk_error(code = 23)]
+    TransferLimitExceeded(u128, u128),
+
+    #[error("too many scheduled calls")]
+    #[sdk_error(code = 24)]
+    TooManyScheduledCalls,
+
+    #[error("transaction gas limit too high (max: {0} wanted: {1})")]
+    #[sdk_error(code = 25)]
+    TxGasLimitTooHigh(u64, u64),
+
+    #[error("round {0} has been pruned")]
+    #[sdk_error(code = 26)]
+    RoundPruned(u64),
+
+    #[error("unsupported transaction features: {0:#x}")]
+    #[sdk_error(code = 27)]
+    UnsupportedFeature(u64),
+
+    #[error("forbidden")]
+    #[sdk_error(code = 28)]
+    Forbidden,
+
+    #[error("method not allowed in safe mode: {0}")]
+    #[sdk_error(code = 29)]
+    SafeMode(String),
+
+    #[error("method requires an encrypted call: {0}")]
+    #[sdk_error(code = 30)]
+    EncryptionRequired(String),
+
+    #[error("transaction fee too low (min: {0} wanted: {1})")]
+    #[sdk_error(code = 31)]
+    TxFeeTooLow(u128, u128),
+
+    #[error("transaction fee too high (max: {0} wanted: {1})")]
+    #[sdk_error(code = 32)]
+    TxFeeTooHigh(u128, u128),
+
+    #[error("atomic group transaction {0} failed: {1}")]
+    #[sdk_error(code = 33)]
+    AtomicGroupFailed(usize, String),
+
+    #[error("key manager unavailable")]
+    #[sdk_error(code = 34)]
+    KeyManagerUnavailable,
+
+    #[error("too many prefetch prefixes (max: {0} wanted: {1})")]
+    #[sdk_error(code = 35)]
+    TooManyPrefetchPrefixes(u16, usize),
+
+    #[error("value too large (max: {0} wanted: {1})")]
+    #[sdk_error(code = 36)]
+    ValueTooLarge(u32, usize),
+
+    #[error("parameters of modules {0:?} have not been initialized")]
+    #[sdk_error(code = 37)]
+    UninitializedParameters(Vec<&'static str>),
+}
+
+/// Events emitted by the core module.
+#[derive(Debug, cbor::Encode, oasis_runtime_sdk_macros::Event)]
+#[cbor(untagged)]
+pub enum Event {
+    /// Root over the events emitted by a transaction (see the `receipt` module).
+    #[sdk_event(code = 1)]
+    EventsRoot { root: Hash },
+
+    /// Root over the events roots of all transactions in a block (see the `receipt` module).
+    #[sdk_event(code = 2)]
+    BlockEventsRoot { root: Hash },
+
+    /// Summary of the consensus messages emitted in a block.
+    #[sdk_event(code = 3)]
+    MessagesEmitted { count: u32, handlers: Vec<String> },
+
+    /// Result of a message emitted in the previous round for which no handler could be found.
+    #[sdk_event(code = 4)]
+    DanglingMessage {
+        index: u32,
+        hook_name: Option<String>,
+    },
+
+    /// Amount of gas used by an executed transaction, emitted regardless of whether the
+    /// transaction succeeded in case receipt tags are enabled.
+    #[sdk_event(code = 5)]
+    GasUsed { amount: u64 },
 }
 
 /// Gas costs.
@@ -123,6 +228,38 @@ pub struct GasCosts {
     pub callformat_x25519_deoxysii: u64,
 }
 
+/// Policy for consuming signer nonces of transactions that fail during execution.
+///
+/// Transactions that fail authentication (e.g. due to an invalid nonce or insufficient fee
+/// balance) never consume nonces. This only affects transactions that were successfully
+/// authenticated, but whose call then failed (e.g. ran out of gas).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+#[repr(u8)]
+pub enum NonceConsumption {
+    /// Nonces are consumed as soon as the transaction is authenticated, regardless of whether
+    /// the call succeeds.
+    ///
+    /// Once included, the transaction can never be replayed, so wallets must always use the next
+    /// nonce even after a failure.
+    Always = 0,
+    /// Nonces of transactions that do not pay any fee are only consumed when the call succeeds,
+    /// so wallets may resubmit such a failed transaction using the same nonce. Transactions
+    /// paying a fee always consume nonces.
+    ///
+    /// Since the nonce is not consumed, a failed free transaction remains valid and can be
+    /// included again by anyone until a transaction from the same signer succeeds. Replays can
+    /// not charge the signer anything, but may still succeed in case the state has changed in
+    /// the meantime. Nonces of transactions paying a fee are never left unconsumed, as anyone
+    /// could otherwise replay them to charge the fee again.
+    OnSuccessIfFree = 1,
+}
+
+impl Default for NonceConsumption {
+    fn default() -> Self {
+        Self::Always
+    }
+}
+
 /// Parameters for the core module.
 #[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
 pub struct Parameters {
@@ -131,6 +268,101 @@ pub struct Parameters {
     pub max_multisig_signers: u32,
     pub gas_costs: GasCosts,
     pub min_gas_price: BTreeMap<token::Denomination, u128>,
+
+    /// Methods that may be called by transactions that do not pay any fees. In case this is not
+    /// set, zero-fee transactions are not restricted. An empty set forbids them entirely.
+    #[cbor(optional)]
+    pub allow_zero_fee_methods: Option<BTreeSet<String>>,
+
+    /// Maximum number of events that can be emitted by a single transaction. In case this is not
+    /// set, the number of events is not limited.
+    #[cbor(optional)]
+    pub max_tx_events: Option<u32>,
+
+    /// Maximum amount of each denomination that a single account can transfer via
+    /// `accounts.Transfer` within a single block, summed over all of its transfers. Denominations
+    /// that are not listed are not limited.
+    #[cbor(optional, default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub block_transfer_limits: BTreeMap<token::Denomination, u128>,
+
+    /// Maximum amount of each denomination that can be transferred by a single
+    /// `accounts.Transfer` call. Denominations that are not listed are not limited.
+    #[cbor(optional, default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub transfer_limits: BTreeMap<token::Denomination, u128>,
+
+    /// Maximum number of calls that can be scheduled for execution in a single round. Zero
+    /// disables scheduling of calls.
+    #[cbor(optional, default)]
+    pub max_scheduled_calls_per_round: u32,
+
+    /// Interval (in rounds) at which invariants of all modules are checked at the end of a block.
+    /// Zero means that invariants are only checked on demand via the `core.CheckInvariants` query.
+    #[cbor(optional, default)]
+    pub invariant_check_interval: u64,
+
+    /// Maximum amount of gas that a single transaction can use, independent of the batch gas
+    /// limit. Transactions declaring a higher gas limit are rejected. In case this is not set,
+    /// transactions are only limited by the batch gas limit.
+    #[cbor(optional)]
+    pub max_tx_gas: Option<u64>,
+
+    /// Address of the account allowed to manage runtime-level metadata entries. In case this is
+    /// not set, metadata can not be modified.
+    #[cbor(optional)]
+    pub admin: Option<Address>,
+
+    /// Whether nonces of transactions that fail during execution are consumed.
+    #[cbor(optional, default)]
+    pub nonce_consumption: NonceConsumption,
+
+    /// Methods that may still be called while the runtime is in safe mode. Toggling safe mode
+    /// via `core.SetSafeMode` is always allowed.
+    #[cbor(optional, default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub safe_mode_allowed_methods: BTreeSet<String>,
+
+    /// Call formats required by specific methods. Calls to these methods using any other call
+    /// format (e.g., plain text calls to methods that require encryption) are rejected.
+    #[cbor(optional, default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub required_call_formats: BTreeMap<String, CallFormat>,
+
+    /// Minimum total fee amount of each denomination that a transaction must pay, independent of
+    /// the gas price. Denominations that are not listed are not limited. Zero-fee calls to methods
+    /// listed in `allow_zero_fee_methods` are exempt.
+    #[cbor(optional, default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub min_tx_fee: BTreeMap<token::Denomination, u128>,
+
+    /// Maximum total fee amount of each denomination that a transaction may pay. Denominations
+    /// that are not listed are not limited.
+    #[cbor(optional, default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub max_tx_fee: BTreeMap<token::Denomination, u128>,
+
+    /// Whether the fee paid for gas that a successful transaction did not use is refunded to the
+    /// fee payer, in the denomination that the fee was paid in.
+    #[cbor(optional, default)]
+    pub refund_unused_gas: bool,
+
+    /// Denomination that is automatically converted to pay for transaction fees when the fee
+    /// payer's balance in the fee denomination is insufficient. The conversion rate is taken from
+    /// the conversions supported by the accounts module.
+    #[cbor(optional)]
+    pub fee_fallback_denomination: Option<token::Denomination>,
+
+    /// Maximum size (in bytes) of an encoded transaction. Larger transactions are rejected before
+    /// being decoded. In case this is not set, transaction size is not limited.
+    #[cbor(optional)]
+    pub max_tx_size: Option<u32>,
+
+    /// Names of the modules allowed to mint each of the listed denominations. Denominations that
+    /// are not listed may be minted by any module. This can be used to make sure that only the
+    /// consensus accounts module mints the consensus denomination, as otherwise its invariants
+    /// could be violated.
+    #[cbor(optional, default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mint_allowlist: BTreeMap<token::Denomination, BTreeSet<String>>,
+
+    /// Maximum size (in bytes) of a single value stored by modules that enforce it via
+    /// `Module::ensure_value_size`. In case this is not set, value size is not limited.
+    #[cbor(optional)]
+    pub max_value_size: Option<u32>,
 }
 
 impl module::Parameters for Parameters {
@@ -148,6 +380,11 @@ pub trait API {
     /// increased.
     fn use_tx_gas<C: TxContext>(ctx: &mut C, gas: u64) -> Result<(), Error>;
 
+    /// Attempt to use gas for work performed by a query. Pure read-only queries are not metered so
+    /// this is a no-op unless the query runs in a simulation context (e.g., when estimating gas),
+    /// in which case batch gas is used.
+    fn use_query_gas<C: Context>(ctx: &mut C, gas: u64) -> Result<(), Error>;
+
     /// Returns the remaining batch-wide gas.
     fn remaining_batch_gas<C: Context>(ctx: &mut C) -> u64;
 
@@ -183,13 +420,67 @@ pub mod state {
     pub const METADATA: &[u8] = &[0x01];
     /// Map of message idx to message handlers for messages emitted in previous round.
     pub const MESSAGE_HANDLERS: &[u8] = &[0x02];
+    /// Ring buffer of recent block timestamps.
+    pub const RECENT_TIMESTAMPS: &[u8] = &[0x03];
+    /// Map of addresses to amounts they transferred in the current round.
+    pub const TRANSFER_TOTALS: &[u8] = &[0x04];
+    /// Map of round to calls scheduled for execution at the start of that round.
+    pub const SCHEDULED_CALLS: &[u8] = &[0x05];
+    /// Map of transaction hashes to results of transactions executed in replay mode.
+    pub const REPLAYED_TXS: &[u8] = &[0x06];
+    /// History of minimum gas price changes.
+    pub const MIN_GAS_PRICE_HISTORY: &[u8] = &[0x07];
+    /// Map of runtime-level metadata entries.
+    pub const RUNTIME_METADATA: &[u8] = &[0x08];
+    /// Whether the runtime is in safe mode.
+    pub const SAFE_MODE: &[u8] = &[0x09];
+    /// Set of names of modules that have been disabled.
+    pub const DISABLED_MODULES: &[u8] = &[0x0a];
 }
 
+/// Number of recent block timestamps that the smoothed time is computed from.
+pub const SMOOTHED_TIME_WINDOW: usize = 11;
+
+/// Number of minimum gas price changes retained for historic queries.
+pub const MIN_GAS_PRICE_HISTORY_SIZE: usize = 128;
+
+/// Maximum number of queries that can be performed in a single batch query.
+pub const MAX_BATCH_QUERY_SIZE: usize = 16;
+
+/// Name of the batch query method.
+const METHOD_BATCH_QUERY: &str = "core.BatchQuery";
+
+/// Queries that can not be performed as part of a batch query to keep the amount of work done by
+/// a single batch bounded, e.g. because they execute transactions in simulation.
+const BATCH_QUERY_DISALLOWED_METHODS: &[&str] = &[
+    METHOD_BATCH_QUERY,
+    "core.EstimateGas",
+    "core.EstimateGasEncoded",
+    "core.Trace",
+    "core.CheckInvariants",
+];
+
+/// Name of the method toggling safe mode.
+const METHOD_SET_SAFE_MODE: &str = "core.SetSafeMode";
+
+/// Maximum number of transactions that can be executed in a single atomic group.
+pub const MAX_ATOMIC_GROUP_SIZE: usize = 16;
+
+/// Maximum number of scheduled calls that are executed at the start of a single block.
+pub const MAX_SCHEDULED_CALLS_PER_BLOCK: usize = 16;
+
 pub struct Module;
 
 const CONTEXT_KEY_GAS_USED: &str = "core.GasUsed";
+const CONTEXT_KEY_GAS_RESERVED: &str = "core.GasReserved";
 const CONTEXT_KEY_PRIORITY: &str = "core.Priority";
 const CONTEXT_KEY_WEIGHTS: &str = "core.Weights";
+const CONTEXT_KEY_TX_HASH: &str = "core.TxHash";
+const CONTEXT_KEY_ATOMIC_GROUP: &str = "core.AtomicGroup";
+const CONTEXT_KEY_SCHEDULED_CALL: &str = "core.ScheduledCall";
+
+/// Domain separation context for hashes of executed scheduled calls.
+const SCHEDULED_CALL_HASH_CONTEXT: &[u8] = b"oasis-runtime-sdk/core: scheduled call";
 
 const GAS_WEIGHT_NAME: &str = "gas";
 
@@ -205,6 +496,223 @@ impl Module {
         // No migrations currently supported.
         false
     }
+
+    /// Gas limit of the current transaction, capped by the per-transaction gas limit.
+    fn tx_gas_limit<C: TxContext>(ctx: &mut C) -> u64 {
+        let gas_limit = ctx.tx_auth_info().fee.gas;
+        match Self::params(ctx.runtime_state()).max_tx_gas {
+            Some(max_tx_gas) => gas_limit.min(max_tx_gas),
+            None => gas_limit,
+        }
+    }
+
+    /// Median of the recent block timestamps or `None` if no timestamps have been recorded yet.
+    pub fn smoothed_time<S: storage::Store>(state: S) -> Option<u64> {
+        let store = storage::TypedStore::new(storage::PrefixStore::new(state, &MODULE_NAME));
+        let recent: types::RecentTimestamps =
+            store.get(state::RECENT_TIMESTAMPS).unwrap_or_default();
+        recent.median()
+    }
+
+    /// Checks whether the given amount may be transferred by the given account according to the
+    /// configured transfer limits and records the transfer towards its per-block limits.
+    ///
+    /// Only transfers initiated by users are limited, transfers performed internally by modules
+    /// are not subject to the limits.
+    pub fn check_transfer_limits<C: Context>(
+        ctx: &mut C,
+        from: Address,
+        amount: &token::BaseUnits,
+    ) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        if let Some(limit) = params.transfer_limits.get(amount.denomination()) {
+            if amount.amount() > *limit {
+                return Err(Error::TransferLimitExceeded(*limit, amount.amount()));
+            }
+        }
+
+        let limit = match params.block_transfer_limits.get(amount.denomination()) {
+            Some(limit) => *limit,
+            None => return Ok(()),
+        };
+
+        let round = ctx.runtime_header().round.saturating_add(1);
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut totals_store =
+            storage::TypedStore::new(storage::PrefixStore::new(store, &state::TRANSFER_TOTALS));
+        let mut totals: types::TransferTotals = totals_store.get(from).unwrap_or_default();
+        if totals.round != round {
+            // Totals of previous blocks are no longer relevant.
+            totals = types::TransferTotals {
+                round,
+                ..Default::default()
+            };
+        }
+
+        let total = totals
+            .amounts
+            .entry(amount.denomination().clone())
+            .or_default();
+        let wanted = total.saturating_add(amount.amount());
+        if wanted > limit {
+            return Err(Error::TransferLimitExceeded(limit, wanted));
+        }
+        *total = wanted;
+        totals_store.insert(from, totals);
+
+        Ok(())
+    }
+
+    /// Amount of gas used by the current transaction so far.
+    pub fn used_tx_gas<C: TxContext>(ctx: &mut C) -> u64 {
+        *ctx.tx_value::<u64>(CONTEXT_KEY_GAS_USED).or_default()
+    }
+
+    /// Amount of gas reserved by the current transaction that has not been used yet.
+    pub fn reserved_tx_gas<C: TxContext>(ctx: &mut C) -> u64 {
+        *ctx.tx_value::<u64>(CONTEXT_KEY_GAS_RESERVED).or_default()
+    }
+
+    /// Reserve gas for a cleanup phase (e.g., refunds or event emission) that must be able to
+    /// complete even when the main logic exhausts the rest of the gas.
+    ///
+    /// Reserved gas can only be used via `use_reserved_gas`. It cannot be used via `use_tx_gas`
+    /// and is not included in `remaining_tx_gas`. The reservation is accounted against the batch
+    /// gas limit right away. Reserved gas that remains unused is not accounted as used by the
+    /// transaction.
+    pub fn reserve_gas<C: TxContext>(ctx: &mut C, amount: u64) -> Result<(), Error> {
+        let gas_limit = Self::tx_gas_limit(ctx);
+        let reserved = Self::reserved_tx_gas(ctx);
+        let total = Self::used_tx_gas(ctx)
+            .checked_add(reserved)
+            .and_then(|total| total.checked_add(amount))
+            .ok_or(Error::OutOfGas(gas_limit, u64::MAX))?;
+        if total > gas_limit {
+            return Err(Error::OutOfGas(gas_limit, total));
+        }
+
+        Self::use_batch_gas(ctx, amount)?;
+
+        ctx.tx_value::<u64>(CONTEXT_KEY_GAS_RESERVED)
+            .set(reserved + amount);
+
+        Ok(())
+    }
+
+    /// Use gas previously reserved via `reserve_gas`. Fails with `Error::OutOfGas` in case more
+    /// gas than remains reserved is requested.
+    pub fn use_reserved_gas<C: TxContext>(ctx: &mut C, gas: u64) -> Result<(), Error> {
+        let reserved = Self::reserved_tx_gas(ctx);
+        if gas > reserved {
+            return Err(Error::OutOfGas(reserved, gas));
+        }
+
+        // The reservation has already been accounted against the batch gas limit and the used
+        // and reserved gas together never exceed the transaction gas limit.
+        ctx.tx_value::<u64>(CONTEXT_KEY_GAS_RESERVED)
+            .set(reserved - gas);
+        *ctx.tx_value::<u64>(CONTEXT_KEY_GAS_USED).or_default() += gas;
+
+        Self::add_weight(ctx, GAS_WEIGHT_NAME.into(), gas)?;
+
+        if let Some(tracer) = ctx.tracer() {
+            tracer.record(trace::Operation::UseGas { amount: gas });
+        }
+
+        Ok(())
+    }
+
+    /// Hash of the enclosing transaction.
+    ///
+    /// In case the hash has not been set (e.g., during simulation), the default hash is returned.
+    pub fn tx_hash<C: TxContext>(ctx: &mut C) -> Hash {
+        ctx.tx_value::<Hash>(CONTEXT_KEY_TX_HASH)
+            .get()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sets the hash of the enclosing transaction.
+    ///
+    /// This is called by the dispatcher and should also be called by modules that dispatch
+    /// internal transactions on behalf of an enclosing transaction.
+    pub fn set_tx_hash<C: TxContext>(ctx: &mut C, tx_hash: Hash) {
+        ctx.tx_value::<Hash>(CONTEXT_KEY_TX_HASH).set(tx_hash);
+    }
+
+    /// Names of the modules that have been disabled via `core.SetModuleEnabled`.
+    pub fn disabled_modules<C: Context>(ctx: &mut C) -> BTreeSet<String> {
+        let store =
+            storage::TypedStore::new(storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME));
+        store.get(state::DISABLED_MODULES).unwrap_or_default()
+    }
+
+    /// Ensures that module `M` is allowed to mint the given denomination according to the mint
+    /// allowlist.
+    pub fn ensure_mint_allowed<M: module::Module, C: Context>(
+        ctx: &mut C,
+        denomination: &token::Denomination,
+    ) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        match params.mint_allowlist.get(denomination) {
+            Some(allowed) if !allowed.contains(M::NAME) => Err(Error::Forbidden),
+            _ => Ok(()),
+        }
+    }
+
+    /// Ensures that a value of the given size (in bytes) may be stored according to the maximum
+    /// value size. Modules should call this before inserting values whose size is controlled by
+    /// callers in order to bound state growth.
+    pub fn ensure_value_size<C: Context>(ctx: &mut C, size: usize) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        match params.max_value_size {
+            Some(max) if size > max as usize => Err(Error::ValueTooLarge(max, size)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Take the amount of gas used by the batch so far, resetting it to zero.
+    pub(crate) fn take_used_batch_gas<C: Context>(ctx: &mut C) -> u64 {
+        ctx.value::<u64>(CONTEXT_KEY_GAS_USED)
+            .take()
+            .unwrap_or_default()
+    }
+
+    /// Set the amount of gas used by the batch so far.
+    pub(crate) fn set_used_batch_gas<C: Context>(ctx: &mut C, gas: u64) {
+        ctx.value::<u64>(CONTEXT_KEY_GAS_USED).set(gas);
+    }
+
+    /// Refunds the fee paid for the gas that the current transaction did not use to the fee
+    /// payer, in case refunds are enabled. The refund is the unused gas times the gas price and
+    /// is made in the denomination that the fee was paid in.
+    ///
+    /// This is called by the dispatcher after a transaction has been successfully executed.
+    pub fn refund_unused_gas<C: TxContext>(ctx: &mut C) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        if !params.refund_unused_gas || ctx.is_check_only() {
+            return Ok(());
+        }
+
+        let fee = ctx.tx_auth_info().fee.clone();
+        let gas_used = Self::used_tx_gas(ctx);
+        let unused = fee.gas.saturating_sub(gas_used);
+        let refund = fee
+            .gas_price()
+            .checked_mul(unused.into())
+            .ok_or(Error::GasOverflow)?
+            .min(fee.amount.amount());
+        if refund == 0 {
+            return Ok(());
+        }
+
+        let payer = ctx.tx_auth_info().signer_info[0].address_spec.address();
+        Accounts::move_from_fee_accumulator(
+            ctx,
+            payer,
+            &token::BaseUnits::new(refund, fee.amount.denomination().clone()),
+        )
+    }
 }
 
 impl API for Module {
@@ -215,9 +723,11 @@ impl API for Module {
         }
         let batch_gas_limit = Self::params(ctx.runtime_state()).max_batch_gas;
         let batch_gas_used = ctx.value::<u64>(CONTEXT_KEY_GAS_USED).or_default();
+        // Overflowing the accumulator could never fit into the limit, so treat it as running out
+        // of gas instead of wrapping around.
         let batch_new_gas_used = batch_gas_used
             .checked_add(gas)
-            .ok_or(Error::BatchGasOverflow)?;
+            .ok_or(Error::BatchOutOfGas(batch_gas_limit, u64::MAX))?;
         if batch_new_gas_used > batch_gas_limit {
             return Err(Error::BatchOutOfGas(batch_gas_limit, batch_new_gas_used));
         }
@@ -229,12 +739,18 @@ impl API for Module {
     }
 
     fn use_tx_gas<C: TxContext>(ctx: &mut C, gas: u64) -> Result<(), Error> {
-        let gas_limit = ctx.tx_auth_info().fee.gas;
+        let gas_limit = Self::tx_gas_limit(ctx);
+        let reserved = Self::reserved_tx_gas(ctx);
         let gas_used = ctx.tx_value::<u64>(CONTEXT_KEY_GAS_USED).or_default();
         let new_gas_used = {
-            let sum = gas_used.checked_add(gas).ok_or(Error::GasOverflow)?;
-            if sum > gas_limit {
-                return Err(Error::OutOfGas(gas_limit, sum));
+            // Overflowing the accumulator could never fit into the limit, so treat it as running
+            // out of gas instead of wrapping around.
+            let sum = gas_used
+                .checked_add(gas)
+                .ok_or(Error::OutOfGas(gas_limit, u64::MAX))?;
+            // Reserved gas is not available here.
+            if sum > gas_limit.saturating_sub(reserved) {
+                return Err(Error::OutOfGas(gas_limit, sum.saturating_add(reserved)));
             }
             sum
         };
@@ -245,6 +761,24 @@ impl API for Module {
 
         Self::add_weight(ctx, GAS_WEIGHT_NAME.into(), gas)?;
 
+        if let Some(tracer) = ctx.tracer() {
+            tracer.record(trace::Operation::UseGas { amount: gas });
+        }
+
+        Ok(())
+    }
+
+    fn use_query_gas<C: Context>(ctx: &mut C, gas: u64) -> Result<(), Error> {
+        if !ctx.is_simulation() {
+            return Ok(());
+        }
+
+        Self::use_batch_gas(ctx, gas)?;
+
+        if let Some(tracer) = ctx.tracer() {
+            tracer.record(trace::Operation::UseGas { amount: gas });
+        }
+
         Ok(())
     }
 
@@ -255,9 +789,10 @@ impl API for Module {
     }
 
     fn remaining_tx_gas<C: TxContext>(ctx: &mut C) -> u64 {
-        let gas_limit = ctx.tx_auth_info().fee.gas;
+        let gas_limit = Self::tx_gas_limit(ctx);
+        let reserved = Self::reserved_tx_gas(ctx);
         let gas_used = ctx.tx_value::<u64>(CONTEXT_KEY_GAS_USED).or_default();
-        let remaining_tx = gas_limit.saturating_sub(*gas_used);
+        let remaining_tx = gas_limit.saturating_sub(*gas_used).saturating_sub(reserved);
         // Also check remaining batch gas limit and return the minimum of the two.
         let remaining_batch = Self::remaining_batch_gas(ctx);
         std::cmp::min(remaining_tx, remaining_batch)
@@ -310,11 +845,7 @@ impl Module {
         ctx: &mut C,
         mut args: types::EstimateGasQuery,
     ) -> Result<u64, Error> {
-        // Assume maximum amount of gas in a batch, a reasonable maximum fee and maximum amount of consensus messages.
-        args.tx.auth_info.fee.gas = Self::params(ctx.runtime_state()).max_batch_gas;
-        args.tx.auth_info.fee.amount =
-            token::BaseUnits::new(u64::MAX.into(), token::Denomination::NATIVE);
-        args.tx.auth_info.fee.consensus_messages = ctx.remaining_messages();
+        Self::set_estimation_fee(ctx, &mut args.tx);
         // Estimate transaction size. Since the transaction given to us is not signed, we need to
         // estimate how large each of the auth proofs would be.
         let auth_proofs: Result<_, Error> = args
@@ -370,17 +901,516 @@ impl Module {
             extra_gas += params.gas_costs.auth_signature;
         }
 
+        Self::simulate_gas_used(ctx, tx_size, args.tx, args.balance_overrides)
+            .map(|gas_used| gas_used + extra_gas)
+    }
+
+    /// Run a transaction encoded using a module-controlled scheme in simulation and return how
+    /// much gas it uses. The transaction is decoded the same way as when it is submitted, so it
+    /// must carry any authentication required by the scheme. Like with `query_estimate_gas`, the
+    /// estimate is returned even in case the transaction fails.
+    fn query_estimate_gas_encoded<C: Context>(
+        ctx: &mut C,
+        args: types::EstimateGasEncodedQuery,
+    ) -> Result<u64, Error> {
+        let mut tx = <C::Runtime as Runtime>::Modules::decode_tx(ctx, &args.scheme, &args.body)?
+            .ok_or_else(|| {
+                Error::InvalidArgument(anyhow!(
+                    "transaction decoding scheme {} not supported",
+                    args.scheme
+                ))
+            })?;
+        Self::set_estimation_fee(ctx, &mut tx);
+
+        // The size is that of the transaction as it would be submitted.
+        let tx_envelope = transaction::UnverifiedTransaction(
+            args.body,
+            vec![transaction::AuthProof::Module(args.scheme)],
+        );
+        let tx_size: u32 = cbor::to_vec(tx_envelope)
+            .len()
+            .try_into()
+            .map_err(|_| Error::InvalidArgument(anyhow!("transaction too large")))?;
+
+        Self::simulate_gas_used(ctx, tx_size, tx, args.balance_overrides)
+    }
+
+    /// Set the fee of a transaction being estimated, assuming the maximum amount of gas, a
+    /// reasonable maximum fee and the maximum amount of consensus messages.
+    fn set_estimation_fee<C: Context>(ctx: &mut C, tx: &mut transaction::Transaction) {
+        let params = Self::params(ctx.runtime_state());
+        tx.auth_info.fee.gas = params
+            .max_tx_gas
+            .map_or(params.max_batch_gas, |max_tx_gas| {
+                max_tx_gas.min(params.max_batch_gas)
+            });
+        tx.auth_info.fee.amount =
+            token::BaseUnits::new(u64::MAX.into(), token::Denomination::NATIVE);
+        tx.auth_info.fee.consensus_messages = ctx.remaining_messages();
+    }
+
+    /// Run a transaction in simulation and return how much gas it uses.
+    fn simulate_gas_used<C: Context>(
+        ctx: &mut C,
+        tx_size: u32,
+        tx: transaction::Transaction,
+        balance_overrides: types::BalanceOverrides,
+    ) -> Result<u64, Error> {
         ctx.with_simulation(|mut sim_ctx| {
-            sim_ctx.with_tx(tx_size, args.tx, |mut tx_ctx, call| {
+            Self::apply_balance_overrides::<Accounts, _>(&mut sim_ctx, balance_overrides);
+
+            sim_ctx.with_tx(tx_size, tx, |mut tx_ctx, call| {
                 dispatcher::Dispatcher::<C::Runtime>::dispatch_tx_call(&mut tx_ctx, call);
                 // Warning: we don't report success or failure. If the call fails, we still report
                 // how much gas it uses while it fails.
                 let gas_used = *tx_ctx.value::<u64>(CONTEXT_KEY_GAS_USED).or_default();
-                Ok(gas_used + extra_gas)
+                Ok(gas_used)
             })
         })
     }
 
+    /// Run a transaction in simulation while recording a trace of all the operations that it
+    /// performs. Like with gas estimation, the transaction does not need to be signed.
+    fn query_trace<C: Context>(
+        ctx: &mut C,
+        mut args: types::TraceQuery,
+    ) -> Result<types::TraceQueryResponse, Error> {
+        Self::set_estimation_fee(ctx, &mut args.tx);
+        let tx_size: u32 = cbor::to_vec(args.tx.clone())
+            .len()
+            .try_into()
+            .map_err(|_| Error::InvalidArgument(anyhow!("transaction too large")))?;
+
+        let tracer = trace::Tracer::new();
+        let result = ctx.with_tracing(tracer.clone(), |mut sim_ctx| {
+            Self::apply_balance_overrides::<Accounts, _>(&mut sim_ctx, args.balance_overrides);
+
+            sim_ctx.with_tx(tx_size, args.tx, |mut tx_ctx, call| {
+                dispatcher::Dispatcher::<C::Runtime>::dispatch_tx_call(&mut tx_ctx, call)
+            })
+        });
+
+        Ok(types::TraceQueryResponse {
+            result: result.into(),
+            trace: tracer.take(),
+        })
+    }
+
+    /// Override account balances in the given (simulation) context using the given accounts
+    /// handler.
+    ///
+    /// Total supplies are not updated as the overrides are only meant to be visible to the
+    /// simulated transaction.
+    fn apply_balance_overrides<A: accounts::API, C: Context>(
+        ctx: &mut C,
+        overrides: types::BalanceOverrides,
+    ) {
+        for (address, balances) in overrides {
+            for (denomination, amount) in balances {
+                A::set_balance(
+                    ctx.runtime_state(),
+                    address,
+                    &token::BaseUnits::new(amount, denomination),
+                );
+            }
+        }
+    }
+
+    /// Schedule a call for execution at the start of a future round.
+    ///
+    /// The gas used by the scheduled call is prepaid by the scheduling transaction. The call is
+    /// executed on behalf of the caller of the scheduling transaction and fails in case it tries
+    /// to emit any consensus messages.
+    fn tx_schedule<C: TxContext>(ctx: &mut C, body: types::Schedule) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+
+        // The round being executed follows the last runtime block.
+        let current_round = ctx.runtime_header().round.saturating_add(1);
+        if body.round <= current_round {
+            return Err(Error::InvalidArgument(anyhow!(
+                "round must be in the future"
+            )));
+        }
+        if !matches!(body.call.format, CallFormat::Plain) {
+            return Err(Error::InvalidArgument(anyhow!(
+                "scheduled calls must use the plain call format"
+            )));
+        }
+
+        Self::use_tx_gas(ctx, body.gas)?;
+
+        let caller = ctx.tx_caller_address();
+        let check_only = ctx.is_check_only();
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut scheduled =
+            storage::TypedStore::new(storage::PrefixStore::new(store, &state::SCHEDULED_CALLS));
+        let round = body.round.to_be_bytes();
+        let mut calls: Vec<types::ScheduledCall> = scheduled.get(round).unwrap_or_default();
+        if calls.len() >= params.max_scheduled_calls_per_round as usize {
+            return Err(Error::TooManyScheduledCalls);
+        }
+
+        if check_only {
+            return Ok(());
+        }
+
+        let sequence = calls.len() as u64;
+        calls.push(types::ScheduledCall {
+            caller,
+            call: body.call,
+            gas: body.gas,
+            scheduled_round: current_round,
+            sequence,
+        });
+        scheduled.insert(round, calls);
+
+        Ok(())
+    }
+
+    /// Set or remove a runtime-level metadata entry.
+    ///
+    /// Only the configured admin is allowed to modify metadata.
+    fn tx_set_metadata<C: TxContext>(ctx: &mut C, body: types::SetMetadata) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        if params.admin != Some(ctx.tx_caller_address()) {
+            return Err(Error::Forbidden);
+        }
+        if body.key.is_empty() {
+            return Err(Error::InvalidArgument(anyhow!(
+                "metadata key must not be empty"
+            )));
+        }
+
+        if ctx.is_check_only() {
+            return Ok(());
+        }
+
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut entries = storage::PrefixStore::new(store, &state::RUNTIME_METADATA);
+        match body.value {
+            Some(value) => entries.insert(body.key.as_bytes(), &value),
+            None => entries.remove(body.key.as_bytes()),
+        }
+
+        Ok(())
+    }
+
+    /// Enable or disable safe mode.
+    ///
+    /// While in safe mode, only the methods configured in the `safe_mode_allowed_methods`
+    /// parameter can be called. Only the configured admin is allowed to toggle safe mode.
+    fn tx_set_safe_mode<C: TxContext>(ctx: &mut C, body: types::SetSafeMode) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        if params.admin != Some(ctx.tx_caller_address()) {
+            return Err(Error::Forbidden);
+        }
+
+        if ctx.is_check_only() {
+            return Ok(());
+        }
+
+        let mut store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        if body.enabled {
+            store.insert(state::SAFE_MODE, &[0x01]);
+        } else {
+            store.remove(state::SAFE_MODE);
+        }
+
+        Ok(())
+    }
+
+    /// Enable or disable a module.
+    ///
+    /// The methods and queries of a disabled module are unavailable and its block hooks are not
+    /// invoked. Only the configured admin is allowed to enable or disable modules and the core
+    /// module itself can not be disabled.
+    ///
+    /// Disabling a module does not stop it consistently at any particular point. Other modules
+    /// may still use its API directly, results of consensus messages it has emitted are still
+    /// delivered to its message handlers and any per-block bookkeeping it would otherwise have
+    /// performed is skipped and not caught up on once the module is enabled again. Operators
+    /// should make sure that the module's state remains valid under these conditions.
+    fn tx_set_module_enabled<C: TxContext>(
+        ctx: &mut C,
+        body: types::SetModuleEnabled,
+    ) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        if params.admin != Some(ctx.tx_caller_address()) {
+            return Err(Error::Forbidden);
+        }
+        if body.module == MODULE_NAME {
+            return Err(Error::InvalidArgument(anyhow!(
+                "core module can not be disabled"
+            )));
+        }
+
+        if ctx.is_check_only() {
+            return Ok(());
+        }
+
+        let mut disabled = Self::disabled_modules(ctx);
+        if body.enabled {
+            disabled.remove(&body.module);
+        } else {
+            disabled.insert(body.module);
+        }
+
+        let mut store =
+            storage::TypedStore::new(storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME));
+        if disabled.is_empty() {
+            store.remove(state::DISABLED_MODULES);
+        } else {
+            store.insert(state::DISABLED_MODULES, disabled);
+        }
+
+        Ok(())
+    }
+
+    /// Execute a group of transactions signed by different signers atomically.
+    ///
+    /// The group transaction pays a single fee covering the gas of all grouped transactions,
+    /// while each grouped transaction is authenticated by its own signers using the signature
+    /// context bound to the group. In case any of the
+    /// grouped transactions fails, the effects of all of them are reverted.
+    ///
+    /// The gas limits of all grouped transactions must fit into the remaining gas of the group
+    /// transaction, which is then charged for the gas actually used by the grouped transactions.
+    fn tx_atomic_group<C: TxContext>(ctx: &mut C, body: types::AtomicGroup) -> Result<(), Error> {
+        if body.txs.is_empty() || body.txs.len() > MAX_ATOMIC_GROUP_SIZE {
+            return Err(Error::InvalidArgument(anyhow!("invalid atomic group size")));
+        }
+        if Self::is_in_atomic_group(ctx) {
+            return Err(Error::InvalidArgument(anyhow!(
+                "atomic groups cannot be nested"
+            )));
+        }
+
+        // Verify all transactions and ensure that the group can pay for the gas that they can
+        // use. Signatures of grouped transactions are bound to the signed bodies of all
+        // transactions in the group.
+        let bodies: Vec<&[u8]> = body.txs.iter().map(|utx| utx.0.as_slice()).collect();
+        let mut txs = Vec::with_capacity(body.txs.len());
+        let mut gas_limit: u64 = 0;
+        for utx in &body.txs {
+            let raw = cbor::to_vec(utx.clone());
+            let tx_hash = Hash::digest_bytes(&raw);
+            let (tx_size, tx) =
+                dispatcher::Dispatcher::<C::Runtime>::decode_grouped_tx(ctx, &raw, &bodies)?;
+            if tx.auth_info.fee.amount.amount() != 0 {
+                return Err(Error::InvalidArgument(anyhow!(
+                    "grouped transactions must not pay fees"
+                )));
+            }
+            if !matches!(tx.call.format, CallFormat::Plain) {
+                return Err(Error::InvalidArgument(anyhow!(
+                    "grouped transactions must use the plain call format"
+                )));
+            }
+
+            gas_limit = gas_limit.saturating_add(tx.auth_info.fee.gas);
+            txs.push((tx_size, tx_hash, tx));
+        }
+        if gas_limit > Self::remaining_tx_gas(ctx) {
+            let outer_limit = Self::tx_gas_limit(ctx);
+            return Err(Error::OutOfGas(
+                outer_limit,
+                Self::used_tx_gas(ctx).saturating_add(gas_limit),
+            ));
+        }
+
+        // Execute all transactions in a child context which is only committed in case all of
+        // them succeed. Grouped transactions are metered against their own gas limits, the gas
+        // that they use is charged to the group transaction once they are done.
+        let mut gas_used: u64 = 0;
+        let result = ctx.with_child(ctx.mode(), |mut ctx| {
+            ctx.value::<bool>(CONTEXT_KEY_ATOMIC_GROUP).set(true);
+
+            // Like the dispatcher, this also runs in check mode, so that grouped transactions are
+            // authenticated and their calls are checked by the handlers.
+            for (index, (tx_size, tx_hash, tx)) in txs.into_iter().enumerate() {
+                <C::Runtime as Runtime>::Modules::authenticate_tx(&mut ctx, &tx)
+                    .map_err(|err| Error::AtomicGroupFailed(index, err.to_string()))?;
+
+                let (tags, messages) = ctx.with_tx(tx_size, tx, |mut ctx, call| {
+                    Self::set_tx_hash(&mut ctx, tx_hash);
+
+                    let result =
+                        dispatcher::Dispatcher::<C::Runtime>::dispatch_tx_call(&mut ctx, call);
+                    gas_used = gas_used.saturating_add(Self::used_tx_gas(&mut ctx));
+
+                    match result {
+                        module::CallResult::Ok(_) => Ok(ctx.commit()),
+                        module::CallResult::Failed {
+                            module,
+                            code,
+                            message,
+                        } => Err(Error::AtomicGroupFailed(
+                            index,
+                            format!("module: {} code: {} message: {}", module, code, message),
+                        )),
+                        module::CallResult::Aborted(err) => Err(Error::Abort(err)),
+                    }
+                })?;
+
+                for tag in tags {
+                    ctx.try_emit_tag(tag)?;
+                }
+                ctx.emit_messages(messages)?;
+            }
+
+            Ok::<_, Error>(ctx.commit())
+        });
+
+        // Charge the gas used by the grouped transactions even if the group failed.
+        Self::use_tx_gas(ctx, gas_used)?;
+        let (tags, messages) = result?;
+
+        for tag in tags {
+            ctx.try_emit_tag(tag)?;
+        }
+        for (msg, hook) in messages {
+            ctx.emit_message(msg, hook)?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether transactions are being executed as part of an atomic group.
+    fn is_in_atomic_group<C: Context>(ctx: &mut C) -> bool {
+        ctx.value::<bool>(CONTEXT_KEY_ATOMIC_GROUP)
+            .get()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Whether a scheduled call is being executed.
+    fn is_scheduled_call<C: Context>(ctx: &mut C) -> bool {
+        ctx.value::<bool>(CONTEXT_KEY_SCHEDULED_CALL)
+            .get()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Whether the runtime is in safe mode.
+    fn is_safe_mode<C: Context>(ctx: &mut C) -> bool {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        store.get(state::SAFE_MODE).is_some()
+    }
+
+    /// Execute scheduled calls whose round has arrived.
+    ///
+    /// Calls scheduled for rounds in which no block has been executed are executed in the next
+    /// executed block. At most `MAX_SCHEDULED_CALLS_PER_BLOCK` calls are executed in a single
+    /// block, the remaining due calls are executed in the following blocks.
+    fn execute_scheduled_calls<C: Context>(ctx: &mut C) {
+        // The round being executed follows the last runtime block.
+        let current_round = ctx.runtime_header().round.saturating_add(1);
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut scheduled =
+            storage::TypedStore::new(storage::PrefixStore::new(store, &state::SCHEDULED_CALLS));
+
+        let mut due: Vec<(u64, Vec<types::ScheduledCall>, Vec<types::ScheduledCall>)> = vec![];
+        let mut remaining = MAX_SCHEDULED_CALLS_PER_BLOCK;
+        for (round, mut calls) in scheduled.iter::<Vec<u8>, Vec<types::ScheduledCall>>() {
+            let round: [u8; 8] = round
+                .as_slice()
+                .try_into()
+                .expect("corrupted scheduled call round");
+            let round = u64::from_be_bytes(round);
+            if remaining == 0 || round > current_round {
+                break;
+            }
+
+            // Defer the calls that do not fit into this block.
+            let deferred = calls.split_off(calls.len().min(remaining));
+            remaining -= calls.len();
+            due.push((round, calls, deferred));
+        }
+        for (round, _, deferred) in &due {
+            if deferred.is_empty() {
+                scheduled.remove(round.to_be_bytes());
+            } else {
+                scheduled.insert(round.to_be_bytes(), deferred.clone());
+            }
+        }
+
+        for (round, calls, _) in due {
+            for call in calls {
+                Self::execute_scheduled_call(ctx, round, call);
+            }
+        }
+    }
+
+    /// Execute a single scheduled call in a child context, forwarding any emitted events when the
+    /// call succeeds. Failed calls have no effect. Scheduled calls cannot emit consensus messages.
+    ///
+    /// The hash of the executed call is derived from the round for which it has been scheduled,
+    /// the round in which it has been scheduled and its sequence number among the calls scheduled
+    /// for the same round, which together uniquely identify the call, and the call itself.
+    fn execute_scheduled_call<C: Context>(
+        ctx: &mut C,
+        round: u64,
+        scheduled: types::ScheduledCall,
+    ) {
+        let tx_hash = Hash::digest_bytes_list(&[
+            SCHEDULED_CALL_HASH_CONTEXT,
+            &round.to_be_bytes(),
+            &scheduled.scheduled_round.to_be_bytes(),
+            &scheduled.sequence.to_be_bytes(),
+            &cbor::to_vec(scheduled.clone()),
+        ]);
+        let tx = transaction::Transaction {
+            version: transaction::LATEST_TRANSACTION_VERSION,
+            call: scheduled.call,
+            auth_info: transaction::AuthInfo {
+                signer_info: vec![transaction::SignerInfo {
+                    // The call is being performed on the scheduler's behalf.
+                    address_spec: AddressSpec::Internal(transaction::CallerAddress::Address(
+                        scheduled.caller,
+                    )),
+                    nonce: 0,
+                }],
+                fee: transaction::Fee {
+                    amount: token::BaseUnits::new(0, token::Denomination::NATIVE),
+                    // Gas has been prepaid when scheduling the call.
+                    gas: scheduled.gas,
+                    consensus_messages: 0,
+                },
+                features: 0,
+            },
+        };
+
+        let tags = ctx.with_child(ctx.mode(), |mut ctx| {
+            ctx.value::<bool>(CONTEXT_KEY_SCHEDULED_CALL).set(true);
+
+            let tags = ctx.with_tx(0, tx, |mut ctx, call| {
+                Self::set_tx_hash(&mut ctx, tx_hash);
+                // Block hooks have no way of handling the results of consensus messages, so any
+                // attempt to emit them makes the call fail.
+                ctx.limit_max_messages(0)
+                    .expect("decreasing the message limit should not fail");
+
+                let result = dispatcher::Dispatcher::<C::Runtime>::dispatch_tx_call(&mut ctx, call);
+                if result.is_success() {
+                    let (tags, _) = ctx.commit();
+                    tags
+                } else {
+                    // Ignore tags on failure.
+                    vec![]
+                }
+            });
+
+            // Commit storage. Note that if the call failed, this is basically a no-op.
+            ctx.commit();
+
+            tags
+        });
+
+        for tag in tags {
+            ctx.emit_tag(tag);
+        }
+    }
+
     /// Check invariants of all modules in the runtime.
     fn query_check_invariants<C: Context>(ctx: &mut C, _args: ()) -> Result<(), Error> {
         if !ctx.are_expensive_queries_allowed() {
@@ -390,14 +1420,52 @@ impl Module {
         <C::Runtime as Runtime>::Modules::check_invariants(ctx)
     }
 
+    /// Perform multiple queries in order, sharing the same context. A failure of one query does
+    /// not affect the others.
+    ///
+    /// Expensive queries (see `BATCH_QUERY_DISALLOWED_METHODS`) fail when included in a batch.
+    fn query_batch<C: Context>(
+        ctx: &mut C,
+        args: types::BatchQuery,
+    ) -> Result<types::BatchQueryResponse, Error> {
+        if args.queries.len() > MAX_BATCH_QUERY_SIZE {
+            return Err(Error::InvalidArgument(anyhow!("too many queries in batch")));
+        }
+
+        let results = args
+            .queries
+            .into_iter()
+            .map(|query| {
+                // Only queries are dispatched so calls can never be performed. Nested batches and
+                // expensive queries are not allowed to keep the amount of work bounded.
+                let result = if BATCH_QUERY_DISALLOWED_METHODS.contains(&query.method.as_str()) {
+                    Err(Error::InvalidMethod(query.method).into())
+                } else {
+                    <C::Runtime as Runtime>::Modules::dispatch_query(ctx, &query.method, query.args)
+                        .ok_or_else(|| Error::InvalidMethod(query.method).into())
+                        .and_then(|result| result)
+                };
+
+                match result {
+                    Ok(value) => transaction::CallResult::Ok(value),
+                    Err(err) => transaction::CallResult::Failed {
+                        module: err.module,
+                        code: err.code,
+                        message: err.message,
+                    },
+                }
+            })
+            .collect();
+
+        Ok(types::BatchQueryResponse { results })
+    }
+
     /// Retrieve the public key for encrypting call data.
     fn query_calldata_public_key<C: Context>(
         ctx: &mut C,
         _args: (),
     ) -> Result<types::CallDataPublicKeyQueryResponse, Error> {
-        let key_manager = ctx
-            .key_manager()
-            .ok_or_else(|| Error::InvalidArgument(anyhow!("key manager not available")))?;
+        let key_manager = ctx.key_manager().ok_or(Error::KeyManagerUnavailable)?;
         let public_key = key_manager
             .get_public_key(callformat::get_key_pair_id(ctx))
             .map_err(|err| Error::Abort(err.into()))?
@@ -415,12 +1483,80 @@ impl Module {
 
         Ok(params.min_gas_price)
     }
+
+    /// Query the minimum gas prices that were in effect at the given round.
+    ///
+    /// Prices are recorded at the start of each round, so changes made while executing a round
+    /// are only reflected starting with the following round.
+    fn query_historic_min_gas_price<C: Context>(
+        ctx: &mut C,
+        args: types::HistoricMinGasPriceQuery,
+    ) -> Result<BTreeMap<token::Denomination, u128>, Error> {
+        if args.round > ctx.runtime_header().round {
+            return Err(Error::InvalidArgument(anyhow!("round is in the future")));
+        }
+
+        let store =
+            storage::TypedStore::new(storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME));
+        let history: types::MinGasPriceHistory =
+            store.get(state::MIN_GAS_PRICE_HISTORY).unwrap_or_default();
+        history
+            .at(args.round)
+            .cloned()
+            .ok_or(Error::RoundPruned(args.round))
+    }
+
+    /// Query a runtime-level metadata entry.
+    fn query_metadata<C: Context>(
+        ctx: &mut C,
+        args: types::MetadataQuery,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let entries = storage::PrefixStore::new(store, &state::RUNTIME_METADATA);
+        Ok(entries.get(args.key.as_bytes()))
+    }
+
+    /// Query whether the runtime is in safe mode.
+    fn query_safe_mode<C: Context>(ctx: &mut C, _args: ()) -> Result<bool, Error> {
+        Ok(Self::is_safe_mode(ctx))
+    }
+
+    /// Query the names of the modules that have been disabled.
+    fn query_disabled_modules<C: Context>(
+        ctx: &mut C,
+        _args: (),
+    ) -> Result<BTreeSet<String>, Error> {
+        Ok(Self::disabled_modules(ctx))
+    }
+
+    /// Query the smoothed time.
+    fn query_time<C: Context>(ctx: &mut C, _args: ()) -> Result<u64, Error> {
+        Ok(ctx.smoothed_time())
+    }
+
+    /// Registry of error codes that can be emitted by the runtime's modules.
+    fn query_error_registry<C: Context>(
+        _ctx: &mut C,
+        _args: (),
+    ) -> Result<types::ErrorRegistry, Error> {
+        let mut registry = BTreeMap::new();
+        <C::Runtime as Runtime>::Modules::register_error_codes(&mut registry);
+        Ok(registry)
+    }
+
+    /// Optional capabilities supported by the runtime.
+    fn query_capabilities<C: Context>(
+        _ctx: &mut C,
+        _args: (),
+    ) -> Result<types::Capabilities, Error> {
+        Ok(<C::Runtime as Runtime>::capabilities())
+    }
 }
 
 impl module::Module for Module {
     const NAME: &'static str = MODULE_NAME;
     type Error = Error;
-    type Event = ();
+    type Event = Event;
     type Parameters = Parameters;
 }
 
@@ -443,17 +1579,82 @@ impl module::AuthHandler for Module {
         Ok(())
     }
 
+    fn authenticate_tx<C: Context>(
+        ctx: &mut C,
+        tx: &transaction::Transaction,
+    ) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+
+        // Reject transactions that could use more gas than allowed for a single transaction.
+        if let Some(max_tx_gas) = params.max_tx_gas {
+            if tx.auth_info.fee.gas > max_tx_gas {
+                return Err(Error::TxGasLimitTooHigh(max_tx_gas, tx.auth_info.fee.gas));
+            }
+        }
+
+        // Transactions in an atomic group do not pay fees as the group transaction pays for them.
+        if Self::is_in_atomic_group(ctx) {
+            return Ok(());
+        }
+
+        // Only allow zero-fee transactions for the configured methods.
+        let fee = &tx.auth_info.fee.amount;
+        let zero_fee_allowed = match params.allow_zero_fee_methods {
+            Some(allowed) => {
+                let allowed = allowed.contains(&tx.call.method);
+                if fee.amount() == 0 && !allowed {
+                    return Err(Error::FeeRequired);
+                }
+                allowed
+            }
+            None => false,
+        };
+
+        // Enforce absolute bounds on the fee amount.
+        if let Some(min_tx_fee) = params.min_tx_fee.get(fee.denomination()) {
+            if fee.amount() < *min_tx_fee && !(fee.amount() == 0 && zero_fee_allowed) {
+                return Err(Error::TxFeeTooLow(*min_tx_fee, fee.amount()));
+            }
+        }
+        if let Some(max_tx_fee) = params.max_tx_fee.get(fee.denomination()) {
+            if fee.amount() > *max_tx_fee {
+                return Err(Error::TxFeeTooHigh(*max_tx_fee, fee.amount()));
+            }
+        }
+
+        Ok(())
+    }
+
     fn before_handle_call<C: TxContext>(ctx: &mut C, call: &Call) -> Result<(), Error> {
         let params = Self::params(ctx.runtime_state());
 
+        // Only allow recovery calls while in safe mode.
+        if call.method != METHOD_SET_SAFE_MODE
+            && !params.safe_mode_allowed_methods.contains(&call.method)
+            && Self::is_safe_mode(ctx)
+        {
+            return Err(Error::SafeMode(call.method.clone()));
+        }
+
+        // Enforce the call format required by the method, if any.
+        if let Some(required) = params.required_call_formats.get(&call.method) {
+            if ctx.tx_call_format() != *required {
+                return Err(Error::EncryptionRequired(call.method.clone()));
+            }
+        }
+
         // Check that the fee's denomination is in the min_gas_price map and
-        // that the gas price is higher or equal than the set minimum.
-        let fee = ctx.tx_auth_info().fee.clone();
-        match params.min_gas_price.get(fee.amount.denomination()) {
-            None => return Err(Error::GasPriceTooLow),
-            Some(min_gas_price) => {
-                if &fee.gas_price() < min_gas_price {
-                    return Err(Error::GasPriceTooLow);
+        // that the gas price is higher or equal than the set minimum. Transactions in an atomic
+        // group are paid for by the group transaction and scheduled calls are paid for by the
+        // scheduling transaction.
+        if !Self::is_in_atomic_group(ctx) && !Self::is_scheduled_call(ctx) {
+            let fee = ctx.tx_auth_info().fee.clone();
+            match params.min_gas_price.get(fee.amount.denomination()) {
+                None => return Err(Error::GasPriceTooLow),
+                Some(min_gas_price) => {
+                    if &fee.gas_price() < min_gas_price {
+                        return Err(Error::GasPriceTooLow);
+                    }
                 }
             }
         }
@@ -511,6 +1712,11 @@ impl module::AuthHandler for Module {
             consensus_messages as u64,
         )?;
 
+        // Limit the maximum number of events that can be emitted.
+        if let Some(max_tx_events) = params.max_tx_events {
+            ctx.limit_max_events(max_tx_events)?;
+        }
+
         Ok(())
     }
 }
@@ -537,6 +1743,23 @@ impl module::MigrationHandler for Module {
 }
 
 impl module::MethodHandler for Module {
+    fn dispatch_call<C: TxContext>(
+        ctx: &mut C,
+        method: &str,
+        body: cbor::Value,
+    ) -> module::DispatchResult<cbor::Value, module::CallResult> {
+        match method {
+            "core.Schedule" => module::dispatch_call(ctx, body, Self::tx_schedule),
+            "core.SetMetadata" => module::dispatch_call(ctx, body, Self::tx_set_metadata),
+            METHOD_SET_SAFE_MODE => module::dispatch_call(ctx, body, Self::tx_set_safe_mode),
+            "core.SetModuleEnabled" => {
+                module::dispatch_call(ctx, body, Self::tx_set_module_enabled)
+            }
+            "core.AtomicGroup" => module::dispatch_call(ctx, body, Self::tx_atomic_group),
+            _ => module::DispatchResult::Unhandled(body),
+        }
+    }
+
     fn dispatch_query<C: Context>(
         ctx: &mut C,
         method: &str,
@@ -544,6 +1767,10 @@ impl module::MethodHandler for Module {
     ) -> module::DispatchResult<cbor::Value, Result<cbor::Value, error::RuntimeError>> {
         match method {
             "core.EstimateGas" => module::dispatch_query(ctx, args, Self::query_estimate_gas),
+            "core.EstimateGasEncoded" => {
+                module::dispatch_query(ctx, args, Self::query_estimate_gas_encoded)
+            }
+            "core.Trace" => module::dispatch_query(ctx, args, Self::query_trace),
             "core.CheckInvariants" => {
                 module::dispatch_query(ctx, args, Self::query_check_invariants)
             }
@@ -551,12 +1778,54 @@ impl module::MethodHandler for Module {
                 module::dispatch_query(ctx, args, Self::query_calldata_public_key)
             }
             "core.MinGasPrice" => module::dispatch_query(ctx, args, Self::query_min_gas_price),
+            "core.HistoricMinGasPrice" => {
+                module::dispatch_query(ctx, args, Self::query_historic_min_gas_price)
+            }
+            "core.Time" => module::dispatch_query(ctx, args, Self::query_time),
+            "core.Metadata" => module::dispatch_query(ctx, args, Self::query_metadata),
+            "core.SafeMode" => module::dispatch_query(ctx, args, Self::query_safe_mode),
+            "core.DisabledModules" => {
+                module::dispatch_query(ctx, args, Self::query_disabled_modules)
+            }
+            "core.ErrorRegistry" => module::dispatch_query(ctx, args, Self::query_error_registry),
+            "core.Capabilities" => module::dispatch_query(ctx, args, Self::query_capabilities),
+            METHOD_BATCH_QUERY => module::dispatch_query(ctx, args, Self::query_batch),
             _ => module::DispatchResult::Unhandled(args),
         }
     }
+
+    fn register_error_codes(registry: &mut types::ErrorRegistry) {
+        module::register_error_codes::<Self>(registry)
+    }
 }
 
 impl module::BlockHandler for Module {
+    fn begin_block<C: Context>(ctx: &mut C) {
+        // Record the timestamp of the last runtime block.
+        let timestamp = ctx.runtime_header().timestamp;
+        let mut store =
+            storage::TypedStore::new(storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME));
+        let mut recent: types::RecentTimestamps =
+            store.get(state::RECENT_TIMESTAMPS).unwrap_or_default();
+        recent.push(timestamp, SMOOTHED_TIME_WINDOW);
+        store.insert(state::RECENT_TIMESTAMPS, recent);
+
+        // Record the minimum gas prices in effect for the round being executed. The history is
+        // only written in case the prices have changed.
+        let round = ctx.runtime_header().round.saturating_add(1);
+        let min_gas_price = Self::params(ctx.runtime_state()).min_gas_price;
+        let mut store =
+            storage::TypedStore::new(storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME));
+        let mut history: types::MinGasPriceHistory =
+            store.get(state::MIN_GAS_PRICE_HISTORY).unwrap_or_default();
+        if history.record(round, &min_gas_price, MIN_GAS_PRICE_HISTORY_SIZE) {
+            store.insert(state::MIN_GAS_PRICE_HISTORY, history);
+        }
+
+        // Execute any calls scheduled for this round.
+        Self::execute_scheduled_calls(ctx);
+    }
+
     fn get_block_weight_limits<C: Context>(ctx: &mut C) -> BTreeMap<TransactionWeight, u64> {
         let batch_gas_limit = Self::params(ctx.runtime_state()).max_batch_gas;
 
@@ -567,4 +1836,5 @@ impl module::BlockHandler for Module {
     }
 }
 
+impl module::EventHandler for Module {}
 impl module::InvariantHandler for Module {}
@@ -1,16 +1,24 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, str::FromStr};
 
-use oasis_core_runtime::types::BATCH_WEIGHT_LIMIT_QUERY_METHOD;
+use oasis_core_runtime::{
+    common::crypto::signature::{PrivateKey, Signer as _},
+    types::BATCH_WEIGHT_LIMIT_QUERY_METHOD,
+};
 
 use crate::{
     context::{BatchContext, Context, Mode, TxContext},
     core::common::version::Version,
-    crypto::multisig,
+    crypto::{
+        multisig,
+        signature::context::{get_chain_context_for, set_chain_context, test as context},
+    },
     dispatcher, module,
-    module::{AuthHandler as _, BlockHandler, Module as _},
+    module::{AuthHandler as _, BlockHandler, MigrationHandler as _, Module as _},
+    modules::accounts::{self, Module as Accounts, API as _},
     runtime::Runtime,
     testing::{keys, mock},
     types::{
+        address::{Address, SignatureAddressSpec},
         token, transaction,
         transaction::{CallerAddress, TransactionWeight},
     },
@@ -36,6 +44,24 @@ fn test_use_gas() {
                 mgp.insert(token::Denomination::NATIVE, 0);
                 mgp
             },
+            allow_zero_fee_methods: None,
+            max_tx_events: None,
+            block_transfer_limits: BTreeMap::new(),
+            transfer_limits: BTreeMap::new(),
+            max_scheduled_calls_per_round: 0,
+            invariant_check_interval: 0,
+            max_tx_gas: None,
+            admin: None,
+            nonce_consumption: Default::default(),
+            safe_mode_allowed_methods: Default::default(),
+            required_call_formats: Default::default(),
+            min_tx_fee: Default::default(),
+            max_tx_fee: Default::default(),
+            refund_unused_gas: false,
+            fee_fallback_denomination: None,
+            max_tx_size: None,
+            mint_allowlist: Default::default(),
+            max_value_size: None,
         },
     );
 
@@ -110,6 +136,129 @@ fn test_use_gas() {
     });
 }
 
+#[test]
+fn test_use_gas_overflow() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            max_batch_gas: u64::MAX,
+            ..Default::default()
+        },
+    );
+
+    let mut tx = mock::transaction();
+    tx.auth_info.fee.gas = u64::MAX;
+
+    ctx.with_tx(0, tx.clone(), |mut tx_ctx, _call| {
+        Core::use_tx_gas(&mut tx_ctx, u64::MAX - 10).expect("using gas under limit should succeed");
+
+        // Charging past u64::MAX should be a clean out-of-gas instead of wrapping around.
+        let err = Core::use_tx_gas(&mut tx_ctx, 100).expect_err("overflow should run out of gas");
+        assert!(matches!(err, super::Error::OutOfGas(u64::MAX, u64::MAX)));
+        assert_eq!(
+            Core::remaining_tx_gas(&mut tx_ctx),
+            10,
+            "failed charge should not be accounted"
+        );
+
+        Core::use_tx_gas(&mut tx_ctx, 10).expect("using the remaining gas should succeed");
+        assert_eq!(Core::remaining_tx_gas(&mut tx_ctx), 0);
+    });
+
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        // The transaction has gas left, but the batch accumulator would overflow.
+        let err =
+            Core::use_tx_gas(&mut tx_ctx, 1).expect_err("batch overflow should run out of gas");
+        assert!(matches!(
+            err,
+            super::Error::BatchOutOfGas(u64::MAX, u64::MAX)
+        ));
+        assert_eq!(Core::remaining_batch_gas(&mut tx_ctx), 0);
+    });
+}
+
+#[test]
+fn test_reserve_gas() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            max_batch_gas: 10_000,
+            ..Default::default()
+        },
+    );
+
+    let mut tx = mock::transaction();
+    tx.auth_info.fee.gas = 1_000;
+
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        Core::use_tx_gas(&mut tx_ctx, 100).unwrap();
+
+        // Gas that is not available can't be reserved.
+        let err = Core::reserve_gas(&mut tx_ctx, 901).expect_err("reservation should fail");
+        assert!(matches!(err, super::Error::OutOfGas(1_000, 1_001)));
+
+        Core::reserve_gas(&mut tx_ctx, 100).expect("reservation should succeed");
+        assert_eq!(Core::reserved_tx_gas(&mut tx_ctx), 100);
+        assert_eq!(Core::remaining_tx_gas(&mut tx_ctx), 800);
+        assert_eq!(
+            Core::remaining_batch_gas(&mut tx_ctx),
+            10_000 - 100 - 100,
+            "reservation should be accounted against the batch"
+        );
+
+        // The main logic nearly exhausts the gas and can't use the reserved gas.
+        Core::use_tx_gas(&mut tx_ctx, 790).unwrap();
+        let err = Core::use_tx_gas(&mut tx_ctx, 20).expect_err("reserved gas should not be used");
+        assert!(matches!(err, super::Error::OutOfGas(1_000, 1_010)));
+        assert_eq!(Core::remaining_tx_gas(&mut tx_ctx), 10);
+
+        // Cleanup still completes using the reserved gas.
+        Core::use_reserved_gas(&mut tx_ctx, 60).expect("cleanup should succeed");
+        Core::use_reserved_gas(&mut tx_ctx, 30).expect("cleanup should succeed");
+        assert_eq!(Core::used_tx_gas(&mut tx_ctx), 100 + 790 + 90);
+        assert_eq!(Core::reserved_tx_gas(&mut tx_ctx), 10);
+        assert!(matches!(
+            Core::use_reserved_gas(&mut tx_ctx, 11),
+            Err(super::Error::OutOfGas(10, 11))
+        ));
+
+        // Reserved gas is not double-counted against the batch.
+        assert_eq!(
+            Core::remaining_batch_gas(&mut tx_ctx),
+            10_000 - 100 - 100 - 790
+        );
+    });
+}
+
+#[test]
+fn test_use_query_gas() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            max_batch_gas: 1000,
+            ..Default::default()
+        },
+    );
+
+    // Queries outside of simulations are not metered.
+    Core::use_query_gas(&mut ctx, 2000).expect("query gas should not be metered");
+    assert_eq!(Core::remaining_batch_gas(&mut ctx), 1000);
+
+    ctx.with_simulation(|mut sim_ctx| {
+        Core::use_query_gas(&mut sim_ctx, 400).expect("using gas under limit should succeed");
+        assert_eq!(Core::remaining_batch_gas(&mut sim_ctx), 600);
+
+        let err = Core::use_query_gas(&mut sim_ctx, 700).expect_err("should run out of gas");
+        assert!(matches!(err, super::Error::BatchOutOfGas(1000, 1100)));
+    });
+}
+
 #[test]
 fn test_query_min_gas_price() {
     let mut mock = mock::Mock::default();
@@ -126,6 +275,24 @@ fn test_query_min_gas_price() {
                 mgp.insert(token::Denomination::NATIVE, 123);
                 mgp
             },
+            allow_zero_fee_methods: None,
+            max_tx_events: None,
+            block_transfer_limits: BTreeMap::new(),
+            transfer_limits: BTreeMap::new(),
+            max_scheduled_calls_per_round: 0,
+            invariant_check_interval: 0,
+            max_tx_gas: None,
+            admin: None,
+            nonce_consumption: Default::default(),
+            safe_mode_allowed_methods: Default::default(),
+            required_call_formats: Default::default(),
+            min_tx_fee: Default::default(),
+            max_tx_fee: Default::default(),
+            refund_unused_gas: false,
+            fee_fallback_denomination: None,
+            max_tx_size: None,
+            mint_allowlist: Default::default(),
+            max_value_size: None,
         },
     );
 
@@ -135,6 +302,345 @@ fn test_query_min_gas_price() {
     assert!(*mgp.get(&token::Denomination::NATIVE).unwrap() == 123);
 }
 
+#[test]
+fn test_allow_zero_fee_methods() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    let mut tx = mock::transaction();
+    tx.auth_info.fee.amount = token::BaseUnits::new(0, token::Denomination::NATIVE);
+
+    // Without any restrictions, zero-fee transactions should be allowed.
+    tx.call.method = "accounts.Transfer".to_owned();
+    Core::authenticate_tx(&mut ctx, &tx).expect("zero-fee transaction should be allowed");
+
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            allow_zero_fee_methods: Some(vec!["faucet.Claim".to_owned()].into_iter().collect()),
+            ..Default::default()
+        },
+    );
+
+    // Zero-fee calls to allowed methods should be accepted.
+    tx.call.method = "faucet.Claim".to_owned();
+    Core::authenticate_tx(&mut ctx, &tx).expect("zero-fee faucet call should be allowed");
+
+    // Zero-fee calls to other methods should be rejected.
+    tx.call.method = "accounts.Transfer".to_owned();
+    let err = Core::authenticate_tx(&mut ctx, &tx).expect_err("zero-fee transfer should fail");
+    assert!(matches!(err, super::Error::FeeRequired));
+
+    // Transactions paying a fee should not be restricted.
+    tx.auth_info.fee.amount = token::BaseUnits::new(100, token::Denomination::NATIVE);
+    Core::authenticate_tx(&mut ctx, &tx).expect("transfer paying a fee should be allowed");
+
+    // An empty set should forbid zero-fee transactions entirely.
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            allow_zero_fee_methods: Some(Default::default()),
+            ..Default::default()
+        },
+    );
+    tx.auth_info.fee.amount = token::BaseUnits::new(0, token::Denomination::NATIVE);
+    tx.call.method = "faucet.Claim".to_owned();
+    Core::authenticate_tx(&mut ctx, &tx).expect_err("zero-fee transactions should be forbidden");
+}
+
+#[test]
+fn test_max_tx_gas() {
+    const MAX_TX_GAS: u64 = 1_000;
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            max_batch_gas: u64::MAX,
+            max_tx_gas: Some(MAX_TX_GAS),
+            ..Default::default()
+        },
+    );
+
+    let mut tx = mock::transaction();
+
+    // Transactions under and at the limit should be accepted.
+    tx.auth_info.fee.gas = MAX_TX_GAS - 1;
+    Core::authenticate_tx(&mut ctx, &tx).expect("gas under the limit should be allowed");
+    tx.auth_info.fee.gas = MAX_TX_GAS;
+    Core::authenticate_tx(&mut ctx, &tx).expect("gas at the limit should be allowed");
+
+    // Transactions just over the limit should be rejected.
+    tx.auth_info.fee.gas = MAX_TX_GAS + 1;
+    let err = Core::authenticate_tx(&mut ctx, &tx).expect_err("gas over the limit should fail");
+    assert!(matches!(
+        err,
+        super::Error::TxGasLimitTooHigh(MAX_TX_GAS, wanted) if wanted == MAX_TX_GAS + 1
+    ));
+
+    // The limit should also be a hard ceiling during execution.
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        assert_eq!(Core::remaining_tx_gas(&mut tx_ctx), MAX_TX_GAS);
+        Core::use_tx_gas(&mut tx_ctx, MAX_TX_GAS).expect("using gas at the limit should succeed");
+        let err =
+            Core::use_tx_gas(&mut tx_ctx, 1).expect_err("using gas over the limit should fail");
+        assert!(matches!(err, super::Error::OutOfGas(MAX_TX_GAS, _)));
+    });
+}
+
+#[test]
+fn test_tx_fee_bounds() {
+    const MIN_TX_FEE: u128 = 100;
+    const MAX_TX_FEE: u128 = 1_000;
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            min_tx_fee: vec![(token::Denomination::NATIVE, MIN_TX_FEE)]
+                .into_iter()
+                .collect(),
+            max_tx_fee: vec![(token::Denomination::NATIVE, MAX_TX_FEE)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        },
+    );
+
+    let mut tx = mock::transaction();
+    let other: token::Denomination = "SMALLER".parse().unwrap();
+    let mut authenticate = |amount, denomination: &token::Denomination| {
+        tx.auth_info.fee.amount = token::BaseUnits::new(amount, denomination.clone());
+        Core::authenticate_tx(&mut ctx, &tx)
+    };
+
+    // Fees at and within the bounds should be accepted.
+    authenticate(MIN_TX_FEE, &token::Denomination::NATIVE)
+        .expect("fee at the minimum should be allowed");
+    authenticate(MAX_TX_FEE, &token::Denomination::NATIVE)
+        .expect("fee at the maximum should be allowed");
+
+    // Fees just outside the bounds should be rejected.
+    let err = authenticate(MIN_TX_FEE - 1, &token::Denomination::NATIVE)
+        .expect_err("fee under the minimum should fail");
+    assert!(matches!(
+        err,
+        super::Error::TxFeeTooLow(MIN_TX_FEE, wanted) if wanted == MIN_TX_FEE - 1
+    ));
+    let err = authenticate(MAX_TX_FEE + 1, &token::Denomination::NATIVE)
+        .expect_err("fee over the maximum should fail");
+    assert!(matches!(
+        err,
+        super::Error::TxFeeTooHigh(MAX_TX_FEE, wanted) if wanted == MAX_TX_FEE + 1
+    ));
+    let err = authenticate(0, &token::Denomination::NATIVE).expect_err("zero fee should fail");
+    assert!(matches!(err, super::Error::TxFeeTooLow(MIN_TX_FEE, 0)));
+
+    // Denominations without bounds should not be limited.
+    authenticate(0, &other).expect("zero fee in other denominations should be allowed");
+    authenticate(u128::MAX, &other).expect("large fee in other denominations should be allowed");
+
+    // Zero-fee calls to explicitly allowed methods should be exempt from the minimum.
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            allow_zero_fee_methods: Some(vec![tx.call.method.clone()].into_iter().collect()),
+            min_tx_fee: vec![(token::Denomination::NATIVE, MIN_TX_FEE)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        },
+    );
+    let mut authenticate = |amount| {
+        tx.auth_info.fee.amount = token::BaseUnits::new(amount, token::Denomination::NATIVE);
+        Core::authenticate_tx(&mut ctx, &tx)
+    };
+    authenticate(0).expect("zero fee for an allowed method should be exempt");
+    let err = authenticate(1).expect_err("non-zero fee under the minimum should fail");
+    assert!(matches!(err, super::Error::TxFeeTooLow(MIN_TX_FEE, 1)));
+}
+
+#[test]
+fn test_max_tx_events() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            max_batch_gas: u64::MAX,
+            min_gas_price: {
+                let mut mgp = BTreeMap::new();
+                mgp.insert(token::Denomination::NATIVE, 0);
+                mgp
+            },
+            max_tx_events: Some(2),
+            ..Default::default()
+        },
+    );
+
+    let mut tx = mock::transaction();
+    tx.auth_info.fee.consensus_messages = 0;
+
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        Core::before_handle_call(&mut tx_ctx, &call).expect("before_handle_call should succeed");
+        assert_eq!(tx_ctx.remaining_events(), 2);
+
+        for _ in 0..2 {
+            tx_ctx
+                .try_emit_event(super::Event::EventsRoot {
+                    root: Default::default(),
+                })
+                .expect("emitting events under the limit should succeed");
+        }
+        assert_eq!(tx_ctx.remaining_events(), 0);
+
+        let err = tx_ctx
+            .try_emit_event(super::Event::EventsRoot {
+                root: Default::default(),
+            })
+            .expect_err("emitting events over the limit should fail");
+        assert!(matches!(err, super::Error::TooManyEvents));
+
+        // Child contexts should share the same limit.
+        tx_ctx.with_child(tx_ctx.mode(), |mut child_ctx| {
+            assert_eq!(child_ctx.remaining_events(), 0);
+            child_ctx
+                .try_emit_event(super::Event::EventsRoot {
+                    root: Default::default(),
+                })
+                .expect_err("emitting events from a child context should fail");
+        });
+
+        // The limit can only be lowered.
+        tx_ctx
+            .limit_max_events(10)
+            .expect_err("raising the event limit should fail");
+    });
+}
+
+#[test]
+fn test_smoothed_time() {
+    let mut mock = mock::Mock::default();
+    mock.runtime_header.timestamp = 42;
+
+    // Without any recorded timestamps, the last block timestamp should be used.
+    {
+        let mut ctx = mock.create_ctx();
+        assert_eq!(ctx.smoothed_time(), 42);
+    }
+
+    // Timestamps of consecutive blocks, including an outlier from a misbehaving proposer.
+    for ts in [100, 110, 120, 1_000_000, 140, 150].iter() {
+        mock.runtime_header.timestamp = *ts;
+        let mut ctx = mock.create_ctx();
+        Core::begin_block(&mut ctx);
+    }
+
+    {
+        let mut ctx = mock.create_ctx();
+        assert_eq!(
+            ctx.smoothed_time(),
+            120,
+            "outlier should not affect smoothed time"
+        );
+        let time = Core::query_time(&mut ctx, ()).expect("time query should succeed");
+        assert_eq!(time, 120);
+    }
+
+    // Once the window is full, the oldest timestamps should be evicted.
+    for i in 0..super::SMOOTHED_TIME_WINDOW as u64 {
+        mock.runtime_header.timestamp = 200 + i * 10;
+        let mut ctx = mock.create_ctx();
+        Core::begin_block(&mut ctx);
+    }
+
+    let mut ctx = mock.create_ctx();
+    assert_eq!(ctx.smoothed_time(), 250);
+}
+
+#[test]
+fn test_query_historic_min_gas_price() {
+    let mut mock = mock::Mock::default();
+    let mgp = |price: u128| {
+        let mut mgp = BTreeMap::new();
+        mgp.insert(token::Denomination::NATIVE, price);
+        mgp
+    };
+    let execute_round = |mock: &mut mock::Mock, round: u64, price: u128| {
+        mock.runtime_header.round = round - 1;
+        let mut ctx = mock.create_ctx();
+        Core::set_params(
+            ctx.runtime_state(),
+            Parameters {
+                min_gas_price: mgp(price),
+                ..Default::default()
+            },
+        );
+        Core::begin_block(&mut ctx);
+    };
+
+    // Execute rounds 1 to 6, changing the minimum gas price in rounds 3 and 5.
+    for (round, price) in [(1, 10), (2, 10), (3, 20), (4, 20), (5, 30), (6, 30)].iter() {
+        execute_round(&mut mock, *round, *price);
+    }
+
+    mock.runtime_header.round = 6;
+    let mut ctx = mock.create_ctx();
+    for (round, price) in [(1, 10), (2, 10), (3, 20), (4, 20), (5, 30), (6, 30)].iter() {
+        let historic = Core::query_historic_min_gas_price(
+            &mut ctx,
+            types::HistoricMinGasPriceQuery { round: *round },
+        )
+        .expect("historic min gas price query should succeed");
+        assert_eq!(historic, mgp(*price), "price at round {}", round);
+    }
+
+    // Rounds preceding the history should not be available.
+    let err =
+        Core::query_historic_min_gas_price(&mut ctx, types::HistoricMinGasPriceQuery { round: 0 })
+            .expect_err("round before history should fail");
+    assert!(matches!(err, super::Error::RoundPruned(0)));
+
+    // Future rounds should be rejected.
+    let err =
+        Core::query_historic_min_gas_price(&mut ctx, types::HistoricMinGasPriceQuery { round: 7 })
+            .expect_err("future round should fail");
+    assert!(matches!(err, super::Error::InvalidArgument(_)));
+
+    // Once the history is full, the oldest changes should be pruned.
+    let size = super::MIN_GAS_PRICE_HISTORY_SIZE as u64;
+    for i in 0..size {
+        execute_round(&mut mock, 7 + i, 100 + i as u128);
+    }
+
+    mock.runtime_header.round = 6 + size;
+    let mut ctx = mock.create_ctx();
+    let err =
+        Core::query_historic_min_gas_price(&mut ctx, types::HistoricMinGasPriceQuery { round: 6 })
+            .expect_err("pruned round should fail");
+    assert!(matches!(err, super::Error::RoundPruned(6)));
+    let historic =
+        Core::query_historic_min_gas_price(&mut ctx, types::HistoricMinGasPriceQuery { round: 7 })
+            .expect("retained round should succeed");
+    assert_eq!(historic, mgp(100));
+
+    // The history should only be modified when the prices change.
+    let mut history = types::MinGasPriceHistory::default();
+    assert!(
+        history.record(1, &mgp(10), 2),
+        "first prices should be recorded"
+    );
+    assert!(
+        !history.record(2, &mgp(10), 2),
+        "unchanged prices should not be recorded"
+    );
+    assert!(
+        history.record(3, &mgp(20), 2),
+        "changed prices should be recorded"
+    );
+    assert_eq!(history.changes.len(), 2);
+}
+
 // Module that implements the gas waster method.
 struct GasWasterModule;
 
@@ -173,6 +679,7 @@ impl module::AuthHandler for GasWasterModule {}
 impl module::MigrationHandler for GasWasterModule {
     type Genesis = ();
 }
+impl module::EventHandler for GasWasterModule {}
 impl module::InvariantHandler for GasWasterModule {}
 
 // Runtime that knows how to waste gas.
@@ -206,6 +713,24 @@ impl Runtime for GasWasterRuntime {
                         mgp.insert(token::Denomination::NATIVE, 0);
                         mgp
                     },
+                    allow_zero_fee_methods: None,
+                    max_tx_events: None,
+                    block_transfer_limits: BTreeMap::new(),
+                    transfer_limits: BTreeMap::new(),
+                    max_scheduled_calls_per_round: 0,
+                    invariant_check_interval: 0,
+                    max_tx_gas: None,
+                    admin: None,
+                    nonce_consumption: Default::default(),
+                    safe_mode_allowed_methods: Default::default(),
+                    required_call_formats: Default::default(),
+                    min_tx_fee: Default::default(),
+                    max_tx_fee: Default::default(),
+                    refund_unused_gas: false,
+                    fee_fallback_denomination: None,
+                    max_tx_size: None,
+                    mint_allowlist: Default::default(),
+                    max_value_size: None,
                 },
             },
             (),
@@ -248,6 +773,7 @@ fn test_reject_txs() {
                 gas: u64::MAX,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
 
@@ -287,6 +813,7 @@ fn test_query_estimate_gas() {
                 gas: u64::MAX,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
 
@@ -294,6 +821,7 @@ fn test_query_estimate_gas() {
     let args = types::EstimateGasQuery {
         caller: None,
         tx: tx.clone(),
+        balance_overrides: BTreeMap::new(),
     };
 
     let est = Core::query_estimate_gas(&mut ctx, args).expect("query_estimate_gas should succeed");
@@ -306,6 +834,7 @@ fn test_query_estimate_gas() {
     let args = types::EstimateGasQuery {
         caller: Some(CallerAddress::Address(keys::alice::address())),
         tx,
+        balance_overrides: BTreeMap::new(),
     };
 
     let est = Core::query_estimate_gas(&mut ctx, args).expect("query_estimate_gas should succeed");
@@ -331,6 +860,24 @@ fn test_approve_unverified_tx() {
                 mgp.insert(token::Denomination::NATIVE, 0);
                 mgp
             },
+            allow_zero_fee_methods: None,
+            max_tx_events: None,
+            block_transfer_limits: BTreeMap::new(),
+            transfer_limits: BTreeMap::new(),
+            max_scheduled_calls_per_round: 0,
+            invariant_check_interval: 0,
+            max_tx_gas: None,
+            admin: None,
+            nonce_consumption: Default::default(),
+            safe_mode_allowed_methods: Default::default(),
+            required_call_formats: Default::default(),
+            min_tx_fee: Default::default(),
+            max_tx_fee: Default::default(),
+            refund_unused_gas: false,
+            fee_fallback_denomination: None,
+            max_tx_size: None,
+            mint_allowlist: Default::default(),
+            max_value_size: None,
         },
     );
     let dummy_bytes = b"you look, you die".to_vec();
@@ -550,6 +1097,24 @@ fn test_min_gas_price() {
                 mgp.insert(token::Denomination::NATIVE, 1000);
                 mgp
             },
+            allow_zero_fee_methods: None,
+            max_tx_events: None,
+            block_transfer_limits: BTreeMap::new(),
+            transfer_limits: BTreeMap::new(),
+            max_scheduled_calls_per_round: 0,
+            invariant_check_interval: 0,
+            max_tx_gas: None,
+            admin: None,
+            nonce_consumption: Default::default(),
+            safe_mode_allowed_methods: Default::default(),
+            required_call_formats: Default::default(),
+            min_tx_fee: Default::default(),
+            max_tx_fee: Default::default(),
+            refund_unused_gas: false,
+            fee_fallback_denomination: None,
+            max_tx_size: None,
+            mint_allowlist: Default::default(),
+            max_value_size: None,
         },
     );
 
@@ -579,6 +1144,7 @@ fn test_min_gas_price() {
                 gas: 100,
                 consensus_messages: 0,
             },
+            features: 0,
         },
     };
 
@@ -592,3 +1158,632 @@ fn test_min_gas_price() {
         Core::before_handle_call(&mut tx_ctx, &call).expect("gas price should be ok");
     });
 }
+
+#[test]
+fn test_apply_balance_overrides() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    let mut overrides = types::BalanceOverrides::new();
+    overrides.insert(keys::alice::address(), {
+        let mut balances = BTreeMap::new();
+        balances.insert(token::Denomination::NATIVE, 1_000);
+        balances
+    });
+
+    // Overrides should only be visible inside the simulation context.
+    ctx.with_simulation(|mut sim_ctx| {
+        Core::apply_balance_overrides::<Accounts, _>(&mut sim_ctx, overrides);
+        let balance = Accounts::get_balance(
+            sim_ctx.runtime_state(),
+            keys::alice::address(),
+            token::Denomination::NATIVE,
+        )
+        .expect("get_balance should succeed");
+        assert_eq!(balance, 1_000, "balance should be overridden");
+    });
+    let balance = Accounts::get_balance(
+        ctx.runtime_state(),
+        keys::alice::address(),
+        token::Denomination::NATIVE,
+    )
+    .expect("get_balance should succeed");
+    assert_eq!(balance, 0, "overrides should not persist");
+}
+
+#[test]
+fn test_runtime_metadata() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            admin: Some(keys::alice::address()),
+            ..Default::default()
+        },
+    );
+
+    let tx_by = |sigspec| {
+        let mut tx = mock::transaction();
+        tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(sigspec, 0)];
+        tx
+    };
+    let set_name = |value: &[u8]| types::SetMetadata {
+        key: "name".to_owned(),
+        value: Some(value.to_vec()),
+    };
+
+    // The admin should be able to set metadata.
+    let tx = tx_by(keys::alice::sigspec());
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        Core::tx_set_metadata(&mut tx_ctx, set_name(b"My Runtime"))
+            .expect("admin should be able to set metadata");
+        tx_ctx.commit();
+    });
+    let value = Core::query_metadata(
+        &mut ctx,
+        types::MetadataQuery {
+            key: "name".to_owned(),
+        },
+    )
+    .expect("metadata query should succeed");
+    assert_eq!(value, Some(b"My Runtime".to_vec()));
+
+    // Other accounts should not be able to set metadata.
+    let tx = tx_by(keys::bob::sigspec());
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        let err = Core::tx_set_metadata(&mut tx_ctx, set_name(b"Evil Runtime"))
+            .expect_err("non-admin should not be able to set metadata");
+        assert!(matches!(err, super::Error::Forbidden));
+    });
+    let value = Core::query_metadata(
+        &mut ctx,
+        types::MetadataQuery {
+            key: "name".to_owned(),
+        },
+    )
+    .expect("metadata query should succeed");
+    assert_eq!(value, Some(b"My Runtime".to_vec()));
+
+    // Unknown keys should not have a value.
+    let value = Core::query_metadata(
+        &mut ctx,
+        types::MetadataQuery {
+            key: "upgrade".to_owned(),
+        },
+    )
+    .expect("metadata query should succeed");
+    assert_eq!(value, None);
+}
+
+#[test]
+fn test_safe_mode() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            max_batch_gas: u64::MAX,
+            min_gas_price: {
+                let mut mgp = BTreeMap::new();
+                mgp.insert(token::Denomination::NATIVE, 0);
+                mgp
+            },
+            admin: Some(keys::alice::address()),
+            safe_mode_allowed_methods: vec!["core.SetMetadata".to_owned()].into_iter().collect(),
+            ..Default::default()
+        },
+    );
+
+    let tx_for = |method: &str, sigspec| {
+        let mut tx = mock::transaction();
+        tx.call.method = method.to_owned();
+        tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(sigspec, 0)];
+        tx.auth_info.fee.consensus_messages = 0;
+        tx
+    };
+    let set_safe_mode = |enabled| types::SetSafeMode { enabled };
+
+    // Calls should be allowed outside safe mode.
+    let tx = tx_for("mock", keys::bob::sigspec());
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        Core::before_handle_call(&mut tx_ctx, &call).expect("calls should be allowed");
+    });
+
+    // Only the admin should be able to toggle safe mode.
+    let tx = tx_for("core.SetSafeMode", keys::bob::sigspec());
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        let err = Core::tx_set_safe_mode(&mut tx_ctx, set_safe_mode(true))
+            .expect_err("non-admin should not be able to enable safe mode");
+        assert!(matches!(err, super::Error::Forbidden));
+    });
+    assert!(!Core::query_safe_mode(&mut ctx, ()).unwrap());
+
+    let tx = tx_for("core.SetSafeMode", keys::alice::sigspec());
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        Core::tx_set_safe_mode(&mut tx_ctx, set_safe_mode(true))
+            .expect("admin should be able to enable safe mode");
+        tx_ctx.commit();
+    });
+    assert!(Core::query_safe_mode(&mut ctx, ()).unwrap());
+
+    // Normal calls should be rejected in safe mode.
+    let tx = tx_for("mock", keys::bob::sigspec());
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        let err = Core::before_handle_call(&mut tx_ctx, &call)
+            .expect_err("normal calls should be rejected in safe mode");
+        assert!(matches!(err, super::Error::SafeMode(method) if method == "mock"));
+    });
+
+    // Recovery calls should proceed in safe mode.
+    for method in ["core.SetMetadata", "core.SetSafeMode"] {
+        let tx = tx_for(method, keys::alice::sigspec());
+        ctx.with_tx(0, tx, |mut tx_ctx, call| {
+            Core::before_handle_call(&mut tx_ctx, &call)
+                .expect("recovery calls should be allowed in safe mode");
+        });
+    }
+
+    // Disabling safe mode should allow normal calls again.
+    let tx = tx_for("core.SetSafeMode", keys::alice::sigspec());
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        Core::tx_set_safe_mode(&mut tx_ctx, set_safe_mode(false))
+            .expect("admin should be able to disable safe mode");
+        tx_ctx.commit();
+    });
+    assert!(!Core::query_safe_mode(&mut ctx, ()).unwrap());
+
+    let tx = tx_for("mock", keys::bob::sigspec());
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        Core::before_handle_call(&mut tx_ctx, &call).expect("calls should be allowed");
+    });
+}
+
+#[test]
+fn test_required_call_formats() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            max_batch_gas: u64::MAX,
+            min_gas_price: {
+                let mut mgp = BTreeMap::new();
+                mgp.insert(token::Denomination::NATIVE, 0);
+                mgp
+            },
+            required_call_formats: {
+                let mut rcf = BTreeMap::new();
+                rcf.insert(
+                    "secret.Reveal".to_owned(),
+                    transaction::CallFormat::EncryptedX25519DeoxysII,
+                );
+                rcf
+            },
+            ..Default::default()
+        },
+    );
+
+    let tx_for = |method: &str, format| {
+        let mut tx = mock::transaction();
+        tx.call.method = method.to_owned();
+        tx.call.format = format;
+        tx.auth_info.fee.consensus_messages = 0;
+        tx
+    };
+
+    // Plain calls to methods requiring encryption should be rejected.
+    let tx = tx_for("secret.Reveal", transaction::CallFormat::Plain);
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        let err = Core::before_handle_call(&mut tx_ctx, &call)
+            .expect_err("plain calls should be rejected");
+        assert!(
+            matches!(err, super::Error::EncryptionRequired(method) if method == "secret.Reveal")
+        );
+    });
+
+    // Encrypted calls to methods requiring encryption should be accepted.
+    let tx = tx_for(
+        "secret.Reveal",
+        transaction::CallFormat::EncryptedX25519DeoxysII,
+    );
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        Core::before_handle_call(&mut tx_ctx, &call).expect("encrypted calls should be accepted");
+    });
+
+    // Methods without a required call format should accept any call format.
+    let tx = tx_for("mock", transaction::CallFormat::Plain);
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        Core::before_handle_call(&mut tx_ctx, &call).expect("plain calls should be accepted");
+    });
+}
+
+// Runtime that supports transfers in atomic groups.
+struct AtomicGroupRuntime;
+
+impl Runtime for AtomicGroupRuntime {
+    const VERSION: Version = Version::new(0, 0, 0);
+
+    type Modules = (Core, Accounts);
+
+    fn genesis_state() -> <Self::Modules as module::MigrationHandler>::Genesis {
+        Default::default()
+    }
+}
+
+fn signer_address(sk: &PrivateKey) -> Address {
+    Address::from_sigspec(&SignatureAddressSpec::Ed25519(sk.public_key().into()))
+}
+
+fn grouped_transfer(
+    from: &PrivateKey,
+    nonce: u64,
+    to: Address,
+    amount: u128,
+) -> transaction::Transaction {
+    let mut tx = mock::transaction();
+    tx.call.method = "accounts.Transfer".to_owned();
+    tx.call.body = cbor::to_value(accounts::types::Transfer {
+        to,
+        amount: token::BaseUnits::new(amount, token::Denomination::NATIVE),
+    });
+    tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(
+        SignatureAddressSpec::Ed25519(from.public_key().into()),
+        nonce,
+    )];
+    tx.auth_info.fee.gas = 1_000;
+    tx.auth_info.fee.consensus_messages = 0;
+    tx
+}
+
+fn sign_tx(
+    sk: &PrivateKey,
+    body: Vec<u8>,
+    context_base: &[u8],
+) -> transaction::UnverifiedTransaction {
+    let signature = sk
+        .sign(&get_chain_context_for(context_base), &body)
+        .expect("signing should work");
+    transaction::UnverifiedTransaction(
+        body,
+        vec![transaction::AuthProof::Signature(
+            signature.as_ref().to_vec().into(),
+        )],
+    )
+}
+
+fn sign_atomic_group(
+    txs: Vec<(&PrivateKey, transaction::Transaction)>,
+) -> Vec<transaction::UnverifiedTransaction> {
+    let bodies: Vec<Vec<u8>> = txs.iter().map(|(_, tx)| cbor::to_vec(tx.clone())).collect();
+    let context_base = transaction::atomic_group_signature_context_base(
+        &bodies.iter().map(Vec::as_slice).collect::<Vec<_>>(),
+    );
+    txs.into_iter()
+        .zip(bodies)
+        .map(|((sk, _), body)| sign_tx(sk, body, &context_base))
+        .collect()
+}
+
+fn native_balance<C: Context>(ctx: &mut C, address: Address) -> u128 {
+    Accounts::get_balance(ctx.runtime_state(), address, token::Denomination::NATIVE).unwrap()
+}
+
+fn atomic_group<C: BatchContext>(
+    ctx: &mut C,
+    txs: Vec<transaction::UnverifiedTransaction>,
+) -> Result<(), super::Error> {
+    let mut tx = mock::transaction();
+    tx.call.method = "core.AtomicGroup".to_owned();
+    tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(
+        keys::dave::sigspec(),
+        0,
+    )];
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        let result = Core::tx_atomic_group(&mut tx_ctx, types::AtomicGroup { txs });
+        if result.is_ok() {
+            tx_ctx.commit();
+        }
+        result
+    })
+}
+
+#[test]
+fn test_atomic_group() {
+    let _guard = context::TEST_GUARD.lock().unwrap();
+    context::reset_chain_context();
+    set_chain_context(
+        "8000000000000000000000000000000000000000000000000000000000000000".into(),
+        "643fb06848be7e970af3b5b2d772eb8cfb30499c8162bc18ac03df2f5e22520e",
+    );
+
+    let alice = PrivateKey::from_test_seed("atomic group alice".to_string());
+    let bob = PrivateKey::from_test_seed("atomic group bob".to_string());
+
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<AtomicGroupRuntime>(Mode::ExecuteTx);
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            max_batch_gas: u64::MAX,
+            max_tx_signers: 8,
+            // Grouped transactions do not pay fees so they should not be subject to this.
+            min_gas_price: {
+                let mut mgp = BTreeMap::new();
+                mgp.insert(token::Denomination::NATIVE, 1);
+                mgp
+            },
+            ..Default::default()
+        },
+    );
+    Accounts::init_or_migrate(
+        &mut ctx,
+        &mut types::Metadata::default(),
+        accounts::Genesis {
+            balances: {
+                let mut balances = BTreeMap::new();
+                balances.insert(signer_address(&alice), {
+                    let mut denominations = BTreeMap::new();
+                    denominations.insert(token::Denomination::NATIVE, 1_000);
+                    denominations
+                });
+                balances.insert(signer_address(&bob), {
+                    let mut denominations = BTreeMap::new();
+                    denominations.insert(token::Denomination::NATIVE, 50);
+                    denominations
+                });
+                balances
+            },
+            total_supplies: {
+                let mut total_supplies = BTreeMap::new();
+                total_supplies.insert(token::Denomination::NATIVE, 1_050);
+                total_supplies
+            },
+            parameters: accounts::Parameters {
+                gas_costs: accounts::GasCosts {
+                    tx_transfer: 100,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    // Groups must not be empty.
+    assert!(matches!(
+        atomic_group(&mut ctx, vec![]),
+        Err(super::Error::InvalidArgument(_))
+    ));
+
+    // Grouped transactions must be properly signed.
+    let dummy_bytes = b"you look, you die".to_vec();
+    assert!(matches!(
+        atomic_group(
+            &mut ctx,
+            vec![transaction::UnverifiedTransaction(
+                dummy_bytes.clone(),
+                vec![transaction::AuthProof::Signature(dummy_bytes.into())],
+            )],
+        ),
+        Err(super::Error::MalformedTransaction(_))
+    ));
+
+    // Transactions signed for standalone execution must not be accepted in a group.
+    let tx = grouped_transfer(&alice, 0, keys::charlie::address(), 100);
+    assert!(matches!(
+        atomic_group(
+            &mut ctx,
+            vec![sign_tx(
+                &alice,
+                cbor::to_vec(tx),
+                transaction::SIGNATURE_CONTEXT_BASE
+            )],
+        ),
+        Err(super::Error::MalformedTransaction(_))
+    ));
+
+    // Transactions signed for one group must not be accepted in a different group.
+    let mut txs = sign_atomic_group(vec![
+        (
+            &alice,
+            grouped_transfer(&alice, 0, keys::charlie::address(), 100),
+        ),
+        (
+            &bob,
+            grouped_transfer(&bob, 0, keys::charlie::address(), 50),
+        ),
+    ]);
+    txs.swap(0, 1);
+    assert!(matches!(
+        atomic_group(&mut ctx, txs.clone()),
+        Err(super::Error::MalformedTransaction(_))
+    ));
+    txs.truncate(1);
+    assert!(matches!(
+        atomic_group(&mut ctx, txs),
+        Err(super::Error::MalformedTransaction(_))
+    ));
+
+    // In case a transaction in the middle of the group fails, none of them should take effect.
+    let err = atomic_group(
+        &mut ctx,
+        sign_atomic_group(vec![
+            (
+                &alice,
+                grouped_transfer(&alice, 0, keys::charlie::address(), 100),
+            ),
+            (
+                &bob,
+                grouped_transfer(&bob, 0, keys::charlie::address(), 1_000),
+            ),
+            (
+                &alice,
+                grouped_transfer(&alice, 1, keys::charlie::address(), 10),
+            ),
+        ]),
+    )
+    .expect_err("group with a failing transaction should fail");
+    assert!(matches!(err, super::Error::AtomicGroupFailed(1, _)));
+    assert_eq!(native_balance(&mut ctx, signer_address(&alice)), 1_000);
+    assert_eq!(native_balance(&mut ctx, keys::charlie::address()), 0);
+    assert_eq!(
+        Accounts::get_nonce(ctx.runtime_state(), signer_address(&alice)).unwrap(),
+        0,
+        "nonces of a failed group should not be updated"
+    );
+
+    // All transactions of a successful group should take effect.
+    atomic_group(
+        &mut ctx,
+        sign_atomic_group(vec![
+            (
+                &alice,
+                grouped_transfer(&alice, 0, keys::charlie::address(), 100),
+            ),
+            (
+                &bob,
+                grouped_transfer(&bob, 0, keys::charlie::address(), 50),
+            ),
+            (
+                &alice,
+                grouped_transfer(&alice, 1, keys::charlie::address(), 10),
+            ),
+        ]),
+    )
+    .expect("group should succeed");
+    assert_eq!(native_balance(&mut ctx, signer_address(&alice)), 890);
+    assert_eq!(native_balance(&mut ctx, signer_address(&bob)), 0);
+    assert_eq!(native_balance(&mut ctx, keys::charlie::address()), 160);
+    assert_eq!(
+        Accounts::get_nonce(ctx.runtime_state(), signer_address(&alice)).unwrap(),
+        2
+    );
+
+    // Grouped transactions must not pay fees as the group pays a single fee for all of them.
+    let mut tx = grouped_transfer(&bob, 1, keys::charlie::address(), 0);
+    tx.auth_info.fee.amount = token::BaseUnits::new(1, token::Denomination::NATIVE);
+    assert!(matches!(
+        atomic_group(&mut ctx, sign_atomic_group(vec![(&bob, tx)])),
+        Err(super::Error::InvalidArgument(_))
+    ));
+
+    // The group transaction should be charged once for the gas used by grouped transactions and
+    // must be able to cover their gas limits.
+    let txs = sign_atomic_group(vec![
+        (
+            &alice,
+            grouped_transfer(&alice, 2, keys::charlie::address(), 10),
+        ),
+        (
+            &alice,
+            grouped_transfer(&alice, 3, keys::charlie::address(), 10),
+        ),
+    ]);
+    let mut tx = mock::transaction();
+    tx.call.method = "core.AtomicGroup".to_owned();
+    tx.auth_info.fee.gas = 1_500;
+    ctx.with_tx(0, tx.clone(), |mut tx_ctx, _call| {
+        let err = Core::tx_atomic_group(&mut tx_ctx, types::AtomicGroup { txs: txs.clone() })
+            .expect_err("group exceeding the gas limit should fail");
+        assert!(matches!(err, super::Error::OutOfGas(1_500, 2_000)));
+        assert_eq!(Core::used_tx_gas(&mut tx_ctx), 0);
+    });
+    tx.auth_info.fee.gas = 2_000;
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        Core::tx_atomic_group(&mut tx_ctx, types::AtomicGroup { txs })
+            .expect("group should succeed");
+        assert_eq!(Core::used_tx_gas(&mut tx_ctx), 200);
+    });
+
+    // Grouped transactions should be authenticated in check mode as well.
+    drop(ctx);
+    let mut ctx = mock.create_ctx_for_runtime::<AtomicGroupRuntime>(Mode::CheckTx);
+    let err = atomic_group(
+        &mut ctx,
+        sign_atomic_group(vec![(
+            &alice,
+            grouped_transfer(&alice, 1, keys::charlie::address(), 10),
+        )]),
+    )
+    .expect_err("group with an invalid nonce should fail");
+    assert!(matches!(err, super::Error::AtomicGroupFailed(0, _)));
+    atomic_group(
+        &mut ctx,
+        sign_atomic_group(vec![(
+            &alice,
+            grouped_transfer(&alice, 2, keys::charlie::address(), 10),
+        )]),
+    )
+    .expect("group should pass checks");
+}
+
+#[test]
+fn test_ensure_value_size() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    // By default, value size should not be limited.
+    Core::ensure_value_size(&mut ctx, usize::MAX).expect("value size should not be limited");
+
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            max_value_size: Some(100),
+            ..Default::default()
+        },
+    );
+    Core::ensure_value_size(&mut ctx, 100).expect("value at the limit should be allowed");
+    assert!(matches!(
+        Core::ensure_value_size(&mut ctx, 101),
+        Err(super::Error::ValueTooLarge(100, 101))
+    ));
+}
+
+#[test]
+fn test_refund_unused_gas() {
+    let denom = token::Denomination::from_str("SMALLER").unwrap();
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    Core::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            max_batch_gas: u64::MAX,
+            min_gas_price: {
+                let mut mgp = BTreeMap::new();
+                mgp.insert(denom.clone(), 0);
+                mgp
+            },
+            refund_unused_gas: true,
+            ..Default::default()
+        },
+    );
+    Accounts::set_balance(
+        ctx.runtime_state(),
+        keys::alice::address(),
+        &token::BaseUnits::new(20_000, denom.clone()),
+    );
+
+    // Pay a fee of 10_000 for 1_000 gas, resulting in a gas price of 10.
+    let fee = token::BaseUnits::new(10_000, denom.clone());
+    Accounts::move_into_fee_accumulator(&mut ctx, keys::alice::address(), &fee)
+        .expect("fee payment should succeed");
+
+    let mut tx = mock::transaction();
+    tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(
+        keys::alice::sigspec(),
+        0,
+    )];
+    tx.auth_info.fee.amount = fee;
+    tx.auth_info.fee.gas = 1_000;
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        Core::use_tx_gas(&mut tx_ctx, 400).expect("using gas should succeed");
+        Core::refund_unused_gas(&mut tx_ctx).expect("refund should succeed");
+        tx_ctx.commit();
+    });
+
+    // The unused 600 gas should be refunded at the gas price of 10.
+    let balance = Accounts::get_balance(ctx.runtime_state(), keys::alice::address(), denom)
+        .expect("get_balance should succeed");
+    assert_eq!(balance, 10_000 + 600 * 10, "unused gas should be refunded");
+}
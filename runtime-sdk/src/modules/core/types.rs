@@ -1,8 +1,13 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use crate::{
     keymanager::SignedPublicKey,
-    types::transaction::{CallerAddress, Transaction},
+    trace,
+    types::{
+        address::Address,
+        token,
+        transaction::{Call, CallResult, CallerAddress, Transaction, UnverifiedTransaction},
+    },
 };
 
 /// Key in the versions map used for the global state version.
@@ -24,6 +29,137 @@ pub struct EstimateGasQuery {
     pub caller: Option<CallerAddress>,
     /// The unsigned transaction to estimate.
     pub tx: Transaction,
+    /// Account balances to use instead of the current ones during estimation.
+    #[cbor(optional, default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub balance_overrides: BalanceOverrides,
+}
+
+/// Arguments for the EstimateGasEncoded query.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct EstimateGasEncodedQuery {
+    /// Name of the module-controlled transaction decoding scheme.
+    pub scheme: String,
+    /// The transaction encoded using the given scheme.
+    pub body: Vec<u8>,
+    /// Account balances to use instead of the current ones during estimation.
+    #[cbor(optional, default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub balance_overrides: BalanceOverrides,
+}
+
+/// Account balances, overriding existing ones for the duration of a simulation.
+pub type BalanceOverrides = BTreeMap<Address, BTreeMap<token::Denomination, u128>>;
+
+/// Arguments for the Schedule method.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct Schedule {
+    /// Round at the start of which the call should be executed.
+    pub round: u64,
+    /// The call to execute.
+    pub call: Call,
+    /// Amount of gas the call can use.
+    pub gas: u64,
+}
+
+/// Arguments for the SetMetadata method.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct SetMetadata {
+    /// Key of the metadata entry.
+    pub key: String,
+    /// New value of the metadata entry. In case this is not set, the entry is removed.
+    #[cbor(optional)]
+    pub value: Option<Vec<u8>>,
+}
+
+/// Arguments for the SetSafeMode method.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct SetSafeMode {
+    /// Whether safe mode should be enabled.
+    pub enabled: bool,
+}
+
+/// Arguments for the SetModuleEnabled method.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct SetModuleEnabled {
+    /// Name of the module.
+    pub module: String,
+    /// Whether the module should be enabled.
+    pub enabled: bool,
+}
+
+/// Arguments for the AtomicGroup method.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct AtomicGroup {
+    /// Signed transactions to execute in order. Each transaction is authenticated by its own
+    /// signers, must not pay any fees and its gas limit is paid for by the group transaction.
+    ///
+    /// Transactions must be signed using the signature context returned by
+    /// [`atomic_group_signature_context_base`](crate::types::transaction::atomic_group_signature_context_base)
+    /// for the bodies of all transactions in the group.
+    pub txs: Vec<UnverifiedTransaction>,
+}
+
+/// Arguments for the Metadata query.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct MetadataQuery {
+    /// Key of the metadata entry.
+    pub key: String,
+}
+
+/// A call scheduled for execution at a future round.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct ScheduledCall {
+    /// Address of the account that scheduled the call.
+    pub caller: Address,
+    /// The call to execute.
+    pub call: Call,
+    /// Amount of gas the call can use.
+    pub gas: u64,
+    /// Round in which the call has been scheduled.
+    pub scheduled_round: u64,
+    /// Position of the call among the calls scheduled for the same round.
+    pub sequence: u64,
+}
+
+/// Arguments for the trace query.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct TraceQuery {
+    /// The unsigned transaction to trace.
+    pub tx: Transaction,
+    /// Account balances to use instead of the current ones during tracing.
+    #[cbor(optional, default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub balance_overrides: BalanceOverrides,
+}
+
+/// Response to the trace query.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct TraceQueryResponse {
+    /// Result of the traced call.
+    pub result: CallResult,
+    /// Operations performed by the call, in order.
+    pub trace: Vec<trace::Operation>,
+}
+
+/// A single query in a batch.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct BatchQueryItem {
+    /// Name of the query method.
+    pub method: String,
+    /// Query arguments.
+    pub args: cbor::Value,
+}
+
+/// Arguments for the BatchQuery query.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct BatchQuery {
+    /// Queries to perform, in order.
+    pub queries: Vec<BatchQueryItem>,
+}
+
+/// Response to the BatchQuery query.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct BatchQueryResponse {
+    /// Results of the queries, in the same order as the queries.
+    pub results: Vec<CallResult>,
 }
 
 /// Response to the call data public key query.
@@ -32,3 +168,121 @@ pub struct CallDataPublicKeyQueryResponse {
     /// Public key used for deriving the shared secret for encrypting call data.
     pub public_key: SignedPublicKey,
 }
+
+/// Error code that can be emitted by a module.
+#[derive(Clone, Debug, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct ErrorCode {
+    /// Error code.
+    pub code: u32,
+    /// Name of the error.
+    pub name: String,
+}
+
+/// Registry of known error codes, keyed by module name.
+pub type ErrorRegistry = BTreeMap<String, Vec<ErrorCode>>;
+
+/// Set of optional capabilities supported by a runtime.
+pub type Capabilities = BTreeSet<String>;
+
+/// Arguments for the HistoricMinGasPrice query.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct HistoricMinGasPriceQuery {
+    pub round: u64,
+}
+
+/// Minimum gas prices in effect starting at a given round.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct MinGasPriceChange {
+    pub round: u64,
+    pub min_gas_price: BTreeMap<token::Denomination, u128>,
+}
+
+/// History of minimum gas price changes, ordered from oldest to newest.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct MinGasPriceHistory {
+    pub changes: Vec<MinGasPriceChange>,
+}
+
+impl MinGasPriceHistory {
+    /// Records the minimum gas prices in effect at the given round in case they differ from the
+    /// most recently recorded ones, dropping the oldest change in case more than `capacity`
+    /// changes would be retained.
+    ///
+    /// Returns whether the history has been modified.
+    pub fn record(
+        &mut self,
+        round: u64,
+        min_gas_price: &BTreeMap<token::Denomination, u128>,
+        capacity: usize,
+    ) -> bool {
+        if matches!(self.changes.last(), Some(last) if &last.min_gas_price == min_gas_price) {
+            return false;
+        }
+
+        self.changes.push(MinGasPriceChange {
+            round,
+            min_gas_price: min_gas_price.clone(),
+        });
+        if self.changes.len() > capacity {
+            self.changes.remove(0);
+        }
+        true
+    }
+
+    /// Minimum gas prices in effect at the given round or `None` in case the round precedes the
+    /// retained history.
+    pub fn at(&self, round: u64) -> Option<&BTreeMap<token::Denomination, u128>> {
+        self.changes
+            .iter()
+            .rev()
+            .find(|change| change.round <= round)
+            .map(|change| &change.min_gas_price)
+    }
+}
+
+/// Ring buffer of recent block timestamps used for computing the smoothed time.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct RecentTimestamps {
+    /// Index of the slot that will be overwritten next once the buffer is full.
+    pub next: u64,
+    /// Recent block timestamps.
+    pub timestamps: Vec<u64>,
+}
+
+impl RecentTimestamps {
+    /// Records a new timestamp, overwriting the oldest one in case the buffer already holds
+    /// `capacity` timestamps.
+    pub fn push(&mut self, timestamp: u64, capacity: usize) {
+        if self.timestamps.len() < capacity {
+            self.timestamps.push(timestamp);
+            return;
+        }
+
+        let next = (self.next as usize) % capacity;
+        self.timestamps[next] = timestamp;
+        self.next = ((next + 1) % capacity) as u64;
+    }
+
+    /// Median of the recorded timestamps or `None` if no timestamps have been recorded.
+    ///
+    /// In case of an even number of timestamps, the lower of the two middle values is used.
+    pub fn median(&self) -> Option<u64> {
+        if self.timestamps.is_empty() {
+            return None;
+        }
+
+        let mut sorted = self.timestamps.clone();
+        sorted.sort_unstable();
+        Some(sorted[(sorted.len() - 1) / 2])
+    }
+}
+
+/// Amounts transferred by an account in a round, used for enforcing the per-block transfer
+/// limits.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct TransferTotals {
+    /// Round in which the amounts have been transferred.
+    pub round: u64,
+    /// Total amount transferred so far, per denomination.
+    pub amounts: BTreeMap<token::Denomination, u128>,
+}
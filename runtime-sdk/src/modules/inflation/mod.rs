@@ -0,0 +1,272 @@
+//! Inflation module.
+//!
+//! Mints new tokens at the end of each block based on a configured annual inflation rate and the
+//! expected block time. The rate is applied to a fixed base supply, which is the total supply at
+//! the time the parameters were last changed, so that inflation does not compound every block.
+//! Any fractional amounts that cannot be minted in a given block are carried over to subsequent
+//! blocks so that no tokens are lost to truncation.
+use num_traits::Zero;
+use once_cell::sync::Lazy;
+use slog::error;
+use thiserror::Error;
+
+use crate::{
+    context::Context,
+    error,
+    module::{self, Module as _, Parameters as _},
+    modules, storage,
+    types::{address::Address, token},
+};
+
+#[cfg(test)]
+mod test;
+
+/// Unique module name.
+const MODULE_NAME: &str = "inflation";
+
+/// Number of seconds in a year.
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Errors emitted by the inflation module.
+#[derive(Error, Debug, oasis_runtime_sdk_macros::Error)]
+pub enum Error {
+    #[error("invalid argument")]
+    #[sdk_error(code = 1)]
+    InvalidArgument,
+}
+
+/// Events emitted by the inflation module.
+#[derive(Debug, cbor::Encode, oasis_runtime_sdk_macros::Event)]
+#[cbor(untagged)]
+pub enum Event {
+    #[sdk_event(code = 1)]
+    Inflation {
+        pool: Address,
+        amount: token::BaseUnits,
+    },
+}
+
+/// Parameters for the inflation module.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct Parameters {
+    /// Denomination that is being minted.
+    pub denomination: token::Denomination,
+
+    /// Annual inflation rate as a fraction of the denomination's total supply.
+    pub annual_rate_numerator: u64,
+    pub annual_rate_denominator: u64,
+
+    /// Expected time between blocks (in seconds).
+    pub block_time: u64,
+}
+
+/// Errors emitted during inflation parameter validation.
+#[derive(Error, Debug)]
+pub enum ParameterValidationError {
+    #[error("invalid annual rate (zero denominator)")]
+    InvalidAnnualRate,
+
+    #[error("invalid block time")]
+    InvalidBlockTime,
+}
+
+impl module::Parameters for Parameters {
+    type Error = ParameterValidationError;
+
+    fn validate_basic(&self) -> Result<(), Self::Error> {
+        if self.annual_rate_denominator.is_zero() {
+            return Err(ParameterValidationError::InvalidAnnualRate);
+        }
+        if self.block_time.is_zero() {
+            return Err(ParameterValidationError::InvalidBlockTime);
+        }
+
+        Ok(())
+    }
+}
+
+/// Genesis state for the inflation module.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct Genesis {
+    pub parameters: Parameters,
+}
+
+/// State schema constants.
+pub mod state {
+    /// Fractional amount carried over from previous blocks, scaled by the per-block divisor.
+    pub const REMAINDER: &[u8] = &[0x01];
+    /// Total supply that the inflation is computed from.
+    pub const BASE_SUPPLY: &[u8] = &[0x02];
+    /// Parameters that the base supply and the remainder have been computed for.
+    pub const APPLIED_PARAMETERS: &[u8] = &[0x03];
+}
+
+pub struct Module<Accounts: modules::accounts::API> {
+    _accounts: std::marker::PhantomData<Accounts>,
+}
+
+/// Module's address that receives the minted tokens.
+pub static ADDRESS_INFLATION_POOL: Lazy<Address> =
+    Lazy::new(|| Address::from_module(MODULE_NAME, "inflation-pool"));
+
+impl<Accounts: modules::accounts::API> Module<Accounts> {
+    fn query_parameters<C: Context>(ctx: &mut C, _args: ()) -> Result<Parameters, Error> {
+        Ok(Self::params(ctx.runtime_state()))
+    }
+
+    /// Computes the amount to mint in the current block given the base supply and the remainder
+    /// carried over from previous blocks.
+    ///
+    /// Returns the amount and the new remainder or `None` in case of overflow.
+    fn block_amount(
+        params: &Parameters,
+        base_supply: u128,
+        remainder: u128,
+    ) -> Option<(u128, u128)> {
+        let divisor =
+            (params.annual_rate_denominator as u128).checked_mul(SECONDS_PER_YEAR as u128)?;
+        let scaled = base_supply
+            .checked_mul(params.annual_rate_numerator as u128)?
+            .checked_mul(params.block_time as u128)?
+            .checked_add(remainder)?;
+
+        Some((scaled / divisor, scaled % divisor))
+    }
+}
+
+impl<Accounts: modules::accounts::API> module::Module for Module<Accounts> {
+    const NAME: &'static str = MODULE_NAME;
+    type Error = Error;
+    type Event = Event;
+    type Parameters = Parameters;
+}
+
+impl<Accounts: modules::accounts::API> module::MethodHandler for Module<Accounts> {
+    fn dispatch_query<C: Context>(
+        ctx: &mut C,
+        method: &str,
+        args: cbor::Value,
+    ) -> module::DispatchResult<cbor::Value, Result<cbor::Value, error::RuntimeError>> {
+        match method {
+            "inflation.Parameters" => module::dispatch_query(ctx, args, Self::query_parameters),
+            _ => module::DispatchResult::Unhandled(args),
+        }
+    }
+
+    fn register_error_codes(registry: &mut modules::core::types::ErrorRegistry) {
+        module::register_error_codes::<Self>(registry)
+    }
+}
+
+impl<Accounts: modules::accounts::API> Module<Accounts> {
+    /// Initialize state from genesis.
+    fn init<C: Context>(ctx: &mut C, genesis: Genesis) {
+        genesis
+            .parameters
+            .validate_basic()
+            .expect("invalid genesis parameters");
+
+        // Set genesis parameters.
+        Self::set_params(ctx.runtime_state(), genesis.parameters);
+    }
+
+    /// Migrate state from a previous version.
+    fn migrate<C: Context>(_ctx: &mut C, _from: u32) -> bool {
+        // No migrations currently supported.
+        false
+    }
+}
+
+impl<Accounts: modules::accounts::API> module::MigrationHandler for Module<Accounts> {
+    type Genesis = Genesis;
+
+    fn init_or_migrate<C: Context>(
+        ctx: &mut C,
+        meta: &mut modules::core::types::Metadata,
+        genesis: Self::Genesis,
+    ) -> bool {
+        let version = meta.versions.get(Self::NAME).copied().unwrap_or_default();
+        if version == 0 {
+            // Initialize state from genesis.
+            Self::init(ctx, genesis);
+            meta.versions.insert(Self::NAME.to_owned(), Self::VERSION);
+            return true;
+        }
+
+        // Perform migration.
+        Self::migrate(ctx, version)
+    }
+}
+
+impl<Accounts: modules::accounts::API> module::AuthHandler for Module<Accounts> {}
+
+impl<Accounts: modules::accounts::API> module::BlockHandler for Module<Accounts> {
+    fn end_block<C: Context>(ctx: &mut C) {
+        let params = Self::params(ctx.runtime_state());
+        if params.annual_rate_numerator.is_zero() {
+            return;
+        }
+
+        let total_supply = Accounts::get_total_supplies(ctx.runtime_state())
+            .expect("failed to fetch total supplies")
+            .get(&params.denomination)
+            .copied()
+            .unwrap_or_default();
+
+        let mut store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut tstore = storage::TypedStore::new(&mut store);
+        let applied: Option<Parameters> = tstore.get(state::APPLIED_PARAMETERS);
+        let (base_supply, remainder) = if applied.as_ref() == Some(&params) {
+            (
+                tstore.get(state::BASE_SUPPLY).unwrap_or_default(),
+                tstore.get(state::REMAINDER).unwrap_or_default(),
+            )
+        } else {
+            // Parameters have changed, so start over from the current total supply. The remainder
+            // is scaled by the previous divisor and is therefore discarded.
+            tstore.insert(state::APPLIED_PARAMETERS, params.clone());
+            tstore.insert(state::BASE_SUPPLY, total_supply);
+            tstore.insert(state::REMAINDER, 0u128);
+            (total_supply, 0)
+        };
+
+        // In case of overflow, skip minting for this block.
+        let (amount, remainder) = match Self::block_amount(&params, base_supply, remainder) {
+            Some(result) => result,
+            None => return,
+        };
+
+        if !amount.is_zero() {
+            // Mint in a child context so that a failed mint does not leave partial state behind.
+            let amount = token::BaseUnits::new(amount, params.denomination);
+            let result = ctx.with_child(ctx.mode(), |mut ctx| {
+                Accounts::mint::<Self, _>(&mut ctx, *ADDRESS_INFLATION_POOL, &amount)?;
+                ctx.try_emit_event(Event::Inflation {
+                    pool: *ADDRESS_INFLATION_POOL,
+                    amount,
+                })?;
+                Ok::<_, modules::accounts::Error>(ctx.commit().0)
+            });
+            let tags = match result {
+                Ok(tags) => tags,
+                Err(err) => {
+                    // Do not consume the remainder so that minting resumes once possible.
+                    error!(ctx.get_logger("inflation"), "failed to mint inflation";
+                        "err" => ?err,
+                    );
+                    return;
+                }
+            };
+            for tag in tags {
+                ctx.emit_tag(tag);
+            }
+        }
+
+        let mut store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut tstore = storage::TypedStore::new(&mut store);
+        tstore.insert(state::REMAINDER, remainder);
+    }
+}
+
+impl<Accounts: modules::accounts::API> module::EventHandler for Module<Accounts> {}
+impl<Accounts: modules::accounts::API> module::InvariantHandler for Module<Accounts> {}
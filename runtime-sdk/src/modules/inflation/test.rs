@@ -0,0 +1,239 @@
+//! Tests for the inflation module.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    iter::FromIterator,
+};
+
+use crate::{
+    context::Context,
+    module::{BlockHandler, InvariantHandler, MigrationHandler, Module as _},
+    modules::{
+        accounts::{self, Module as Accounts, API as _},
+        core,
+    },
+    testing::{keys, mock},
+    types::token::Denomination,
+};
+
+use super::{Genesis, Parameters, ADDRESS_INFLATION_POOL, SECONDS_PER_YEAR};
+
+type Inflation = super::Module<Accounts>;
+
+const INITIAL_SUPPLY: u128 = 1_000_000_000;
+
+fn init_accounts<C: Context>(ctx: &mut C) {
+    Accounts::init_or_migrate(
+        ctx,
+        &mut core::types::Metadata::default(),
+        accounts::Genesis {
+            balances: {
+                let mut balances = BTreeMap::new();
+                // Alice.
+                balances.insert(keys::alice::address(), {
+                    let mut denominations = BTreeMap::new();
+                    denominations.insert(Denomination::NATIVE, INITIAL_SUPPLY);
+                    denominations
+                });
+                balances
+            },
+            total_supplies: {
+                let mut total_supplies = BTreeMap::new();
+                total_supplies.insert(Denomination::NATIVE, INITIAL_SUPPLY);
+                total_supplies
+            },
+            ..Default::default()
+        },
+    );
+}
+
+fn total_supply<C: Context>(ctx: &mut C) -> u128 {
+    Accounts::get_total_supplies(ctx.runtime_state())
+        .expect("get_total_supplies should succeed")
+        .get(&Denomination::NATIVE)
+        .copied()
+        .unwrap_or_default()
+}
+
+#[test]
+#[should_panic]
+fn test_init_invalid_block_time() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    Inflation::init_or_migrate(
+        &mut ctx,
+        &mut core::types::Metadata::default(),
+        Genesis {
+            parameters: Parameters {
+                denomination: Denomination::NATIVE,
+                annual_rate_numerator: 5,
+                annual_rate_denominator: 100,
+                block_time: 0,
+            },
+        },
+    );
+}
+
+#[test]
+fn test_inflation_over_many_blocks() {
+    const BLOCKS: u64 = 10_000;
+    const BLOCK_TIME: u64 = 6;
+
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    init_accounts(&mut ctx);
+    Inflation::init_or_migrate(
+        &mut ctx,
+        &mut core::types::Metadata::default(),
+        Genesis {
+            parameters: Parameters {
+                denomination: Denomination::NATIVE,
+                annual_rate_numerator: 5,
+                annual_rate_denominator: 100,
+                block_time: BLOCK_TIME,
+            },
+        },
+    );
+
+    for _ in 0..BLOCKS {
+        Inflation::end_block(&mut ctx);
+    }
+
+    // Inflation should be computed from the initial supply and no tokens should be lost to
+    // truncation.
+    let divisor = 100 * SECONDS_PER_YEAR as u128;
+    let minted = total_supply(&mut ctx) - INITIAL_SUPPLY;
+    assert_eq!(
+        minted,
+        INITIAL_SUPPLY * 5 * (BLOCKS * BLOCK_TIME) as u128 / divisor
+    );
+
+    // Everything should have been minted into the inflation pool.
+    let pool = Accounts::get_balance(
+        ctx.runtime_state(),
+        *ADDRESS_INFLATION_POOL,
+        Denomination::NATIVE,
+    )
+    .expect("get_balance should succeed");
+    assert_eq!(pool, minted);
+
+    // Every block should have emitted an inflation event.
+    let (tags, _) = ctx.commit();
+    let inflation_events = tags
+        .iter()
+        .filter(|tag| tag.key.starts_with(b"inflation"))
+        .count();
+    assert_eq!(inflation_events, BLOCKS as usize);
+}
+
+#[test]
+fn test_inflation_disabled() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    init_accounts(&mut ctx);
+    Inflation::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            denomination: Denomination::NATIVE,
+            annual_rate_numerator: 0,
+            annual_rate_denominator: 100,
+            block_time: 6,
+        },
+    );
+
+    for _ in 0..100 {
+        Inflation::end_block(&mut ctx);
+    }
+    assert_eq!(total_supply(&mut ctx), INITIAL_SUPPLY);
+}
+
+#[test]
+fn test_inflation_parameter_change() {
+    const BLOCKS: u64 = 1_000;
+
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    init_accounts(&mut ctx);
+    let mut params = Parameters {
+        denomination: Denomination::NATIVE,
+        annual_rate_numerator: 5,
+        annual_rate_denominator: 100,
+        block_time: 6,
+    };
+    Inflation::set_params(ctx.runtime_state(), params.clone());
+    for _ in 0..BLOCKS {
+        Inflation::end_block(&mut ctx);
+    }
+
+    // Changing the parameters should rebase inflation on the current total supply and discard
+    // the remainder accumulated under the previous parameters.
+    let supply = total_supply(&mut ctx);
+    params.annual_rate_numerator = 10;
+    params.block_time = 5;
+    Inflation::set_params(ctx.runtime_state(), params);
+    for _ in 0..BLOCKS {
+        Inflation::end_block(&mut ctx);
+    }
+
+    let minted = total_supply(&mut ctx) - supply;
+    assert_eq!(
+        minted,
+        supply * 10 * (BLOCKS * 5) as u128 / (100 * SECONDS_PER_YEAR as u128)
+    );
+}
+
+#[test]
+fn test_inflation_mint_forbidden() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    init_accounts(&mut ctx);
+    Inflation::set_params(
+        ctx.runtime_state(),
+        Parameters {
+            denomination: Denomination::NATIVE,
+            annual_rate_numerator: 5,
+            annual_rate_denominator: 100,
+            block_time: 6,
+        },
+    );
+    let mut core_params = core::Parameters {
+        mint_allowlist: {
+            let mut allowlist = BTreeMap::new();
+            allowlist.insert(
+                Denomination::NATIVE,
+                BTreeSet::from_iter(vec!["other".to_owned()]),
+            );
+            allowlist
+        },
+        ..Default::default()
+    };
+    core::Module::set_params(ctx.runtime_state(), core_params.clone());
+
+    // Failing to mint should not halt the node nor leave any partial state behind.
+    for _ in 0..10 {
+        Inflation::end_block(&mut ctx);
+    }
+    assert_eq!(total_supply(&mut ctx), INITIAL_SUPPLY);
+    Accounts::check_invariants(&mut ctx).expect("invariants should hold");
+
+    // Minting should resume once it is allowed again.
+    core_params.mint_allowlist.clear();
+    core::Module::set_params(ctx.runtime_state(), core_params);
+    Inflation::end_block(&mut ctx);
+    assert_eq!(
+        total_supply(&mut ctx) - INITIAL_SUPPLY,
+        INITIAL_SUPPLY * 5 * 6 / (100 * SECONDS_PER_YEAR as u128)
+    );
+
+    // Only the successful mint should have emitted an inflation event.
+    let (tags, _) = ctx.commit();
+    let inflation_events = tags
+        .iter()
+        .filter(|tag| tag.key.starts_with(b"inflation"))
+        .count();
+    assert_eq!(inflation_events, 1);
+}
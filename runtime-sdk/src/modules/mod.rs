@@ -4,4 +4,6 @@ pub mod accounts;
 pub mod consensus;
 pub mod consensus_accounts;
 pub mod core;
+pub mod inflation;
+pub mod names;
 pub mod rewards;
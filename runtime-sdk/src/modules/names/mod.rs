@@ -0,0 +1,452 @@
+//! Names module.
+//!
+//! This module allows accounts to register short human-readable names that resolve to addresses.
+//! Registrations are paid for and may expire, in which case they need to be renewed by the owner
+//! before anyone else can register the name.
+use std::collections::BTreeSet;
+
+use thiserror::Error;
+
+use crate::{
+    context::{Context, TxContext},
+    core::consensus::beacon::EpochTime,
+    error, module,
+    module::{CallResult, Module as _, Parameters as _},
+    modules,
+    modules::core::{Module as Core, API as _},
+    storage::{self, Prefix},
+    types::{address::Address, token, transaction::AuthInfo},
+};
+
+#[cfg(test)]
+mod test;
+pub mod types;
+
+/// Unique module name.
+const MODULE_NAME: &str = "names";
+
+/// Errors emitted by the names module.
+#[derive(Error, Debug, oasis_runtime_sdk_macros::Error)]
+pub enum Error {
+    #[error("invalid argument")]
+    #[sdk_error(code = 1)]
+    InvalidArgument,
+
+    #[error("invalid name")]
+    #[sdk_error(code = 2)]
+    InvalidName,
+
+    #[error("name already registered")]
+    #[sdk_error(code = 3)]
+    NameTaken,
+
+    #[error("name not found")]
+    #[sdk_error(code = 4)]
+    NotFound,
+
+    #[error("forbidden")]
+    #[sdk_error(code = 5)]
+    Forbidden,
+
+    #[error("insufficient balance to pay the registration fee")]
+    #[sdk_error(code = 6)]
+    InsufficientBalance,
+
+    #[error("core: {0}")]
+    #[sdk_error(transparent)]
+    Core(#[from] modules::core::Error),
+}
+
+/// Gas costs.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct GasCosts {
+    pub tx_register: u64,
+    pub tx_renew: u64,
+}
+
+/// Parameters for the names module.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct Parameters {
+    pub gas_costs: GasCosts,
+
+    /// Fee paid into the common pool for each registration or renewal.
+    pub registration_fee: token::BaseUnits,
+    /// Number of epochs that a registration or renewal lasts. Zero means that names never expire.
+    pub registration_period: EpochTime,
+
+    pub min_name_length: u32,
+    pub max_name_length: u32,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            gas_costs: Default::default(),
+            registration_fee: Default::default(),
+            registration_period: 0,
+            min_name_length: 3,
+            max_name_length: 32,
+        }
+    }
+}
+
+/// Errors emitted during names parameter validation.
+#[derive(Error, Debug)]
+pub enum ParameterValidationError {
+    #[error("invalid name length limits")]
+    InvalidNameLength,
+}
+
+impl module::Parameters for Parameters {
+    type Error = ParameterValidationError;
+
+    fn validate_basic(&self) -> Result<(), Self::Error> {
+        if self.min_name_length == 0 || self.min_name_length > self.max_name_length {
+            return Err(ParameterValidationError::InvalidNameLength);
+        }
+
+        Ok(())
+    }
+}
+
+/// Events emitted by the names module.
+#[derive(Debug, cbor::Encode, oasis_runtime_sdk_macros::Event)]
+#[cbor(untagged)]
+pub enum Event {
+    #[sdk_event(code = 1)]
+    Register {
+        name: String,
+        owner: Address,
+        address: Address,
+        #[cbor(optional)]
+        expiration: Option<EpochTime>,
+    },
+
+    #[sdk_event(code = 2)]
+    Renew {
+        name: String,
+        owner: Address,
+        #[cbor(optional)]
+        expiration: Option<EpochTime>,
+    },
+}
+
+/// Genesis state for the names module.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct Genesis {
+    pub parameters: Parameters,
+}
+
+/// State schema constants.
+pub mod state {
+    /// Map of names to their registrations.
+    pub const NAMES: &[u8] = &[0x01];
+    /// Set of (address, name) pairs used for reverse resolution.
+    pub const REVERSE: &[u8] = &[0x02];
+}
+
+/// Interface that can be called from other modules.
+pub trait API {
+    /// Resolve the given name, returning its registration in case it is active.
+    fn resolve<C: Context>(ctx: &mut C, name: &str) -> Result<types::NameRecord, Error>;
+}
+
+pub struct Module<Accounts: modules::accounts::API> {
+    _accounts: std::marker::PhantomData<Accounts>,
+}
+
+impl<Accounts: modules::accounts::API> API for Module<Accounts> {
+    fn resolve<C: Context>(ctx: &mut C, name: &str) -> Result<types::NameRecord, Error> {
+        let epoch = ctx.epoch();
+        Self::get_record(ctx, name)
+            .filter(|record| record.is_active(epoch))
+            .ok_or(Error::NotFound)
+    }
+}
+
+impl<Accounts: modules::accounts::API> Module<Accounts> {
+    /// Ensure the given name is well-formed. Names consist of lowercase ASCII letters, digits
+    /// and inner hyphens.
+    fn ensure_valid_name(params: &Parameters, name: &str) -> Result<(), Error> {
+        let length = name.len();
+        if length < params.min_name_length as usize || length > params.max_name_length as usize {
+            return Err(Error::InvalidName);
+        }
+        if !name
+            .bytes()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == b'-')
+            || name.starts_with('-')
+            || name.ends_with('-')
+        {
+            return Err(Error::InvalidName);
+        }
+
+        Ok(())
+    }
+
+    fn get_record<C: Context>(ctx: &mut C, name: &str) -> Option<types::NameRecord> {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let names = storage::TypedStore::new(storage::PrefixStore::new(store, &state::NAMES));
+        names.get(name)
+    }
+
+    /// Store the given registration, updating the reverse resolution entries.
+    fn set_record<C: Context>(ctx: &mut C, name: &str, record: types::NameRecord) {
+        let previous = Self::get_record(ctx, name);
+
+        let mut store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut reverse =
+            storage::TypedStore::new(storage::PrefixStore::new(&mut store, &state::REVERSE));
+        if let Some(previous) = previous {
+            reverse.remove([previous.address.as_ref(), name.as_bytes()].concat());
+        }
+        reverse.insert([record.address.as_ref(), name.as_bytes()].concat(), ());
+
+        let mut names = storage::TypedStore::new(storage::PrefixStore::new(store, &state::NAMES));
+        names.insert(name, record);
+    }
+
+    /// Pay the registration fee and return the expiration of a registration made now.
+    fn pay_registration<C: TxContext>(
+        ctx: &mut C,
+        params: &Parameters,
+        from: Option<EpochTime>,
+    ) -> Result<Option<EpochTime>, Error> {
+        if params.registration_fee.amount() > 0 {
+            let payer = ctx.tx_caller_address();
+            Accounts::transfer(
+                ctx,
+                payer,
+                *modules::accounts::ADDRESS_COMMON_POOL,
+                &params.registration_fee,
+            )
+            .map_err(|_| Error::InsufficientBalance)?;
+        }
+
+        if params.registration_period == 0 {
+            return Ok(None);
+        }
+        let start = from.unwrap_or_default().max(ctx.epoch());
+        start
+            .checked_add(params.registration_period)
+            .map(Some)
+            .ok_or(Error::InvalidArgument)
+    }
+
+    fn tx_register<C: TxContext>(ctx: &mut C, body: types::Register) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        Core::use_tx_gas(ctx, params.gas_costs.tx_register)?;
+
+        Self::ensure_valid_name(&params, &body.name)?;
+        if Self::resolve(ctx, &body.name).is_ok() {
+            return Err(Error::NameTaken);
+        }
+
+        let owner = ctx.tx_caller_address();
+        let expiration = Self::pay_registration(ctx, &params, None)?;
+        if ctx.is_check_only() {
+            return Ok(());
+        }
+
+        Self::set_record(
+            ctx,
+            &body.name,
+            types::NameRecord {
+                owner,
+                address: body.address,
+                expiration,
+            },
+        );
+
+        ctx.try_emit_event(Event::Register {
+            name: body.name,
+            owner,
+            address: body.address,
+            expiration,
+        })?;
+
+        Ok(())
+    }
+
+    fn tx_renew<C: TxContext>(ctx: &mut C, body: types::Renew) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        Core::use_tx_gas(ctx, params.gas_costs.tx_renew)?;
+
+        // Expired names can still be renewed by their owner as long as nobody else registered
+        // them in the meantime.
+        let mut record = Self::get_record(ctx, &body.name).ok_or(Error::NotFound)?;
+        let owner = ctx.tx_caller_address();
+        if record.owner != owner {
+            return Err(Error::Forbidden);
+        }
+
+        record.expiration = Self::pay_registration(ctx, &params, record.expiration)?;
+        if ctx.is_check_only() {
+            return Ok(());
+        }
+
+        let expiration = record.expiration;
+        Self::set_record(ctx, &body.name, record);
+
+        ctx.try_emit_event(Event::Renew {
+            name: body.name,
+            owner,
+            expiration,
+        })?;
+
+        Ok(())
+    }
+
+    fn query_resolve<C: Context>(
+        ctx: &mut C,
+        args: types::ResolveQuery,
+    ) -> Result<types::NameRecord, Error> {
+        Self::resolve(ctx, &args.name)
+    }
+
+    fn query_reverse_resolve<C: Context>(
+        ctx: &mut C,
+        args: types::ReverseResolveQuery,
+    ) -> Result<Vec<String>, Error> {
+        let names: Vec<String> = {
+            let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+            let reverse = storage::PrefixStore::new(store, &state::REVERSE);
+            let account =
+                storage::TypedStore::new(storage::PrefixStore::new(reverse, &args.address));
+            account
+                .iter::<Vec<u8>, ()>()
+                .filter_map(|(name, _)| String::from_utf8(name).ok())
+                .collect()
+        };
+
+        // Only return names that are active and still resolve to the given address.
+        Ok(names
+            .into_iter()
+            .filter(|name| {
+                Self::resolve(ctx, name)
+                    .map(|record| record.address == args.address)
+                    .unwrap_or(false)
+            })
+            .collect())
+    }
+
+    fn query_parameters<C: Context>(ctx: &mut C, _args: ()) -> Result<Parameters, Error> {
+        Ok(Self::params(ctx.runtime_state()))
+    }
+}
+
+impl<Accounts: modules::accounts::API> module::Module for Module<Accounts> {
+    const NAME: &'static str = MODULE_NAME;
+    type Error = Error;
+    type Event = Event;
+    type Parameters = Parameters;
+}
+
+impl<Accounts: modules::accounts::API> module::MethodHandler for Module<Accounts> {
+    fn prefetch(
+        prefixes: &mut BTreeSet<Prefix>,
+        method: &str,
+        body: cbor::Value,
+        auth_info: &AuthInfo,
+    ) -> module::DispatchResult<cbor::Value, Result<(), error::RuntimeError>> {
+        match method {
+            "names.Register" | "names.Renew" => {
+                // Prefetch the balance of the account paying the registration fee.
+                let addr = auth_info.signer_info[0].address_spec.address();
+                prefixes.insert(Prefix::from(
+                    [
+                        modules::accounts::Module::NAME.as_bytes(),
+                        modules::accounts::state::BALANCES,
+                        addr.as_ref(),
+                    ]
+                    .concat(),
+                ));
+                module::DispatchResult::Handled(Ok(()))
+            }
+            _ => module::DispatchResult::Unhandled(body),
+        }
+    }
+
+    fn dispatch_call<C: TxContext>(
+        ctx: &mut C,
+        method: &str,
+        body: cbor::Value,
+    ) -> module::DispatchResult<cbor::Value, CallResult> {
+        match method {
+            "names.Register" => module::dispatch_call(ctx, body, Self::tx_register),
+            "names.Renew" => module::dispatch_call(ctx, body, Self::tx_renew),
+            _ => module::DispatchResult::Unhandled(body),
+        }
+    }
+
+    fn dispatch_query<C: Context>(
+        ctx: &mut C,
+        method: &str,
+        args: cbor::Value,
+    ) -> module::DispatchResult<cbor::Value, Result<cbor::Value, error::RuntimeError>> {
+        match method {
+            "names.Resolve" => module::dispatch_query(ctx, args, Self::query_resolve),
+            "names.ReverseResolve" => {
+                module::dispatch_query(ctx, args, Self::query_reverse_resolve)
+            }
+            "names.Parameters" => module::dispatch_query(ctx, args, Self::query_parameters),
+            _ => module::DispatchResult::Unhandled(args),
+        }
+    }
+
+    fn register_error_codes(registry: &mut modules::core::types::ErrorRegistry) {
+        module::register_error_codes::<Self>(registry)
+    }
+
+    fn register_capabilities(capabilities: &mut modules::core::types::Capabilities) {
+        capabilities.insert("names".to_owned());
+    }
+}
+
+impl<Accounts: modules::accounts::API> Module<Accounts> {
+    /// Initialize state from genesis.
+    fn init<C: Context>(ctx: &mut C, genesis: Genesis) {
+        genesis
+            .parameters
+            .validate_basic()
+            .expect("invalid genesis parameters");
+
+        // Set genesis parameters.
+        Self::set_params(ctx.runtime_state(), genesis.parameters);
+    }
+
+    /// Migrate state from a previous version.
+    fn migrate<C: Context>(_ctx: &mut C, _from: u32) -> bool {
+        // No migrations currently supported.
+        false
+    }
+}
+
+impl<Accounts: modules::accounts::API> module::MigrationHandler for Module<Accounts> {
+    type Genesis = Genesis;
+
+    fn init_or_migrate<C: Context>(
+        ctx: &mut C,
+        meta: &mut modules::core::types::Metadata,
+        genesis: Self::Genesis,
+    ) -> bool {
+        let version = meta.versions.get(Self::NAME).copied().unwrap_or_default();
+        if version == 0 {
+            // Initialize state from genesis.
+            Self::init(ctx, genesis);
+            meta.versions.insert(Self::NAME.to_owned(), Self::VERSION);
+            return true;
+        }
+
+        // Perform migration.
+        Self::migrate(ctx, version)
+    }
+}
+
+impl<Accounts: modules::accounts::API> module::AuthHandler for Module<Accounts> {}
+
+impl<Accounts: modules::accounts::API> module::BlockHandler for Module<Accounts> {}
+
+impl<Accounts: modules::accounts::API> module::EventHandler for Module<Accounts> {}
+
+impl<Accounts: modules::accounts::API> module::InvariantHandler for Module<Accounts> {}
@@ -0,0 +1,322 @@
+//! Tests for the names module.
+use std::collections::BTreeMap;
+
+use crate::{
+    context::{BatchContext, Context},
+    module::MigrationHandler,
+    modules::{
+        accounts::{self, Module as Accounts, API as _},
+        core,
+    },
+    testing::{keys, mock},
+    types::{
+        address::Address,
+        token::{BaseUnits, Denomination},
+        transaction,
+    },
+};
+
+use super::{types, Error, Genesis, Parameters, API as _};
+
+type Names = super::Module<Accounts>;
+
+fn init_accounts<C: Context>(ctx: &mut C) {
+    Accounts::init_or_migrate(
+        ctx,
+        &mut core::types::Metadata::default(),
+        accounts::Genesis {
+            balances: {
+                let mut balances = BTreeMap::new();
+                balances.insert(keys::alice::address(), {
+                    let mut denominations = BTreeMap::new();
+                    denominations.insert(Denomination::NATIVE, 1_000);
+                    denominations
+                });
+                balances.insert(keys::bob::address(), {
+                    let mut denominations = BTreeMap::new();
+                    denominations.insert(Denomination::NATIVE, 50);
+                    denominations
+                });
+                balances
+            },
+            total_supplies: {
+                let mut total_supplies = BTreeMap::new();
+                total_supplies.insert(Denomination::NATIVE, 1_050);
+                total_supplies
+            },
+            ..Default::default()
+        },
+    );
+}
+
+fn init_names<C: Context>(ctx: &mut C) {
+    Names::init_or_migrate(
+        ctx,
+        &mut core::types::Metadata::default(),
+        Genesis {
+            parameters: Parameters {
+                registration_fee: BaseUnits::new(100, Denomination::NATIVE),
+                registration_period: 10,
+                ..Default::default()
+            },
+        },
+    );
+}
+
+fn signed_by(
+    signer: transaction::SignerInfo,
+    method: &str,
+    body: cbor::Value,
+) -> transaction::Transaction {
+    let mut tx = mock::transaction();
+    tx.call.method = method.to_owned();
+    tx.call.body = body;
+    tx.auth_info.signer_info = vec![signer];
+    tx
+}
+
+fn register<C: BatchContext>(
+    ctx: &mut C,
+    signer: transaction::SignerInfo,
+    name: &str,
+    address: Address,
+) -> Result<(), Error> {
+    let tx = signed_by(
+        signer,
+        "names.Register",
+        cbor::to_value(types::Register {
+            name: name.to_owned(),
+            address,
+        }),
+    );
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        let result = Names::tx_register(&mut tx_ctx, cbor::from_value(call.body).unwrap());
+        if result.is_ok() {
+            tx_ctx.commit();
+        }
+        result
+    })
+}
+
+fn renew<C: BatchContext>(
+    ctx: &mut C,
+    signer: transaction::SignerInfo,
+    name: &str,
+) -> Result<(), Error> {
+    let tx = signed_by(
+        signer,
+        "names.Renew",
+        cbor::to_value(types::Renew {
+            name: name.to_owned(),
+        }),
+    );
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        let result = Names::tx_renew(&mut tx_ctx, cbor::from_value(call.body).unwrap());
+        if result.is_ok() {
+            tx_ctx.commit();
+        }
+        result
+    })
+}
+
+fn alice() -> transaction::SignerInfo {
+    transaction::SignerInfo::new_sigspec(keys::alice::sigspec(), 0)
+}
+
+fn bob() -> transaction::SignerInfo {
+    transaction::SignerInfo::new_sigspec(keys::bob::sigspec(), 0)
+}
+
+#[test]
+#[should_panic]
+fn test_init_incorrect_name_length() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+
+    Names::init_or_migrate(
+        &mut ctx,
+        &mut core::types::Metadata::default(),
+        Genesis {
+            parameters: Parameters {
+                min_name_length: 10,
+                max_name_length: 5,
+                ..Default::default()
+            },
+        },
+    );
+}
+
+#[test]
+fn test_register_and_resolve() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    init_accounts(&mut ctx);
+    init_names(&mut ctx);
+
+    register(&mut ctx, alice(), "alice", keys::dave::address())
+        .expect("registration should succeed");
+
+    // Registration fee should be paid into the common pool.
+    let balance = Accounts::get_balance(
+        ctx.runtime_state(),
+        keys::alice::address(),
+        Denomination::NATIVE,
+    )
+    .unwrap();
+    assert_eq!(balance, 900, "registration fee should be deducted");
+    let pool = Accounts::get_balance(
+        ctx.runtime_state(),
+        *accounts::ADDRESS_COMMON_POOL,
+        Denomination::NATIVE,
+    )
+    .unwrap();
+    assert_eq!(
+        pool, 100,
+        "registration fee should be paid into the common pool"
+    );
+
+    let record = Names::resolve(&mut ctx, "alice").expect("name should resolve");
+    assert_eq!(
+        record,
+        types::NameRecord {
+            owner: keys::alice::address(),
+            address: keys::dave::address(),
+            expiration: Some(11),
+        }
+    );
+    assert!(matches!(
+        Names::resolve(&mut ctx, "unknown"),
+        Err(Error::NotFound)
+    ));
+
+    let names = Names::query_reverse_resolve(
+        &mut ctx,
+        types::ReverseResolveQuery {
+            address: keys::dave::address(),
+        },
+    )
+    .unwrap();
+    assert_eq!(names, vec!["alice".to_owned()]);
+    let names = Names::query_reverse_resolve(
+        &mut ctx,
+        types::ReverseResolveQuery {
+            address: keys::alice::address(),
+        },
+    )
+    .unwrap();
+    assert!(names.is_empty());
+}
+
+#[test]
+fn test_register_rejected() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    init_accounts(&mut ctx);
+    init_names(&mut ctx);
+
+    for name in vec!["ab", "Alice", "-alice", "alice-", "al ice", "a_lice"] {
+        assert!(
+            matches!(
+                register(&mut ctx, alice(), name, keys::alice::address()),
+                Err(Error::InvalidName)
+            ),
+            "name {:?} should be rejected",
+            name
+        );
+    }
+    register(&mut ctx, alice(), "al-1ce", keys::alice::address())
+        .expect("registration should succeed");
+
+    // Names can only be registered once.
+    assert!(matches!(
+        register(&mut ctx, bob(), "al-1ce", keys::bob::address()),
+        Err(Error::NameTaken)
+    ));
+    assert!(matches!(
+        register(&mut ctx, alice(), "al-1ce", keys::bob::address()),
+        Err(Error::NameTaken)
+    ));
+
+    // Bob cannot afford the registration fee.
+    assert!(matches!(
+        register(&mut ctx, bob(), "bob", keys::bob::address()),
+        Err(Error::InsufficientBalance)
+    ));
+    assert!(matches!(
+        Names::resolve(&mut ctx, "bob"),
+        Err(Error::NotFound)
+    ));
+}
+
+#[test]
+fn test_expiration_and_renewal() {
+    let mut mock = mock::Mock::default();
+    {
+        let mut ctx = mock.create_ctx();
+        init_accounts(&mut ctx);
+        init_names(&mut ctx);
+
+        register(&mut ctx, alice(), "alice", keys::alice::address())
+            .expect("registration should succeed");
+        register(&mut ctx, alice(), "other", keys::alice::address())
+            .expect("registration should succeed");
+
+        // Only the owner may renew a name.
+        assert!(matches!(
+            renew(&mut ctx, bob(), "alice"),
+            Err(Error::Forbidden)
+        ));
+        assert!(matches!(
+            renew(&mut ctx, alice(), "unknown"),
+            Err(Error::NotFound)
+        ));
+
+        // Renewing an active registration should extend it.
+        renew(&mut ctx, alice(), "alice").expect("renewal should succeed");
+        let record = Names::resolve(&mut ctx, "alice").unwrap();
+        assert_eq!(record.expiration, Some(21));
+    }
+
+    // Advance past the expiration of the name that has not been renewed.
+    mock.epoch = 11;
+    let mut ctx = mock.create_ctx();
+    assert!(Names::resolve(&mut ctx, "alice").is_ok());
+    assert!(matches!(
+        Names::resolve(&mut ctx, "other"),
+        Err(Error::NotFound)
+    ));
+    let names = Names::query_reverse_resolve(
+        &mut ctx,
+        types::ReverseResolveQuery {
+            address: keys::alice::address(),
+        },
+    )
+    .unwrap();
+    assert_eq!(names, vec!["alice".to_owned()]);
+
+    // Expired names can be registered by anyone.
+    Accounts::set_balance(
+        ctx.runtime_state(),
+        keys::bob::address(),
+        &BaseUnits::new(100, Denomination::NATIVE),
+    );
+    register(&mut ctx, bob(), "other", keys::bob::address())
+        .expect("registration of an expired name should succeed");
+    let record = Names::resolve(&mut ctx, "other").unwrap();
+    assert_eq!(record.owner, keys::bob::address());
+    assert_eq!(record.expiration, Some(21));
+
+    // The previous owner can no longer renew it and the old reverse entry should be gone.
+    assert!(matches!(
+        renew(&mut ctx, alice(), "other"),
+        Err(Error::Forbidden)
+    ));
+    let names = Names::query_reverse_resolve(
+        &mut ctx,
+        types::ReverseResolveQuery {
+            address: keys::bob::address(),
+        },
+    )
+    .unwrap();
+    assert_eq!(names, vec!["other".to_owned()]);
+}
@@ -0,0 +1,49 @@
+//! Names module types.
+use crate::{core::consensus::beacon::EpochTime, types::address::Address};
+
+/// Register name call.
+/// The transaction signer becomes the owner of the name, which resolves to the given address.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct Register {
+    pub name: String,
+    pub address: Address,
+}
+
+/// Renew name call.
+/// Extends the registration of a name owned by the transaction signer.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct Renew {
+    pub name: String,
+}
+
+/// Name resolution query.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct ResolveQuery {
+    pub name: String,
+}
+
+/// Reverse name resolution query.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct ReverseResolveQuery {
+    pub address: Address,
+}
+
+/// A registered name.
+#[derive(Clone, Debug, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct NameRecord {
+    /// Account that owns the name and may renew it.
+    pub owner: Address,
+    /// Address that the name resolves to.
+    pub address: Address,
+    /// Epoch at which the registration expires. In case this is not set, the name never expires.
+    #[cbor(optional)]
+    pub expiration: Option<EpochTime>,
+}
+
+impl NameRecord {
+    /// Whether the registration is still active at the given epoch.
+    pub fn is_active(&self, epoch: EpochTime) -> bool {
+        self.expiration
+            .map_or(true, |expiration| epoch < expiration)
+    }
+}
@@ -1,17 +1,30 @@
 //! Rewards module.
-use std::convert::{Infallible, TryFrom, TryInto};
+//!
+//! Besides disbursing per-epoch rewards to compute entities, the module supports index-based
+//! accrual of rewards to accounts in proportion to an accrual weight (e.g., the amount staked or
+//! supplied) that other modules maintain via the [`API`].
+use std::{
+    collections::BTreeSet,
+    convert::{TryFrom, TryInto},
+};
 
 use num_traits::Zero;
 use once_cell::sync::Lazy;
 use thiserror::Error;
 
 use crate::{
-    context::Context,
+    context::{Context, TxContext},
     core::consensus::beacon,
     error,
-    module::{self, Module as _, Parameters as _},
-    modules, storage,
-    types::address::{Address, SignatureAddressSpec},
+    module::{self, CallResult, Module as _, Parameters as _},
+    modules,
+    modules::core::{Module as Core, API as _},
+    storage::{self, Prefix},
+    types::{
+        address::{Address, SignatureAddressSpec},
+        token,
+        transaction::AuthInfo,
+    },
 };
 
 #[cfg(test)]
@@ -27,6 +40,16 @@ pub enum Error {
     #[error("invalid argument")]
     #[sdk_error(code = 1)]
     InvalidArgument,
+
+    #[error("core: {0}")]
+    #[sdk_error(transparent)]
+    Core(#[from] modules::core::Error),
+}
+
+/// Gas costs.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct GasCosts {
+    pub tx_claim: u64,
 }
 
 /// Parameters for the rewards module.
@@ -36,6 +59,14 @@ pub struct Parameters {
 
     pub participation_threshold_numerator: u64,
     pub participation_threshold_denominator: u64,
+
+    /// Amount accrued in each block, split among all accounts in proportion to their accrual
+    /// weight. Accrued rewards are minted in this denomination when claimed.
+    #[cbor(optional, default)]
+    pub accrual_rate: token::BaseUnits,
+
+    #[cbor(optional, default)]
+    pub gas_costs: GasCosts,
 }
 
 /// Errors emitted during rewards parameter validation.
@@ -65,6 +96,17 @@ impl module::Parameters for Parameters {
     }
 }
 
+/// Events emitted by the rewards module.
+#[derive(Debug, cbor::Encode, oasis_runtime_sdk_macros::Event)]
+#[cbor(untagged)]
+pub enum Event {
+    #[sdk_event(code = 1)]
+    Claim {
+        address: Address,
+        amount: token::BaseUnits,
+    },
+}
+
 /// Genesis state for the rewards module.
 #[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
 pub struct Genesis {
@@ -77,6 +119,26 @@ pub mod state {
     pub const LAST_EPOCH: &[u8] = &[0x01];
     /// Map of epochs to rewards pending distribution.
     pub const REWARDS: &[u8] = &[0x02];
+    /// Cumulative amount accrued per unit of accrual weight (scaled by
+    /// [`ACCRUAL_INDEX_PRECISION`](super::ACCRUAL_INDEX_PRECISION)).
+    pub const ACCRUAL_INDEX: &[u8] = &[0x03];
+    /// Sum of the accrual weights of all accounts.
+    pub const ACCRUAL_TOTAL_WEIGHT: &[u8] = &[0x04];
+    /// Map of account addresses to their accrual state.
+    pub const ACCRUAL_ACCOUNTS: &[u8] = &[0x05];
+}
+
+/// Precision of the accrual index.
+pub const ACCRUAL_INDEX_PRECISION: u128 = 1_000_000_000_000;
+
+/// Interface that can be called from other modules.
+pub trait API {
+    /// Set the accrual weight of the given account (e.g., the amount it has staked). Rewards
+    /// accrued under the previous weight are kept for the account to claim.
+    fn set_accrual_weight<C: Context>(ctx: &mut C, address: Address, weight: u128);
+
+    /// Amount accrued by the given account that has not been claimed yet.
+    fn pending_rewards<C: Context>(ctx: &mut C, address: Address) -> token::BaseUnits;
 }
 
 pub struct Module<Accounts: modules::accounts::API> {
@@ -87,7 +149,119 @@ pub struct Module<Accounts: modules::accounts::API> {
 pub static ADDRESS_REWARD_POOL: Lazy<Address> =
     Lazy::new(|| Address::from_module(MODULE_NAME, "reward-pool"));
 
+impl<Accounts: modules::accounts::API> API for Module<Accounts> {
+    fn set_accrual_weight<C: Context>(ctx: &mut C, address: Address, weight: u128) {
+        let mut account = Self::checkpoint_accrual(ctx, address);
+        let previous_weight = account.weight;
+        account.weight = weight;
+
+        let mut store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut accounts = storage::TypedStore::new(storage::PrefixStore::new(
+            &mut store,
+            &state::ACCRUAL_ACCOUNTS,
+        ));
+        accounts.insert(address, account);
+
+        let mut tstore = storage::TypedStore::new(store);
+        let total_weight: u128 = tstore.get(state::ACCRUAL_TOTAL_WEIGHT).unwrap_or_default();
+        tstore.insert(
+            state::ACCRUAL_TOTAL_WEIGHT,
+            total_weight
+                .saturating_sub(previous_weight)
+                .saturating_add(weight),
+        );
+    }
+
+    fn pending_rewards<C: Context>(ctx: &mut C, address: Address) -> token::BaseUnits {
+        let params = Self::params(ctx.runtime_state());
+        let index = Self::accrual_index(ctx);
+        let account = Self::accrual_account(ctx, address);
+        token::BaseUnits::new(
+            account.pending_at(index),
+            params.accrual_rate.denomination().clone(),
+        )
+    }
+}
+
 impl<Accounts: modules::accounts::API> Module<Accounts> {
+    fn accrual_index<C: Context>(ctx: &mut C) -> u128 {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        storage::TypedStore::new(store)
+            .get(state::ACCRUAL_INDEX)
+            .unwrap_or_default()
+    }
+
+    fn accrual_account<C: Context>(ctx: &mut C, address: Address) -> types::AccrualAccount {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let accounts =
+            storage::TypedStore::new(storage::PrefixStore::new(store, &state::ACCRUAL_ACCOUNTS));
+        accounts.get(address).unwrap_or_default()
+    }
+
+    /// Materialize rewards accrued by the given account up to the current index and return the
+    /// updated (but not yet stored) account state.
+    fn checkpoint_accrual<C: Context>(ctx: &mut C, address: Address) -> types::AccrualAccount {
+        let index = Self::accrual_index(ctx);
+        let mut account = Self::accrual_account(ctx, address);
+        account.pending = account.pending_at(index);
+        account.checkpoint = index;
+        account
+    }
+
+    /// Advance the accrual index by the amount accrued in a single block.
+    fn accrue<C: Context>(ctx: &mut C, params: &Parameters) {
+        let rate = params.accrual_rate.amount();
+        if rate.is_zero() {
+            return;
+        }
+
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut tstore = storage::TypedStore::new(store);
+        let total_weight: u128 = tstore.get(state::ACCRUAL_TOTAL_WEIGHT).unwrap_or_default();
+        if total_weight.is_zero() {
+            // Nobody to accrue to, the rewards for this block are forfeited.
+            return;
+        }
+        let index: u128 = tstore.get(state::ACCRUAL_INDEX).unwrap_or_default();
+        let delta = rate.saturating_mul(ACCRUAL_INDEX_PRECISION) / total_weight;
+        tstore.insert(state::ACCRUAL_INDEX, index.saturating_add(delta));
+    }
+
+    fn tx_claim<C: TxContext>(ctx: &mut C, _body: ()) -> Result<token::BaseUnits, Error> {
+        let params = Self::params(ctx.runtime_state());
+        Core::use_tx_gas(ctx, params.gas_costs.tx_claim)?;
+
+        let address = ctx.tx_caller_address();
+        let mut account = Self::checkpoint_accrual(ctx, address);
+        let amount =
+            token::BaseUnits::new(account.pending, params.accrual_rate.denomination().clone());
+        if ctx.is_check_only() || amount.amount().is_zero() {
+            return Ok(amount);
+        }
+
+        Accounts::mint::<Self, _>(ctx, address, &amount).map_err(|_| Error::InvalidArgument)?;
+
+        account.pending = 0;
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
+        let mut accounts =
+            storage::TypedStore::new(storage::PrefixStore::new(store, &state::ACCRUAL_ACCOUNTS));
+        accounts.insert(address, account);
+
+        ctx.try_emit_event(Event::Claim {
+            address,
+            amount: amount.clone(),
+        })?;
+
+        Ok(amount)
+    }
+
+    fn query_pending<C: Context>(
+        ctx: &mut C,
+        args: types::PendingQuery,
+    ) -> Result<token::BaseUnits, Error> {
+        Ok(Self::pending_rewards(ctx, args.address))
+    }
+
     fn query_parameters<C: Context>(ctx: &mut C, _args: ()) -> Result<Parameters, Error> {
         Ok(Self::params(ctx.runtime_state()))
     }
@@ -95,22 +269,62 @@ impl<Accounts: modules::accounts::API> Module<Accounts> {
 
 impl<Accounts: modules::accounts::API> module::Module for Module<Accounts> {
     const NAME: &'static str = MODULE_NAME;
-    type Error = Infallible;
-    type Event = ();
+    type Error = Error;
+    type Event = Event;
     type Parameters = Parameters;
 }
 
 impl<Accounts: modules::accounts::API> module::MethodHandler for Module<Accounts> {
+    fn prefetch(
+        prefixes: &mut BTreeSet<Prefix>,
+        method: &str,
+        body: cbor::Value,
+        auth_info: &AuthInfo,
+    ) -> module::DispatchResult<cbor::Value, Result<(), error::RuntimeError>> {
+        match method {
+            "rewards.Claim" => {
+                // Prefetch the accrual state of the claiming account.
+                let addr = auth_info.signer_info[0].address_spec.address();
+                prefixes.insert(Prefix::from(
+                    [
+                        MODULE_NAME.as_bytes(),
+                        state::ACCRUAL_ACCOUNTS,
+                        addr.as_ref(),
+                    ]
+                    .concat(),
+                ));
+                module::DispatchResult::Handled(Ok(()))
+            }
+            _ => module::DispatchResult::Unhandled(body),
+        }
+    }
+
+    fn dispatch_call<C: TxContext>(
+        ctx: &mut C,
+        method: &str,
+        body: cbor::Value,
+    ) -> module::DispatchResult<cbor::Value, CallResult> {
+        match method {
+            "rewards.Claim" => module::dispatch_call(ctx, body, Self::tx_claim),
+            _ => module::DispatchResult::Unhandled(body),
+        }
+    }
+
     fn dispatch_query<C: Context>(
         ctx: &mut C,
         method: &str,
         args: cbor::Value,
     ) -> module::DispatchResult<cbor::Value, Result<cbor::Value, error::RuntimeError>> {
         match method {
+            "rewards.Pending" => module::dispatch_query(ctx, args, Self::query_pending),
             "rewards.Parameters" => module::dispatch_query(ctx, args, Self::query_parameters),
             _ => module::DispatchResult::Unhandled(args),
         }
     }
+
+    fn register_error_codes(registry: &mut modules::core::types::ErrorRegistry) {
+        module::register_error_codes::<Self>(registry)
+    }
 }
 
 impl<Accounts: modules::accounts::API> Module<Accounts> {
@@ -159,6 +373,10 @@ impl<Accounts: modules::accounts::API> module::BlockHandler for Module<Accounts>
     fn end_block<C: Context>(ctx: &mut C) {
         let epoch = ctx.epoch();
 
+        // Accrue rewards for this block.
+        let params = Self::params(ctx.runtime_state());
+        Self::accrue(ctx, &params);
+
         // Load previous epoch.
         let mut store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
         let mut tstore = storage::TypedStore::new(&mut store);
@@ -184,7 +402,6 @@ impl<Accounts: modules::accounts::API> module::BlockHandler for Module<Accounts>
 
         // Disburse any rewards for previous epochs when the epoch changes.
         if epoch != previous_epoch {
-            let params = Self::params(ctx.runtime_state());
             let store = storage::PrefixStore::new(ctx.runtime_state(), &MODULE_NAME);
             let mut epochs =
                 storage::TypedStore::new(storage::PrefixStore::new(store, &state::REWARDS));
@@ -233,6 +450,7 @@ impl<Accounts: modules::accounts::API> module::BlockHandler for Module<Accounts>
     }
 }
 
+impl<Accounts: modules::accounts::API> module::EventHandler for Module<Accounts> {}
 impl<Accounts: modules::accounts::API> module::InvariantHandler for Module<Accounts> {}
 
 /// A trait that exists solely to convert `beacon::EpochTime` to bytes for use as a storage key.
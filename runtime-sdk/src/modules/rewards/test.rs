@@ -2,17 +2,20 @@
 use std::collections::BTreeMap;
 
 use crate::{
-    context::Context,
+    context::{BatchContext, Context},
     module::{BlockHandler, MigrationHandler},
     modules::{
         accounts::{self, Module as Accounts, API as _},
         core,
     },
     testing::{keys, mock},
-    types::token::{BaseUnits, Denomination},
+    types::{
+        token::{BaseUnits, Denomination},
+        transaction,
+    },
 };
 
-use super::{types, Genesis, Parameters, ADDRESS_REWARD_POOL};
+use super::{types, Genesis, Parameters, ADDRESS_REWARD_POOL, API as _};
 
 type Rewards = super::Module<Accounts>;
 
@@ -312,6 +315,99 @@ fn test_reward_disbursement() {
     );
 }
 
+#[test]
+fn test_accrual() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    init_accounts(&mut ctx);
+
+    Rewards::init_or_migrate(
+        &mut ctx,
+        &mut core::types::Metadata::default(),
+        Genesis {
+            parameters: Parameters {
+                participation_threshold_denominator: 1,
+                accrual_rate: BaseUnits::new(400, Denomination::NATIVE),
+                ..Default::default()
+            },
+        },
+    );
+
+    // Nothing should accrue while there is no weight.
+    Rewards::end_block(&mut ctx);
+    Rewards::set_accrual_weight(&mut ctx, keys::alice::address(), 1);
+    Rewards::set_accrual_weight(&mut ctx, keys::bob::address(), 3);
+    assert_eq!(
+        Rewards::pending_rewards(&mut ctx, keys::alice::address()),
+        BaseUnits::new(0, Denomination::NATIVE)
+    );
+
+    // Simulate some blocks.
+    for _ in 0..3 {
+        Rewards::end_block(&mut ctx);
+    }
+    assert_eq!(
+        Rewards::pending_rewards(&mut ctx, keys::alice::address()).amount(),
+        300
+    );
+    let pending = Rewards::query_pending(
+        &mut ctx,
+        types::PendingQuery {
+            address: keys::bob::address(),
+        },
+    )
+    .unwrap();
+    assert_eq!(pending, BaseUnits::new(900, Denomination::NATIVE));
+
+    // Changing the weight should keep what has already been accrued.
+    Rewards::set_accrual_weight(&mut ctx, keys::bob::address(), 1);
+    Rewards::end_block(&mut ctx);
+    assert_eq!(
+        Rewards::pending_rewards(&mut ctx, keys::alice::address()).amount(),
+        500
+    );
+    assert_eq!(
+        Rewards::pending_rewards(&mut ctx, keys::bob::address()).amount(),
+        1_100
+    );
+
+    // Claim Alice's rewards.
+    let mut tx = mock::transaction();
+    tx.call.method = "rewards.Claim".to_owned();
+    tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(
+        keys::alice::sigspec(),
+        0,
+    )];
+    ctx.with_tx(0, tx, |mut tx_ctx, _call| {
+        let claimed = Rewards::tx_claim(&mut tx_ctx, ()).expect("claim should succeed");
+        assert_eq!(claimed, BaseUnits::new(500, Denomination::NATIVE));
+        tx_ctx.commit();
+    });
+
+    let balance = Accounts::get_balance(
+        ctx.runtime_state(),
+        keys::alice::address(),
+        Denomination::NATIVE,
+    )
+    .unwrap();
+    assert_eq!(balance, 500, "claimed rewards should be minted");
+
+    // The claim should reset the checkpoint.
+    assert_eq!(
+        Rewards::pending_rewards(&mut ctx, keys::alice::address()).amount(),
+        0
+    );
+    Rewards::end_block(&mut ctx);
+    assert_eq!(
+        Rewards::pending_rewards(&mut ctx, keys::alice::address()).amount(),
+        200
+    );
+    assert_eq!(
+        Rewards::pending_rewards(&mut ctx, keys::bob::address()).amount(),
+        1_300
+    );
+}
+
 #[test]
 fn test_reward_pool_address() {
     // Make sure the reward pool address doesn't change.
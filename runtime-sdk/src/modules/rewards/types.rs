@@ -159,6 +159,34 @@ impl EpochRewards {
     }
 }
 
+/// Accrual state of an account.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct AccrualAccount {
+    /// Weight in proportion to which the account accrues rewards.
+    pub weight: u128,
+    /// Accrual index at the time of the last checkpoint.
+    pub checkpoint: u128,
+    /// Amount accrued up to the last checkpoint that has not been claimed.
+    pub pending: u128,
+}
+
+impl AccrualAccount {
+    /// Compute the amount accrued and not yet claimed at the given accrual index.
+    pub fn pending_at(&self, index: u128) -> u128 {
+        let accrued = self
+            .weight
+            .saturating_mul(index.saturating_sub(self.checkpoint))
+            / super::ACCRUAL_INDEX_PRECISION;
+        self.pending.saturating_add(accrued)
+    }
+}
+
+/// Pending rewards query.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct PendingQuery {
+    pub address: Address,
+}
+
 #[cfg(test)]
 mod test {
     use crate::testing::keys;
@@ -0,0 +1,193 @@
+//! Transaction receipts.
+//!
+//! Each executed transaction commits to the tags it emitted via an events root which allows light
+//! clients to verify that a given event has been emitted by a transaction without having to fetch
+//! all of its events. Receipt tags are only emitted by runtimes that enable
+//! `Runtime::EMIT_RECEIPT_TAGS`.
+//!
+//! # Hashing scheme
+//!
+//! The events root is the root of a binary Merkle tree using SHA-512/256 as the hash function:
+//!
+//! ```text
+//! leaf = H(0x00 || len(key) (big-endian u32) || key || value)
+//! node = H(0x01 || left || right)
+//! ```
+//!
+//! Leaves are ordered by emission order. A node without a sibling on any given level is promoted
+//! to the next level unchanged. The root of an empty tree is `H()`.
+//!
+//! The block-level events root is computed over the per-transaction events roots (in batch order)
+//! using the same tree construction, with the per-transaction roots being used as leaves directly.
+use oasis_core_runtime::{common::crypto::hash::Hash, transaction::tags::Tag};
+
+const LEAF_PREFIX: &[u8] = &[0x00];
+const NODE_PREFIX: &[u8] = &[0x01];
+
+/// Proof of inclusion of a leaf under a given root.
+#[derive(Clone, Debug, Default, PartialEq, cbor::Encode, cbor::Decode)]
+pub struct InclusionProof {
+    /// Index of the leaf.
+    pub index: u64,
+    /// Total number of leaves.
+    pub total: u64,
+    /// Sibling hashes from the leaf level up to (but not including) the root.
+    pub siblings: Vec<Hash>,
+}
+
+/// Computes the leaf hash for the given tag.
+pub fn tag_hash(tag: &Tag) -> Hash {
+    let key_len = (tag.key.len() as u32).to_be_bytes();
+    Hash::digest_bytes_list(&[LEAF_PREFIX, &key_len, &tag.key, &tag.value])
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    Hash::digest_bytes_list(&[NODE_PREFIX, left.as_ref(), right.as_ref()])
+}
+
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Computes the Merkle root over the given leaves.
+pub fn merkle_root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return Hash::digest_bytes(&[]);
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Generates a proof of inclusion for the leaf at the given index.
+///
+/// Returns `None` if the index is out of range.
+pub fn merkle_proof(leaves: &[Hash], index: usize) -> Option<InclusionProof> {
+    if index >= leaves.len() {
+        return None;
+    }
+
+    let mut siblings = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut idx = index;
+    while level.len() > 1 {
+        let sibling = idx ^ 1;
+        if sibling < level.len() {
+            siblings.push(level[sibling]);
+        }
+        level = next_level(&level);
+        idx /= 2;
+    }
+
+    Some(InclusionProof {
+        index: index as u64,
+        total: leaves.len() as u64,
+        siblings,
+    })
+}
+
+/// Verifies that the given leaf is included under the given root.
+pub fn verify_inclusion(root: &Hash, leaf: &Hash, proof: &InclusionProof) -> bool {
+    if proof.index >= proof.total {
+        return false;
+    }
+
+    let mut siblings = proof.siblings.iter();
+    let mut hash = *leaf;
+    let mut idx = proof.index;
+    let mut total = proof.total;
+    while total > 1 {
+        if idx % 2 == 1 {
+            match siblings.next() {
+                Some(sibling) => hash = node_hash(sibling, &hash),
+                None => return false,
+            }
+        } else if idx + 1 < total {
+            match siblings.next() {
+                Some(sibling) => hash = node_hash(&hash, sibling),
+                None => return false,
+            }
+        }
+        idx /= 2;
+        total = (total + 1) / 2;
+    }
+
+    siblings.next().is_none() && &hash == root
+}
+
+/// Computes the events root over the tags emitted by a transaction.
+pub fn events_root(tags: &[Tag]) -> Hash {
+    let leaves: Vec<Hash> = tags.iter().map(tag_hash).collect();
+    merkle_root(&leaves)
+}
+
+/// Computes the block-level events root over the per-transaction events roots.
+pub fn block_events_root(tx_roots: &[Hash]) -> Hash {
+    merkle_root(tx_roots)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tags(n: usize) -> Vec<Tag> {
+        (0..n)
+            .map(|i| Tag::new(format!("key{}", i).into_bytes(), vec![i as u8; i]))
+            .collect()
+    }
+
+    #[test]
+    fn test_event_inclusion() {
+        for n in 1..=9 {
+            let tags = tags(n);
+            let root = events_root(&tags);
+            let leaves: Vec<Hash> = tags.iter().map(tag_hash).collect();
+
+            for (index, tag) in tags.iter().enumerate() {
+                let proof = merkle_proof(&leaves, index).expect("proof should be generated");
+                assert!(
+                    verify_inclusion(&root, &tag_hash(tag), &proof),
+                    "event {} of {} should be included",
+                    index,
+                    n
+                );
+
+                // A different event should not verify under the same proof.
+                let other = Tag::new(b"other".to_vec(), vec![]);
+                assert!(!verify_inclusion(&root, &tag_hash(&other), &proof));
+            }
+
+            assert!(merkle_proof(&leaves, n).is_none());
+        }
+    }
+
+    #[test]
+    fn test_events_root() {
+        assert_eq!(events_root(&[]), Hash::digest_bytes(&[]));
+
+        let tags = tags(2);
+        let expected = node_hash(&tag_hash(&tags[0]), &tag_hash(&tags[1]));
+        assert_eq!(events_root(&tags), expected);
+
+        // Roots should depend on the order of events.
+        let reversed: Vec<Tag> = tags.iter().rev().cloned().collect();
+        assert_ne!(events_root(&reversed), expected);
+
+        // Block root should aggregate transaction roots.
+        let tx_roots = vec![events_root(&tags), events_root(&reversed)];
+        assert_eq!(
+            block_events_root(&tx_roots),
+            node_hash(&tx_roots[0], &tx_roots[1])
+        );
+    }
+}
@@ -10,8 +10,11 @@ use crate::{
     context::Context,
     crypto, dispatcher,
     keymanager::{KeyManagerClient, TrustedPolicySigners},
-    module::{AuthHandler, BlockHandler, InvariantHandler, MethodHandler, MigrationHandler},
-    modules, storage,
+    module::{
+        AuthHandler, BlockHandler, EventHandler, InvariantHandler, MethodHandler, MigrationHandler,
+        ParametersHandler,
+    },
+    modules, storage, types,
 };
 
 /// A runtime.
@@ -24,7 +27,74 @@ pub trait Runtime {
     /// Prefetch limit. To enable prefetch set it to a non-zero value.
     const PREFETCH_LIMIT: u16 = 0;
 
-    type Modules: AuthHandler + MigrationHandler + MethodHandler + BlockHandler + InvariantHandler;
+    /// Maximum number of prefixes that a single transaction may declare for prefetching.
+    /// Transactions declaring more prefixes are rejected so that a single transaction cannot
+    /// exhaust the prefetch budget of the whole batch.
+    const MAX_PREFETCH_PREFIXES_PER_TX: u16 = 64;
+
+    /// Human readable part used for Bech32-encoded addresses.
+    const ADDRESS_BECH32_HRP: &'static str = types::address::ADDRESS_BECH32_HRP;
+
+    /// Optional transaction features supported by the runtime, as a bitfield. Transactions
+    /// requesting any other features are rejected.
+    const SUPPORTED_TX_FEATURES: u64 = 0;
+
+    /// Whether the runtime should refuse to process batches and queries in case the parameters of
+    /// any of its modules have not been initialized. The parameters are checked whenever state is
+    /// initialized or migrated. Otherwise default parameters are silently used instead.
+    const STRICT_PARAMETERS: bool = false;
+
+    /// Whether results of messages emitted in the previous round for which no handler can be
+    /// found are tolerated. In this case a core `DanglingMessage` event is emitted instead of
+    /// aborting the block. This is intended for upgrades which rename or remove message handlers.
+    const ALLOW_DANGLING_MESSAGES: bool = false;
+
+    /// Whether receipt tags are emitted for executed transactions. In this case the core `GasUsed`
+    /// and `EventsRoot` events are appended to the tags of each transaction and the core
+    /// `BlockEventsRoot` event is emitted at the end of each batch (see [`crate::receipt`]).
+    const EMIT_RECEIPT_TAGS: bool = false;
+
+    /// Whether the transactions of a batch are executed in order of decreasing priority instead of
+    /// the batch order, with transactions of equal priority being ordered by their hash (see
+    /// [`dispatcher::sort_by_priority`]). The priority of a transaction is the gas price of its fee.
+    ///
+    /// Note that transactions of the same signer are then not necessarily executed in the order of
+    /// their nonces.
+    const PRIORITY_ORDERING: bool = false;
+
+    /// Whether the transactions of a batch are partitioned into groups that do not conflict with
+    /// each other, based on the prefixes that they declare for prefetching (see
+    /// [`dispatcher::partition_by_prefixes`]).
+    ///
+    /// The groups are not executed concurrently as dispatch contexts cannot be shared between
+    /// threads. Instead, the transactions of each group are executed one after another on the
+    /// dispatcher thread, on an overlay over the state at the start of the batch. The changes are
+    /// then applied in execution order, and transactions which read state written by another group
+    /// (e.g., due to incomplete prefix declarations) are executed again, so the results are
+    /// identical to executing the transactions one after another. Batches that cannot be
+    /// partitioned are executed one transaction after another.
+    ///
+    /// This requires that transactions only observe the gas used by the batch through
+    /// `modules::core::API::remaining_tx_gas`. Batches are executed sequentially in case any
+    /// observers have been configured.
+    const PARTITIONED_EXECUTION: bool = false;
+
+    /// Whether panics occurring during query dispatch are caught and reported as `QueryAborted`
+    /// errors. Disabling this lets panics propagate together with their backtraces, which is only
+    /// intended for local development.
+    const CATCH_QUERY_PANICS: bool = true;
+
+    /// Maximum nesting depth of CBOR-encoded transactions and query arguments. Deeper inputs are
+    /// rejected before being decoded.
+    const MAX_CBOR_NESTING_DEPTH: u16 = 64;
+
+    type Modules: AuthHandler
+        + MigrationHandler
+        + MethodHandler
+        + BlockHandler
+        + EventHandler
+        + InvariantHandler
+        + ParametersHandler;
 
     /// Return the trusted policy signers for this runtime; if `None`, a key manager connection will
     /// not be established on startup.
@@ -38,6 +108,40 @@ pub trait Runtime {
         None
     }
 
+    /// Return the transaction signature context base for the given method; if `None`, the default
+    /// [`SIGNATURE_CONTEXT_BASE`](types::transaction::SIGNATURE_CONTEXT_BASE) is used.
+    ///
+    /// Giving sensitive methods a distinct context ensures that signatures authorizing them can
+    /// not be used for any other purpose.
+    fn signature_context_for(_method: &str) -> Option<&'static [u8]> {
+        None
+    }
+
+    /// Return the observer notified of the state keys written by each executed transaction; if
+    /// `None`, written keys are not collected.
+    fn state_observer() -> Option<dispatcher::StateObserver> {
+        None
+    }
+
+    /// Return the observer notified of the execution of each transaction; if `None`, execution is
+    /// not observed.
+    fn execution_observer() -> Option<Arc<dyn dispatcher::ExecutionObserver>> {
+        None
+    }
+
+    /// Optional capabilities supported by the runtime, as registered by its modules.
+    ///
+    /// In case the runtime has a key manager configured, the `key-manager` capability is included
+    /// as well.
+    fn capabilities() -> modules::core::types::Capabilities {
+        let mut capabilities = modules::core::types::Capabilities::new();
+        Self::Modules::register_capabilities(&mut capabilities);
+        if Self::trusted_policy_signers().is_some() {
+            capabilities.insert("key-manager".to_owned());
+        }
+        capabilities
+    }
+
     /// Genesis state for the runtime.
     fn genesis_state() -> <Self::Modules as MigrationHandler>::Genesis;
 
@@ -48,7 +152,9 @@ pub trait Runtime {
     }
 
     /// Perform state migrations if required.
-    fn migrate<C: Context>(ctx: &mut C) {
+    ///
+    /// Returns true in case any state has been initialized or migrated.
+    fn migrate<C: Context>(ctx: &mut C) -> bool {
         let store = storage::TypedStore::new(storage::PrefixStore::new(
             ctx.runtime_state(),
             &modules::core::MODULE_NAME,
@@ -93,6 +199,23 @@ pub trait Runtime {
             ));
             store.insert(modules::core::state::METADATA, metadata);
         }
+
+        has_changes
+    }
+
+    /// Perform state migrations if required and, in strict mode, make sure that the parameters of
+    /// all modules have been initialized afterwards.
+    fn migrate_checked<C: Context>(ctx: &mut C) -> Result<(), modules::core::Error> {
+        if !Self::migrate(ctx) || !Self::STRICT_PARAMETERS {
+            return Ok(());
+        }
+
+        let mut missing = vec![];
+        Self::Modules::missing_params(ctx.runtime_state(), &mut missing);
+        if !missing.is_empty() {
+            return Err(modules::core::Error::UninitializedParameters(missing));
+        }
+        Ok(())
     }
 
     /// Start the runtime.
@@ -100,6 +223,9 @@ pub trait Runtime {
     where
         Self: Sized + Send + Sync + 'static,
     {
+        // Configure how addresses are rendered.
+        types::address::set_bech32_hrp(Self::ADDRESS_BECH32_HRP);
+
         // Initializer.
         let init = |protocol: &Arc<Protocol>,
                     rak: &Arc<RAK>,
@@ -125,8 +251,12 @@ pub trait Runtime {
                 )
             });
 
-            // Register runtime's methods.
-            let dispatcher = dispatcher::Dispatcher::<Self>::new(hi, key_manager);
+            // Register runtime's methods. Asynchronous queries are driven by the host's runtime.
+            let dispatcher = dispatcher::Dispatcher::<Self>::new(
+                hi,
+                key_manager,
+                tokio::runtime::Handle::try_current().ok(),
+            );
             Some(Box::new(dispatcher))
         };
 
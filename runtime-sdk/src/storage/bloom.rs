@@ -0,0 +1,190 @@
+use std::convert::TryInto;
+
+use oasis_core_runtime::storage::mkvs;
+use sha2::{Digest, Sha512Trunc256};
+
+use super::Store;
+
+/// Number of filter bits allocated per expected key.
+const BITS_PER_KEY: usize = 10;
+/// Number of hash functions used by the filter.
+const NUM_HASHES: u64 = 7;
+
+/// A key-value store wrapper that maintains an in-memory bloom filter over all of its keys so
+/// that lookups of absent keys can usually be answered without touching the underlying store.
+///
+/// The filter is not persisted. It is rebuilt from all keys of the parent store when the wrapper
+/// is created, so this is only worth it when many lookups are performed afterwards (e.g. checking
+/// membership in a large nullifier set).
+///
+/// # False positives
+///
+/// A bloom filter can report that a key may be present even though it is not. In that case the
+/// lookup simply falls through to the parent store, so results are always exact and false
+/// positives only cost performance. As bits can not be cleared, removed keys also keep falling
+/// through until the filter is rebuilt.
+///
+/// All modifications must go through the wrapper as changes made to the parent store directly
+/// are not reflected in the filter.
+pub struct BloomStore<S: Store> {
+    parent: S,
+    bits: Vec<u64>,
+}
+
+impl<S: Store> BloomStore<S> {
+    /// Create a new bloom store sized for the given expected number of keys, building the filter
+    /// from all keys currently in the parent store.
+    pub fn new(parent: S, expected_keys: usize) -> Self {
+        let num_bits = expected_keys.max(1).saturating_mul(BITS_PER_KEY);
+        let mut store = Self {
+            parent,
+            bits: vec![0; (num_bits + 63) / 64],
+        };
+
+        let keys: Vec<Vec<u8>> = store.parent.iter().map(|(key, _)| key).collect();
+        for key in keys {
+            store.add(&key);
+        }
+        store
+    }
+
+    /// Whether the given key exists in the store.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Whether the filter indicates that the given key may be present. A negative answer means
+    /// that the key is definitely absent.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.bit_indices(key)
+            .all(|index| self.bits[index / 64] & (1 << (index % 64)) != 0)
+    }
+
+    fn add(&mut self, key: &[u8]) {
+        for index in self.bit_indices(key).collect::<Vec<_>>() {
+            self.bits[index / 64] |= 1 << (index % 64);
+        }
+    }
+
+    fn bit_indices(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        // Derive all indices from a single hash using double hashing.
+        let hash = Sha512Trunc256::digest(key);
+        let h1 = u64::from_le_bytes(hash[..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(hash[8..16].try_into().unwrap());
+        let num_bits = (self.bits.len() * 64) as u64;
+        (0..NUM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+}
+
+impl<S: Store> Store for BloomStore<S> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        if !self.may_contain(key) {
+            return None;
+        }
+        self.parent.get(key)
+    }
+
+    fn insert(&mut self, key: &[u8], value: &[u8]) {
+        self.add(key);
+        self.parent.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        // Bits can not be cleared as they may be shared with other keys.
+        self.parent.remove(key);
+    }
+
+    fn iter(&self) -> Box<dyn mkvs::Iterator + '_> {
+        self.parent.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::{context::Context, storage::PrefixStore, testing::mock};
+
+    /// A store wrapper counting lookups that reach it.
+    struct CountingStore<S: Store> {
+        inner: S,
+        gets: Cell<usize>,
+    }
+
+    impl<S: Store> Store for CountingStore<S> {
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            self.gets.set(self.gets.get() + 1);
+            self.inner.get(key)
+        }
+
+        fn insert(&mut self, key: &[u8], value: &[u8]) {
+            self.inner.insert(key, value)
+        }
+
+        fn remove(&mut self, key: &[u8]) {
+            self.inner.remove(key)
+        }
+
+        fn iter(&self) -> Box<dyn mkvs::Iterator + '_> {
+            self.inner.iter()
+        }
+    }
+
+    #[test]
+    fn test_bloom_store() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx();
+
+        // Populate the store before the filter is built.
+        {
+            let mut store = PrefixStore::new(ctx.runtime_state(), b"nullifiers");
+            for i in 0..50u8 {
+                store.insert(&[b'a', i], b"spent");
+            }
+        }
+
+        let counting = CountingStore {
+            inner: PrefixStore::new(ctx.runtime_state(), b"nullifiers"),
+            gets: Cell::new(0),
+        };
+        let mut store = BloomStore::new(counting, 100);
+
+        // Keys present when loading should still resolve.
+        for i in 0..50u8 {
+            assert!(
+                store.may_contain(&[b'a', i]),
+                "filter should be rebuilt on load"
+            );
+            assert_eq!(store.get(&[b'a', i]), Some(b"spent".to_vec()));
+        }
+
+        // Lookups of definitely absent keys should not reach the parent store.
+        store.parent.gets.set(0);
+        let mut negatives = 0;
+        for i in 0..50u8 {
+            let key = [b'b', i];
+            assert!(!store.contains(&key));
+            if !store.may_contain(&key) {
+                negatives += 1;
+            }
+        }
+        assert!(negatives > 0, "filter should rule out some absent keys");
+        assert_eq!(
+            store.parent.gets.get(),
+            50 - negatives,
+            "only possible positives should fall through"
+        );
+
+        // Inserted keys should become visible.
+        assert!(!store.contains(b"new"));
+        store.insert(b"new", b"spent");
+        assert!(store.may_contain(b"new"));
+        assert_eq!(store.get(b"new"), Some(b"spent".to_vec()));
+
+        // Removed keys should no longer resolve even though the filter still matches them.
+        store.remove(b"new");
+        assert!(store.may_contain(b"new"));
+        assert!(!store.contains(b"new"));
+    }
+}
@@ -0,0 +1,217 @@
+use oasis_core_runtime::storage::mkvs;
+
+use super::Store;
+
+/// Flag byte prefixed to values stored as-is.
+const FLAG_UNCOMPRESSED: u8 = 0x00;
+/// Flag byte prefixed to values stored compressed.
+const FLAG_COMPRESSED: u8 = 0x01;
+
+/// A key-value store that transparently compresses large values.
+///
+/// Values larger than the configured threshold are compressed using Snappy on insert and
+/// decompressed on get. Each stored value is prefixed by a flag byte indicating whether it is
+/// compressed, so values that do not compress well are stored as-is. As the flag byte is always
+/// present, the parent store must only be accessed through this wrapper.
+pub struct CompressingStore<S: Store> {
+    parent: S,
+    threshold: usize,
+}
+
+impl<S: Store> CompressingStore<S> {
+    /// Create a new compressing store which compresses values larger than `threshold` bytes.
+    pub fn new(parent: S, threshold: usize) -> Self {
+        Self { parent, threshold }
+    }
+
+    fn encode_value(&self, value: &[u8]) -> Vec<u8> {
+        if value.len() > self.threshold {
+            let compressed = snap::raw::Encoder::new()
+                .compress_vec(value)
+                .expect("compression should not fail");
+            if compressed.len() < value.len() {
+                return [&[FLAG_COMPRESSED], compressed.as_slice()].concat();
+            }
+        }
+        [&[FLAG_UNCOMPRESSED], value].concat()
+    }
+}
+
+fn decode_value(data: &[u8]) -> Vec<u8> {
+    match data.split_first() {
+        Some((&FLAG_UNCOMPRESSED, value)) => value.to_vec(),
+        Some((&FLAG_COMPRESSED, compressed)) => snap::raw::Decoder::new()
+            .decompress_vec(compressed)
+            .expect("corrupted compressed value"),
+        _ => panic!("malformed compressed store value"),
+    }
+}
+
+impl<S: Store> Store for CompressingStore<S> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.parent.get(key).map(|data| decode_value(&data))
+    }
+
+    fn insert(&mut self, key: &[u8], value: &[u8]) {
+        let data = self.encode_value(value);
+        self.parent.insert(key, &data);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.parent.remove(key);
+    }
+
+    fn iter(&self) -> Box<dyn mkvs::Iterator + '_> {
+        Box::new(CompressingStoreIterator::new(self.parent.iter()))
+    }
+}
+
+/// An iterator over the `CompressingStore`.
+pub(crate) struct CompressingStoreIterator<'store> {
+    inner: Box<dyn mkvs::Iterator + 'store>,
+    value: Option<Vec<u8>>,
+}
+
+impl<'store> CompressingStoreIterator<'store> {
+    fn new(inner: Box<dyn mkvs::Iterator + 'store>) -> Self {
+        let mut iter = Self { inner, value: None };
+        iter.update_value();
+        iter
+    }
+
+    fn update_value(&mut self) {
+        self.value = self.inner.get_value().as_deref().map(decode_value);
+    }
+}
+
+impl<'store> Iterator for CompressingStoreIterator<'store> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use mkvs::Iterator;
+
+        if !self.is_valid() {
+            return None;
+        }
+
+        let key = self.get_key().as_ref().expect("iterator is valid").clone();
+        let value = self.value.take().expect("iterator is valid");
+        Iterator::next(self);
+
+        Some((key, value))
+    }
+}
+
+impl<'store> mkvs::Iterator for CompressingStoreIterator<'store> {
+    fn set_prefetch(&mut self, prefetch: usize) {
+        self.inner.set_prefetch(prefetch)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    fn error(&self) -> &Option<anyhow::Error> {
+        self.inner.error()
+    }
+
+    fn rewind(&mut self) {
+        self.inner.rewind();
+        self.update_value();
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        self.inner.seek(key);
+        self.update_value();
+    }
+
+    fn get_key(&self) -> &Option<mkvs::Key> {
+        self.inner.get_key()
+    }
+
+    fn get_value(&self) -> &Option<Vec<u8>> {
+        &self.value
+    }
+
+    fn next(&mut self) {
+        mkvs::Iterator::next(&mut *self.inner);
+        self.update_value();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{context::Context, storage::PrefixStore, testing::mock};
+
+    const THRESHOLD: usize = 64;
+
+    #[test]
+    fn test_compressing_store() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx();
+
+        let small = b"small value".to_vec();
+        let large = vec![42u8; 4 * 1024];
+
+        {
+            let mut store =
+                CompressingStore::new(PrefixStore::new(ctx.runtime_state(), b"blobs"), THRESHOLD);
+            store.insert(b"large", &large);
+            store.insert(b"small", &small);
+
+            assert_eq!(store.get(b"large"), Some(large.clone()));
+            assert_eq!(store.get(b"small"), Some(small.clone()));
+            assert_eq!(store.get(b"missing"), None);
+        }
+
+        // Check how values are actually stored.
+        let raw = PrefixStore::new(ctx.runtime_state(), b"blobs");
+        let stored_small = raw.get(b"small").unwrap();
+        assert_eq!(stored_small[0], FLAG_UNCOMPRESSED);
+        assert_eq!(&stored_small[1..], small.as_slice());
+        let stored_large = raw.get(b"large").unwrap();
+        assert_eq!(stored_large[0], FLAG_COMPRESSED);
+        assert!(
+            stored_large.len() < large.len(),
+            "large value should be compressed"
+        );
+
+        // Iteration should decompress values.
+        let store =
+            CompressingStore::new(PrefixStore::new(ctx.runtime_state(), b"blobs"), THRESHOLD);
+        let items: Vec<_> = store.iter().collect();
+        assert_eq!(
+            items,
+            vec![
+                (b"large".to_vec(), large.clone()),
+                (b"small".to_vec(), small.clone()),
+            ]
+        );
+
+        let mut it = store.iter();
+        it.seek(b"small");
+        assert!(it.is_valid());
+        assert_eq!(it.get_value(), &Some(small));
+        it.rewind();
+        assert_eq!(it.get_value(), &Some(large));
+    }
+
+    #[test]
+    fn test_compressing_store_incompressible() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx();
+
+        // Large values that do not compress should be stored as-is.
+        let value: Vec<u8> = (0..=255u8).collect();
+        let mut store =
+            CompressingStore::new(PrefixStore::new(ctx.runtime_state(), b"blobs"), THRESHOLD);
+        store.insert(b"random", &value);
+        assert_eq!(store.get(b"random"), Some(value.clone()));
+
+        let raw = PrefixStore::new(ctx.runtime_state(), b"blobs");
+        let stored = raw.get(b"random").unwrap();
+        assert_eq!(stored[0], FLAG_UNCOMPRESSED);
+        assert_eq!(&stored[1..], value.as_slice());
+    }
+}
@@ -0,0 +1,100 @@
+use std::convert::TryInto;
+
+use super::Store;
+
+/// Key under which the counter value is stored.
+const COUNTER_KEY: &[u8] = &[0x00];
+
+/// A monotonically increasing counter persisted in the underlying store, suitable for allocating
+/// nonces, identifiers or indices.
+///
+/// The counter does not cache its value, so all counters over the same store observe each other's
+/// updates. As contexts are executed sequentially, this makes allocation safe as long as the
+/// value is only modified through counters. Updates are subject to the same commit and rollback
+/// semantics as any other changes made in the enclosing context.
+pub struct Counter<S: Store> {
+    parent: S,
+}
+
+impl<S: Store> Counter<S> {
+    /// Create a new counter.
+    pub fn new(parent: S) -> Self {
+        Self { parent }
+    }
+
+    /// Current value of the counter. This is the value that will be returned by the next call to
+    /// `next`.
+    pub fn current(&self) -> u64 {
+        self.parent
+            .get(COUNTER_KEY)
+            .map(|data| {
+                u64::from_be_bytes(data.as_slice().try_into().expect("corrupted counter value"))
+            })
+            .unwrap_or_default()
+    }
+
+    /// Allocate the next value, advancing the counter.
+    pub fn next(&mut self) -> u64 {
+        let value = self.current();
+        self.parent.insert(
+            COUNTER_KEY,
+            &value
+                .checked_add(1)
+                .expect("counter overflow")
+                .to_be_bytes(),
+        );
+        value
+    }
+
+    /// Underlying store. Any data stored alongside the counter must use keys other than the
+    /// counter key (`0x00`).
+    pub(crate) fn store(&self) -> &S {
+        &self.parent
+    }
+
+    /// Mutable reference to the underlying store. Any data stored alongside the counter must use
+    /// keys other than the counter key (`0x00`).
+    pub(crate) fn store_mut(&mut self) -> &mut S {
+        &mut self.parent
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{context::Context, storage::PrefixStore, testing::mock};
+
+    #[test]
+    fn test_sequential_allocation() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx();
+        let mut counter = Counter::new(PrefixStore::new(ctx.runtime_state(), b"ids"));
+
+        assert_eq!(counter.current(), 0);
+        assert_eq!(counter.next(), 0);
+        assert_eq!(counter.next(), 1);
+        assert_eq!(counter.next(), 2);
+        assert_eq!(counter.current(), 3);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx();
+
+        {
+            let mut counter = Counter::new(PrefixStore::new(ctx.runtime_state(), b"ids"));
+            counter.next();
+            counter.next();
+        }
+
+        // Reloading the counter should continue from the stored value.
+        let mut counter = Counter::new(PrefixStore::new(ctx.runtime_state(), b"ids"));
+        assert_eq!(counter.current(), 2);
+        assert_eq!(counter.next(), 2);
+
+        // Counters under different prefixes should be independent.
+        let mut other = Counter::new(PrefixStore::new(ctx.runtime_state(), b"other"));
+        assert_eq!(other.next(), 0);
+    }
+}
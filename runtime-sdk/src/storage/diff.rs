@@ -0,0 +1,152 @@
+use std::cmp::Ordering;
+
+use oasis_core_runtime::storage::mkvs::Iterator as _;
+
+use super::Store;
+
+/// A change of a single key between two states.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateChange {
+    /// The key has been added.
+    Added { key: Vec<u8>, value: Vec<u8> },
+    /// The value of an existing key has been modified.
+    Modified {
+        key: Vec<u8>,
+        old_value: Vec<u8>,
+        new_value: Vec<u8>,
+    },
+    /// The key has been removed.
+    Removed { key: Vec<u8>, old_value: Vec<u8> },
+}
+
+impl StateChange {
+    /// Key that has changed.
+    pub fn key(&self) -> &[u8] {
+        match self {
+            Self::Added { key, .. } | Self::Modified { key, .. } | Self::Removed { key, .. } => key,
+        }
+    }
+}
+
+/// Compute the changes between two states, ordered by key.
+///
+/// The states will usually be MKVS trees at two different roots (see
+/// [`MKVSStore`](super::MKVSStore)). Only keys starting with the given prefix are compared, an
+/// empty prefix compares all keys.
+pub fn diff<O: Store, N: Store>(old: &O, new: &N, prefix: &[u8]) -> Vec<StateChange> {
+    let mut old_iter = old.iter();
+    old_iter.seek(prefix);
+    let mut old_iter = old_iter
+        .take_while(|(key, _)| key.starts_with(prefix))
+        .peekable();
+    let mut new_iter = new.iter();
+    new_iter.seek(prefix);
+    let mut new_iter = new_iter
+        .take_while(|(key, _)| key.starts_with(prefix))
+        .peekable();
+
+    // Both iterators return keys in order, so the states can be compared in a single pass.
+    let mut changes = Vec::new();
+    loop {
+        let ordering = match (old_iter.peek(), new_iter.peek()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some((old_key, _)), Some((new_key, _))) => old_key.cmp(new_key),
+        };
+
+        match ordering {
+            Ordering::Less => {
+                let (key, old_value) = old_iter.next().unwrap();
+                changes.push(StateChange::Removed { key, old_value });
+            }
+            Ordering::Greater => {
+                let (key, value) = new_iter.next().unwrap();
+                changes.push(StateChange::Added { key, value });
+            }
+            Ordering::Equal => {
+                let (key, old_value) = old_iter.next().unwrap();
+                let (_, new_value) = new_iter.next().unwrap();
+                if old_value != new_value {
+                    changes.push(StateChange::Modified {
+                        key,
+                        old_value,
+                        new_value,
+                    });
+                }
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{context::Context, testing::mock};
+
+    #[test]
+    fn test_diff() {
+        let mut old_mock = mock::Mock::default();
+        let mut old_ctx = old_mock.create_ctx();
+        let mut new_mock = mock::Mock::default();
+        let mut new_ctx = new_mock.create_ctx();
+
+        let old = old_ctx.runtime_state();
+        old.insert(b"a/unchanged", b"1");
+        old.insert(b"a/modified", b"2");
+        old.insert(b"a/removed", b"3");
+        old.insert(b"b/removed", b"4");
+
+        let new = new_ctx.runtime_state();
+        new.insert(b"a/unchanged", b"1");
+        new.insert(b"a/modified", b"20");
+        new.insert(b"a/added", b"5");
+        new.insert(b"c/added", b"6");
+
+        assert_eq!(
+            diff(old, new, b""),
+            vec![
+                StateChange::Added {
+                    key: b"a/added".to_vec(),
+                    value: b"5".to_vec(),
+                },
+                StateChange::Modified {
+                    key: b"a/modified".to_vec(),
+                    old_value: b"2".to_vec(),
+                    new_value: b"20".to_vec(),
+                },
+                StateChange::Removed {
+                    key: b"a/removed".to_vec(),
+                    old_value: b"3".to_vec(),
+                },
+                StateChange::Removed {
+                    key: b"b/removed".to_vec(),
+                    old_value: b"4".to_vec(),
+                },
+                StateChange::Added {
+                    key: b"c/added".to_vec(),
+                    value: b"6".to_vec(),
+                },
+            ]
+        );
+
+        // Changes should be restricted to the prefix.
+        let keys: Vec<_> = diff(old, new, b"a/")
+            .iter()
+            .map(|change| change.key().to_vec())
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                b"a/added".to_vec(),
+                b"a/modified".to_vec(),
+                b"a/removed".to_vec(),
+            ]
+        );
+        assert!(diff(old, new, b"b/unknown").is_empty());
+
+        // Identical states should have no changes.
+        assert!(diff(old, old, b"").is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+use super::{Counter, Store};
+
+/// Prefix of keys under which the entries are stored.
+const ENTRY_PREFIX: &[u8] = &[0x01];
+
+/// An append-only log store where entries are keyed by a monotonically increasing index.
+///
+/// Indices are allocated by a [`Counter`] stored alongside the entries, so the counter value is
+/// the number of entries in the log.
+pub struct LogStore<S: Store> {
+    counter: Counter<S>,
+}
+
+impl<S: Store> LogStore<S> {
+    /// Create a new log store.
+    pub fn new(parent: S) -> Self {
+        Self {
+            counter: Counter::new(parent),
+        }
+    }
+
+    fn entry_key(index: u64) -> Vec<u8> {
+        [ENTRY_PREFIX, &index.to_be_bytes()].concat()
+    }
+
+    /// Number of entries in the log.
+    pub fn len(&self) -> u64 {
+        self.counter.current()
+    }
+
+    /// Whether the log is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append an entry to the log and return its index.
+    pub fn append(&mut self, entry: &[u8]) -> u64 {
+        let index = self.counter.next();
+        self.counter
+            .store_mut()
+            .insert(&Self::entry_key(index), entry);
+        index
+    }
+
+    /// Fetch the entry with the given index.
+    pub fn get(&self, index: u64) -> Option<Vec<u8>> {
+        self.counter.store().get(&Self::entry_key(index))
+    }
+
+    /// Returns an iterator over all entries starting at the given index (inclusive).
+    pub fn iter_from(&self, index: u64) -> impl Iterator<Item = (u64, Vec<u8>)> + '_ {
+        (index..self.len()).map(move |index| {
+            let entry = self.get(index).expect("missing log entry");
+            (index, entry)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{context::Context, storage::PrefixStore, testing::mock};
+
+    #[test]
+    fn test_append_ordering() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx();
+        let mut log = LogStore::new(PrefixStore::new(ctx.runtime_state(), b"log"));
+
+        assert!(log.is_empty());
+        assert_eq!(log.get(0), None);
+
+        assert_eq!(log.append(b"first"), 0);
+        assert_eq!(log.append(b"second"), 1);
+        assert_eq!(log.append(b"third"), 2);
+
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.get(0), Some(b"first".to_vec()));
+        assert_eq!(log.get(1), Some(b"second".to_vec()));
+        assert_eq!(log.get(2), Some(b"third".to_vec()));
+        assert_eq!(log.get(3), None);
+    }
+
+    #[test]
+    fn test_iter_from() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx();
+        let mut log = LogStore::new(PrefixStore::new(ctx.runtime_state(), b"log"));
+
+        for i in 0..10u8 {
+            log.append(&[i]);
+        }
+
+        let entries: Vec<_> = log.iter_from(7).collect();
+        assert_eq!(
+            entries,
+            vec![(7, vec![7u8]), (8, vec![8u8]), (9, vec![9u8])]
+        );
+        assert_eq!(log.iter_from(0).count(), 10);
+        assert_eq!(log.iter_from(10).count(), 0);
+        assert_eq!(log.iter_from(100).count(), 0);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx();
+
+        {
+            let mut log = LogStore::new(PrefixStore::new(ctx.runtime_state(), b"log"));
+            log.append(b"first");
+        }
+
+        // Reopening the log should continue from the stored length.
+        let mut log = LogStore::new(PrefixStore::new(ctx.runtime_state(), b"log"));
+        assert_eq!(log.append(b"second"), 1);
+        assert_eq!(log.get(0), Some(b"first".to_vec()));
+    }
+}
@@ -1,10 +1,18 @@
 //! Storage.
 use oasis_core_runtime::storage::mkvs::Iterator;
 
+mod bloom;
+mod compressing;
+mod counter;
+mod diff;
 mod hashed;
+mod log;
 mod mkvs;
 mod overlay;
 mod prefix;
+#[cfg(feature = "debug-storage-access")]
+mod recording;
+mod speculative;
 mod typed;
 
 /// A key-value store.
@@ -51,10 +59,18 @@ impl<S: Store + ?Sized> Store for &mut S {
     }
 }
 
+pub use bloom::BloomStore;
+pub use compressing::CompressingStore;
+pub use counter::Counter;
+pub use diff::{diff, StateChange};
 pub use hashed::HashedStore;
+pub use log::LogStore;
 pub use mkvs::MKVSStore;
 pub use overlay::OverlayStore;
 pub use prefix::PrefixStore;
+#[cfg(feature = "debug-storage-access")]
+pub use recording::RecordingStore;
+pub use speculative::{SpeculativeChanges, SpeculativeStore};
 pub use typed::TypedStore;
 
 // Re-export the mkvs storage prefix.
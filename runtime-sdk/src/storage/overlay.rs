@@ -6,12 +6,14 @@ use std::{
 use oasis_core_runtime::storage::mkvs;
 
 use super::{NestedStore, Store};
+use crate::trace;
 
 /// An overlay store which keeps values locally until explicitly committed.
 pub struct OverlayStore<S: Store> {
     parent: S,
     overlay: BTreeMap<Vec<u8>, Vec<u8>>,
     dirty: HashSet<Vec<u8>>,
+    tracer: Option<trace::Tracer>,
 }
 
 impl<S: Store> OverlayStore<S> {
@@ -21,8 +23,22 @@ impl<S: Store> OverlayStore<S> {
             parent,
             overlay: BTreeMap::new(),
             dirty: HashSet::new(),
+            tracer: None,
         }
     }
+
+    /// Record all reads and writes performed through this store using the given tracer.
+    pub fn set_tracer(&mut self, tracer: trace::Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Keys that have been inserted or removed through this store and not yet committed, in
+    /// ascending order.
+    pub fn written_keys(&self) -> Vec<Vec<u8>> {
+        let mut keys: Vec<_> = self.dirty.iter().cloned().collect();
+        keys.sort();
+        keys
+    }
 }
 
 impl<S: Store> NestedStore for OverlayStore<S> {
@@ -46,21 +62,43 @@ impl<S: Store> NestedStore for OverlayStore<S> {
 
 impl<S: Store> Store for OverlayStore<S> {
     fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        // For dirty values, check the overlay.
-        if self.dirty.contains(key) {
-            return self.overlay.get(key).cloned();
+        let value = if self.dirty.contains(key) {
+            // For dirty values, check the overlay.
+            self.overlay.get(key).cloned()
+        } else {
+            // Otherwise fetch from parent store.
+            self.parent.get(key)
+        };
+
+        if let Some(tracer) = &self.tracer {
+            tracer.record(trace::Operation::Get {
+                key: key.to_owned(),
+                value: value.clone(),
+            });
         }
 
-        // Otherwise fetch from parent store.
-        self.parent.get(key)
+        value
     }
 
     fn insert(&mut self, key: &[u8], value: &[u8]) {
+        if let Some(tracer) = &self.tracer {
+            tracer.record(trace::Operation::Insert {
+                key: key.to_owned(),
+                value: value.to_owned(),
+            });
+        }
+
         self.overlay.insert(key.to_owned(), value.to_owned());
         self.dirty.insert(key.to_owned());
     }
 
     fn remove(&mut self, key: &[u8]) {
+        if let Some(tracer) = &self.tracer {
+            tracer.record(trace::Operation::Remove {
+                key: key.to_owned(),
+            });
+        }
+
         // For dirty values, remove from the overlay.
         if self.dirty.contains(key) {
             self.overlay.remove(key);
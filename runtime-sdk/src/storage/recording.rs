@@ -0,0 +1,202 @@
+use std::{cell::RefCell, collections::BTreeSet};
+
+use oasis_core_runtime::storage::mkvs;
+use slog::debug;
+
+use super::{Prefix, Store};
+
+/// A key-value store that records the keys accessed through it.
+///
+/// This is meant as an instrumentation aid for tuning the prefixes declared by method handlers in
+/// `prefetch`. Wrap the store used to execute a transaction, then compare the recorded accesses
+/// against the declared prefixes to detect prefixes that are missing or never used.
+pub struct RecordingStore<S: Store> {
+    parent: S,
+    accessed: RefCell<BTreeSet<Vec<u8>>>,
+}
+
+impl<S: Store> RecordingStore<S> {
+    /// Create a new recording store.
+    pub fn new(parent: S) -> Self {
+        Self {
+            parent,
+            accessed: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    /// Keys accessed so far. Iteration is recorded as an access of each key that an iterator was
+    /// positioned at by seeking.
+    pub fn accessed(&self) -> BTreeSet<Vec<u8>> {
+        self.accessed.borrow().clone()
+    }
+
+    /// Accessed keys that are not covered by any of the given prefixes.
+    pub fn undeclared(&self, prefixes: &BTreeSet<Prefix>) -> Vec<Vec<u8>> {
+        self.accessed
+            .borrow()
+            .iter()
+            .filter(|key| !prefixes.iter().any(|prefix| covers(prefix, key)))
+            .cloned()
+            .collect()
+    }
+
+    /// Prefixes that do not cover any of the accessed keys.
+    pub fn unused(&self, prefixes: &BTreeSet<Prefix>) -> Vec<Prefix> {
+        let accessed = self.accessed.borrow();
+        prefixes
+            .iter()
+            .filter(|prefix| !accessed.iter().any(|key| covers(prefix, key)))
+            .cloned()
+            .collect()
+    }
+
+    /// Log the accessed keys that are not covered by the given prefetch prefixes and the
+    /// prefixes that were not needed.
+    pub fn log_report(&self, logger: &slog::Logger, prefixes: &BTreeSet<Prefix>) {
+        for key in self.undeclared(prefixes) {
+            debug!(logger, "accessed key not covered by prefetch";
+                "key" => hex::encode(&key),
+            );
+        }
+        for prefix in self.unused(prefixes) {
+            debug!(logger, "prefetch prefix not accessed";
+                "prefix" => hex::encode(prefix.as_ref() as &[u8]),
+            );
+        }
+    }
+
+    fn record(&self, key: &[u8]) {
+        self.accessed.borrow_mut().insert(key.to_vec());
+    }
+}
+
+impl<S: Store> Store for RecordingStore<S> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.record(key);
+        self.parent.get(key)
+    }
+
+    fn insert(&mut self, key: &[u8], value: &[u8]) {
+        self.record(key);
+        self.parent.insert(key, value)
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.record(key);
+        self.parent.remove(key)
+    }
+
+    fn iter(&self) -> Box<dyn mkvs::Iterator + '_> {
+        Box::new(RecordingStoreIterator {
+            inner: self.parent.iter(),
+            accessed: &self.accessed,
+        })
+    }
+}
+
+/// Whether the given prefix covers the given key.
+fn covers(prefix: &Prefix, key: &[u8]) -> bool {
+    let prefix: &[u8] = prefix.as_ref();
+    key.starts_with(prefix)
+}
+
+/// An iterator over the `RecordingStore`.
+struct RecordingStoreIterator<'store> {
+    inner: Box<dyn mkvs::Iterator + 'store>,
+    accessed: &'store RefCell<BTreeSet<Vec<u8>>>,
+}
+
+impl<'store> Iterator for RecordingStoreIterator<'store> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Iterator::next(&mut self.inner)
+    }
+}
+
+impl<'store> mkvs::Iterator for RecordingStoreIterator<'store> {
+    fn set_prefetch(&mut self, prefetch: usize) {
+        self.inner.set_prefetch(prefetch)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    fn error(&self) -> &Option<anyhow::Error> {
+        self.inner.error()
+    }
+
+    fn rewind(&mut self) {
+        self.accessed.borrow_mut().insert(vec![]);
+        self.inner.rewind()
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        self.accessed.borrow_mut().insert(key.to_vec());
+        self.inner.seek(key)
+    }
+
+    fn get_key(&self) -> &Option<mkvs::Key> {
+        self.inner.get_key()
+    }
+
+    fn get_value(&self) -> &Option<Vec<u8>> {
+        self.inner.get_value()
+    }
+
+    fn next(&mut self) {
+        mkvs::Iterator::next(&mut *self.inner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        context::Context,
+        storage::{PrefixStore, TypedStore},
+        testing::mock,
+    };
+
+    #[test]
+    fn test_keyvalue_insert() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx();
+        let mut store = RecordingStore::new(ctx.runtime_state());
+
+        // Perform the same accesses as a keyvalue insert: check whether the key exists, then
+        // insert it.
+        {
+            let mut kv = TypedStore::new(PrefixStore::new(&mut store, &"keyvalue"));
+            assert_eq!(kv.get::<_, Vec<u8>>("key"), None);
+            kv.insert("key", b"value".to_vec());
+        }
+
+        let key = [&b"keyvalue"[..], b"key"].concat();
+        let mut expected = BTreeSet::new();
+        expected.insert(key.clone());
+        assert_eq!(store.accessed(), expected);
+
+        let mut prefixes = BTreeSet::new();
+        prefixes.insert(Prefix::from(b"keyvalue".to_vec()));
+        assert!(store.undeclared(&prefixes).is_empty());
+        assert!(store.unused(&prefixes).is_empty());
+
+        // Under- and over-declared prefixes should be reported.
+        let mut prefixes = BTreeSet::new();
+        prefixes.insert(Prefix::from(b"accounts".to_vec()));
+        assert_eq!(store.undeclared(&prefixes), vec![key]);
+        assert_eq!(
+            store.unused(&prefixes),
+            vec![Prefix::from(b"accounts".to_vec())]
+        );
+
+        // Iteration should be recorded as an access of the iterated prefix.
+        {
+            let kv = PrefixStore::new(&mut store, &"other");
+            assert_eq!(kv.iter().count(), 0);
+        }
+        assert!(store.accessed().contains(&b"other"[..]));
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet},
+};
+
+use oasis_core_runtime::storage::mkvs;
+
+use super::{NestedStore, OverlayStore, Store};
+
+/// An overlay store used to execute a transaction speculatively.
+///
+/// Besides keeping all writes local until they are explicitly committed, the store records the
+/// keys that were read from the parent store so that the speculation can later be validated
+/// against the writes of other transactions.
+pub struct SpeculativeStore<S: Store> {
+    inner: OverlayStore<S>,
+    written: BTreeSet<Vec<u8>>,
+    reads: RefCell<BTreeSet<Vec<u8>>>,
+    iterated: Cell<bool>,
+}
+
+impl<S: Store> SpeculativeStore<S> {
+    /// Create a new speculative store.
+    pub fn new(parent: S) -> Self {
+        Self {
+            inner: OverlayStore::new(parent),
+            written: BTreeSet::new(),
+            reads: RefCell::new(BTreeSet::new()),
+            iterated: Cell::new(false),
+        }
+    }
+
+    /// Changes made through this store together with the keys read from the parent store.
+    pub fn changes(&self) -> SpeculativeChanges {
+        SpeculativeChanges {
+            reads: self.reads.borrow().clone(),
+            iterated: self.iterated.get(),
+            writes: self
+                .written
+                .iter()
+                .map(|key| (key.clone(), self.inner.get(key)))
+                .collect(),
+        }
+    }
+}
+
+impl<S: Store> NestedStore for SpeculativeStore<S> {
+    type Inner = S;
+
+    fn commit(self) -> Self::Inner {
+        self.inner.commit()
+    }
+}
+
+impl<S: Store> Store for SpeculativeStore<S> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        // Keys written through this store are served from the overlay.
+        if !self.written.contains(key) {
+            self.reads.borrow_mut().insert(key.to_vec());
+        }
+        self.inner.get(key)
+    }
+
+    fn insert(&mut self, key: &[u8], value: &[u8]) {
+        self.written.insert(key.to_vec());
+        self.inner.insert(key, value)
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.written.insert(key.to_vec());
+        self.inner.remove(key)
+    }
+
+    fn iter(&self) -> Box<dyn mkvs::Iterator + '_> {
+        // The keys visited by an iterator depend on the keys that are present, so any write
+        // may affect it.
+        self.iterated.set(true);
+        self.inner.iter()
+    }
+}
+
+/// State changes made by a speculatively executed transaction.
+#[derive(Clone, Debug, Default)]
+pub struct SpeculativeChanges {
+    /// Keys read from the parent store.
+    pub reads: BTreeSet<Vec<u8>>,
+    /// Whether the parent store has been iterated over.
+    pub iterated: bool,
+    /// Written keys with their new values, `None` for removed keys.
+    pub writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl SpeculativeChanges {
+    /// Whether the outcome of the speculation may depend on any of the given keys.
+    pub fn reads_any(&self, keys: &BTreeSet<Vec<u8>>) -> bool {
+        if keys.is_empty() {
+            return false;
+        }
+        self.iterated || self.reads.iter().any(|key| keys.contains(key))
+    }
+
+    /// Apply the writes to the given store.
+    pub fn apply<S: Store>(&self, store: &mut S) {
+        for (key, value) in &self.writes {
+            match value {
+                Some(value) => store.insert(key, value),
+                None => store.remove(key),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mkvs::Iterator as _;
+
+    use super::*;
+    use crate::{context::Context, testing::mock};
+
+    #[test]
+    fn test_read_write_sets() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx();
+        let state = ctx.runtime_state();
+        state.insert(b"a", b"1");
+        state.insert(b"b", b"2");
+        state.insert(b"c", b"3");
+
+        let mut store = SpeculativeStore::new(&mut *state);
+        assert_eq!(store.get(b"a"), Some(b"1".to_vec()));
+        store.insert(b"b", b"20");
+        // Reads of own writes do not depend on the parent store.
+        assert_eq!(store.get(b"b"), Some(b"20".to_vec()));
+        store.remove(b"c");
+        assert_eq!(store.get(b"c"), None);
+        assert_eq!(store.get(b"d"), None);
+
+        let changes = store.changes();
+        assert_eq!(
+            changes.reads,
+            vec![b"a".to_vec(), b"d".to_vec()].into_iter().collect()
+        );
+        assert!(!changes.iterated);
+        assert_eq!(changes.writes.len(), 2);
+        assert_eq!(changes.writes[&b"b".to_vec()], Some(b"20".to_vec()));
+        assert_eq!(changes.writes[&b"c".to_vec()], None);
+
+        let keys = |keys: &[&str]| -> BTreeSet<Vec<u8>> {
+            keys.iter().map(|key| key.as_bytes().to_vec()).collect()
+        };
+        assert!(changes.reads_any(&keys(&["a"])));
+        assert!(changes.reads_any(&keys(&["d", "x"])));
+        assert!(!changes.reads_any(&keys(&["b", "c"])));
+        assert!(!changes.reads_any(&keys(&[])));
+
+        // Nothing is written to the parent until the changes are applied.
+        drop(store);
+        assert_eq!(state.get(b"b"), Some(b"2".to_vec()));
+        changes.apply(state);
+        assert_eq!(state.get(b"a"), Some(b"1".to_vec()));
+        assert_eq!(state.get(b"b"), Some(b"20".to_vec()));
+        assert_eq!(state.get(b"c"), None);
+    }
+
+    #[test]
+    fn test_iteration() {
+        let mut mock = mock::Mock::default();
+        let mut ctx = mock.create_ctx();
+        let state = ctx.runtime_state();
+        state.insert(b"a", b"1");
+
+        let store = SpeculativeStore::new(&mut *state);
+        let mut it = store.iter();
+        it.rewind();
+        assert_eq!(it.count(), 1);
+
+        let changes = store.changes();
+        assert!(changes.iterated);
+        // Iteration conflicts with any write.
+        assert!(changes.reads_any(&vec![b"z".to_vec()].into_iter().collect()));
+    }
+}
@@ -25,7 +25,7 @@ pub struct EmptyRuntime;
 impl Runtime for EmptyRuntime {
     const VERSION: Version = Version::new(0, 0, 0);
 
-    type Modules = modules::core::Module;
+    type Modules = (modules::core::Module,);
 
     fn genesis_state() -> <Self::Modules as MigrationHandler>::Genesis {
         Default::default()
@@ -129,6 +129,7 @@ pub fn transaction() -> transaction::Transaction {
                 gas: 1_000_000,
                 consensus_messages: 32,
             },
+            features: 0,
         },
     }
 }
@@ -0,0 +1,87 @@
+//! Execution tracing.
+//!
+//! A tracer records an ordered list of operations (state accesses, gas usage and emitted events
+//! and messages) performed while executing a transaction. It is only used when debugging
+//! transactions via simulation and is never enabled during regular execution.
+use std::{cell::RefCell, rc::Rc};
+
+use oasis_core_runtime::consensus::roothash;
+
+/// A traced operation.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub enum Operation {
+    /// State has been read.
+    #[cbor(rename = "get")]
+    Get {
+        key: Vec<u8>,
+        #[cbor(optional)]
+        value: Option<Vec<u8>>,
+    },
+
+    /// State has been written.
+    #[cbor(rename = "insert")]
+    Insert { key: Vec<u8>, value: Vec<u8> },
+
+    /// State has been removed.
+    #[cbor(rename = "remove")]
+    Remove { key: Vec<u8> },
+
+    /// Gas has been used.
+    #[cbor(rename = "use_gas")]
+    UseGas { amount: u64 },
+
+    /// An event has been emitted.
+    #[cbor(rename = "emit_event")]
+    EmitEvent { key: Vec<u8>, value: Vec<u8> },
+
+    /// A consensus message has been emitted.
+    #[cbor(rename = "emit_message")]
+    EmitMessage { message: roothash::Message },
+}
+
+/// A recorder of traced operations.
+///
+/// Clones of a tracer share the same underlying trace.
+#[derive(Clone, Default)]
+pub struct Tracer {
+    operations: Rc<RefCell<Vec<Operation>>>,
+}
+
+impl Tracer {
+    /// Create a new empty tracer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an operation.
+    pub fn record(&self, op: Operation) {
+        self.operations.borrow_mut().push(op);
+    }
+
+    /// Take all of the operations recorded so far.
+    pub fn take(&self) -> Vec<Operation> {
+        self.operations.take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tracer() {
+        let tracer = Tracer::new();
+        let shared = tracer.clone();
+
+        tracer.record(Operation::UseGas { amount: 10 });
+        shared.record(Operation::Remove {
+            key: b"foo".to_vec(),
+        });
+
+        let ops = tracer.take();
+        assert_eq!(ops.len(), 2, "clones should share the trace");
+        assert!(matches!(ops[0], Operation::UseGas { amount: 10 }));
+        assert!(matches!(ops[1], Operation::Remove { .. }));
+        assert!(shared.take().is_empty(), "trace should be empty after take");
+    }
+}
@@ -3,6 +3,7 @@ use std::{convert::TryFrom, fmt};
 
 use bech32::{self, FromBase32, ToBase32, Variant};
 use digest::Digest;
+use once_cell::sync::OnceCell;
 use thiserror::Error;
 
 use oasis_core_runtime::{
@@ -39,9 +40,37 @@ pub const ADDRESS_RUNTIME_V0_VERSION: u8 = 0;
 /// V0 multisig address context.
 pub const ADDRESS_V0_MULTISIG_CONTEXT: &[u8] = b"oasis-runtime-sdk/address: multisig";
 
-/// Human readable part for Bech32-encoded addresses.
+/// Default human readable part for Bech32-encoded addresses.
 pub const ADDRESS_BECH32_HRP: &str = "oasis";
 
+static BECH32_HRP: OnceCell<&'static str> = OnceCell::new();
+
+/// Return the globally configured human readable part for Bech32-encoded addresses.
+///
+/// If none has been configured using `set_bech32_hrp`, this is `ADDRESS_BECH32_HRP`.
+pub fn bech32_hrp() -> &'static str {
+    BECH32_HRP.get().copied().unwrap_or(ADDRESS_BECH32_HRP)
+}
+
+/// Configure the global human readable part for Bech32-encoded addresses.
+///
+/// # Panics
+///
+/// This function will panic in case the human readable part is invalid or in case a different
+/// human readable part was already set.
+///
+pub fn set_bech32_hrp(hrp: &'static str) {
+    if bech32::encode(hrp, Vec::<bech32::u5>::new(), Variant::Bech32).is_err()
+        || hrp.to_lowercase() != hrp
+    {
+        panic!("invalid bech32 human readable part: {}", hrp);
+    }
+    let existing = BECH32_HRP.get_or_init(|| hrp);
+    if *existing != hrp {
+        panic!("bech32 human readable part already set: {}", existing);
+    }
+}
+
 /// Information for signature-based authentication and public key-based address derivation.
 #[derive(Clone, Debug, PartialEq, Eq, cbor::Encode, cbor::Decode)]
 pub enum SignatureAddressSpec {
@@ -164,10 +193,17 @@ impl Address {
         Address::new(ADDRESS_V0_MULTISIG_CONTEXT, ADDRESS_V0_VERSION, &config_vec)
     }
 
-    /// Tries to create a new address from Bech32-encoded string.
+    /// Tries to create a new address from Bech32-encoded string using the globally configured
+    /// human readable part.
     pub fn from_bech32(data: &str) -> Result<Self, Error> {
+        Address::from_bech32_with_hrp(data, bech32_hrp())
+    }
+
+    /// Tries to create a new address from Bech32-encoded string with the given human readable
+    /// part. Strings with any other human readable part are rejected.
+    pub fn from_bech32_with_hrp(data: &str, expected_hrp: &str) -> Result<Self, Error> {
         let (hrp, data, variant) = bech32::decode(data).map_err(|_| Error::MalformedAddress)?;
-        if hrp != ADDRESS_BECH32_HRP {
+        if hrp != expected_hrp {
             return Err(Error::MalformedAddress);
         }
         if variant != Variant::Bech32 {
@@ -178,9 +214,20 @@ impl Address {
         Address::from_bytes(&data)
     }
 
-    /// Converts an address to Bech32 representation.
+    /// Converts an address to Bech32 representation using the globally configured human readable
+    /// part.
     pub fn to_bech32(self) -> String {
-        bech32::encode(ADDRESS_BECH32_HRP, self.0.to_base32(), Variant::Bech32).unwrap()
+        self.to_bech32_with_hrp(bech32_hrp())
+    }
+
+    /// Converts an address to Bech32 representation with the given human readable part.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic in case the human readable part is invalid.
+    ///
+    pub fn to_bech32_with_hrp(self, hrp: &str) -> String {
+        bech32::encode(hrp, self.0.to_base32(), Variant::Bech32).unwrap()
     }
 }
 
@@ -323,6 +370,29 @@ mod test {
         ));
     }
 
+    #[test]
+    fn test_address_bech32_custom_hrp() {
+        let addr = Address::from_module("test", "custom");
+
+        let encoded = addr.to_bech32_with_hrp("myrt");
+        assert!(encoded.starts_with("myrt1"), "custom hrp should be used");
+        assert_eq!(
+            Address::from_bech32_with_hrp(&encoded, "myrt").unwrap(),
+            addr,
+            "address should round-trip under custom hrp"
+        );
+
+        // Addresses with a foreign HRP should be rejected.
+        assert!(matches!(
+            Address::from_bech32_with_hrp(&addr.to_bech32(), "myrt").unwrap_err(),
+            Error::MalformedAddress,
+        ));
+        assert!(matches!(
+            Address::from_bech32(&encoded).unwrap_err(),
+            Error::MalformedAddress,
+        ));
+    }
+
     #[test]
     fn test_address_from_bech32_invalid_variant() {
         let b = vec![42u8; ADDRESS_SIZE];
@@ -3,6 +3,7 @@ use anyhow::anyhow;
 use thiserror::Error;
 
 use crate::{
+    core::common::crypto::hash::Hash,
     crypto::{
         multisig,
         signature::{self, PublicKey, Signature},
@@ -19,6 +20,8 @@ pub use oasis_core_runtime::types::TransactionWeight;
 
 /// Transaction signature domain separation context base.
 pub const SIGNATURE_CONTEXT_BASE: &[u8] = b"oasis-runtime-sdk/tx: v0";
+/// Signature domain separation context base prefix for transactions in an atomic group.
+pub const ATOMIC_GROUP_SIGNATURE_CONTEXT_BASE: &[u8] = b"oasis-runtime-sdk/tx: atomic group v0";
 /// The latest transaction format version.
 pub const LATEST_TRANSACTION_VERSION: u16 = 1;
 
@@ -53,6 +56,36 @@ pub struct UnverifiedTransaction(pub Vec<u8>, pub Vec<AuthProof>);
 impl UnverifiedTransaction {
     /// Verify and deserialize the unverified transaction.
     pub fn verify(self) -> Result<Transaction, Error> {
+        self.verify_with_contexts(|_| None)
+    }
+
+    /// Verify and deserialize the unverified transaction, using method-specific signature
+    /// domain separation contexts.
+    ///
+    /// The `method_context` function returns the signature context base for the called method.
+    /// Methods without a specific context use [`SIGNATURE_CONTEXT_BASE`].
+    pub fn verify_with_contexts<F>(self, method_context: F) -> Result<Transaction, Error>
+    where
+        F: Fn(&str) -> Option<&'static [u8]>,
+    {
+        self.verify_with_context_base(|body| {
+            method_context(&body.call.method).unwrap_or(SIGNATURE_CONTEXT_BASE)
+        })
+    }
+
+    /// Verify and deserialize an unverified transaction that is part of an atomic group.
+    ///
+    /// The `bodies` are the signed bodies of all transactions in the group, in order. See
+    /// [`atomic_group_signature_context_base`] for the signature context used.
+    pub fn verify_for_atomic_group(self, bodies: &[&[u8]]) -> Result<Transaction, Error> {
+        let base = atomic_group_signature_context_base(bodies);
+        self.verify_with_context_base(|_| base.as_slice())
+    }
+
+    fn verify_with_context_base<'a, F>(self, context_base: F) -> Result<Transaction, Error>
+    where
+        F: FnOnce(&Transaction) -> &'a [u8],
+    {
         // Deserialize the inner body.
         let body: Transaction =
             cbor::from_slice(&self.0).map_err(|e| Error::MalformedTransaction(e.into()))?;
@@ -68,7 +101,7 @@ impl UnverifiedTransaction {
         }
 
         // Verify all signatures.
-        let ctx = signature::context::get_chain_context_for(SIGNATURE_CONTEXT_BASE);
+        let ctx = signature::context::get_chain_context_for(context_base(&body));
         let mut public_keys = vec![];
         let mut signatures = vec![];
         for (si, auth_proof) in body.auth_info.signer_info.iter().zip(self.1.iter()) {
@@ -83,6 +116,16 @@ impl UnverifiedTransaction {
     }
 }
 
+/// Signature context base for the transactions of an atomic group with the given signed bodies.
+///
+/// The context commits to the bodies of all transactions in the group and their order, so that
+/// signatures of grouped transactions can neither be used outside of the group nor in any other
+/// group.
+pub fn atomic_group_signature_context_base(bodies: &[&[u8]]) -> Vec<u8> {
+    let digest = Hash::digest_bytes_list(bodies);
+    [ATOMIC_GROUP_SIGNATURE_CONTEXT_BASE, b" ", digest.as_ref()].concat()
+}
+
 /// Transaction.
 #[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
 pub struct Transaction {
@@ -111,7 +154,7 @@ impl Transaction {
 }
 
 /// Format used for encoding the call (and output) information.
-#[derive(Clone, Copy, Debug, cbor::Encode, cbor::Decode)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, cbor::Encode, cbor::Decode)]
 #[repr(u8)]
 pub enum CallFormat {
     /// Plain text call data.
@@ -145,6 +188,11 @@ pub struct AuthInfo {
     #[cbor(rename = "si")]
     pub signer_info: Vec<SignerInfo>,
     pub fee: Fee,
+    /// Optional features requested by the transaction, as a bitfield.
+    ///
+    /// Transactions requesting any features not supported by the runtime are rejected.
+    #[cbor(optional, default, skip_serializing_if = "num_traits::Zero::is_zero")]
+    pub features: u64,
 }
 
 /// Transaction fee.
@@ -306,7 +354,12 @@ impl CallResult {
 
 #[cfg(test)]
 mod test {
-    use crate::types::token::{BaseUnits, Denomination};
+    use oasis_core_runtime::common::crypto::signature::{PrivateKey, Signer as _};
+
+    use crate::{
+        crypto::signature::context::{get_chain_context_for, set_chain_context, test as context},
+        types::token::{BaseUnits, Denomination},
+    };
 
     use super::*;
 
@@ -355,4 +408,74 @@ mod test {
         };
         assert_eq!(2, fee.gas_price(), "non empty fee - gas price should match");
     }
+
+    #[test]
+    fn test_verify_method_signature_context() {
+        let _guard = context::TEST_GUARD.lock().unwrap();
+        context::reset_chain_context();
+        set_chain_context(
+            "8000000000000000000000000000000000000000000000000000000000000000".into(),
+            "643fb06848be7e970af3b5b2d772eb8cfb30499c8162bc18ac03df2f5e22520e",
+        );
+
+        const METHOD_A_CONTEXT: &[u8] = b"test/method-a: v0";
+        let method_context = |method: &str| match method {
+            "test.A" => Some(METHOD_A_CONTEXT),
+            _ => None,
+        };
+
+        let sk = PrivateKey::from_test_seed("method signature context".to_string());
+        let tx_for = |method: &str| Transaction {
+            version: LATEST_TRANSACTION_VERSION,
+            call: Call {
+                format: CallFormat::Plain,
+                method: method.to_owned(),
+                body: cbor::Value::Simple(cbor::SimpleValue::NullValue),
+            },
+            auth_info: AuthInfo {
+                signer_info: vec![SignerInfo::new_sigspec(
+                    SignatureAddressSpec::Ed25519(sk.public_key().into()),
+                    0,
+                )],
+                fee: Default::default(),
+                features: 0,
+            },
+        };
+        let sign = |tx: &Transaction, base: &[u8]| {
+            let body = cbor::to_vec(tx.clone());
+            let signature = sk
+                .sign(&get_chain_context_for(base), &body)
+                .expect("signing should work");
+            UnverifiedTransaction(
+                body,
+                vec![AuthProof::Signature(signature.as_ref().to_vec().into())],
+            )
+        };
+
+        // Methods with a specific context should require signatures using that context.
+        let utx = sign(&tx_for("test.A"), METHOD_A_CONTEXT);
+        utx.clone()
+            .verify_with_contexts(method_context)
+            .expect("signature using the method context should verify");
+        utx.verify()
+            .expect_err("signature using the method context should not verify by default");
+        sign(&tx_for("test.A"), SIGNATURE_CONTEXT_BASE)
+            .verify_with_contexts(method_context)
+            .expect_err("signature using the default context should not verify");
+
+        // Signatures for method A should not be usable for method B.
+        let utx = sign(&tx_for("test.A"), METHOD_A_CONTEXT);
+        let replayed = UnverifiedTransaction(cbor::to_vec(tx_for("test.B")), utx.1);
+        replayed
+            .verify_with_contexts(method_context)
+            .expect_err("signature for method A should not verify for method B");
+        sign(&tx_for("test.B"), METHOD_A_CONTEXT)
+            .verify_with_contexts(method_context)
+            .expect_err("method B should not accept the context of method A");
+
+        // Other methods should use the default context.
+        sign(&tx_for("test.B"), SIGNATURE_CONTEXT_BASE)
+            .verify_with_contexts(method_context)
+            .expect("signature using the default context should verify");
+    }
 }
@@ -65,9 +65,15 @@ pub enum Request {
     #[cbor(rename = "call_self")]
     CallSelf,
 
+    #[cbor(rename = "call_self_limited")]
+    CallSelfLimited { max_gas: u64 },
+
     #[cbor(rename = "increment_counter")]
     IncrementCounter,
 
+    #[cbor(rename = "emit_logs")]
+    EmitLogs,
+
     #[cbor(rename = "instantiate_oas20")]
     InstantiateOas20 {
         code_id: CodeId,
@@ -197,12 +203,27 @@ impl sdk::Contract for HelloWorld {
                 });
                 Ok(Response::Empty)
             }
+            Request::CallSelfLimited { max_gas } => {
+                // Same as above, but the callee can only use a limited amount of gas. Once it
+                // runs out, only the subcall fails and the failure is reported in the reply.
+                let instance_id = ctx.instance_id();
+                ctx.call_contract(1, instance_id, Request::CallSelf, &[], Some(max_gas));
+                Ok(Response::Empty)
+            }
             Request::IncrementCounter => {
                 // Just increment the counter and return an empty response.
                 Self::increment_counter(ctx, 1);
 
                 Ok(Response::Empty)
             }
+            Request::EmitLogs => {
+                // Emit a few logs with topics so that they can be filtered.
+                ctx.emit_log(&[b"transfer", b"alice", b"bob"], b"100");
+                ctx.emit_log(&[b"transfer", b"bob", b"alice"], b"50");
+                ctx.emit_log(&[b"approve", b"alice"], b"10");
+
+                Ok(Response::Empty)
+            }
             Request::InstantiateOas20 {
                 code_id,
                 token_instantiation,
@@ -302,6 +323,18 @@ impl sdk::Contract for HelloWorld {
                 // Do not modify the result.
                 Ok(None)
             }
+            Reply::Call { id, result, .. } if id == 1 => {
+                // Report failures without failing the call itself.
+                let greeting = if result.is_success() {
+                    "subcall succeeded"
+                } else {
+                    "subcall failed"
+                };
+
+                Ok(Some(Response::Hello {
+                    greeting: greeting.to_string(),
+                }))
+            }
             Reply::Call { id, result, data } if id == 42 => {
                 let data = cbor::from_value(data.unwrap()).unwrap();
 
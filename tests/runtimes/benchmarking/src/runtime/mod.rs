@@ -61,7 +61,7 @@ impl<Accounts: modules::accounts::API> Module<Accounts> {
     fn tx_accounts_mint<C: TxContext>(ctx: &mut C, body: types::AccountsMint) -> Result<(), Error> {
         // XXX: no gas costs atm.
 
-        Accounts::mint(ctx, ctx.tx_caller_address(), &body.amount)?;
+        Accounts::mint::<Self, _>(ctx, ctx.tx_caller_address(), &body.amount)?;
 
         Ok(())
     }
@@ -207,4 +207,5 @@ impl<Accounts: modules::accounts::API> module::AuthHandler for Module<Accounts>
 
 impl<Accounts: modules::accounts::API> module::BlockHandler for Module<Accounts> {}
 
+impl<Accounts: modules::accounts::API> module::EventHandler for Module<Accounts> {}
 impl<Accounts: modules::accounts::API> module::InvariantHandler for Module<Accounts> {}
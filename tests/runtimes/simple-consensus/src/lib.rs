@@ -39,6 +39,7 @@ impl sdk::Runtime for Runtime {
                     consensus_denomination: "TEST".parse().unwrap(),
                     // Test scaling consensus base units when transferring them into the runtime.
                     consensus_scaling_factor: 1000,
+                    allowed_denominations: Default::default(),
                 },
             },
             modules::consensus_accounts::Genesis {
@@ -46,6 +47,9 @@ impl sdk::Runtime for Runtime {
                     // These are free, in order to simplify testing. We do test gas accounting
                     // with other methods elsewhere though.
                     gas_costs: Default::default(),
+                    disable_delegated_withdraw: false,
+                    check_deposit_allowance: false,
+                    debonding_interval: None,
                 },
             },
             modules::core::Genesis {
@@ -60,6 +64,24 @@ impl sdk::Runtime for Runtime {
                         mgp.insert(Denomination::NATIVE, 0);
                         mgp
                     },
+                    allow_zero_fee_methods: None,
+                    max_tx_events: None,
+                    block_transfer_limits: BTreeMap::new(),
+                    transfer_limits: BTreeMap::new(),
+                    max_scheduled_calls_per_round: 0,
+                    invariant_check_interval: 0,
+                    max_tx_gas: None,
+                    admin: None,
+                    nonce_consumption: Default::default(),
+                    safe_mode_allowed_methods: Default::default(),
+                    required_call_formats: Default::default(),
+                    min_tx_fee: Default::default(),
+                    max_tx_fee: Default::default(),
+                    refund_unused_gas: false,
+                    fee_fallback_denomination: None,
+                    max_tx_size: None,
+                    mint_allowlist: Default::default(),
+                    max_value_size: None,
                 },
             },
         )
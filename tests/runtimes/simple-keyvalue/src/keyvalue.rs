@@ -4,7 +4,7 @@ use anyhow::Context as _;
 
 use oasis_runtime_sdk::{
     self as sdk,
-    context::{Context, TxContext},
+    context::{Context, ExecutionMode, TxContext},
     core::common::crypto::hash::Hash,
     error::RuntimeError,
     keymanager::KeyPairId,
@@ -22,7 +22,7 @@ pub mod types;
 const MODULE_NAME: &str = "keyvalue";
 
 /// The signature context used in the special greeting encoding scheme signature.
-const SPECIAL_GREETING_SIGNATURE_CONTEXT: &[u8] =
+pub const SPECIAL_GREETING_SIGNATURE_CONTEXT: &[u8] =
     "oasis-runtime-sdk-test/simplekv-special-greeting: v0".as_bytes();
 
 /// Errors emitted by the keyvalue module.
@@ -133,6 +133,7 @@ impl sdk::module::AuthHandler for Module {
                             gas: 500,
                             ..Default::default()
                         },
+                        features: 0,
                     },
                 }))
                 // After we decode this, the accounts module will check the nonce.
@@ -143,6 +144,7 @@ impl sdk::module::AuthHandler for Module {
 }
 
 impl sdk::module::BlockHandler for Module {}
+impl sdk::module::EventHandler for Module {}
 impl sdk::module::InvariantHandler for Module {}
 
 impl sdk::module::MethodHandler for Module {
@@ -169,6 +171,10 @@ impl sdk::module::MethodHandler for Module {
             _ => sdk::module::DispatchResult::Unhandled(args),
         }
     }
+
+    fn register_error_codes(registry: &mut core::types::ErrorRegistry) {
+        sdk::module::register_error_codes::<Self>(registry)
+    }
 }
 
 // Actual implementation of this runtime's externally-callable methods.
@@ -185,9 +191,11 @@ impl Module {
         };
         // We must drop ts and store so that use_gas can borrow ctx.
         Core::use_tx_gas(ctx, cost)?;
+        Core::ensure_value_size(ctx, body.value.len())?;
 
-        if ctx.is_check_only() {
-            return Ok(());
+        match ctx.execution_mode() {
+            ExecutionMode::Check => return Ok(()),
+            ExecutionMode::Simulate | ExecutionMode::Execute => {}
         }
 
         // Recreate store and ts after we get ctx back
@@ -195,7 +203,7 @@ impl Module {
         let mut ts = sdk::storage::TypedStore::new(&mut store);
         let bc = body.clone();
         ts.insert(&body.key, body.value);
-        ctx.emit_event(Event::Insert { kv: bc });
+        ctx.try_emit_event(Event::Insert { kv: bc })?;
         Ok(())
     }
 
@@ -212,8 +220,9 @@ impl Module {
         // We must drop ts and store so that use_gas can borrow ctx.
         Core::use_tx_gas(ctx, cost)?;
 
-        if ctx.is_check_only() {
-            return Ok(());
+        match ctx.execution_mode() {
+            ExecutionMode::Check => return Ok(()),
+            ExecutionMode::Simulate | ExecutionMode::Execute => {}
         }
 
         // Recreate store and ts after we get ctx back
@@ -221,18 +230,21 @@ impl Module {
         let mut ts = sdk::storage::TypedStore::new(&mut store);
         let bc = body.clone();
         ts.remove(&body.key);
-        ctx.emit_event(Event::Remove { key: bc });
+        ctx.try_emit_event(Event::Remove { key: bc })?;
         Ok(())
     }
 
     fn tx_getcreatekey<C: TxContext>(ctx: &mut C, body: types::Key) -> Result<(), Error> {
-        if ctx.is_check_only() || ctx.is_simulation() {
-            return Ok(());
+        // Key generation has effects outside of the runtime state which can't be discarded
+        // after a simulation, so only do it when executing.
+        match ctx.execution_mode() {
+            ExecutionMode::Check | ExecutionMode::Simulate => return Ok(()),
+            ExecutionMode::Execute => {}
         }
 
         let key_result = ctx
             .key_manager()
-            .unwrap()
+            .ok_or(core::Error::KeyManagerUnavailable)?
             .get_or_create_keys(KeyPairId::from(Hash::digest_bytes(&body.key).as_ref()));
         match key_result {
             Ok(_) => Ok(()),
@@ -33,6 +33,9 @@ impl sdk::Runtime for Runtime {
     // to test the migration functionality.
     const STATE_VERSION: u32 = 1;
 
+    // Emit receipt tags so that clients can verify the events of transactions.
+    const EMIT_RECEIPT_TAGS: bool = true;
+
     type Modules = (
         keyvalue::Module,
         modules::accounts::Module,
@@ -76,7 +79,10 @@ impl sdk::Runtime for Runtime {
             },
             modules::accounts::Genesis {
                 parameters: modules::accounts::Parameters {
-                    gas_costs: modules::accounts::GasCosts { tx_transfer: 100 },
+                    gas_costs: modules::accounts::GasCosts {
+                        tx_transfer: 100,
+                        tx_convert: 100,
+                    },
                     ..Default::default()
                 },
                 balances: {
@@ -130,6 +136,8 @@ impl sdk::Runtime for Runtime {
                     },
                     participation_threshold_numerator: 1, // These are updated below.
                     participation_threshold_denominator: 1,
+                    accrual_rate: Default::default(),
+                    gas_costs: Default::default(),
                 },
             },
             modules::core::Genesis {
@@ -148,6 +156,24 @@ impl sdk::Runtime for Runtime {
                         mgp.insert(Denomination::NATIVE, 0);
                         mgp
                     },
+                    allow_zero_fee_methods: None,
+                    max_tx_events: None,
+                    block_transfer_limits: BTreeMap::new(),
+                    transfer_limits: BTreeMap::new(),
+                    max_scheduled_calls_per_round: 0,
+                    invariant_check_interval: 0,
+                    max_tx_gas: None,
+                    admin: None,
+                    nonce_consumption: Default::default(),
+                    safe_mode_allowed_methods: Default::default(),
+                    required_call_formats: Default::default(),
+                    min_tx_fee: Default::default(),
+                    max_tx_fee: Default::default(),
+                    refund_unused_gas: false,
+                    fee_fallback_denomination: None,
+                    max_tx_size: None,
+                    mint_allowlist: Default::default(),
+                    max_value_size: None,
                 },
             },
             contracts::Genesis {
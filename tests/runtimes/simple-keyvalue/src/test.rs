@@ -1,11 +1,31 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use oasis_runtime_sdk::{
-    module::AuthHandler as _,
-    modules::{core, core::Module as Core},
-    testing::mock,
+    context::{BatchContext, Context, Mode},
+    core::common::{
+        crypto::{
+            hash::Hash,
+            signature::{PrivateKey, Signer as _},
+        },
+        version::Version,
+    },
+    dispatcher,
+    error::RuntimeError,
+    module::{self, AuthHandler as _, BlockHandler as _},
+    modules::{
+        accounts::{self, API as _},
+        core,
+        core::Module as Core,
+    },
+    storage,
+    testing::{keys, mock},
+    trace,
     types::{token, transaction},
-    Context as _, Module as _,
+    Module as _, Runtime,
 };
 
 #[test]
@@ -24,6 +44,24 @@ fn test_impl_for_tuple() {
                 mgp.insert(token::Denomination::NATIVE, 0);
                 mgp
             },
+            allow_zero_fee_methods: None,
+            max_tx_events: None,
+            block_transfer_limits: BTreeMap::new(),
+            transfer_limits: BTreeMap::new(),
+            max_scheduled_calls_per_round: 0,
+            invariant_check_interval: 0,
+            max_tx_gas: None,
+            admin: None,
+            nonce_consumption: Default::default(),
+            safe_mode_allowed_methods: Default::default(),
+            required_call_formats: Default::default(),
+            min_tx_fee: Default::default(),
+            max_tx_fee: Default::default(),
+            refund_unused_gas: false,
+            fee_fallback_denomination: None,
+            max_tx_size: None,
+            mint_allowlist: Default::default(),
+            max_value_size: None,
         },
     );
     let dummy_bytes = b"you look, you die".to_vec();
@@ -39,3 +77,1283 @@ fn test_impl_for_tuple() {
     )
     .expect_err("too many authentication slots");
 }
+
+#[test]
+fn test_error_registry() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::CheckTx);
+
+    let res = dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+        &mut ctx,
+        "core.ErrorRegistry",
+        cbor::to_vec(cbor::Value::Simple(cbor::SimpleValue::NullValue)),
+    )
+    .expect("error registry query should work");
+    let registry: core::types::ErrorRegistry =
+        cbor::from_slice(&res).expect("error registry should be well-formed");
+
+    // Errors of the keyvalue module should be registered.
+    let keyvalue = registry
+        .get("keyvalue")
+        .expect("keyvalue errors should be registered");
+    assert_eq!(
+        keyvalue,
+        &vec![core::types::ErrorCode {
+            code: 1,
+            name: "InvalidArgument".to_owned(),
+        }]
+    );
+
+    // Errors of the SDK modules should be registered as well.
+    let core_errors = registry
+        .get("core")
+        .expect("core errors should be registered");
+    assert!(core_errors.contains(&core::types::ErrorCode {
+        code: 12,
+        name: "OutOfGas".to_owned(),
+    }));
+}
+
+#[test]
+fn test_capabilities() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::CheckTx);
+
+    let res = dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+        &mut ctx,
+        "core.Capabilities",
+        cbor::to_vec(cbor::Value::Simple(cbor::SimpleValue::NullValue)),
+    )
+    .expect("capabilities query should work");
+    let capabilities: core::types::Capabilities =
+        cbor::from_slice(&res).expect("capabilities should be well-formed");
+
+    // The contracts module is included and a key manager is configured, but there are no
+    // consensus accounts or EVM modules.
+    assert_eq!(
+        capabilities,
+        vec!["contracts".to_owned(), "key-manager".to_owned()]
+            .into_iter()
+            .collect(),
+    );
+    assert_eq!(capabilities, <super::Runtime as Runtime>::capabilities());
+}
+
+#[test]
+fn test_trace_insert() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+    Core::set_params(
+        ctx.runtime_state(),
+        core::Parameters {
+            max_batch_gas: u64::MAX,
+            min_gas_price: {
+                let mut mgp = BTreeMap::new();
+                mgp.insert(token::Denomination::NATIVE, 0);
+                mgp
+            },
+            ..Default::default()
+        },
+    );
+    super::keyvalue::Module::set_params(
+        ctx.runtime_state(),
+        super::keyvalue::Parameters {
+            gas_costs: super::keyvalue::GasCosts {
+                insert_absent: 200,
+                ..Default::default()
+            },
+        },
+    );
+
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "keyvalue.Insert".to_owned(),
+            body: cbor::to_value(super::keyvalue::types::KeyValue {
+                key: b"foo".to_vec(),
+                value: b"bar".to_vec(),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: Default::default(),
+            features: 0,
+        },
+    };
+
+    let res = dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+        &mut ctx,
+        "core.Trace",
+        cbor::to_vec(core::types::TraceQuery {
+            tx,
+            balance_overrides: BTreeMap::new(),
+        }),
+    )
+    .expect("trace query should work");
+    let rsp: core::types::TraceQueryResponse =
+        cbor::from_slice(&res).expect("trace should be well-formed");
+    assert!(
+        matches!(rsp.result, transaction::CallResult::Ok(_)),
+        "traced call should succeed"
+    );
+
+    // The write should be recorded after the gas for it has been charged.
+    let write = rsp
+        .trace
+        .iter()
+        .position(|op| {
+            matches!(op, trace::Operation::Insert { key, value }
+                if key == b"keyvaluefoo" && value == &cbor::to_vec(b"bar".to_vec()))
+        })
+        .expect("write should be recorded");
+    let gas = rsp
+        .trace
+        .iter()
+        .position(|op| matches!(op, trace::Operation::UseGas { amount: 200 }))
+        .expect("gas charge should be recorded");
+    assert!(gas < write, "operations should be recorded in order");
+    assert!(
+        rsp.trace[write..]
+            .iter()
+            .any(|op| matches!(op, trace::Operation::EmitEvent { .. })),
+        "insert event should be recorded"
+    );
+
+    // Tracing should not modify state.
+    let res = dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+        &mut ctx,
+        "keyvalue.Get",
+        cbor::to_vec(super::keyvalue::types::Key {
+            key: b"foo".to_vec(),
+        }),
+    );
+    assert!(res.is_err(), "traced write should not be persisted");
+}
+
+#[test]
+fn test_simulation_balance_overrides() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::CheckTx);
+    Core::set_params(
+        ctx.runtime_state(),
+        core::Parameters {
+            max_batch_gas: u64::MAX,
+            min_gas_price: {
+                let mut mgp = BTreeMap::new();
+                mgp.insert(token::Denomination::NATIVE, 0);
+                mgp
+            },
+            ..Default::default()
+        },
+    );
+
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "accounts.Transfer".to_owned(),
+            body: cbor::to_value(accounts::types::Transfer {
+                to: keys::bob::address(),
+                amount: token::BaseUnits::new(1_000, token::Denomination::NATIVE),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: Default::default(),
+            features: 0,
+        },
+    };
+    let trace = |ctx: &mut _, balance_overrides| -> transaction::CallResult {
+        let res = dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+            ctx,
+            "core.Trace",
+            cbor::to_vec(core::types::TraceQuery {
+                tx: tx.clone(),
+                balance_overrides,
+            }),
+        )
+        .expect("trace query should work");
+        let rsp: core::types::TraceQueryResponse =
+            cbor::from_slice(&res).expect("trace should be well-formed");
+        rsp.result
+    };
+
+    // Without any overrides the caller has no funds.
+    let result = trace(&mut ctx, BTreeMap::new());
+    assert!(
+        !result.is_success(),
+        "transfer should fail without balance override"
+    );
+
+    // With a balance override the transfer should succeed.
+    let mut overrides = BTreeMap::new();
+    overrides.insert(keys::alice::address(), {
+        let mut balances = BTreeMap::new();
+        balances.insert(token::Denomination::NATIVE, 1_000);
+        balances
+    });
+    let result = trace(&mut ctx, overrides.clone());
+    assert!(
+        result.is_success(),
+        "transfer should succeed with balance override"
+    );
+
+    let res = dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+        &mut ctx,
+        "core.EstimateGas",
+        cbor::to_vec(core::types::EstimateGasQuery {
+            caller: None,
+            tx: tx.clone(),
+            balance_overrides: overrides,
+        }),
+    );
+    assert!(res.is_ok(), "estimation with balance override should work");
+
+    // Overrides should not be persisted.
+    let balance = accounts::Module::get_balance(
+        ctx.runtime_state(),
+        keys::alice::address(),
+        token::Denomination::NATIVE,
+    )
+    .expect("balance query should work");
+    assert_eq!(balance, 0, "balance override should not be persisted");
+}
+
+#[test]
+fn test_estimate_gas_encoded() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::CheckTx);
+    Core::set_params(
+        ctx.runtime_state(),
+        core::Parameters {
+            max_batch_gas: u64::MAX,
+            min_gas_price: {
+                let mut mgp = BTreeMap::new();
+                mgp.insert(token::Denomination::NATIVE, 0);
+                mgp
+            },
+            ..Default::default()
+        },
+    );
+    super::keyvalue::Module::set_params(
+        ctx.runtime_state(),
+        super::keyvalue::Parameters {
+            gas_costs: super::keyvalue::GasCosts {
+                insert_absent: 200,
+                ..Default::default()
+            },
+        },
+    );
+
+    let sk = PrivateKey::from_test_seed("special greeting test".to_string());
+    let params_cbor = cbor::to_vec(super::keyvalue::types::SpecialGreetingParams {
+        nonce: 0,
+        greeting: "hello".to_owned(),
+    });
+    let signature = sk
+        .sign(
+            super::keyvalue::SPECIAL_GREETING_SIGNATURE_CONTEXT,
+            &params_cbor,
+        )
+        .expect("signing should work");
+    let mut special_greeting = super::keyvalue::types::SpecialGreeting {
+        params_cbor,
+        from: sk.public_key().into(),
+        signature: signature.as_ref().to_vec().into(),
+    };
+
+    let estimate = |ctx: &mut _, scheme: &str, body| {
+        dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+            ctx,
+            "core.EstimateGasEncoded",
+            cbor::to_vec(core::types::EstimateGasEncodedQuery {
+                scheme: scheme.to_owned(),
+                body,
+                balance_overrides: BTreeMap::new(),
+            }),
+        )
+    };
+
+    let res = estimate(
+        &mut ctx,
+        "keyvalue.special-greeting.v0",
+        cbor::to_vec(special_greeting.clone()),
+    )
+    .expect("estimation of an encoded transaction should work");
+    let gas: u64 = cbor::from_slice(&res).expect("estimate should be well-formed");
+    assert!(gas >= 200, "estimate should include the insert cost");
+
+    // Unknown schemes should be rejected.
+    estimate(
+        &mut ctx,
+        "keyvalue.unknown",
+        cbor::to_vec(special_greeting.clone()),
+    )
+    .expect_err("unknown scheme should be rejected");
+
+    // Transactions that fail to decode should be rejected.
+    special_greeting.params_cbor = cbor::to_vec(super::keyvalue::types::SpecialGreetingParams {
+        nonce: 0,
+        greeting: "tampered".to_owned(),
+    });
+    estimate(
+        &mut ctx,
+        "keyvalue.special-greeting.v0",
+        cbor::to_vec(special_greeting),
+    )
+    .expect_err("invalid signature should be rejected");
+}
+
+#[test]
+fn test_batch_query() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::CheckTx);
+
+    let batch = |queries: Vec<(&str, cbor::Value)>| core::types::BatchQuery {
+        queries: queries
+            .into_iter()
+            .map(|(method, args)| core::types::BatchQueryItem {
+                method: method.to_owned(),
+                args,
+            })
+            .collect(),
+    };
+
+    let res = dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+        &mut ctx,
+        "core.BatchQuery",
+        cbor::to_vec(batch(vec![
+            (
+                "accounts.Balances",
+                cbor::to_value(accounts::types::BalancesQuery {
+                    address: keys::alice::address(),
+                }),
+            ),
+            (
+                "rewards.Parameters",
+                cbor::Value::Simple(cbor::SimpleValue::NullValue),
+            ),
+            // Calls can not be included.
+            (
+                "keyvalue.Insert",
+                cbor::to_value(super::keyvalue::types::KeyValue {
+                    key: b"foo".to_vec(),
+                    value: b"bar".to_vec(),
+                }),
+            ),
+            // Expensive queries can not be included.
+            (
+                "core.EstimateGas",
+                cbor::to_value(core::types::EstimateGasQuery {
+                    caller: None,
+                    tx: mock::transaction(),
+                    balance_overrides: Default::default(),
+                }),
+            ),
+            (
+                "core.CheckInvariants",
+                cbor::Value::Simple(cbor::SimpleValue::NullValue),
+            ),
+        ])),
+    )
+    .expect("batch query should work");
+    let rsp: core::types::BatchQueryResponse =
+        cbor::from_slice(&res).expect("response should be well-formed");
+    assert_eq!(
+        rsp.results.len(),
+        5,
+        "there should be a result for each query"
+    );
+
+    let balances: accounts::types::AccountBalances = match &rsp.results[0] {
+        transaction::CallResult::Ok(value) => cbor::from_value(value.clone()).unwrap(),
+        _ => panic!("balance query should succeed"),
+    };
+    assert_eq!(
+        balances.balances[&token::Denomination::NATIVE],
+        10_003_000,
+        "balance should be correct"
+    );
+
+    let params: oasis_runtime_sdk::modules::rewards::Parameters = match &rsp.results[1] {
+        transaction::CallResult::Ok(value) => cbor::from_value(value.clone()).unwrap(),
+        _ => panic!("parameters query should succeed"),
+    };
+    assert_eq!(
+        params.schedule.steps.len(),
+        1,
+        "parameters should be correct"
+    );
+
+    assert!(
+        matches!(&rsp.results[2], transaction::CallResult::Failed { module, .. } if module == "core"),
+        "calls should be rejected"
+    );
+    for result in &rsp.results[3..] {
+        assert!(
+            matches!(result, transaction::CallResult::Failed { module, code, .. } if module == "core" && *code == 3),
+            "expensive queries should be rejected"
+        );
+    }
+
+    // Batches that are too large should be rejected.
+    let queries = (0..=core::MAX_BATCH_QUERY_SIZE)
+        .map(|_| {
+            (
+                "rewards.Parameters",
+                cbor::Value::Simple(cbor::SimpleValue::NullValue),
+            )
+        })
+        .collect();
+    dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+        &mut ctx,
+        "core.BatchQuery",
+        cbor::to_vec(batch(queries)),
+    )
+    .expect_err("oversized batch should be rejected");
+}
+
+#[test]
+fn test_execution_modes() {
+    fn call<C: BatchContext<Runtime = super::Runtime>>(
+        ctx: &mut C,
+        method: &str,
+        body: cbor::Value,
+    ) -> module::CallResult {
+        let tx = transaction::Transaction {
+            version: 1,
+            call: transaction::Call {
+                format: transaction::CallFormat::Plain,
+                method: method.to_owned(),
+                body,
+            },
+            auth_info: transaction::AuthInfo {
+                signer_info: vec![transaction::SignerInfo::new_sigspec(
+                    keys::alice::sigspec(),
+                    0,
+                )],
+                fee: transaction::Fee {
+                    amount: token::BaseUnits::new(0, token::Denomination::NATIVE),
+                    gas: 1_000,
+                    consensus_messages: 0,
+                },
+                features: 0,
+            },
+        };
+        ctx.with_tx(0, tx, |mut tx_ctx, call| {
+            let result =
+                dispatcher::Dispatcher::<super::Runtime>::dispatch_tx_call(&mut tx_ctx, call);
+            tx_ctx.commit();
+            result
+        })
+    }
+
+    fn insert<C: BatchContext<Runtime = super::Runtime>>(ctx: &mut C) -> module::CallResult {
+        call(
+            ctx,
+            "keyvalue.Insert",
+            cbor::to_value(super::keyvalue::types::KeyValue {
+                key: b"foo".to_vec(),
+                value: b"bar".to_vec(),
+            }),
+        )
+    }
+
+    fn get_create_key<C: BatchContext<Runtime = super::Runtime>>(
+        ctx: &mut C,
+    ) -> module::CallResult {
+        call(
+            ctx,
+            "keyvalue.GetCreateKey",
+            cbor::to_value(super::keyvalue::types::Key {
+                key: b"foo".to_vec(),
+            }),
+        )
+    }
+
+    fn is_stored<C: Context>(ctx: &mut C) -> bool {
+        dispatcher::Dispatcher::<C::Runtime>::dispatch_query(
+            ctx,
+            "keyvalue.Get",
+            cbor::to_vec(super::keyvalue::types::Key {
+                key: b"foo".to_vec(),
+            }),
+        )
+        .is_ok()
+    }
+
+    let mut mock = mock::Mock::default();
+    {
+        let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+        Core::set_params(
+            ctx.runtime_state(),
+            core::Parameters {
+                max_batch_gas: u64::MAX,
+                min_gas_price: {
+                    let mut mgp = BTreeMap::new();
+                    mgp.insert(token::Denomination::NATIVE, 0);
+                    mgp
+                },
+                ..Default::default()
+            },
+        );
+    }
+
+    // Checks should charge gas but skip all effects.
+    {
+        let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::CheckTx);
+        assert!(insert(&mut ctx).is_success(), "insert check should succeed");
+        assert!(!is_stored(&mut ctx), "insert check should not write");
+        // No key manager is available in tests, so this would fail if keys were requested.
+        assert!(
+            get_create_key(&mut ctx).is_success(),
+            "key creation check should succeed"
+        );
+    }
+
+    // Simulations should perform effects within the simulation only.
+    {
+        let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+        ctx.with_simulation(|mut sim_ctx| {
+            assert!(
+                insert(&mut sim_ctx).is_success(),
+                "simulated insert should succeed"
+            );
+            assert!(is_stored(&mut sim_ctx), "simulated insert should write");
+            assert!(
+                get_create_key(&mut sim_ctx).is_success(),
+                "simulated key creation should succeed"
+            );
+        });
+        assert!(
+            !is_stored(&mut ctx),
+            "simulated insert should not be persisted"
+        );
+    }
+
+    // Execution should perform all effects.
+    {
+        let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+        assert!(insert(&mut ctx).is_success(), "insert should succeed");
+        assert!(is_stored(&mut ctx), "insert should write");
+
+        // No key manager is available in tests, so key creation should fail cleanly.
+        assert!(
+            matches!(
+                get_create_key(&mut ctx),
+                module::CallResult::Failed { ref module, code: 34, .. } if module == "core"
+            ),
+            "key creation should fail as the key manager is unavailable"
+        );
+    }
+}
+
+#[test]
+fn test_max_value_size() {
+    fn insert<C: BatchContext<Runtime = super::Runtime>>(
+        ctx: &mut C,
+        value: &[u8],
+    ) -> module::CallResult {
+        let mut tx = mock::transaction();
+        tx.call.method = "keyvalue.Insert".to_owned();
+        tx.call.body = cbor::to_value(super::keyvalue::types::KeyValue {
+            key: b"foo".to_vec(),
+            value: value.to_vec(),
+        });
+        tx.auth_info.signer_info = vec![transaction::SignerInfo::new_sigspec(
+            keys::alice::sigspec(),
+            0,
+        )];
+        tx.auth_info.fee.consensus_messages = 0;
+        ctx.with_tx(0, tx, |mut tx_ctx, call| {
+            let result =
+                dispatcher::Dispatcher::<super::Runtime>::dispatch_tx_call(&mut tx_ctx, call);
+            tx_ctx.commit();
+            result
+        })
+    }
+
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+    Core::set_params(
+        ctx.runtime_state(),
+        core::Parameters {
+            max_batch_gas: u64::MAX,
+            min_gas_price: {
+                let mut mgp = BTreeMap::new();
+                mgp.insert(token::Denomination::NATIVE, 0);
+                mgp
+            },
+            max_value_size: Some(4),
+            ..Default::default()
+        },
+    );
+
+    assert!(
+        insert(&mut ctx, b"abcd").is_success(),
+        "insert of a value at the limit should succeed"
+    );
+    assert!(
+        matches!(
+            insert(&mut ctx, b"abcde"),
+            module::CallResult::Failed { ref module, code: 36, .. } if module == "core"
+        ),
+        "insert of a value above the limit should fail"
+    );
+
+    // The value stored by the successful insert should remain.
+    let res = dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+        &mut ctx,
+        "keyvalue.Get",
+        cbor::to_vec(super::keyvalue::types::Key {
+            key: b"foo".to_vec(),
+        }),
+    )
+    .expect("value should be stored");
+    let kv: super::keyvalue::types::KeyValue = cbor::from_slice(&res).unwrap();
+    assert_eq!(kv.value, b"abcd".to_vec());
+}
+
+#[test]
+fn test_disabled_module() {
+    fn call<C: BatchContext<Runtime = super::Runtime>>(
+        ctx: &mut C,
+        method: &str,
+        body: cbor::Value,
+    ) -> module::CallResult {
+        let tx = transaction::Transaction {
+            version: 1,
+            call: transaction::Call {
+                format: transaction::CallFormat::Plain,
+                method: method.to_owned(),
+                body,
+            },
+            auth_info: transaction::AuthInfo {
+                signer_info: vec![transaction::SignerInfo::new_sigspec(
+                    keys::alice::sigspec(),
+                    0,
+                )],
+                fee: transaction::Fee {
+                    amount: token::BaseUnits::new(0, token::Denomination::NATIVE),
+                    gas: 1_000,
+                    consensus_messages: 0,
+                },
+                features: 0,
+            },
+        };
+        ctx.with_tx(0, tx, |mut tx_ctx, call| {
+            let result =
+                dispatcher::Dispatcher::<super::Runtime>::dispatch_tx_call(&mut tx_ctx, call);
+            tx_ctx.commit();
+            result
+        })
+    }
+
+    fn set_enabled<C: BatchContext<Runtime = super::Runtime>>(
+        ctx: &mut C,
+        module: &str,
+        enabled: bool,
+    ) -> module::CallResult {
+        call(
+            ctx,
+            "core.SetModuleEnabled",
+            cbor::to_value(core::types::SetModuleEnabled {
+                module: module.to_owned(),
+                enabled,
+            }),
+        )
+    }
+
+    fn insert<C: BatchContext<Runtime = super::Runtime>>(ctx: &mut C) -> module::CallResult {
+        call(
+            ctx,
+            "keyvalue.Insert",
+            cbor::to_value(super::keyvalue::types::KeyValue {
+                key: b"foo".to_vec(),
+                value: b"bar".to_vec(),
+            }),
+        )
+    }
+
+    fn get<C: BatchContext>(ctx: &mut C) -> Result<Vec<u8>, RuntimeError> {
+        dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+            ctx,
+            "keyvalue.Get",
+            cbor::to_vec(super::keyvalue::types::Key {
+                key: b"foo".to_vec(),
+            }),
+        )
+    }
+
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+    Core::set_params(
+        ctx.runtime_state(),
+        core::Parameters {
+            max_batch_gas: u64::MAX,
+            min_gas_price: {
+                let mut mgp = BTreeMap::new();
+                mgp.insert(token::Denomination::NATIVE, 0);
+                mgp
+            },
+            admin: Some(keys::alice::address()),
+            ..Default::default()
+        },
+    );
+
+    assert!(insert(&mut ctx).is_success(), "insert should succeed");
+    assert!(get(&mut ctx).is_ok(), "get should succeed");
+
+    // The core module can not be disabled.
+    assert!(
+        matches!(
+            set_enabled(&mut ctx, "core", false),
+            module::CallResult::Failed { code: 10, .. }
+        ),
+        "disabling the core module should fail"
+    );
+
+    assert!(
+        set_enabled(&mut ctx, "keyvalue", false).is_success(),
+        "disabling the keyvalue module should succeed"
+    );
+    assert_eq!(
+        Core::disabled_modules(&mut ctx)
+            .into_iter()
+            .collect::<Vec<_>>(),
+        vec!["keyvalue".to_owned()]
+    );
+
+    // Methods and queries of a disabled module should be unavailable.
+    assert!(
+        matches!(
+            insert(&mut ctx),
+            module::CallResult::Failed { ref module, code: 3, .. } if module == "core"
+        ),
+        "insert should fail with an invalid method error"
+    );
+    let err = get(&mut ctx).expect_err("get should fail");
+    assert_eq!(err.module, "core");
+    assert_eq!(err.code, 3);
+
+    // Enabling the module again should make its methods available.
+    assert!(
+        set_enabled(&mut ctx, "keyvalue", true).is_success(),
+        "enabling the keyvalue module should succeed"
+    );
+    assert!(Core::disabled_modules(&mut ctx).is_empty());
+    assert!(insert(&mut ctx).is_success(), "insert should succeed");
+    assert!(get(&mut ctx).is_ok(), "get should succeed");
+}
+
+#[test]
+fn test_scheduled_call() {
+    fn get<C: BatchContext>(ctx: &mut C, key: &[u8]) -> Result<Vec<u8>, RuntimeError> {
+        dispatcher::Dispatcher::<super::Runtime>::dispatch_query(
+            ctx,
+            "keyvalue.Get",
+            cbor::to_vec(super::keyvalue::types::Key { key: key.to_vec() }),
+        )
+    }
+
+    let mut mock = mock::Mock::default();
+
+    // Schedule an insert in the first round.
+    mock.runtime_header.round = 0;
+    {
+        let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+        Core::set_params(
+            ctx.runtime_state(),
+            core::Parameters {
+                max_batch_gas: u64::MAX,
+                min_gas_price: {
+                    let mut mgp = BTreeMap::new();
+                    mgp.insert(token::Denomination::NATIVE, 1);
+                    mgp
+                },
+                max_scheduled_calls_per_round: 1,
+                ..Default::default()
+            },
+        );
+        super::keyvalue::Module::set_params(
+            ctx.runtime_state(),
+            super::keyvalue::Parameters {
+                gas_costs: super::keyvalue::GasCosts {
+                    insert_absent: 200,
+                    ..Default::default()
+                },
+            },
+        );
+
+        let schedule_tx = |key: &[u8]| transaction::Transaction {
+            version: 1,
+            call: transaction::Call {
+                format: transaction::CallFormat::Plain,
+                method: "core.Schedule".to_owned(),
+                body: cbor::to_value(core::types::Schedule {
+                    round: 3,
+                    call: transaction::Call {
+                        format: transaction::CallFormat::Plain,
+                        method: "keyvalue.Insert".to_owned(),
+                        body: cbor::to_value(super::keyvalue::types::KeyValue {
+                            key: key.to_vec(),
+                            value: b"bar".to_vec(),
+                        }),
+                    },
+                    gas: 1_000,
+                }),
+            },
+            auth_info: transaction::AuthInfo {
+                signer_info: vec![transaction::SignerInfo::new_sigspec(
+                    keys::alice::sigspec(),
+                    0,
+                )],
+                fee: transaction::Fee {
+                    amount: token::BaseUnits::new(10_000, token::Denomination::NATIVE),
+                    gas: 10_000,
+                    consensus_messages: 0,
+                },
+                features: 0,
+            },
+        };
+
+        ctx.with_tx(0, schedule_tx(b"foo"), |mut tx_ctx, call| {
+            let result =
+                dispatcher::Dispatcher::<super::Runtime>::dispatch_tx_call(&mut tx_ctx, call);
+            assert!(result.is_success(), "scheduling should succeed");
+            tx_ctx.commit();
+        });
+        ctx.with_tx(0, schedule_tx(b"baz"), |mut tx_ctx, call| {
+            let result =
+                dispatcher::Dispatcher::<super::Runtime>::dispatch_tx_call(&mut tx_ctx, call);
+            assert!(
+                matches!(result, module::CallResult::Failed { code: 24, .. }),
+                "scheduling more calls than allowed per round should fail"
+            );
+        });
+    }
+
+    // The call should not be executed before its round.
+    mock.runtime_header.round = 1;
+    {
+        let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+        Core::begin_block(&mut ctx);
+        assert!(
+            get(&mut ctx, b"foo").is_err(),
+            "call should not be executed yet"
+        );
+    }
+
+    // The call should be executed at the start of its round, even though it does not pay a fee
+    // itself.
+    mock.runtime_header.round = 2;
+    {
+        let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+        Core::begin_block(&mut ctx);
+        let res = get(&mut ctx, b"foo").expect("call should have been executed");
+        let kv: super::keyvalue::types::KeyValue =
+            cbor::from_slice(&res).expect("result should be well-formed");
+        assert_eq!(kv.value, b"bar".to_vec());
+        let (tags, _) = ctx.commit();
+        assert_eq!(tags.len(), 1, "insert event should be emitted");
+    }
+
+    // The call should only be executed once.
+    mock.runtime_header.round = 3;
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+    Core::begin_block(&mut ctx);
+    let (tags, _) = ctx.commit();
+    assert!(tags.is_empty(), "call should not be executed again");
+}
+
+#[test]
+fn test_scheduled_call_limit() {
+    let mut mock = mock::Mock::default();
+    let calls = core::MAX_SCHEDULED_CALLS_PER_BLOCK + 1;
+
+    // Schedule more calls than can be executed in a single block.
+    mock.runtime_header.round = 0;
+    {
+        let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+        Core::set_params(
+            ctx.runtime_state(),
+            core::Parameters {
+                max_batch_gas: u64::MAX,
+                min_gas_price: {
+                    let mut mgp = BTreeMap::new();
+                    mgp.insert(token::Denomination::NATIVE, 0);
+                    mgp
+                },
+                max_scheduled_calls_per_round: calls as u32,
+                ..Default::default()
+            },
+        );
+
+        for i in 0..calls {
+            let tx = transaction::Transaction {
+                version: 1,
+                call: transaction::Call {
+                    format: transaction::CallFormat::Plain,
+                    method: "core.Schedule".to_owned(),
+                    body: cbor::to_value(core::types::Schedule {
+                        round: 3,
+                        call: transaction::Call {
+                            format: transaction::CallFormat::Plain,
+                            method: "keyvalue.Insert".to_owned(),
+                            body: cbor::to_value(super::keyvalue::types::KeyValue {
+                                key: format!("key{}", i).into_bytes(),
+                                value: b"value".to_vec(),
+                            }),
+                        },
+                        gas: 1_000,
+                    }),
+                },
+                auth_info: transaction::AuthInfo {
+                    signer_info: vec![transaction::SignerInfo::new_sigspec(
+                        keys::alice::sigspec(),
+                        0,
+                    )],
+                    fee: transaction::Fee {
+                        amount: token::BaseUnits::new(0, token::Denomination::NATIVE),
+                        gas: 10_000,
+                        consensus_messages: 0,
+                    },
+                    features: 0,
+                },
+            };
+            ctx.with_tx(0, tx, |mut tx_ctx, call| {
+                let result =
+                    dispatcher::Dispatcher::<super::Runtime>::dispatch_tx_call(&mut tx_ctx, call);
+                assert!(result.is_success(), "scheduling should succeed");
+                tx_ctx.commit();
+            });
+        }
+        ctx.commit();
+    }
+
+    // Only the maximum number of calls should be executed in the first block.
+    mock.runtime_header.round = 2;
+    {
+        let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+        Core::begin_block(&mut ctx);
+        let (tags, _) = ctx.commit();
+        assert_eq!(tags.len(), core::MAX_SCHEDULED_CALLS_PER_BLOCK);
+    }
+
+    // The remaining call should be executed in the next block.
+    mock.runtime_header.round = 3;
+    {
+        let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+        Core::begin_block(&mut ctx);
+        let (tags, _) = ctx.commit();
+        assert_eq!(tags.len(), 1, "deferred call should be executed");
+    }
+
+    mock.runtime_header.round = 4;
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+    Core::begin_block(&mut ctx);
+    let (tags, _) = ctx.commit();
+    assert!(tags.is_empty(), "calls should not be executed again");
+}
+
+/// Module counting keyvalue insertions by handling the keyvalue module's events.
+struct InsertCounter;
+
+impl InsertCounter {
+    const KEY_COUNT: &'static [u8] = b"count";
+
+    fn count<C: Context>(ctx: &mut C) -> u64 {
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &Self::NAME);
+        storage::TypedStore::new(store)
+            .get(Self::KEY_COUNT)
+            .unwrap_or_default()
+    }
+}
+
+impl module::Module for InsertCounter {
+    const NAME: &'static str = "insertcounter";
+    type Error = std::convert::Infallible;
+    type Event = ();
+    type Parameters = ();
+}
+
+impl module::MethodHandler for InsertCounter {}
+impl module::BlockHandler for InsertCounter {}
+impl module::AuthHandler for InsertCounter {}
+impl module::MigrationHandler for InsertCounter {
+    type Genesis = ();
+}
+impl module::InvariantHandler for InsertCounter {}
+
+impl module::EventHandler for InsertCounter {
+    fn handle_event<C: Context>(ctx: &mut C, module_name: &str, code: u32, _value: &[u8]) {
+        if module_name != super::keyvalue::Module::NAME || code != 1 {
+            return;
+        }
+
+        let count = Self::count(ctx);
+        let store = storage::PrefixStore::new(ctx.runtime_state(), &Self::NAME);
+        storage::TypedStore::new(store).insert(Self::KEY_COUNT, count + 1);
+    }
+}
+
+/// Runtime with a module reacting to keyvalue events.
+struct InsertCounterRuntime;
+
+impl Runtime for InsertCounterRuntime {
+    const VERSION: Version = Version::new(0, 0, 0);
+
+    type Modules = (
+        Core,
+        accounts::Module,
+        super::keyvalue::Module,
+        InsertCounter,
+    );
+
+    fn genesis_state() -> <Self::Modules as module::MigrationHandler>::Genesis {
+        (
+            core::Genesis {
+                parameters: core::Parameters {
+                    max_batch_gas: u64::MAX,
+                    max_tx_signers: 1,
+                    max_multisig_signers: 1,
+                    min_gas_price: {
+                        let mut mgp = BTreeMap::new();
+                        mgp.insert(token::Denomination::NATIVE, 0);
+                        mgp
+                    },
+                    ..Default::default()
+                },
+            },
+            Default::default(),
+            Default::default(),
+            (),
+        )
+    }
+}
+
+#[test]
+fn test_event_handler() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<InsertCounterRuntime>(Mode::ExecuteTx);
+    InsertCounterRuntime::migrate(&mut ctx);
+
+    let txs = [
+        ("keyvalue.Insert", b"foo".to_vec()),
+        ("keyvalue.Insert", b"bar".to_vec()),
+        ("keyvalue.Remove", b"foo".to_vec()),
+        ("keyvalue.Insert", b"foo".to_vec()),
+    ];
+    for (nonce, (method, key)) in txs.iter().enumerate() {
+        let body = if *method == "keyvalue.Remove" {
+            cbor::to_value(super::keyvalue::types::Key { key: key.clone() })
+        } else {
+            cbor::to_value(super::keyvalue::types::KeyValue {
+                key: key.clone(),
+                value: b"value".to_vec(),
+            })
+        };
+        let tx = transaction::Transaction {
+            version: 1,
+            call: transaction::Call {
+                format: transaction::CallFormat::Plain,
+                method: method.to_string(),
+                body,
+            },
+            auth_info: transaction::AuthInfo {
+                signer_info: vec![transaction::SignerInfo::new_sigspec(
+                    keys::alice::sigspec(),
+                    nonce as u64,
+                )],
+                fee: transaction::Fee {
+                    amount: token::BaseUnits::new(0, token::Denomination::NATIVE),
+                    gas: 1_000,
+                    consensus_messages: 0,
+                },
+                features: 0,
+            },
+        };
+
+        let result = dispatcher::Dispatcher::<InsertCounterRuntime>::execute_tx(
+            &mut ctx,
+            1024,
+            Hash::empty_hash(),
+            tx,
+            nonce,
+        )
+        .expect("transaction execution should not abort");
+        let output: transaction::CallResult =
+            cbor::from_slice(&result.output).expect("output should be well-formed");
+        assert!(output.is_success(), "transaction should succeed");
+    }
+
+    assert_eq!(
+        InsertCounter::count(&mut ctx),
+        3,
+        "handler should be invoked for each insert event"
+    );
+}
+
+#[test]
+fn test_state_observer() {
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+    super::Runtime::migrate(&mut ctx);
+
+    let observed: Arc<Mutex<Vec<(Hash, Vec<Vec<u8>>)>>> = Default::default();
+    let recorder = observed.clone();
+    dispatcher::Dispatcher::<super::Runtime>::set_state_observer(
+        &mut ctx,
+        Arc::new(move |tx_hash, keys| {
+            recorder.lock().unwrap().push((*tx_hash, keys.to_vec()));
+        }),
+    );
+
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "keyvalue.Insert".to_owned(),
+            body: cbor::to_value(super::keyvalue::types::KeyValue {
+                key: b"foo".to_vec(),
+                value: b"bar".to_vec(),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: token::BaseUnits::new(0, token::Denomination::NATIVE),
+                gas: 1_000,
+                consensus_messages: 0,
+            },
+            features: 0,
+        },
+    };
+    let tx_hash = Hash::digest_bytes(b"insert");
+    let result =
+        dispatcher::Dispatcher::<super::Runtime>::execute_tx(&mut ctx, 100, tx_hash, tx, 0)
+            .expect("transaction execution should not abort");
+    let output: transaction::CallResult =
+        cbor::from_slice(&result.output).expect("output should be well-formed");
+    assert!(output.is_success(), "transaction should succeed");
+
+    let observed = observed.lock().unwrap();
+    assert_eq!(observed.len(), 1, "observer should be invoked once");
+    let (observed_hash, keys) = &observed[0];
+    assert_eq!(observed_hash, &tx_hash);
+    assert!(
+        keys.contains(&b"keyvaluefoo".to_vec()),
+        "inserted key should be observed"
+    );
+    assert!(
+        keys.windows(2).all(|w| w[0] < w[1]),
+        "keys should be sorted"
+    );
+}
+
+#[test]
+fn test_execution_observer() {
+    #[derive(Default)]
+    struct Recorder {
+        started: Mutex<Vec<usize>>,
+        ended: Mutex<Vec<(usize, bool, u64)>>,
+    }
+
+    impl dispatcher::ExecutionObserver for Recorder {
+        fn on_tx_start(&self, index: usize) {
+            self.started.lock().unwrap().push(index);
+        }
+
+        fn on_tx_end(
+            &self,
+            index: usize,
+            result: &module::CallResult,
+            gas_used: u64,
+            _elapsed: Duration,
+        ) {
+            self.ended
+                .lock()
+                .unwrap()
+                .push((index, result.is_success(), gas_used));
+        }
+    }
+
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx_for_runtime::<super::Runtime>(Mode::ExecuteTx);
+    super::Runtime::migrate(&mut ctx);
+    super::keyvalue::Module::set_params(
+        ctx.runtime_state(),
+        super::keyvalue::Parameters {
+            gas_costs: super::keyvalue::GasCosts {
+                insert_absent: 200,
+                ..Default::default()
+            },
+        },
+    );
+
+    let recorder = Arc::new(Recorder::default());
+    dispatcher::Dispatcher::<super::Runtime>::set_execution_observer(&mut ctx, recorder.clone());
+
+    let tx = transaction::Transaction {
+        version: 1,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "keyvalue.Insert".to_owned(),
+            body: cbor::to_value(super::keyvalue::types::KeyValue {
+                key: b"foo".to_vec(),
+                value: b"bar".to_vec(),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: token::BaseUnits::new(0, token::Denomination::NATIVE),
+                gas: 1_000,
+                consensus_messages: 0,
+            },
+            features: 0,
+        },
+    };
+    let result = dispatcher::Dispatcher::<super::Runtime>::execute_tx(
+        &mut ctx,
+        100,
+        Hash::digest_bytes(b"insert"),
+        tx,
+        3,
+    )
+    .expect("transaction execution should not abort");
+
+    // Gas is used for the transaction size, the signature and the insert.
+    assert_eq!(*recorder.started.lock().unwrap(), vec![3]);
+    assert_eq!(
+        *recorder.ended.lock().unwrap(),
+        vec![(3, true, 100 + 10 + 200)],
+        "observer should see the result and gas used"
+    );
+
+    // The gas used should also be reported in the result.
+    #[derive(Debug, cbor::Decode)]
+    struct GasUsedEvent {
+        amount: u64,
+    }
+    let tag = result
+        .tags
+        .iter()
+        .find(|tag| tag.key == b"core\x00\x00\x00\x05") // core.GasUsed (code = 5) event
+        .expect("gas used event should be emitted");
+    let event: GasUsedEvent = cbor::from_slice(&tag.value).unwrap();
+    assert_eq!(event.amount, 100 + 10 + 200);
+}